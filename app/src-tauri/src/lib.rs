@@ -1,4 +1,6 @@
-use sendme_lib::{progress::*, types::*};
+use sendme_lib::nearby::{Device, DeviceInfo, Nearby, NearbyEvent};
+use sendme_lib::offer::{Answer, IncomingOffer, Offer};
+use sendme_lib::{progress::*, types::*, CancellationToken};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::io::Write;
@@ -168,16 +170,15 @@ async fn handle_content_uri(
 /// Copy exported files from temp_dir to a content URI on Android.
 ///
 /// Uses JNI to call Android's ContentResolver to create and write files
-/// to the selected directory.
+/// to the selected directory. A received archive is copied as a single file.
 #[cfg(target_os = "android")]
 async fn copy_files_to_content_uri(
     _app: &AppHandle,
     temp_dir: &std::path::Path,
     content_uri: &str,
-    collection: &iroh_blobs::format::collection::Collection,
+    result: &ReceiveResult,
 ) -> anyhow::Result<()> {
     log_info!("Starting copy to content URI: {}", content_uri);
-    log_info!("Files to copy: {}", collection.len());
 
     // Extract the tree URI part from the content URI
     // Android SAF may return URIs in format: content://.../tree/.../document/...
@@ -186,10 +187,25 @@ async fn copy_files_to_content_uri(
     log_info!("Extracted tree URI: {}", tree_uri);
 
     // Collect file info to copy
-    let files_to_copy: Vec<(String, std::path::PathBuf)> = collection
-        .iter()
-        .map(|(name, _hash)| (name.to_string(), temp_dir.join(name)))
-        .collect();
+    let files_to_copy: Vec<(String, std::path::PathBuf)> = match &result.archive {
+        Some(path) => {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            vec![(name, path.clone())]
+        }
+        // Renamed files are copied under the name they were written to
+        None => result
+            .exported
+            .iter()
+            .filter_map(|path| {
+                let name = path.strip_prefix(temp_dir).ok()?;
+                Some((name.to_string_lossy().into_owned(), path.clone()))
+            })
+            .collect(),
+    };
+    log_info!("Files to copy: {}", files_to_copy.len());
 
     // Run JNI operations in a blocking thread to avoid issues with async runtime
     let result = tokio::task::spawn_blocking(move || {
@@ -413,12 +429,73 @@ pub struct SendFileRequest {
     /// for preserving the original filename when handling content URIs.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub filename: Option<String>,
+    /// Endpoint ids allowed to fetch the data. If absent, anyone with the
+    /// ticket can.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowlist: Option<Vec<String>>,
+    /// Stop sharing after this many downloads, complete or not.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_downloads: Option<u32>,
+    /// Stop sharing after this many seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_after_secs: Option<u64>,
+    /// Encrypt the files with a key derived from this passphrase. The
+    /// receiver has to enter the same passphrase.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<String>,
+    /// What to do with symlinks in a shared directory: "skip" (default),
+    /// "follow" or "preserve".
+    #[serde(default)]
+    pub symlinks: Option<String>,
+    /// Leave out what .gitignore, .ignore and .sendmeignore files exclude.
+    #[serde(default)]
+    pub ignore_files: bool,
+    /// Only send files matching one of these globs (.gitignore syntax).
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Don't send files matching one of these globs (.gitignore syntax).
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Compress files that are not compressed already while sending them.
+    #[serde(default)]
+    pub compress: bool,
+    /// Stay on the local network, without relays, announcing the share with
+    /// mDNS only.
+    #[serde(default)]
+    pub lan_only: bool,
+    /// Send with this device's stored identity, so recipients see the same
+    /// endpoint id every time. Only one share at a time can use it.
+    #[serde(default)]
+    pub use_identity: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ReceiveFileRequest {
     pub ticket: String,
     pub output_dir: Option<String>,
+    /// What to do with files that already exist in the output directory:
+    /// "overwrite" (default), "skip", "rename", "fail" or "skip_if_identical".
+    #[serde(default)]
+    pub conflict_policy: Option<String>,
+    /// Only move the files into the output directory once all of them have
+    /// been written.
+    #[serde(default)]
+    pub atomic: bool,
+    /// Names of the files or directories to receive. Receives everything if
+    /// not set.
+    #[serde(default)]
+    pub include: Option<Vec<String>>,
+    /// Passphrase for a protected share.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<String>,
+    /// Save the files in a single archive in the output directory instead:
+    /// "tar", "tar_zst" or "zip".
+    #[serde(default)]
+    pub archive: Option<String>,
+    /// Stay on the local network, without relays, finding the sender with
+    /// mDNS only.
+    #[serde(default)]
+    pub lan_only: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -443,6 +520,37 @@ type Transfers = Arc<RwLock<HashMap<String, TransferState>>>;
 struct TransferState {
     info: TransferInfo,
     abort_tx: Option<tokio::sync::oneshot::Sender<()>>,
+    /// The share of a send and the endpoint serving it, once it is serving,
+    /// for offering it to nearby devices
+    share: Option<(SendResult, sendme_lib::Endpoint)>,
+    /// Whether the send uses this device's stored identity
+    identity: bool,
+}
+
+impl TransferState {
+    fn is_finished(&self) -> bool {
+        let status = &self.info.status;
+        status == "completed" || status == "cancelled" || status.starts_with("error")
+    }
+}
+
+// The nearby node announcing this device, and the offers waiting for an answer
+type NearbyHandle = Arc<RwLock<NearbyState>>;
+
+#[derive(Debug, Default)]
+struct NearbyState {
+    node: Option<Arc<Nearby>>,
+    offers: HashMap<String, IncomingOffer>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartNearbyRequest {
+    /// Name other devices see. Defaults to the host name.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Stay on the local network, without relays.
+    #[serde(default)]
+    pub lan_only: bool,
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -501,6 +609,7 @@ pub fn run() {
         .setup(move |app| {
             // Store transfers in app state
             app.manage(transfers.clone());
+            app.manage(NearbyHandle::default());
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -510,13 +619,20 @@ pub fn run() {
             get_transfers,
             get_transfer_status,
             clear_transfers,
+            ticket_requires_passphrase,
             get_hostname,
+            get_endpoint_id,
             get_device_model,
             get_default_download_folder,
             open_received_file,
             list_received_files,
             pick_file,
-            pick_directory
+            pick_directory,
+            start_nearby,
+            stop_nearby,
+            list_nearby_devices,
+            send_to_device,
+            respond_to_offer
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -588,13 +704,51 @@ async fn send_file(
     log_info!("✅ File path resolved: {:?}", file_path);
     log_info!("✅ Display name: {}", display_name);
 
+    // Two endpoints with the same key would fight over relays and addresses,
+    // so shares use a fresh key unless asked to use the stored identity
+    let identity = request.use_identity;
+    let key_dir = identity_dir(&app)?;
+
+    let allowlist = match request.allowlist {
+        Some(ids) => Some(
+            ids.iter()
+                .map(|id| {
+                    id.parse::<sendme_lib::EndpointId>()
+                        .map_err(|e| format!("Invalid endpoint id {}: {}", id, e))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        None => None,
+    };
+
+    let symlinks = match request.symlinks.as_deref() {
+        None | Some("skip") => SymlinkPolicy::Skip,
+        Some("follow") => SymlinkPolicy::Follow,
+        Some("preserve") => SymlinkPolicy::Preserve,
+        Some(other) => return Err(format!("Invalid symlink policy: {}", other)),
+    };
+
     let args = SendArgs {
         path: file_path,
         ticket_type,
         common: CommonConfig {
             temp_dir: Some(temp_dir),
+            identity: identity.then(|| sendme_lib::DEFAULT_IDENTITY.to_string()),
+            key_dir: Some(key_dir),
+            network: network_mode(request.lan_only),
             ..Default::default()
         },
+        allowlist,
+        max_downloads: request.max_downloads,
+        expires_after: request.expires_after_secs.map(std::time::Duration::from_secs),
+        passphrase: request.passphrase,
+        short_code: false,
+        symlinks,
+        ignore_files: request.ignore_files,
+        include: request.include,
+        exclude: request.exclude,
+        stdin: None,
+        compress: request.compress,
     };
     log_info!("⚙️  SendArgs created successfully");
 
@@ -619,11 +773,20 @@ async fn send_file(
     // Store transfer
     log_info!("💾 Storing transfer in state...");
     let mut transfers_guard = transfers.write().await;
+    if identity
+        && transfers_guard
+            .values()
+            .any(|state| state.identity && !state.is_finished())
+    {
+        return Err("Another share is using this device's identity".to_string());
+    }
     transfers_guard.insert(
         transfer_id.clone(),
         TransferState {
             info: transfer_info.clone(),
             abort_tx: Some(abort_tx),
+            share: None,
+            identity,
         },
     );
     drop(transfers_guard);
@@ -632,7 +795,6 @@ async fn send_file(
     let app_clone = app.clone();
     let transfers_clone = transfers.inner().clone();
     let transfer_id_clone = transfer_id.clone();
-    let transfer_id_for_abort = transfer_id.clone();
 
     log_info!("🔄 Spawning progress listener task...");
     tokio::spawn(async move {
@@ -641,15 +803,6 @@ async fn send_file(
             transfer_id_clone
         );
 
-        // Listen for abort signal
-        tokio::spawn(async move {
-            let _ = abort_rx.await;
-            log_info!(
-                "  [Progress Task] Transfer {} aborted",
-                transfer_id_for_abort
-            );
-        });
-
         let mut event_count = 0;
         while let Some(event) = rx.recv().await {
             event_count += 1;
@@ -709,17 +862,22 @@ async fn send_file(
                     }
                 }
                 ProgressEvent::Connection(status) => {
-                    update_transfer_status(
-                        &transfers_clone,
-                        &transfer_id_clone,
-                        &format!("connection: {:?}", status),
-                    )
-                    .await;
+                    // A new path or round trip time doesn't change the status
+                    if !matches!(status, ConnectionStatus::PathChanged { .. }) {
+                        update_transfer_status(
+                            &transfers_clone,
+                            &transfer_id_clone,
+                            &format!("connection: {:?}", status),
+                        )
+                        .await;
+                    }
                     ProgressUpdate {
                         event_type: "connection".to_string(),
                         data: serde_json::json!({
                             "transfer_id": transfer_id_clone,
                             "status": format!("{:?}", status),
+                            "path": serialize_path(&status),
+                            "rate": serialize_request_rate(&status),
                         }),
                     }
                 }
@@ -735,14 +893,31 @@ async fn send_file(
 
     log_info!("🚀 Calling sendme_lib::send_with_progress...");
     match sendme_lib::send_with_progress(args, tx).await {
-        Ok(result) => {
+        Ok((result, handle)) => {
             log_info!("═══════════════════════════════════════════════════");
             log_info!("✅ SEND COMPLETED SUCCESSFULLY");
             log_info!("═══════════════════════════════════════════════════");
-            log_info!("🎫 Ticket: {}", result.ticket.to_string());
+            log_info!("🎫 Ticket: {}", result.share_ticket());
             log_info!("📊 Transfer ID: {}", transfer_id);
             update_transfer_status(transfers.inner(), &transfer_id, "serving").await;
-            Ok(result.ticket.to_string())
+            if let Some(state) = transfers.write().await.get_mut(&transfer_id) {
+                state.share = Some((result.clone(), handle.endpoint().clone()));
+            }
+
+            // Keep serving until the transfer is cancelled or cleared, or its
+            // download or time limit is reached
+            let transfer_id_for_abort = transfer_id.clone();
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = abort_rx => {}
+                    _ = handle.closed() => {}
+                }
+                log_info!("🛑 Stopping share for transfer {}", transfer_id_for_abort);
+                if let Err(e) = handle.shutdown().await {
+                    log_error!("Failed to stop share {}: {}", transfer_id_for_abort, e);
+                }
+            });
+            Ok(result.share_ticket().to_string())
         }
         Err(e) => {
             log_error!("═══════════════════════════════════════════════════");
@@ -769,7 +944,16 @@ async fn receive_file(
     log_info!("Transfer ID: {}", transfer_id);
 
     let (tx, mut rx) = tokio::sync::mpsc::channel(32);
-    let (abort_tx, _abort_rx) = tokio::sync::oneshot::channel();
+    let (abort_tx, abort_rx) = tokio::sync::oneshot::channel();
+
+    // Cancel the receive when the transfer is cancelled or cleared
+    let cancel = CancellationToken::new();
+    let cancel_on_abort = cancel.clone();
+    tokio::spawn(async move {
+        if abort_rx.await.is_ok() {
+            cancel_on_abort.cancel();
+        }
+    });
 
     // On Android, set_current_dir doesn't work with public directories due to sandboxing.
     #[cfg(not(target_os = "android"))]
@@ -783,10 +967,26 @@ async fn receive_file(
     }
 
     log_info!("Parsing ticket...");
-    let ticket = request
+    let ticket: sendme_lib::TicketOrCode = request
         .ticket
         .parse()
         .map_err(|e| format!("Invalid ticket: {}", e))?;
+
+    let conflict_policy = match request.conflict_policy.as_deref() {
+        None | Some("overwrite") => ExportConflictPolicy::Overwrite,
+        Some("skip") => ExportConflictPolicy::Skip,
+        Some("rename") => ExportConflictPolicy::Rename,
+        Some("fail") => ExportConflictPolicy::Fail,
+        Some("skip_if_identical") => ExportConflictPolicy::SkipIfIdentical,
+        Some(other) => return Err(format!("Invalid conflict policy: {}", other)),
+    };
+    let archive = match request.archive.as_deref() {
+        None => None,
+        Some("tar") => Some(ArchiveFormat::Tar),
+        Some("tar_zst") => Some(ArchiveFormat::TarZst),
+        Some("zip") => Some(ArchiveFormat::Zip),
+        Some(other) => return Err(format!("Invalid archive format: {}", other)),
+    };
     log_info!("Ticket parsed successfully");
 
     // Get temp directory for blob storage
@@ -796,6 +996,19 @@ async fn receive_file(
         .map_err(|e| format!("Failed to get temp directory: {}", e))?;
     log_info!("Temp dir (for blob storage): {:?}", temp_dir);
 
+    // A short code is exchanged for the ticket with the sender first
+    let ticket = ticket
+        .resolve(&CommonConfig {
+            temp_dir: Some(temp_dir.clone()),
+            network: network_mode(request.lan_only),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| format!("Failed to pair with the sender: {}", e))?;
+    if ticket.encrypted && request.passphrase.is_none() {
+        return Err("This share is protected by a passphrase".to_string());
+    }
+
     // On Android, detect content URIs and handle them specially
     // Content URIs (like "content://...") cannot be used directly as PathBuf
     // We'll export to temp first, then copy to the content URI location
@@ -862,16 +1075,28 @@ async fn receive_file(
     );
 
     let args = ReceiveArgs {
-        ticket,
+        ticket: ticket.ticket,
         common: CommonConfig {
             format: Format::Hex,
             relay: RelayModeOption::Default,
+            network: network_mode(request.lan_only),
             show_secret: false,
             magic_ipv4_addr: None,
             magic_ipv6_addr: None,
             temp_dir: Some(temp_dir.clone()),
+            identity: None,
+            key_dir: None,
         },
         export_dir,
+        include: match request.include {
+            Some(names) => IncludeFilter::Names(names),
+            None => IncludeFilter::All,
+        },
+        conflict_policy,
+        atomic_export: request.atomic,
+        passphrase: request.passphrase,
+        cancel: cancel.clone(),
+        archive,
     };
 
     // Create transfer info
@@ -895,6 +1120,8 @@ async fn receive_file(
         TransferState {
             info: transfer_info.clone(),
             abort_tx: Some(abort_tx),
+            share: None,
+            identity: false,
         },
     );
     drop(transfers_guard);
@@ -969,17 +1196,22 @@ async fn receive_file(
                     }
                 }
                 ProgressEvent::Connection(status) => {
-                    update_transfer_status(
-                        &transfers_clone,
-                        &transfer_id_clone,
-                        &format!("connection: {:?}", status),
-                    )
-                    .await;
+                    // A new path or round trip time doesn't change the status
+                    if !matches!(status, ConnectionStatus::PathChanged { .. }) {
+                        update_transfer_status(
+                            &transfers_clone,
+                            &transfer_id_clone,
+                            &format!("connection: {:?}", status),
+                        )
+                        .await;
+                    }
                     ProgressUpdate {
                         event_type: "connection".to_string(),
                         data: serde_json::json!({
                             "transfer_id": transfer_id_clone,
                             "status": format!("{:?}", status),
+                            "path": serialize_path(&status),
+                            "rate": serialize_request_rate(&status),
                         }),
                     }
                 }
@@ -1009,8 +1241,7 @@ async fn receive_file(
             if let Some(content_uri) = content_uri_output {
                 log_info!("Copying files to content URI: {}", content_uri);
                 if let Err(e) =
-                    copy_files_to_content_uri(&app, &temp_dir, &content_uri, &result.collection)
-                        .await
+                    copy_files_to_content_uri(&app, &temp_dir, &content_uri, &result).await
                 {
                     log_error!("Failed to copy files to content URI: {}", e);
                     update_transfer_status(
@@ -1026,12 +1257,18 @@ async fn receive_file(
 
             update_transfer_status(transfers.inner(), &transfer_id, "completed").await;
             Ok(format!(
-                "{{\"transfer_id\": \"{}\", \"files\": {}, \"bytes\": {}}}",
+                "{{\"transfer_id\": \"{}\", \"files\": {}, \"bytes\": {}, \"summary\": {}}}",
                 transfer_id,
                 result.total_files,
-                result.stats.total_bytes_read()
+                result.stats.total_bytes_read(),
+                serialize_summary(&result.summary)
             ))
         }
+        Err(e) if cancel.is_cancelled() => {
+            log_info!("🛑 RECEIVE CANCELLED: {}", transfer_id);
+            update_transfer_status(transfers.inner(), &transfer_id, "cancelled").await;
+            Err(e.to_string())
+        }
         Err(e) => {
             log_error!("❌ RECEIVE FAILED: {}", e);
             update_transfer_status(transfers.inner(), &transfer_id, &format!("error: {}", e)).await;
@@ -1094,8 +1331,11 @@ async fn update_transfer_status(transfers: &Transfers, id: &str, status: &str) {
 
 fn serialize_import_progress(progress: &ImportProgress) -> serde_json::Value {
     match progress {
-        ImportProgress::Started { total_files } => {
-            serde_json::json!({"type": "started", "total_files": total_files})
+        ImportProgress::Started {
+            total_files,
+            skipped,
+        } => {
+            serde_json::json!({"type": "started", "total_files": total_files, "skipped": skipped})
         }
         ImportProgress::FileStarted { name, size } => {
             serde_json::json!({"type": "file_started", "name": name, "size": size})
@@ -1106,8 +1346,15 @@ fn serialize_import_progress(progress: &ImportProgress) -> serde_json::Value {
         ImportProgress::FileCompleted { name } => {
             serde_json::json!({"type": "file_completed", "name": name})
         }
-        ImportProgress::Completed { total_size: _ } => {
-            serde_json::json!({"type": "completed"})
+        ImportProgress::Completed {
+            total_size,
+            wire_size,
+        } => {
+            serde_json::json!({
+                "type": "completed",
+                "total_size": total_size,
+                "wire_size": wire_size,
+            })
         }
     }
 }
@@ -1120,18 +1367,86 @@ fn serialize_export_progress(progress: &ExportProgress) -> serde_json::Value {
         ExportProgress::FileStarted { name, size } => {
             serde_json::json!({"type": "file_started", "name": name, "size": size})
         }
-        ExportProgress::FileProgress { name, offset } => {
-            serde_json::json!({"type": "file_progress", "name": name, "offset": offset})
+        ExportProgress::FileProgress { name, offset, rate } => {
+            serde_json::json!({
+                "type": "file_progress",
+                "name": name,
+                "offset": offset,
+                "rate": serialize_rate(rate),
+            })
         }
         ExportProgress::FileCompleted { name } => {
             serde_json::json!({"type": "file_completed", "name": name})
         }
+        ExportProgress::Conflict { name, resolution } => {
+            let (resolution, renamed) = match resolution {
+                ConflictResolution::Overwritten => ("overwritten", None),
+                ConflictResolution::Skipped => ("skipped", None),
+                ConflictResolution::SkippedIdentical => ("skipped_identical", None),
+                ConflictResolution::Renamed { name } => ("renamed", Some(name)),
+                ConflictResolution::Failed => ("failed", None),
+            };
+            serde_json::json!({
+                "type": "conflict",
+                "name": name,
+                "resolution": resolution,
+                "renamed_to": renamed,
+            })
+        }
         ExportProgress::Completed => {
             serde_json::json!({"type": "completed"})
         }
     }
 }
 
+/// The network path of a `PathChanged` connection status, or null
+fn serialize_path(status: &ConnectionStatus) -> serde_json::Value {
+    match status {
+        ConnectionStatus::PathChanged {
+            endpoint_id,
+            direct,
+            remote_addr,
+            relay_url,
+            rtt,
+        } => serde_json::json!({
+            "endpoint_id": endpoint_id,
+            "direct": direct,
+            "remote_addr": remote_addr.map(|addr| addr.to_string()),
+            "relay_url": relay_url.as_ref().map(|url| url.to_string()),
+            "rtt_ms": rtt.map(|rtt| rtt.as_millis() as u64),
+        }),
+        _ => serde_json::Value::Null,
+    }
+}
+
+/// Speeds in bytes per second and times in milliseconds, as the UI shows them
+fn serialize_rate(rate: &TransferRate) -> serde_json::Value {
+    serde_json::json!({
+        "bytes_per_sec": rate.bytes_per_sec,
+        "peak_bytes_per_sec": rate.peak_bytes_per_sec,
+        "elapsed_ms": rate.elapsed.as_millis() as u64,
+        "eta_ms": rate.eta.map(|eta| eta.as_millis() as u64),
+    })
+}
+
+/// The upload rate of a `RequestProgress` connection status, or null
+fn serialize_request_rate(status: &ConnectionStatus) -> serde_json::Value {
+    match status {
+        ConnectionStatus::RequestProgress { rate, .. } => serialize_rate(rate),
+        _ => serde_json::Value::Null,
+    }
+}
+
+fn serialize_summary(summary: &TransferSummary) -> serde_json::Value {
+    serde_json::json!({
+        "payload_bytes": summary.payload_bytes,
+        "total_bytes": summary.total_bytes,
+        "elapsed_ms": summary.elapsed.as_millis() as u64,
+        "average_bytes_per_sec": summary.average_bytes_per_sec,
+        "peak_bytes_per_sec": summary.peak_bytes_per_sec,
+    })
+}
+
 fn serialize_download_progress(progress: &DownloadProgress) -> serde_json::Value {
     match progress {
         DownloadProgress::Connecting => {
@@ -1152,8 +1467,17 @@ fn serialize_download_progress(progress: &DownloadProgress) -> serde_json::Value
                 "names": names
             })
         }
-        DownloadProgress::Downloading { offset, total } => {
-            serde_json::json!({"type": "downloading", "offset": offset, "total": total})
+        DownloadProgress::Downloading {
+            offset,
+            total,
+            rate,
+        } => {
+            serde_json::json!({
+                "type": "downloading",
+                "offset": offset,
+                "total": total,
+                "rate": serialize_rate(rate),
+            })
         }
         DownloadProgress::Completed => {
             serde_json::json!({"type": "completed"})
@@ -1190,6 +1514,16 @@ async fn clear_transfers(transfers: tauri::State<'_, Transfers>) -> Result<(), S
     Ok(())
 }
 
+/// Whether the ticket belongs to a passphrase-protected share. Short codes
+/// only tell once they were exchanged, so they always return false.
+#[tauri::command]
+fn ticket_requires_passphrase(ticket: String) -> Result<bool, String> {
+    let ticket: sendme_lib::TicketOrCode = ticket
+        .parse()
+        .map_err(|e| format!("Invalid ticket: {}", e))?;
+    Ok(matches!(ticket, sendme_lib::TicketOrCode::Ticket(ticket) if ticket.encrypted))
+}
+
 /// Get the local hostname
 #[tauri::command]
 fn get_hostname() -> Result<String, String> {
@@ -1206,6 +1540,214 @@ fn get_hostname() -> Result<String, String> {
     }
 }
 
+/// Start announcing this device to others on the local network
+///
+/// Emits a `nearby-device` event whenever a device appears or disappears, and a
+/// `nearby-offer` event for every share offered to this device, to be answered
+/// with `respond_to_offer`. Returns the endpoint id other devices see.
+#[tauri::command]
+async fn start_nearby(
+    app: AppHandle,
+    nearby: tauri::State<'_, NearbyHandle>,
+    request: StartNearbyRequest,
+) -> Result<String, String> {
+    let mut state = nearby.write().await;
+    if let Some(node) = &state.node {
+        return Ok(node.id().to_string());
+    }
+    let info = DeviceInfo {
+        name: match request.name {
+            Some(name) => name,
+            None => get_hostname()?,
+        },
+        model: get_device_model().ok(),
+    };
+    // A fresh key, since shares of this device may use its identity
+    let common = CommonConfig {
+        network: network_mode(request.lan_only),
+        ..Default::default()
+    };
+    let (node, mut offers) = Nearby::spawn(info, &common)
+        .await
+        .map_err(|e| format!("Failed to start nearby discovery: {}", e))?;
+    log_info!("📡 Announcing this device as {}", node.id());
+
+    let mut events = node.events();
+    let app_clone = app.clone();
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            let payload = match event {
+                NearbyEvent::Appeared(device) => serde_json::json!({
+                    "type": "appeared",
+                    "device": serialize_device(&device),
+                }),
+                NearbyEvent::Disappeared { id } => serde_json::json!({
+                    "type": "disappeared",
+                    "id": id.to_string(),
+                }),
+            };
+            let _ = app_clone.emit("nearby-device", payload);
+        }
+    });
+
+    let state_clone = nearby.inner().clone();
+    tokio::spawn(async move {
+        while let Some(offer) = offers.recv().await {
+            let offer_id = Uuid::new_v4().to_string();
+            let payload = serde_json::json!({
+                "offer_id": offer_id,
+                "sender": offer.sender.to_string(),
+                "from": offer.offer.from,
+                "files": offer.offer.files,
+                "total_size": offer.offer.total_size,
+                "encrypted": offer.offer.encrypted,
+                "message": offer.offer.message,
+            });
+            log_info!("📨 Offer {} from {}", offer_id, offer.offer.from.name);
+            state_clone.write().await.offers.insert(offer_id, offer);
+            let _ = app.emit("nearby-offer", payload);
+        }
+    });
+
+    let id = node.id().to_string();
+    state.node = Some(Arc::new(node));
+    Ok(id)
+}
+
+/// Stop announcing this device, declining the offers that wait for an answer
+#[tauri::command]
+async fn stop_nearby(nearby: tauri::State<'_, NearbyHandle>) -> Result<(), String> {
+    let mut state = nearby.write().await;
+    state.offers.clear();
+    // With an offer on its way, the node stops once that is answered
+    if let Some(Ok(node)) = state.node.take().map(Arc::try_unwrap) {
+        node.shutdown()
+            .await
+            .map_err(|e| format!("Failed to stop nearby discovery: {}", e))?;
+    }
+    Ok(())
+}
+
+/// The devices currently seen on the local network
+#[tauri::command]
+async fn list_nearby_devices(
+    nearby: tauri::State<'_, NearbyHandle>,
+) -> Result<Vec<serde_json::Value>, String> {
+    let state = nearby.read().await;
+    let node = state.node.as_ref().ok_or("Nearby discovery is not running")?;
+    Ok(node.devices().iter().map(serialize_device).collect())
+}
+
+/// Offer the share of a running send to a nearby device, with an optional
+/// message
+///
+/// Waits for the user of the device to answer and returns the answer:
+/// "Accept", {"AcceptSubset": [names]} or "Reject".
+#[tauri::command]
+async fn send_to_device(
+    transfers: tauri::State<'_, Transfers>,
+    nearby: tauri::State<'_, NearbyHandle>,
+    transfer_id: String,
+    device_id: String,
+    message: Option<String>,
+) -> Result<Answer, String> {
+    let device_id: sendme_lib::EndpointId = device_id
+        .parse()
+        .map_err(|e| format!("Invalid device id: {}", e))?;
+    let (share, endpoint) = transfers
+        .read()
+        .await
+        .get(&transfer_id)
+        .and_then(|state| state.share.clone())
+        .ok_or("Transfer is not serving a share")?;
+    // Don't hold the lock while waiting for the answer
+    let node = nearby
+        .read()
+        .await
+        .node
+        .clone()
+        .ok_or("Nearby discovery is not running")?;
+    let mut offer = Offer::new(node.info().clone(), &share);
+    offer.message = message;
+    node.offer(&endpoint, device_id, &offer, &share.share_ticket(), None)
+        .await
+        .map_err(|e| format!("Failed to offer the share: {}", e))
+}
+
+/// Answer an offer from a `nearby-offer` event
+///
+/// With `names`, only those files are accepted. Returns the ticket and the
+/// names to pass to `receive_file` if the offer was accepted.
+#[tauri::command]
+async fn respond_to_offer(
+    nearby: tauri::State<'_, NearbyHandle>,
+    offer_id: String,
+    accept: bool,
+    names: Option<Vec<String>>,
+) -> Result<Option<serde_json::Value>, String> {
+    let offer = nearby
+        .write()
+        .await
+        .offers
+        .remove(&offer_id)
+        .ok_or("Offer not found")?;
+    let answer = match (accept, names) {
+        (false, _) => Answer::Reject,
+        (true, None) => Answer::Accept,
+        (true, Some(names)) => Answer::AcceptSubset(names),
+    };
+    let accepted = offer
+        .answer(answer)
+        .await
+        .map_err(|e| format!("Failed to answer the offer: {}", e))?;
+    Ok(accepted.map(|accepted| {
+        let include = match accepted.include {
+            IncludeFilter::Names(names) => Some(names),
+            _ => None,
+        };
+        serde_json::json!({
+            "ticket": accepted.ticket.to_string(),
+            "include": include,
+        })
+    }))
+}
+
+fn serialize_device(device: &Device) -> serde_json::Value {
+    serde_json::json!({
+        "id": device.id.to_string(),
+        "name": device.info.name,
+        "model": device.info.model,
+    })
+}
+
+/// The network mode for the `lan_only` flag of a request
+fn network_mode(lan_only: bool) -> NetworkMode {
+    if lan_only {
+        NetworkMode::LanOnly
+    } else {
+        NetworkMode::Internet
+    }
+}
+
+/// Directory of the key store holding this device's identities
+fn identity_dir(app: &AppHandle) -> Result<std::path::PathBuf, String> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join("identities"))
+        .map_err(|e| format!("Failed to get config directory: {}", e))
+}
+
+/// Get the endpoint id of this device's identity, creating it if needed. Shares
+/// sent with `use_identity` use it
+#[tauri::command]
+fn get_endpoint_id(app: AppHandle) -> Result<String, String> {
+    let store = sendme_lib::KeyStore::new(identity_dir(&app)?);
+    let key = store
+        .load_or_create(sendme_lib::DEFAULT_IDENTITY)
+        .map_err(|e| format!("Failed to load identity: {}", e))?;
+    Ok(key.public().to_string())
+}
+
 /// Get the device model (mobile-specific)
 #[tauri::command]
 fn get_device_model() -> Result<String, String> {
@@ -7,13 +7,62 @@ export interface SendFileRequest {
   ticket_type: string;
   /** Optional filename from file picker. Used for display and preserving original filename. */
   filename?: string;
+  /** Endpoint ids allowed to fetch the data. Anyone with the ticket can if absent. */
+  allowlist?: string[];
+  /** Stop sharing after this many downloads, complete or not. */
+  max_downloads?: number;
+  /** Stop sharing after this many seconds. */
+  expires_after_secs?: number;
+  /** Encrypt the files with a key derived from this passphrase. */
+  passphrase?: string;
+  /** What to do with symlinks in a shared directory. Defaults to "skip". */
+  symlinks?: SymlinkPolicy;
+  /** Leave out what .gitignore, .ignore and .sendmeignore files exclude. */
+  ignore_files?: boolean;
+  /** Only send files matching one of these globs (.gitignore syntax). */
+  include?: string[];
+  /** Don't send files matching one of these globs (.gitignore syntax). */
+  exclude?: string[];
+  /** Compress files that are not compressed already while sending them. */
+  compress?: boolean;
+  /** Stay on the local network: no relays, announced with mDNS only. */
+  lan_only?: boolean;
+  /**
+   * Send with this device's identity, so recipients see the same endpoint id
+   * every time. Only one share at a time can use it.
+   */
+  use_identity?: boolean;
 }
 
+export type SymlinkPolicy = "skip" | "follow" | "preserve";
+
+export type ConflictPolicy =
+  | "overwrite"
+  | "skip"
+  | "rename"
+  | "fail"
+  | "skip_if_identical";
+
 export interface ReceiveFileRequest {
+  /** A ticket, or a short code like "482913-canyon-violin". */
   ticket: string;
   output_dir?: string;
+  /** What to do with files that already exist. Defaults to "overwrite". */
+  conflict_policy?: ConflictPolicy;
+  /** Only move files into place once the whole collection was written. */
+  atomic?: boolean;
+  /** Names of the files or directories to receive. Defaults to everything. */
+  include?: string[];
+  /** Passphrase for a protected share. */
+  passphrase?: string;
+  /** Save the files in a single archive in the output directory instead. */
+  archive?: ArchiveFormat;
+  /** Stay on the local network: no relays, sender found with mDNS only. */
+  lan_only?: boolean;
 }
 
+export type ArchiveFormat = "tar" | "tar_zst" | "zip";
+
 export interface TransferInfo {
   id: string;
   transfer_type: string;
@@ -27,6 +76,44 @@ export interface ProgressUpdate {
   data: any;
 }
 
+/**
+ * The network path to the remote, as `data.path` of `connection` progress
+ * updates. Null for other connection events.
+ */
+export interface NetworkPath {
+  endpoint_id: string;
+  /** Whether the traffic goes straight to the remote, not via a relay. */
+  direct: boolean;
+  remote_addr: string | null;
+  relay_url: string | null;
+  /** Round trip time in milliseconds, once measured. */
+  rtt_ms: number | null;
+}
+
+/**
+ * Speed of a transfer, as `rate` of `downloading` and export `file_progress`
+ * updates, and as `data.rate` of `connection` updates while uploading.
+ */
+export interface TransferRate {
+  /** Smoothed speed in bytes per second. */
+  bytes_per_sec: number;
+  peak_bytes_per_sec: number;
+  elapsed_ms: number;
+  /** Time left in milliseconds, if known. */
+  eta_ms: number | null;
+}
+
+/**
+ * Summary of a finished download, as `summary` of the receive_file result.
+ */
+export interface TransferSummary {
+  payload_bytes: number;
+  total_bytes: number;
+  elapsed_ms: number;
+  average_bytes_per_sec: number;
+  peak_bytes_per_sec: number;
+}
+
 /**
  * Send a file or directory and return the ticket
  */
@@ -71,6 +158,16 @@ export async function clear_transfers(): Promise<void> {
   return await invoke("clear_transfers");
 }
 
+/**
+ * Whether the ticket belongs to a passphrase-protected share. Always false for
+ * short codes, which only tell once they were exchanged.
+ */
+export async function ticket_requires_passphrase(
+  ticket: string,
+): Promise<boolean> {
+  return await invoke("ticket_requires_passphrase", { ticket });
+}
+
 /**
  * Get the local hostname
  */
@@ -78,6 +175,13 @@ export function get_hostname(): Promise<string> {
   return invoke("get_hostname");
 }
 
+/**
+ * Get the endpoint id this device sends with. It stays the same across runs.
+ */
+export function get_endpoint_id(): Promise<string> {
+  return invoke("get_endpoint_id");
+}
+
 /**
  * Get the device model (mobile-specific, returns hostname on desktop)
  */
@@ -121,6 +225,110 @@ export async function list_received_files(): Promise<string[]> {
   return await invoke("list_received_files");
 }
 
+export interface StartNearbyRequest {
+  /** Name other devices see. Defaults to the host name. */
+  name?: string;
+  /** Stay on the local network, without relays. */
+  lan_only?: boolean;
+}
+
+/**
+ * A device found on the local network
+ */
+export interface NearbyDevice {
+  id: string;
+  name: string;
+  model: string | null;
+}
+
+/**
+ * Payload of the `nearby-device` event
+ */
+export type NearbyDeviceEvent =
+  | { type: "appeared"; device: NearbyDevice }
+  | { type: "disappeared"; id: string };
+
+/**
+ * Payload of the `nearby-offer` event, answered with respond_to_offer
+ */
+export interface NearbyOffer {
+  offer_id: string;
+  sender: string;
+  from: { name: string; model: string | null };
+  /** Empty for passphrase protected shares. */
+  files: { name: string; hash: string; size: number }[];
+  total_size: number;
+  encrypted: boolean;
+  /** A note from the sender. */
+  message: string | null;
+}
+
+/**
+ * How a device answered an offer
+ */
+export type OfferAnswer = "Accept" | { AcceptSubset: string[] } | "Reject";
+
+/**
+ * An accepted offer, to pass to receive_file as `ticket` and `include`
+ */
+export interface AcceptedOffer {
+  ticket: string;
+  /** Names of the accepted files, or null if all were accepted. */
+  include: string[] | null;
+}
+
+/**
+ * Start announcing this device on the local network and return its endpoint id
+ *
+ * Devices are reported with `nearby-device` events, offers made to this device
+ * with `nearby-offer` events.
+ */
+export async function start_nearby(
+  request: StartNearbyRequest = {},
+): Promise<string> {
+  return await invoke("start_nearby", { request });
+}
+
+/**
+ * Stop announcing this device, declining offers that wait for an answer
+ */
+export async function stop_nearby(): Promise<void> {
+  return await invoke("stop_nearby");
+}
+
+/**
+ * List the devices currently seen on the local network
+ */
+export async function list_nearby_devices(): Promise<NearbyDevice[]> {
+  return await invoke("list_nearby_devices");
+}
+
+/**
+ * Offer the share of a running send to a nearby device, with an optional
+ * message
+ *
+ * Resolves once the device answered, to its answer.
+ */
+export async function send_to_device(
+  transferId: string,
+  deviceId: string,
+  message?: string,
+): Promise<OfferAnswer> {
+  return await invoke("send_to_device", { transferId, deviceId, message });
+}
+
+/**
+ * Answer an offer, accepting only the files in `names` if given. Resolves to
+ * what to pass to receive_file if accepted.
+ */
+export async function respond_to_offer(
+  offerId: string,
+  accept: boolean,
+  names?: string[],
+): Promise<AcceptedOffer | null> {
+  return await invoke("respond_to_offer", { offerId, accept, names });
+}
+
 /**
  * File information returned by the mobile file picker
  */
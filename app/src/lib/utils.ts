@@ -105,6 +105,17 @@ export function getTransferStatus(status: string): {
   };
 }
 
+/**
+ * Format the speed and time left of progress data, e.g. "1.5 MB/s, 20s left"
+ */
+export function formatRate(data: any): string {
+  const rate = data?.progress?.rate;
+  if (!rate) return "";
+  const speed = `${formatFileSize(rate.bytes_per_sec)}/s`;
+  if (rate.eta_ms == null) return speed;
+  return `${speed}, ${Math.ceil(rate.eta_ms / 1000)}s left`;
+}
+
 /**
  * Calculate progress percentage from progress data
  */
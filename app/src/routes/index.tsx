@@ -15,6 +15,15 @@ import {
   clear_transfers,
   open_received_file,
   pick_directory,
+  start_nearby,
+  list_nearby_devices,
+  send_to_device,
+  respond_to_offer,
+  ticket_requires_passphrase,
+  type ReceiveFileRequest,
+  type NearbyDevice,
+  type NearbyDeviceEvent,
+  type NearbyOffer,
 } from "~/bindings";
 import { listen } from "@tauri-apps/api/event";
 import { open } from "@tauri-apps/plugin-dialog";
@@ -50,6 +59,7 @@ import {
   Sparkles,
   Shield,
   Zap,
+  Radar,
 } from "lucide-solid";
 import { Toaster, toast } from "solid-sonner";
 import {
@@ -59,6 +69,7 @@ import {
   getFileIcon,
   getTransferStatus,
   getProgressValue,
+  formatRate,
 } from "~/lib/utils";
 
 // Types
@@ -82,6 +93,11 @@ interface ProgressUpdate {
 
 type Theme = "light" | "dark" | "system";
 
+type ReceiveRequest = Pick<
+  ReceiveFileRequest,
+  "ticket" | "include" | "passphrase"
+>;
+
 // Ticket types
 const ticketTypes = [
   {
@@ -114,6 +130,9 @@ export default function Home() {
   const [sendTicketQrCode, setSendTicketQrCode] = createSignal("");
   const [isSending, setIsSending] = createSignal(false);
   const [showTicketPopover, setShowTicketPopover] = createSignal(false);
+  const [sendTransferId, setSendTransferId] = createSignal<string | null>(
+    null,
+  );
 
   // Receive state
   const [receiveTicket, setReceiveTicket] = createSignal("");
@@ -122,6 +141,17 @@ export default function Home() {
   const [currentReceivingId, setCurrentReceivingId] = createSignal<
     string | null
   >(null);
+  // A protected share waiting for its passphrase
+  const [passphraseRequest, setPassphraseRequest] =
+    createSignal<ReceiveRequest | null>(null);
+  const [passphrase, setPassphrase] = createSignal("");
+
+  // Nearby state
+  const [nearbyDevices, setNearbyDevices] = createSignal<NearbyDevice[]>([]);
+  const [offeringTo, setOfferingTo] = createSignal<string | null>(null);
+  // Offers from other devices waiting for an answer, the first one is shown
+  const [offers, setOffers] = createSignal<NearbyOffer[]>([]);
+  const [isAnsweringOffer, setIsAnsweringOffer] = createSignal(false);
 
   // Progress state
   const [progressData, setProgressData] = createSignal<
@@ -265,6 +295,7 @@ export default function Home() {
     setIsSending(true);
     setSendTicket("");
     setSendTicketQrCode("");
+    setSendTransferId(null);
 
     try {
       const result = await send_file({
@@ -280,6 +311,12 @@ export default function Home() {
         }),
       );
       await loadTransfers();
+      // The newest serving send is the one just started
+      setSendTransferId(
+        transfers().find(
+          (t) => t.transfer_type === "send" && t.status === "serving",
+        )?.id ?? null,
+      );
     } catch (e) {
       console.error("Send failed:", e);
       toast.error(`Send failed: ${e}`);
@@ -293,16 +330,49 @@ export default function Home() {
       return;
     }
 
+    // Ask for the passphrase of a protected share before connecting
+    try {
+      if (await ticket_requires_passphrase(receiveTicket())) {
+        setPassphraseRequest({ ticket: receiveTicket() });
+        return;
+      }
+    } catch (e) {
+      toast.error(`Receive failed: ${e}`);
+      return;
+    }
+
+    await startReceive({ ticket: receiveTicket() });
+  }
+
+  async function handleSubmitPassphrase() {
+    const request = passphraseRequest();
+    if (!request || !passphrase()) {
+      return;
+    }
+
+    setPassphraseRequest(null);
+    await startReceive({ ...request, passphrase: passphrase() });
+    setPassphrase("");
+  }
+
+  function handleCancelPassphrase() {
+    setPassphraseRequest(null);
+    setPassphrase("");
+  }
+
+  async function startReceive(request: ReceiveRequest) {
     setIsReceiving(true);
     setCurrentReceivingId(null);
 
     try {
       await receive_file({
-        ticket: receiveTicket(),
+        ...request,
         output_dir: receiveOutputDir() || undefined,
       });
       await loadTransfers();
-      setReceiveTicket("");
+      if (request.ticket === receiveTicket()) {
+        setReceiveTicket("");
+      }
       toast.success("Receive operation started");
     } catch (e) {
       console.error("Receive failed:", e);
@@ -313,6 +383,74 @@ export default function Home() {
     }
   }
 
+  // Nearby handlers
+  function handleNearbyEvent(event: NearbyDeviceEvent) {
+    if (event.type === "appeared") {
+      setNearbyDevices((prev) => [
+        ...prev.filter((d) => d.id !== event.device.id),
+        event.device,
+      ]);
+    } else {
+      setNearbyDevices((prev) => prev.filter((d) => d.id !== event.id));
+    }
+  }
+
+  async function handleSendToDevice(device: NearbyDevice) {
+    if (!sendTransferId()) {
+      return;
+    }
+
+    setOfferingTo(device.id);
+
+    try {
+      const answer = await send_to_device(sendTransferId()!, device.id);
+      if (answer === "Accept") {
+        toast.success(`Accepted by ${device.name}`);
+      } else if (answer === "Reject") {
+        toast.info(`Declined by ${device.name}`);
+      } else {
+        toast.success(`Partly accepted by ${device.name}`, {
+          description: answer.AcceptSubset.join(", "),
+        });
+      }
+    } catch (e) {
+      console.error("Offer failed:", e);
+      toast.error(`Failed to offer to ${device.name}: ${e}`);
+    } finally {
+      setOfferingTo(null);
+    }
+  }
+
+  async function handleAnswerOffer(accept: boolean) {
+    const offer = offers()[0];
+    if (!offer) {
+      return;
+    }
+
+    setIsAnsweringOffer(true);
+
+    try {
+      const accepted = await respond_to_offer(offer.offer_id, accept);
+      if (accepted) {
+        const request = {
+          ticket: accepted.ticket,
+          include: accepted.include ?? undefined,
+        };
+        if (offer.encrypted) {
+          setPassphraseRequest(request);
+        } else {
+          await startReceive(request);
+        }
+      }
+    } catch (e) {
+      console.error("Answering the offer failed:", e);
+      toast.error(`Failed to answer ${offer.from.name}: ${e}`);
+    } finally {
+      setOffers((prev) => prev.filter((o) => o.offer_id !== offer.offer_id));
+      setIsAnsweringOffer(false);
+    }
+  }
+
   async function handleCancelReceive() {
     if (currentReceivingId()) {
       await handleCancel(currentReceivingId()!);
@@ -504,9 +642,27 @@ export default function Home() {
       }
     });
 
+    // Listen for nearby devices and the shares they offer
+    const unlistenDevices = await listen<NearbyDeviceEvent>(
+      "nearby-device",
+      (event) => handleNearbyEvent(event.payload),
+    );
+    const unlistenOffers = await listen<NearbyOffer>("nearby-offer", (event) =>
+      setOffers((prev) => [...prev, event.payload]),
+    );
+    try {
+      await start_nearby();
+      // Already running after a reload, so no events for known devices
+      setNearbyDevices(await list_nearby_devices());
+    } catch (e) {
+      console.error("Failed to start nearby discovery:", e);
+    }
+
     // Cleanup on unmount
     onCleanup(() => {
       unlisten();
+      unlistenDevices();
+      unlistenOffers();
       window.removeEventListener("mousemove", handleMouseMove);
     });
 
@@ -523,6 +679,114 @@ export default function Home() {
     <>
       <Toaster />
 
+      {/* Offer from a nearby device */}
+      <Show when={offers()[0]}>
+        {(offer) => (
+          <div class="fixed inset-0 z-50 flex items-center justify-center bg-black/60 px-4">
+            <div class="glass w-full max-w-md space-y-4 rounded-3xl border border-white/10 p-6 shadow-2xl">
+              <div>
+                <h3 class="text-lg font-semibold text-white">
+                  {offer().from.name} wants to send you{" "}
+                  {offer().encrypted
+                    ? "a protected share"
+                    : `${offer().files.length} files`}
+                </h3>
+                <p class="text-sm text-white/40">
+                  {formatFileSize(offer().total_size)}
+                </p>
+              </div>
+              <Show when={offer().message}>
+                <p class="rounded-xl bg-white/5 px-3 py-2 text-sm text-white/70 italic">
+                  {offer().message}
+                </p>
+              </Show>
+              <Show when={offer().files.length > 0}>
+                <div class="max-h-48 space-y-1 overflow-y-auto">
+                  <For each={offer().files}>
+                    {(file) => (
+                      <div class="flex justify-between gap-4 text-xs text-white/60">
+                        <span class="truncate">{file.name}</span>
+                        <span class="text-white/30">
+                          {formatFileSize(file.size)}
+                        </span>
+                      </div>
+                    )}
+                  </For>
+                </div>
+              </Show>
+              <div class="flex gap-3">
+                <button
+                  onClick={() => handleAnswerOffer(false)}
+                  disabled={isAnsweringOffer()}
+                  class="flex flex-1 items-center justify-center gap-2 rounded-xl border border-white/5 bg-white/5 py-3 text-sm font-semibold text-white/70 transition-all hover:bg-white/10 disabled:opacity-50"
+                >
+                  <X size={16} />
+                  Decline
+                </button>
+                <button
+                  onClick={() => handleAnswerOffer(true)}
+                  disabled={isAnsweringOffer()}
+                  class="flex flex-1 items-center justify-center gap-2 rounded-xl bg-linear-to-r from-indigo-600 to-purple-600 py-3 text-sm font-bold text-white transition-all disabled:opacity-50"
+                >
+                  {isAnsweringOffer() ? (
+                    <Loader2 class="animate-spin" size={16} />
+                  ) : (
+                    <Download size={16} />
+                  )}
+                  Receive
+                </button>
+              </div>
+            </div>
+          </div>
+        )}
+      </Show>
+
+      {/* Passphrase of a protected share */}
+      <Show when={passphraseRequest()}>
+        <div class="fixed inset-0 z-50 flex items-center justify-center bg-black/60 px-4">
+          <div class="glass w-full max-w-md space-y-4 rounded-3xl border border-white/10 p-6 shadow-2xl">
+            <div>
+              <h3 class="text-lg font-semibold text-white">
+                This share is protected
+              </h3>
+              <p class="text-sm text-white/40">
+                Enter the passphrase the sender chose to receive it
+              </p>
+            </div>
+            <input
+              type="password"
+              value={passphrase()}
+              onInput={(e) => setPassphrase(e.currentTarget.value)}
+              onKeyDown={(e) => {
+                if (e.key === "Enter") {
+                  handleSubmitPassphrase();
+                }
+              }}
+              placeholder="Passphrase"
+              autofocus
+              class="h-12 w-full rounded-xl border border-white/5 bg-white/5 px-4 text-sm text-white transition-all placeholder:text-white/20 focus:border-purple-500/50 focus:outline-none"
+            />
+            <div class="flex gap-3">
+              <button
+                onClick={handleCancelPassphrase}
+                class="flex flex-1 items-center justify-center gap-2 rounded-xl border border-white/5 bg-white/5 py-3 text-sm font-semibold text-white/70 transition-all hover:bg-white/10"
+              >
+                <X size={16} />
+                Cancel
+              </button>
+              <button
+                onClick={handleSubmitPassphrase}
+                disabled={!passphrase()}
+                class="flex flex-1 items-center justify-center gap-2 rounded-xl bg-linear-to-r from-indigo-600 to-purple-600 py-3 text-sm font-bold text-white transition-all disabled:opacity-50"
+              >
+                <Shield size={16} />
+                Unlock
+              </button>
+            </div>
+          </div>
+        </div>
+      </Show>
+
       {/* Dynamic Background */}
       <div class="pointer-events-none fixed inset-0 z-0 overflow-hidden">
         <Motion.div
@@ -808,6 +1072,66 @@ export default function Home() {
                                           Share with Friends
                                         </button>
                                       </Show>
+
+                                      <Show when={sendTransferId()}>
+                                        <div class="glass-inset flex flex-col gap-2 rounded-2xl p-4">
+                                          <label class="text-[10px] font-bold tracking-widest text-white/30 uppercase">
+                                            Nearby Devices
+                                          </label>
+                                          <Show
+                                            when={nearbyDevices().length > 0}
+                                            fallback={
+                                              <p class="flex items-center gap-2 py-2 text-sm text-white/30">
+                                                <Radar
+                                                  size={16}
+                                                  class="animate-pulse"
+                                                />
+                                                Looking for devices on this
+                                                network...
+                                              </p>
+                                            }
+                                          >
+                                            <For each={nearbyDevices()}>
+                                              {(device) => (
+                                                <div class="flex items-center gap-3">
+                                                  <div class="min-w-0 flex-1">
+                                                    <div class="truncate text-sm font-semibold text-white">
+                                                      {device.name}
+                                                    </div>
+                                                    <Show when={device.model}>
+                                                      <div class="truncate text-[11px] text-white/30">
+                                                        {device.model}
+                                                      </div>
+                                                    </Show>
+                                                  </div>
+                                                  <button
+                                                    onClick={() =>
+                                                      handleSendToDevice(device)
+                                                    }
+                                                    disabled={
+                                                      offeringTo() !== null
+                                                    }
+                                                    class="flex items-center gap-2 rounded-xl bg-white/5 px-3 py-2 text-xs font-semibold text-white/70 transition-all hover:bg-white/10 hover:text-white disabled:opacity-50"
+                                                  >
+                                                    {offeringTo() ===
+                                                    device.id ? (
+                                                      <Loader2
+                                                        class="animate-spin"
+                                                        size={14}
+                                                      />
+                                                    ) : (
+                                                      <Send size={14} />
+                                                    )}
+                                                    {offeringTo() === device.id
+                                                      ? "Waiting..."
+                                                      : "Send"}
+                                                  </button>
+                                                </div>
+                                              )}
+                                            </For>
+                                          </Show>
+                                        </div>
+                                      </Show>
                                     </div>
                                   </div>
                                 </Motion.div>
@@ -1104,6 +1428,7 @@ export default function Home() {
                                             {progress()?.name}
                                           </span>
                                           <span>
+                                            {formatRate(progress() || {})}{" "}
                                             {Math.round(
                                               getProgressValue(
                                                 progress() || {},
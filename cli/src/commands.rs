@@ -0,0 +1,1278 @@
+//! Non-interactive `send` and `receive` subcommands.
+//!
+//! These print a fixed set of header lines to stdout so that scripts can pick
+//! up the ticket, and draw progress bars to stderr when attached to a terminal.
+//! With `--json`, stdout instead carries one JSON object per line.
+
+use std::{
+    net::{SocketAddrV4, SocketAddrV6},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use console::style;
+use indicatif::{
+    HumanBytes, HumanDuration, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle,
+};
+use sendme_lib::{
+    nearby::{DeviceInfo, Nearby, NearbyEvent},
+    offer::{Accepted, Answer, Offer},
+    progress::*,
+    types::*,
+    CancellationToken, Collection, Endpoint, EndpointId, Hash, KeyStore, DEFAULT_IDENTITY,
+};
+use serde_json::json;
+use tokio::sync::mpsc;
+
+/// Send a file or directory between two machines, using blake3 verified streaming.
+///
+/// Run without a subcommand to open the interactive terminal UI.
+#[derive(Parser, Debug)]
+#[command(
+    name = "sendme",
+    version,
+    about,
+    args_conflicts_with_subcommands = true
+)]
+pub struct Args {
+    #[command(flatten)]
+    pub limits: ShareLimitArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Send a file or directory.
+    Send(SendCmd),
+
+    /// Receive a file or directory.
+    #[command(visible_alias = "recv")]
+    Receive(ReceiveCmd),
+
+    /// List the files behind a ticket without downloading them.
+    Inspect(InspectCmd),
+
+    /// Show the devices on the local network and receive what they send here.
+    Nearby(NearbyCmd),
+
+    /// Manage the stored identities that keep the endpoint id stable across runs.
+    #[command(subcommand)]
+    Identity(IdentityCmd),
+}
+
+/// Options shared by `send` and `receive`.
+#[derive(Parser, Debug)]
+pub struct CommonArgs {
+    /// The IPv4 address that magicsocket will listen on.
+    ///
+    /// If None, defaults to a random free port, but it can be useful to specify a fixed
+    /// port, e.g. to configure a firewall rule.
+    #[arg(long)]
+    pub magic_ipv4_addr: Option<SocketAddrV4>,
+
+    /// The IPv6 address that magicsocket will listen on.
+    ///
+    /// If None, defaults to a random free port, but it can be useful to specify a fixed
+    /// port, e.g. to configure a firewall rule.
+    #[arg(long)]
+    pub magic_ipv6_addr: Option<SocketAddrV6>,
+
+    /// Hash output format (hex or cid).
+    #[arg(long, default_value_t = Format::Hex)]
+    pub format: Format,
+
+    /// The relay to use: "disabled", "default", or a custom relay URL.
+    #[arg(long, default_value_t = RelayModeOption::Default)]
+    pub relay: RelayModeOption,
+
+    /// Stay on the local network: no relays, and endpoints are found with mDNS
+    /// only, so id-only tickets work without internet access.
+    #[arg(long)]
+    pub lan_only: bool,
+
+    /// Print the secret key of the endpoint.
+    #[arg(long)]
+    pub show_secret: bool,
+
+    /// Directory for temporary blob storage. Defaults to the current directory.
+    #[arg(long)]
+    pub temp_dir: Option<PathBuf>,
+
+    /// Name of the stored identity to use, created on first use.
+    ///
+    /// With an identity, recipients see the same endpoint id every time.
+    /// Without one, a fresh random key is used.
+    #[arg(long, value_name = "NAME")]
+    pub identity: Option<String>,
+
+    /// Directory of the identity key store. Defaults to the config directory.
+    #[arg(long)]
+    pub key_dir: Option<PathBuf>,
+
+    /// Suppress progress bars.
+    #[arg(long)]
+    pub no_progress: bool,
+
+    /// Print transfer statistics when done.
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Write progress events and the final result to stdout as JSON lines
+    /// instead of human readable text. Implies --no-progress.
+    #[arg(long)]
+    pub json: bool,
+}
+
+impl From<&CommonArgs> for CommonConfig {
+    fn from(args: &CommonArgs) -> Self {
+        Self {
+            magic_ipv4_addr: args.magic_ipv4_addr,
+            magic_ipv6_addr: args.magic_ipv6_addr,
+            format: args.format,
+            relay: args.relay.clone(),
+            network: if args.lan_only {
+                NetworkMode::LanOnly
+            } else {
+                NetworkMode::Internet
+            },
+            show_secret: args.show_secret,
+            temp_dir: args.temp_dir.clone(),
+            identity: args.identity.clone(),
+            key_dir: args.key_dir.clone(),
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct SendCmd {
+    /// Path to the file or directory to send, or - to send standard input.
+    pub path: PathBuf,
+
+    /// Name of the file when sending standard input.
+    #[arg(long, default_value = "stdin")]
+    pub name: String,
+
+    /// What to include in the ticket: Id, Relay, Addresses or RelayAndAddresses.
+    #[arg(long, default_value_t = AddrInfoOptions::RelayAndAddresses)]
+    pub ticket_type: AddrInfoOptions,
+
+    /// Only let this endpoint id fetch the data. Can be repeated.
+    ///
+    /// Receivers can print their id with `sendme identity show` and receive with
+    /// `--identity default`.
+    #[arg(long, value_name = "ENDPOINT_ID")]
+    pub allow: Vec<EndpointId>,
+
+    /// Encrypt the files and their names with a passphrase.
+    ///
+    /// Prompts for the passphrase, or reads it from SENDME_PASSPHRASE. The ticket
+    /// tells receivers to ask for it.
+    #[arg(long)]
+    pub passphrase: bool,
+
+    /// Also print a short code like 482913-canyon-violin to receive with
+    /// instead of the ticket.
+    ///
+    /// The code works once. The share is served from a fresh endpoint id, not
+    /// from --identity.
+    #[arg(long)]
+    pub code: bool,
+
+    /// What to do with symlinks: Skip, Follow or Preserve.
+    ///
+    /// Follow sends what the links point to. Preserve sends the links themselves,
+    /// unless they point outside of the shared directory's parent.
+    #[arg(long, default_value_t = SymlinkPolicy::Skip)]
+    pub symlinks: SymlinkPolicy,
+
+    /// Leave out what .gitignore, .ignore and .sendmeignore files exclude, and
+    /// .git directories.
+    #[arg(long)]
+    pub ignore_files: bool,
+
+    /// Only send files matching this glob. Can be repeated.
+    ///
+    /// Globs use the .gitignore syntax, relative to the sent directory, and win
+    /// over ignore files.
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Don't send files or directories matching this glob. Can be repeated.
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Compress files that are not compressed already, such as text and logs,
+    /// with zstd while sending them. Receivers decompress them again.
+    #[arg(long)]
+    pub compress: bool,
+
+    /// Offer the share to a device on the local network, by name or endpoint
+    /// id, instead of just printing the ticket.
+    ///
+    /// The device has to run `sendme nearby`. The share is closed again if the
+    /// device declines.
+    #[arg(long, value_name = "DEVICE")]
+    pub to: Option<String>,
+
+    /// A note shown to the device along with the offer.
+    #[arg(long, requires = "to")]
+    pub message: Option<String>,
+
+    #[command(flatten)]
+    pub limits: ShareLimitArgs,
+
+    #[command(flatten)]
+    pub common: CommonArgs,
+}
+
+/// Limits on how long a share stays open.
+///
+/// Also accepted without a subcommand, where they apply to every share started
+/// from the terminal UI.
+#[derive(Parser, Debug, Clone, Copy, Default)]
+pub struct ShareLimitArgs {
+    /// Stop sharing after this many downloads, complete or not.
+    #[arg(long, value_name = "N")]
+    pub max_downloads: Option<u32>,
+
+    /// Stop sharing after this much time, e.g. 90s, 30m, 12h or 7d.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub expires_after: Option<Duration>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ReceiveCmd {
+    /// The ticket or short code to use to connect to the sender.
+    pub ticket: TicketOrCode,
+
+    /// Directory to write the received files to. Defaults to the current directory.
+    #[arg(long)]
+    pub export_dir: Option<PathBuf>,
+
+    /// Only receive files matching this glob pattern. Can be repeated.
+    #[arg(long, value_name = "GLOB", conflicts_with = "file")]
+    pub include: Vec<String>,
+
+    /// Only receive this file, or everything below this directory. Can be repeated.
+    #[arg(long, value_name = "NAME")]
+    pub file: Vec<String>,
+
+    /// What to do with files that already exist: Overwrite, Skip, Rename, Fail or
+    /// SkipIfIdentical.
+    #[arg(long, default_value_t = ExportConflictPolicy::Overwrite)]
+    pub on_conflict: ExportConflictPolicy,
+
+    /// Write all files to a staging directory first and move them into place
+    /// only once the whole collection has been written.
+    #[arg(long)]
+    pub atomic: bool,
+
+    /// Save the received files in a single archive: Tar, TarZst or Zip.
+    ///
+    /// The archive is named after the shared file or directory and saved in the
+    /// export directory, or written to stdout with --stdout.
+    #[arg(long, value_name = "FORMAT", conflicts_with = "atomic")]
+    pub archive: Option<ArchiveFormat>,
+
+    /// Write the received file to stdout instead of exporting it.
+    ///
+    /// Only works for a single file, which --include or --file can select, or
+    /// with --archive.
+    #[arg(long, conflicts_with_all = ["export_dir", "atomic", "on_conflict", "json"])]
+    pub stdout: bool,
+
+    #[command(flatten)]
+    pub common: CommonArgs,
+}
+
+#[derive(Parser, Debug)]
+pub struct InspectCmd {
+    /// The ticket to inspect.
+    pub ticket: ShareTicket,
+
+    #[command(flatten)]
+    pub common: CommonArgs,
+}
+
+#[derive(Parser, Debug)]
+pub struct NearbyCmd {
+    /// Name other devices see this device as. Defaults to the host name.
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Directory to write received files to. Defaults to the current directory.
+    #[arg(long)]
+    pub export_dir: Option<PathBuf>,
+
+    /// What to do with files that already exist: Overwrite, Skip, Rename, Fail or
+    /// SkipIfIdentical.
+    #[arg(long, default_value_t = ExportConflictPolicy::Overwrite)]
+    pub on_conflict: ExportConflictPolicy,
+
+    #[command(flatten)]
+    pub common: CommonArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IdentityCmd {
+    /// List the stored identities and their endpoint ids.
+    List(IdentityArgs),
+
+    /// Print the endpoint id of an identity, creating it if needed.
+    Show {
+        /// Name of the identity.
+        #[arg(default_value = DEFAULT_IDENTITY)]
+        name: String,
+
+        #[command(flatten)]
+        args: IdentityArgs,
+    },
+
+    /// Delete a stored identity.
+    Remove {
+        /// Name of the identity.
+        name: String,
+
+        #[command(flatten)]
+        args: IdentityArgs,
+    },
+}
+
+#[derive(Parser, Debug)]
+pub struct IdentityArgs {
+    /// Directory of the identity key store. Defaults to the config directory.
+    #[arg(long)]
+    pub key_dir: Option<PathBuf>,
+}
+
+impl IdentityArgs {
+    fn key_store(&self) -> Result<KeyStore> {
+        match &self.key_dir {
+            Some(dir) => Ok(KeyStore::new(dir)),
+            None => KeyStore::open_default(),
+        }
+    }
+}
+
+/// Run the `send` subcommand.
+///
+/// Prints three header lines to stdout, the last one ending with the ticket,
+/// then serves the data until interrupted with ctrl-c. With `--code`, a fourth
+/// line ends with the short code.
+///
+/// With `-` as the path, standard input is read to the end and sent as a single
+/// file named by `--name`.
+pub async fn send(cmd: SendCmd) -> Result<()> {
+    let stdin = (cmd.path.as_os_str() == "-").then(|| cmd.name.clone());
+    let (path, source) = match &stdin {
+        Some(name) => (cmd.path.clone(), format!("stdin as {name}")),
+        None => {
+            let path = cmd.path.canonicalize()?;
+            let what = if path.is_dir() { "directory" } else { "file" };
+            (path, format!("{what} {}", cmd.path.display()))
+        }
+    };
+    let common = CommonConfig::from(&cmd.common);
+    let passphrase = if cmd.passphrase {
+        Some(read_passphrase(true)?)
+    } else {
+        None
+    };
+    let args = SendArgs {
+        path,
+        ticket_type: cmd.ticket_type,
+        common,
+        allowlist: (!cmd.allow.is_empty()).then_some(cmd.allow),
+        max_downloads: cmd.limits.max_downloads,
+        expires_after: cmd.limits.expires_after,
+        passphrase,
+        short_code: cmd.code,
+        symlinks: cmd.symlinks,
+        ignore_files: cmd.ignore_files,
+        include: cmd.include,
+        exclude: cmd.exclude,
+        stdin,
+        compress: cmd.compress,
+    };
+
+    let json = cmd.common.json.then(JsonOutput::new);
+    let mp = multi_progress(cmd.common.no_progress || json.is_some());
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let progress = match &json {
+        Some(json) => tokio::spawn(json.clone().forward_progress(progress_rx)),
+        None => tokio::spawn(show_send_progress(mp.clone(), progress_rx)),
+    };
+
+    let send = sendme_lib::send_with_progress(args, progress_tx);
+    let res = tokio::select! {
+        res = send => res,
+        _ = tokio::signal::ctrl_c() => {
+            mp.clear().ok();
+            std::process::exit(130);
+        }
+    };
+    let (result, handle) = match res {
+        Ok(x) => x,
+        Err(e) => {
+            if let Some(json) = &json {
+                json.error(&e);
+            }
+            return Err(e);
+        }
+    };
+
+    if let Some(json) = &json {
+        json.result(json!({
+            "ticket": result.share_ticket().to_string(),
+            "code": result.code.as_ref().map(|code| code.to_string()),
+            "hash": print_hash(&result.hash, cmd.common.format),
+            "total_size": result.total_size,
+            "wire_size": result.wire_size,
+            "import_duration": result.import_duration,
+            "files": files_json(&result.collection, cmd.common.format),
+        }));
+    } else {
+        let size = if cmd.compress {
+            format!(
+                "{} ({} compressed)",
+                HumanBytes(result.total_size),
+                HumanBytes(result.wire_size)
+            )
+        } else {
+            HumanBytes(result.total_size).to_string()
+        };
+        println!(
+            "imported {}, {}, hash {}",
+            source,
+            size,
+            print_hash(&result.hash, cmd.common.format)
+        );
+        if cmd.common.verbose {
+            eprintln!(
+                "{} files imported in {}",
+                result.collection.len(),
+                HumanDuration(result.import_duration)
+            );
+        }
+        println!("to get this data, use");
+        println!("sendme receive {}", result.share_ticket());
+        if let Some(code) = &result.code {
+            println!("or, once, sendme receive {code}");
+        }
+    }
+
+    if let Some(to) = &cmd.to {
+        let common = CommonConfig {
+            relay: cmd.common.relay.clone(),
+            network: CommonConfig::from(&cmd.common).network,
+            ..Default::default()
+        };
+        let offer = offer_to_device(to, &result, handle.endpoint(), cmd.message.clone(), &common);
+        let accepted = tokio::select! {
+            accepted = offer => accepted,
+            _ = tokio::signal::ctrl_c() => Ok(false),
+        };
+        if !matches!(accepted, Ok(true)) {
+            mp.clear().ok();
+            handle.shutdown().await?;
+            progress.abort();
+            return accepted.map(|_| ());
+        }
+    }
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = handle.closed() => {}
+    }
+    mp.clear().ok();
+    handle.shutdown().await?;
+    progress.abort();
+    Ok(())
+}
+
+/// Run the `receive` subcommand.
+///
+/// With `--stdout`, the single received file is written to stdout and nothing
+/// else is.
+pub async fn receive(cmd: ReceiveCmd) -> Result<()> {
+    let common = CommonConfig::from(&cmd.common);
+    let ticket = cmd.ticket.resolve(&common).await?;
+    let hash = ticket.ticket.hash();
+    let passphrase = share_passphrase(&ticket)?;
+    let cancel = CancellationToken::new();
+    // Where the files end up without --export-dir, like in the library
+    let export_dir = cmd
+        .export_dir
+        .as_deref()
+        .or(cmd.common.temp_dir.as_deref())
+        .unwrap_or(Path::new("."));
+    let export_dir = std::path::absolute(export_dir)?;
+    let args = ReceiveArgs {
+        ticket: ticket.ticket,
+        common,
+        export_dir: cmd.export_dir,
+        include: if !cmd.include.is_empty() {
+            IncludeFilter::Globs(cmd.include)
+        } else if !cmd.file.is_empty() {
+            IncludeFilter::Names(cmd.file)
+        } else {
+            IncludeFilter::All
+        },
+        conflict_policy: cmd.on_conflict,
+        atomic_export: cmd.atomic,
+        cancel: cancel.clone(),
+        passphrase,
+        archive: cmd.archive,
+    };
+
+    let json = cmd.common.json.then(JsonOutput::new);
+    let mp = multi_progress(cmd.common.no_progress || json.is_some());
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let progress = match &json {
+        Some(json) => tokio::spawn(json.clone().forward_progress(progress_rx)),
+        None => tokio::spawn(show_receive_progress(mp.clone(), progress_rx)),
+    };
+
+    let cancel_on_ctrl_c = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            cancel_on_ctrl_c.cancel();
+        }
+    });
+
+    let result = if cmd.stdout {
+        let mut stdout = tokio::io::stdout();
+        sendme_lib::receive_to_writer(args, &mut stdout, Some(progress_tx)).await
+    } else {
+        sendme_lib::receive_with_progress(args, progress_tx).await
+    };
+    progress.await.ok();
+    mp.clear().ok();
+
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            if let Some(json) = &json {
+                json.error(&e);
+            }
+            if cancel.is_cancelled() {
+                eprintln!("{}", style("receive cancelled").yellow());
+                std::process::exit(130);
+            }
+            return Err(e);
+        }
+    };
+
+    if let Some(json) = &json {
+        json.result(json!({
+            "hash": print_hash(&hash, cmd.common.format),
+            "total_files": result.total_files,
+            "payload_size": result.payload_size,
+            "stats": result.stats,
+            "summary": result.summary,
+            "files": files_json(&result.collection, cmd.common.format),
+            "archive": result.archive,
+        }));
+        return Ok(());
+    }
+
+    if cmd.stdout {
+        // stdout only carries the data
+    } else if cmd.archive.is_some() {
+        if let Some(path) = &result.archive {
+            println!("exported to {}", path.display());
+        }
+    } else if let Some(path) = result.exported.first() {
+        // The top level file or directory, as it was written after conflicts
+        let top = path
+            .strip_prefix(&export_dir)
+            .ok()
+            .and_then(|path| path.components().next());
+        if let Some(top) = top {
+            println!("exported to {}", top.as_os_str().to_string_lossy());
+        }
+    }
+    if cmd.common.verbose {
+        let summary = &result.summary;
+        eprintln!(
+            "received {} files, {} in {} ({}/s, peak {}/s)",
+            result.total_files,
+            HumanBytes(result.payload_size),
+            HumanDuration(summary.elapsed),
+            HumanBytes(summary.average_bytes_per_sec),
+            HumanBytes(summary.peak_bytes_per_sec)
+        );
+    }
+    Ok(())
+}
+
+/// Run the `inspect` subcommand.
+///
+/// Prints the sender and a summary line, followed by one line per file with
+/// its size, hash and name.
+pub async fn inspect(cmd: InspectCmd) -> Result<()> {
+    let json = cmd.common.json.then(JsonOutput::new);
+    let common = CommonConfig::from(&cmd.common);
+    let result = match share_passphrase(&cmd.ticket)? {
+        Some(passphrase) => {
+            sendme_lib::inspect_with_passphrase(cmd.ticket.ticket, common, &passphrase).await
+        }
+        None => sendme_lib::inspect(cmd.ticket.ticket, common).await,
+    };
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            if let Some(json) = &json {
+                json.error(&e);
+            }
+            return Err(e);
+        }
+    };
+
+    if let Some(json) = &json {
+        json.result(json!({
+            "hash": print_hash(&result.hash, cmd.common.format),
+            "endpoint_id": result.endpoint_id.to_string(),
+            "connection_type": result.connection_type.to_string(),
+            "encrypted": result.encrypted,
+            "total_size": result.total_size,
+            "files": result
+                .files
+                .iter()
+                .map(|file| json!({
+                    "name": file.name,
+                    "hash": print_hash(&file.hash, cmd.common.format),
+                    "size": file.size,
+                }))
+                .collect::<Vec<_>>(),
+        }));
+        return Ok(());
+    }
+
+    println!(
+        "sender {} via {}",
+        result.endpoint_id, result.connection_type
+    );
+    if result.encrypted && result.files.is_empty() {
+        println!(
+            "encrypted share, {}, hash {}",
+            HumanBytes(result.total_size),
+            print_hash(&result.hash, cmd.common.format)
+        );
+        println!("set {PASSPHRASE_ENV} to list the files");
+        return Ok(());
+    }
+    println!(
+        "{} files, {}, hash {}",
+        result.files.len(),
+        HumanBytes(result.total_size),
+        print_hash(&result.hash, cmd.common.format)
+    );
+    for file in &result.files {
+        println!(
+            "{:>10}  {}  {}",
+            HumanBytes(file.size).to_string(),
+            print_hash(&file.hash, cmd.common.format),
+            file.name
+        );
+    }
+    Ok(())
+}
+
+/// How long `send --to` looks for the device on the local network.
+const NEARBY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Offer a share, served by `endpoint`, to the nearby device named or
+/// identified by `to`, and report whether any of it was accepted.
+async fn offer_to_device(
+    to: &str,
+    share: &SendResult,
+    endpoint: &Endpoint,
+    message: Option<String>,
+    common: &CommonConfig,
+) -> Result<bool> {
+    // The share is served with the identity of `common`, so the nearby node
+    // needs a key of its own: two endpoints with the same id break discovery
+    let common = CommonConfig {
+        identity: None,
+        ..common.clone()
+    };
+    let (nearby, _) = Nearby::spawn(this_device(None), &common).await?;
+    let mut offer = Offer::new(nearby.info().clone(), share);
+    offer.message = message;
+    let mut events = nearby.events();
+    let find = async {
+        while let Some(event) = events.recv().await {
+            if let NearbyEvent::Appeared(device) = event {
+                let matches = match to.parse::<EndpointId>() {
+                    Ok(id) => device.id == id,
+                    Err(_) => device.info.name.eq_ignore_ascii_case(to),
+                };
+                if matches {
+                    return Some(device);
+                }
+            }
+        }
+        None
+    };
+    eprintln!("looking for {to} on the local network...");
+    let res = match tokio::time::timeout(NEARBY_TIMEOUT, find)
+        .await
+        .ok()
+        .flatten()
+    {
+        Some(device) => {
+            eprintln!("offering to {}, waiting for an answer...", device.info.name);
+            let answer = nearby
+                .offer(endpoint, device.id, &offer, &share.share_ticket(), None)
+                .await;
+            let name = &device.info.name;
+            match &answer {
+                Ok(Answer::Accept) => eprintln!("{} {name}", style("accepted by").green()),
+                Ok(Answer::AcceptSubset(names)) => eprintln!(
+                    "{} {name}: {}",
+                    style("partly accepted by").green(),
+                    names.join(", ")
+                ),
+                Ok(Answer::Reject) => eprintln!("{} {name}", style("declined by").red()),
+                Err(_) => {}
+            }
+            answer.map(|answer| answer.is_accepted())
+        }
+        None => Err(anyhow::anyhow!(
+            "no device {to:?} found on the local network"
+        )),
+    };
+    nearby.shutdown().await?;
+    res
+}
+
+/// Run the `nearby` subcommand.
+///
+/// Lists devices as they come and go, and asks whether to receive what they
+/// offer. Offers are declined when not attached to a terminal.
+pub async fn nearby(cmd: NearbyCmd) -> Result<()> {
+    let common = CommonConfig::from(&cmd.common);
+    let (nearby, mut offers) = Nearby::spawn(this_device(cmd.name.clone()), &common).await?;
+    println!(
+        "visible as {} ({})",
+        nearby.info().name,
+        nearby.id().fmt_short()
+    );
+    let mut events = nearby.events();
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            Some(event) = events.recv() => match event {
+                NearbyEvent::Appeared(device) => {
+                    let model = device.info.model.as_deref().unwrap_or("unknown");
+                    println!(
+                        "{} {} ({model}) {}",
+                        style("found").green(),
+                        device.info.name,
+                        device.id
+                    );
+                }
+                NearbyEvent::Disappeared { id } => {
+                    println!("{} {}", style("gone").dim(), id.fmt_short());
+                }
+            },
+            Some(incoming) = offers.recv() => {
+                let offer = incoming.offer.clone();
+                let what = match offer.encrypted {
+                    true => "a passphrase protected share".to_string(),
+                    false => format!("{} files", offer.files.len()),
+                };
+                println!(
+                    "{} offers {what}, {}",
+                    offer.from.name,
+                    HumanBytes(offer.total_size)
+                );
+                if let Some(message) = &offer.message {
+                    println!("  \"{message}\"");
+                }
+                for (i, file) in offer.files.iter().enumerate().take(OFFER_LIST_LEN) {
+                    println!(
+                        "  {:>3}. {:>10}  {}",
+                        i + 1,
+                        HumanBytes(file.size).to_string(),
+                        file.name
+                    );
+                }
+                if offer.files.len() > OFFER_LIST_LEN {
+                    println!("  and {} more", offer.files.len() - OFFER_LIST_LEN);
+                }
+                let answer = tokio::task::spawn_blocking(move || ask_answer(&offer)).await??;
+                let accepted = match incoming.answer(answer).await {
+                    Ok(Some(accepted)) => accepted,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        eprintln!("{} {e:#}", style("error").red());
+                        continue;
+                    }
+                };
+                if let Err(e) = receive_offered(&cmd, accepted).await {
+                    eprintln!("{} {e:#}", style("error").red());
+                }
+            }
+        }
+    }
+    nearby.shutdown().await
+}
+
+/// How many files of an offer are listed.
+const OFFER_LIST_LEN: usize = 20;
+
+/// Ask on the terminal whether to accept an offer, or which of its files.
+///
+/// Files are picked by their numbers in the listing, e.g. `1 3`.
+fn ask_answer(offer: &Offer) -> Result<Answer> {
+    let term = console::Term::stderr();
+    if !term.is_term() {
+        eprintln!("declined, not running in a terminal");
+        return Ok(Answer::Reject);
+    }
+    loop {
+        match offer.files.is_empty() {
+            true => term.write_str("receive? [y/N] ")?,
+            false => term.write_str("receive? [y/N, or the numbers of the files] ")?,
+        }
+        let line = term.read_line()?;
+        let line = line.trim();
+        if matches!(line, "y" | "Y" | "yes") {
+            return Ok(Answer::Accept);
+        }
+        if offer.files.is_empty() || !line.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok(Answer::Reject);
+        }
+        let picked = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|n| !n.is_empty())
+            .map(|n| {
+                n.parse::<usize>()
+                    .ok()
+                    .and_then(|n| offer.files.get(n.checked_sub(1)?))
+                    .map(|file| file.name.clone())
+            })
+            .collect::<Option<Vec<_>>>();
+        match picked {
+            Some(names) => return Ok(Answer::AcceptSubset(names)),
+            None => eprintln!("no such file, pick numbers from 1 to {}", offer.files.len()),
+        }
+    }
+}
+
+/// Receive a share accepted in the `nearby` subcommand.
+async fn receive_offered(cmd: &NearbyCmd, accepted: Accepted) -> Result<()> {
+    let ticket = accepted.ticket;
+    let args = ReceiveArgs {
+        passphrase: share_passphrase(&ticket)?,
+        ticket: ticket.ticket,
+        common: CommonConfig::from(&cmd.common),
+        export_dir: cmd.export_dir.clone(),
+        include: accepted.include,
+        conflict_policy: cmd.on_conflict,
+        atomic_export: false,
+        cancel: CancellationToken::new(),
+        archive: None,
+    };
+    let mp = multi_progress(cmd.common.no_progress);
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let progress = tokio::spawn(show_receive_progress(mp.clone(), progress_rx));
+    let result = sendme_lib::receive_with_progress(args, progress_tx).await;
+    progress.await.ok();
+    mp.clear().ok();
+    let result = result?;
+    println!(
+        "received {} files, {}",
+        result.total_files,
+        HumanBytes(result.payload_size)
+    );
+    Ok(())
+}
+
+/// How this device presents itself to nearby ones.
+pub(crate) fn this_device(name: Option<String>) -> DeviceInfo {
+    let name = name
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| {
+            std::fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|name| name.trim().to_string())
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "sendme".to_string());
+    DeviceInfo {
+        name,
+        model: Some(format!("sendme on {}", std::env::consts::OS)),
+    }
+}
+
+/// Run the `identity` subcommand.
+pub fn identity(cmd: IdentityCmd) -> Result<()> {
+    match cmd {
+        IdentityCmd::List(args) => {
+            let store = args.key_store()?;
+            for (name, id) in store.list()? {
+                println!("{name}\t{id}");
+            }
+        }
+        IdentityCmd::Show { name, args } => {
+            let key = args.key_store()?.load_or_create(&name)?;
+            println!("{}", key.public());
+        }
+        IdentityCmd::Remove { name, args } => {
+            if !args.key_store()?.remove(&name)? {
+                anyhow::bail!("identity {name:?} does not exist");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Environment variable that holds the passphrase of a share, to avoid the prompt.
+const PASSPHRASE_ENV: &str = "SENDME_PASSPHRASE";
+
+/// Read a passphrase from [`PASSPHRASE_ENV`], or prompt for it on the terminal.
+///
+/// With `confirm`, a prompted passphrase has to be entered twice.
+fn read_passphrase(confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        anyhow::ensure!(!passphrase.is_empty(), "{PASSPHRASE_ENV} is empty");
+        return Ok(passphrase);
+    }
+    let term = console::Term::stderr();
+    anyhow::ensure!(
+        term.is_term(),
+        "a passphrase is needed, set {PASSPHRASE_ENV} when not running in a terminal"
+    );
+    term.write_str("passphrase: ")?;
+    let passphrase = term.read_secure_line()?;
+    anyhow::ensure!(!passphrase.is_empty(), "passphrase must not be empty");
+    if confirm {
+        term.write_str("repeat passphrase: ")?;
+        anyhow::ensure!(
+            term.read_secure_line()? == passphrase,
+            "passphrases do not match"
+        );
+    }
+    Ok(passphrase)
+}
+
+/// The passphrase to open a share with.
+///
+/// Prompts if the ticket is flagged as passphrase protected, otherwise only
+/// uses [`PASSPHRASE_ENV`] if it is set.
+fn share_passphrase(ticket: &ShareTicket) -> Result<Option<String>> {
+    if ticket.encrypted {
+        return read_passphrase(false).map(Some);
+    }
+    Ok(std::env::var(PASSPHRASE_ENV)
+        .ok()
+        .filter(|passphrase| !passphrase.is_empty()))
+}
+
+/// Parse a duration like `90s`, `30m`, `12h` or `7d`. A plain number is seconds.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (value, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration {s:?}"))?;
+    let secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("invalid unit in {s:?}, use s, m, h or d")),
+    };
+    value
+        .checked_mul(secs)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration {s:?} is too long"))
+}
+
+/// JSON lines writer for `--json` mode.
+///
+/// Every line is an object with a `transfer_id`, a `timestamp` in milliseconds
+/// since the unix epoch, and exactly one of `event`, `result` or `error`.
+#[derive(Debug, Clone)]
+struct JsonOutput {
+    transfer_id: String,
+}
+
+impl JsonOutput {
+    fn new() -> Self {
+        Self {
+            transfer_id: uuid::Uuid::new_v4().to_string(),
+        }
+    }
+
+    fn emit(&self, key: &str, value: serde_json::Value) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut line = json!({
+            "transfer_id": self.transfer_id,
+            "timestamp": timestamp,
+        });
+        line[key] = value;
+        println!("{line}");
+    }
+
+    fn result(&self, value: serde_json::Value) {
+        self.emit("result", value);
+    }
+
+    fn error(&self, error: &anyhow::Error) {
+        self.emit("error", json!(format!("{error:#}")));
+    }
+
+    /// Write every progress event as an `event` line.
+    async fn forward_progress(self, mut rx: mpsc::Receiver<ProgressEvent>) {
+        while let Some(event) = rx.recv().await {
+            match serde_json::to_value(&event) {
+                Ok(value) => self.emit("event", value),
+                Err(e) => tracing::warn!("failed to serialize progress event: {}", e),
+            }
+        }
+    }
+}
+
+fn files_json(collection: &Collection, format: Format) -> serde_json::Value {
+    collection
+        .iter()
+        .map(|(name, hash)| json!({ "name": name, "hash": print_hash(hash, format) }))
+        .collect()
+}
+
+fn print_hash(hash: &Hash, format: Format) -> String {
+    match format {
+        Format::Hex => hash.to_hex().to_string(),
+        Format::Cid => hash.to_string(),
+    }
+}
+
+fn multi_progress(no_progress: bool) -> MultiProgress {
+    let draw_target = if no_progress {
+        ProgressDrawTarget::hidden()
+    } else {
+        ProgressDrawTarget::stderr()
+    };
+    MultiProgress::with_draw_target(draw_target)
+}
+
+fn files_bar(mp: &MultiProgress, total: usize, msg: &'static str) -> ProgressBar {
+    let pb = mp.add(ProgressBar::new(total as u64));
+    pb.set_style(
+        ProgressStyle::with_template("{msg:>10} [{bar:40.cyan/blue}] {pos}/{len} files")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    pb.set_message(msg);
+    pb
+}
+
+/// The speed and time left of a transfer, as shown next to its progress bar.
+fn rate_label(rate: &TransferRate) -> String {
+    let mut label = format!("{}/s", HumanBytes(rate.bytes_per_sec));
+    if let Some(eta) = rate.eta {
+        label.push_str(&format!(", {} left", HumanDuration(eta)));
+    }
+    label
+}
+
+/// Draw import progress and connection events of a send session.
+async fn show_send_progress(mp: MultiProgress, mut rx: mpsc::Receiver<ProgressEvent>) {
+    let mut import_bar: Option<ProgressBar> = None;
+    while let Some(event) = rx.recv().await {
+        match event {
+            ProgressEvent::Import(
+                _,
+                ImportProgress::Started {
+                    total_files,
+                    skipped,
+                },
+            ) => {
+                if skipped > 0 {
+                    mp.println(format!(
+                        "{} {skipped} ignored files",
+                        style("skipping").dim()
+                    ))
+                    .ok();
+                }
+                import_bar = Some(files_bar(&mp, total_files, "importing"));
+            }
+            ProgressEvent::Import(_, ImportProgress::FileCompleted { .. }) => {
+                if let Some(pb) = &import_bar {
+                    pb.inc(1);
+                }
+            }
+            ProgressEvent::Import(_, ImportProgress::Completed { .. }) => {
+                if let Some(pb) = import_bar.take() {
+                    pb.finish_and_clear();
+                }
+            }
+            ProgressEvent::Connection(ConnectionStatus::ClientConnected {
+                endpoint_id,
+                connection_id,
+            }) => {
+                mp.println(format!(
+                    "{} [{connection_id}] {endpoint_id}",
+                    style("connected").green()
+                ))
+                .ok();
+            }
+            ProgressEvent::Connection(ConnectionStatus::ClientRejected {
+                endpoint_id,
+                connection_id,
+            }) => {
+                mp.println(format!(
+                    "{} [{connection_id}] {endpoint_id}, not on the allowlist",
+                    style("rejected").red()
+                ))
+                .ok();
+            }
+            ProgressEvent::Connection(ConnectionStatus::QuotaUpdated {
+                downloads,
+                remaining_downloads,
+                remaining_time,
+            }) => {
+                let mut msg = format!("{} {downloads}", style("downloads").cyan());
+                if let Some(remaining) = remaining_downloads {
+                    msg.push_str(&format!(", {remaining} left"));
+                }
+                if let Some(remaining) = remaining_time {
+                    msg.push_str(&format!(", expires in {}", HumanDuration(remaining)));
+                }
+                mp.println(msg).ok();
+            }
+            ProgressEvent::Connection(ConnectionStatus::ShareClosed { reason }) => {
+                let reason = match reason {
+                    ShareLimit::MaxDownloads => "download limit reached",
+                    ShareLimit::Expired => "share expired",
+                };
+                mp.println(format!("{} {reason}", style("closing").yellow()))
+                    .ok();
+            }
+            ProgressEvent::Connection(ConnectionStatus::CodeUsed {
+                endpoint_id,
+                paired,
+            }) => {
+                let msg = if paired {
+                    format!("{} {endpoint_id}", style("paired").green())
+                } else {
+                    format!("{} {endpoint_id}, wrong code", style("rejected").red())
+                };
+                mp.println(format!("{msg}, the code can't be used again"))
+                    .ok();
+            }
+            ProgressEvent::Connection(ConnectionStatus::ConnectionClosed { connection_id }) => {
+                mp.println(format!("{} [{connection_id}]", style("closed").dim()))
+                    .ok();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Draw download and export progress of a receive.
+async fn show_receive_progress(mp: MultiProgress, mut rx: mpsc::Receiver<ProgressEvent>) {
+    let spinner = mp.add(ProgressBar::new_spinner());
+    spinner.enable_steady_tick(std::time::Duration::from_millis(100));
+    let mut download_bar: Option<ProgressBar> = None;
+    let mut export_bar: Option<ProgressBar> = None;
+    while let Some(event) = rx.recv().await {
+        match event {
+            ProgressEvent::Download(DownloadProgress::Connecting) => {
+                spinner.set_message("connecting...");
+            }
+            ProgressEvent::Download(DownloadProgress::GettingSizes) => {
+                spinner.set_message("getting sizes...");
+            }
+            ProgressEvent::Download(DownloadProgress::Metadata {
+                total_size,
+                file_count,
+                ..
+            }) => {
+                spinner.set_message(format!("{} files, {}", file_count, HumanBytes(total_size)));
+            }
+            ProgressEvent::Download(DownloadProgress::Downloading {
+                offset,
+                total,
+                rate,
+            }) => {
+                let pb = download_bar.get_or_insert_with(|| {
+                    let pb = mp.add(ProgressBar::new(total));
+                    pb.set_style(
+                        ProgressStyle::with_template(
+                            "{msg:>10} [{bar:40.cyan/blue}] {bytes}/{total_bytes} {prefix}",
+                        )
+                        .unwrap()
+                        .progress_chars("=> "),
+                    );
+                    pb.set_message("downloading");
+                    pb
+                });
+                pb.set_length(total);
+                pb.set_position(offset);
+                pb.set_prefix(rate_label(&rate));
+            }
+            ProgressEvent::Export(_, ExportProgress::Started { total_files }) => {
+                if let Some(pb) = download_bar.take() {
+                    pb.finish_and_clear();
+                }
+                export_bar = Some(files_bar(&mp, total_files, "exporting"));
+            }
+            ProgressEvent::Export(_, ExportProgress::FileCompleted { .. }) => {
+                if let Some(pb) = &export_bar {
+                    pb.inc(1);
+                }
+            }
+            ProgressEvent::Export(_, ExportProgress::Conflict { name, resolution }) => {
+                let msg = match &resolution {
+                    ConflictResolution::Overwritten => format!("overwriting {name}"),
+                    ConflictResolution::Skipped => format!("skipped {name}, it already exists"),
+                    ConflictResolution::SkippedIdentical => {
+                        format!("skipped {name}, it is already up to date")
+                    }
+                    ConflictResolution::Renamed { name: renamed } => {
+                        format!("{name} already exists, saving as {renamed}")
+                    }
+                    ConflictResolution::Failed => format!("{name} already exists"),
+                };
+                if mp.is_hidden() {
+                    eprintln!("{msg}");
+                } else {
+                    mp.println(msg).ok();
+                }
+                if matches!(
+                    resolution,
+                    ConflictResolution::Skipped | ConflictResolution::SkippedIdentical
+                ) {
+                    if let Some(pb) = &export_bar {
+                        pb.inc(1);
+                    }
+                }
+            }
+            ProgressEvent::Export(_, ExportProgress::Completed) => {
+                if let Some(pb) = export_bar.take() {
+                    pb.finish_and_clear();
+                }
+            }
+            _ => {}
+        }
+    }
+    spinner.finish_and_clear();
+    if let Some(pb) = download_bar {
+        pb.finish_and_clear();
+    }
+}
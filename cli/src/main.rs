@@ -1,30 +1,76 @@
 //! Sendme CLI - Send files over the internet using iroh.
 //!
-//! Interactive TUI version with ratatui.
+//! Runs the interactive TUI by default, or the non-interactive `send` and
+//! `receive` subcommands for scripting.
 
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
 use anyhow::Result;
+use clap::Parser;
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
-use sendme_lib::{types::*, BlobTicket};
+use sendme_lib::{
+    nearby::Nearby,
+    offer::{Answer, IncomingOffer, Offer},
+    progress::{ConnectionStatus, ProgressEvent},
+    types::*,
+    BlobTicket, CancellationToken, EndpointId, SendHandle,
+};
 use tokio::sync::mpsc;
 
+mod commands;
 mod tui;
 
-use tui::{app::TransferType, App, EventHandler, Transfer};
+use commands::{Args, Commands, ShareLimitArgs};
+
+use tui::{
+    app::{ReceiveTabState, TransferType},
+    App, EventHandler, Transfer,
+};
 
 /// Tick rate for the event loop (ms).
 const TICK_RATE_MS: u64 = 250;
 
+/// Running send sessions and what they share, keyed by transfer id.
+type SendHandles = Arc<Mutex<HashMap<String, (SendHandle, SendResult)>>>;
+
+/// Offers from nearby devices waiting for an answer, keyed by offer id.
+type PendingOffers = Arc<Mutex<HashMap<String, IncomingOffer>>>;
+
+/// Cancellation tokens of running receives, keyed by transfer id.
+type ReceiveTokens = Arc<Mutex<HashMap<String, CancellationToken>>>;
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
+    // Logs go to stderr so stdout stays parseable for the headless subcommands
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+
+    let args = Args::parse();
+    match args.command {
+        Some(Commands::Send(cmd)) => commands::send(cmd).await,
+        Some(Commands::Receive(cmd)) => commands::receive(cmd).await,
+        Some(Commands::Inspect(cmd)) => commands::inspect(cmd).await,
+        Some(Commands::Nearby(cmd)) => commands::nearby(cmd).await,
+        Some(Commands::Identity(cmd)) => commands::identity(cmd),
+        None => run_tui(args.limits).await,
+    }
+}
 
+/// Run the interactive terminal UI.
+///
+/// `limits` apply to every share started from the UI.
+async fn run_tui(limits: ShareLimitArgs) -> Result<()> {
     // Setup terminal in a blocking task
     let backend = tokio::task::spawn_blocking(|| {
         enable_raw_mode()?;
@@ -42,22 +88,83 @@ async fn main() -> Result<()> {
 
     // Channels for async operations
     let (send_tx, mut send_rx) = mpsc::channel::<SendRequest>(32);
+    let (inspect_tx, mut inspect_rx) = mpsc::channel::<TicketOrCode>(32);
     let (receive_tx, mut receive_rx) = mpsc::channel::<ReceiveRequest>(32);
+    let (stop_tx, mut stop_rx) = mpsc::channel::<String>(32);
+    let (answer_tx, answer_rx) = mpsc::channel::<(String, bool)>(32);
+    let (offer_tx, offer_rx) = mpsc::channel::<(String, EndpointId)>(32);
+    let send_handles = SendHandles::default();
+    let receive_tokens = ReceiveTokens::default();
+
+    // Announce this device on the local network, with a key of its own
+    match Nearby::spawn(commands::this_device(None), &CommonConfig::default()).await {
+        Ok((nearby, offers)) => {
+            app.nearby_name = Some(nearby.info().name.clone());
+            run_nearby(
+                nearby,
+                offers,
+                answer_rx,
+                offer_rx,
+                event_handler.clone(),
+                send_handles.clone(),
+            );
+        }
+        Err(e) => {
+            app.nearby_message = format!("Nearby devices are unavailable: {}", e);
+        }
+    }
 
     // Spawn background tasks
     let send_event_handler = event_handler.clone();
+    let send_handles_clone = send_handles.clone();
     tokio::spawn(async move {
         while let Some(event) = send_rx.recv().await {
-            if let Err(e) = handle_send_request(event, send_event_handler.clone()).await {
+            if let Err(e) = handle_send_request(
+                event,
+                limits,
+                send_event_handler.clone(),
+                send_handles_clone.clone(),
+            )
+            .await
+            {
                 eprintln!("Send error: {}", e);
             }
         }
     });
 
+    let receive_tokens_clone = receive_tokens.clone();
+    tokio::spawn(async move {
+        while let Some(transfer_id) = stop_rx.recv().await {
+            if let Some(cancel) = receive_tokens_clone.lock().unwrap().remove(&transfer_id) {
+                cancel.cancel();
+            }
+            let handle = send_handles.lock().unwrap().remove(&transfer_id);
+            if let Some((handle, _)) = handle {
+                if let Err(e) = handle.shutdown().await {
+                    tracing::warn!("failed to stop transfer {}: {}", transfer_id, e);
+                }
+            }
+        }
+    });
+
+    let inspect_event_handler = event_handler.clone();
+    tokio::spawn(async move {
+        while let Some(ticket) = inspect_rx.recv().await {
+            let event_handler = inspect_event_handler.clone();
+            tokio::spawn(async move {
+                let listing = inspect_share(ticket).await.map_err(|e| e.to_string());
+                event_handler.send_inspect_completed(listing);
+            });
+        }
+    });
+
     let receive_event_handler = event_handler.clone();
     tokio::spawn(async move {
         while let Some(event) = receive_rx.recv().await {
-            if let Err(e) = handle_receive_request(event, receive_event_handler.clone()).await {
+            if let Err(e) =
+                handle_receive_request(event, receive_event_handler.clone(), receive_tokens.clone())
+                    .await
+            {
                 eprintln!("Receive error: {}", e);
             }
         }
@@ -75,10 +182,12 @@ async fn main() -> Result<()> {
             loop {
                 match event_rx.try_recv() {
                     Ok(tui::event::AppEvent::Input(key)) => {
+                        // Keys answering an offer are not meant for the tab
+                        let prompted = !app.offers.is_empty();
                         app.handle_key(key);
 
                         // Handle send tab enter key
-                        if app.current_tab == tui::app::Tab::Send {
+                        if !prompted && app.current_tab == tui::app::Tab::Send {
                             if let crossterm::event::KeyCode::Enter = key.code {
                                 if !app.send_input_path.is_empty() {
                                     let path = app.send_input_path.clone();
@@ -98,28 +207,46 @@ async fn main() -> Result<()> {
                             }
                         }
 
-                        // Handle receive tab enter key
-                        if app.current_tab == tui::app::Tab::Receive {
-                            if let crossterm::event::KeyCode::Enter = key.code {
-                                if !app.receive_input_ticket.is_empty() {
-                                    let ticket_str = app.receive_input_ticket.trim();
+                        // Handle receive tab enter key: list the files first, then
+                        // receive once the user confirmed
+                        if !prompted && app.current_tab == tui::app::Tab::Receive {
+                            let confirmed = matches!(
+                                key.code,
+                                crossterm::event::KeyCode::Enter
+                                    | crossterm::event::KeyCode::Char('y' | 'Y')
+                            );
+                            if matches!(app.receive_tab_state, ReceiveTabState::Input)
+                                && key.code == crossterm::event::KeyCode::Enter
+                                && !app.receive_input_ticket.is_empty()
+                            {
+                                // Parse ticket
+                                match parse_ticket(app.receive_input_ticket.trim()) {
+                                    Ok(ticket) => {
+                                        app.receive_tab_state = ReceiveTabState::Inspecting;
+                                        let _ = inspect_tx.try_send(ticket);
+                                    }
+                                    Err(e) => {
+                                        app.receive_message = format!("Invalid ticket: {}", e);
+                                        app.receive_input_ticket.clear();
+                                    }
+                                }
+                                continue;
+                            }
+                            if confirmed
+                                && matches!(app.receive_tab_state, ReceiveTabState::Confirm { .. })
+                            {
+                                if let ReceiveTabState::Confirm { ticket, .. } = std::mem::replace(
+                                    &mut app.receive_tab_state,
+                                    ReceiveTabState::Input,
+                                ) {
                                     let transfer_id = uuid::Uuid::new_v4().to_string();
 
-                                    // Parse ticket
-                                    let ticket = match parse_ticket(ticket_str) {
-                                        Ok(t) => t,
-                                        Err(e) => {
-                                            app.receive_message = format!("Invalid ticket: {}", e);
-                                            app.receive_input_ticket.clear();
-                                            continue;
-                                        }
-                                    };
-
                                     let mut transfer = Transfer::new(
                                         TransferType::Receive,
                                         format!("from ticket"),
                                     );
                                     transfer.id = transfer_id.clone();
+                                    transfer.ticket = Some(ticket.to_string());
                                     app.add_transfer(transfer.clone());
 
                                     app.receive_input_ticket.clear();
@@ -129,17 +256,31 @@ async fn main() -> Result<()> {
                                     let _ = receive_tx.try_send(ReceiveRequest {
                                         ticket,
                                         transfer_id,
+                                        include: IncludeFilter::All,
                                     });
                                 }
                             }
                         }
 
                         // Handle transfers tab cleanup
-                        if app.current_tab == tui::app::Tab::Transfers {
+                        if !prompted && app.current_tab == tui::app::Tab::Transfers {
                             if let crossterm::event::KeyCode::Char('c') = key.code {
                                 app.cleanup_finished_transfers();
                             }
                         }
+
+                        // Stop sessions of transfers that were stopped or deleted
+                        for transfer_id in app.stop_requests.drain(..) {
+                            let _ = stop_tx.try_send(transfer_id);
+                        }
+
+                        // Answer offers and make the ones picked in the nearby tab
+                        for answer in app.offer_answers.drain(..) {
+                            let _ = answer_tx.try_send(answer);
+                        }
+                        for request in app.offer_requests.drain(..) {
+                            let _ = offer_tx.try_send(request);
+                        }
                     }
                     Ok(tui::event::AppEvent::Tick) => {
                         // Periodic updates
@@ -150,6 +291,19 @@ async fn main() -> Result<()> {
                             transfer.update_progress(&event);
                         }
                     }
+                    Ok(tui::event::AppEvent::ShareUpdate {
+                        transfer_id,
+                        status,
+                    }) => {
+                        if let Some(transfer) =
+                            app.transfers.iter_mut().find(|t| t.id == transfer_id)
+                        {
+                            transfer.update_share(&status);
+                        }
+                    }
+                    Ok(tui::event::AppEvent::InspectCompleted { listing }) => {
+                        app.set_receive_listing(listing);
+                    }
                     Ok(tui::event::AppEvent::SendCompleted { ticket, path }) => {
                         // Store ticket in the transfer and show success view
                         if let Some(transfer) = app.transfers.last_mut() {
@@ -158,6 +312,35 @@ async fn main() -> Result<()> {
                         }
                         app.set_send_success(ticket, path);
                     }
+                    Ok(tui::event::AppEvent::Nearby(event)) => {
+                        app.update_nearby(event);
+                    }
+                    Ok(tui::event::AppEvent::OfferReceived { offer_id, offer }) => {
+                        app.add_offer(offer_id, offer);
+                    }
+                    Ok(tui::event::AppEvent::OfferAccepted {
+                        ticket,
+                        include,
+                        from,
+                    }) => {
+                        let transfer_id = uuid::Uuid::new_v4().to_string();
+
+                        let mut transfer =
+                            Transfer::new(TransferType::Receive, format!("from {}", from));
+                        transfer.id = transfer_id.clone();
+                        transfer.ticket = Some(ticket.to_string());
+                        app.add_transfer(transfer);
+
+                        app.nearby_message = format!("Receiving the share from {}...", from);
+                        let _ = receive_tx.try_send(ReceiveRequest {
+                            ticket,
+                            transfer_id,
+                            include,
+                        });
+                    }
+                    Ok(tui::event::AppEvent::NearbyMessage(message)) => {
+                        app.nearby_message = message;
+                    }
                     Err(std::sync::mpsc::TryRecvError::Empty) => {
                         // No more events, break inner loop
                         break;
@@ -194,7 +377,7 @@ async fn main() -> Result<()> {
 }
 
 /// Parse a ticket string, handling various formats.
-fn parse_ticket(s: &str) -> Result<BlobTicket> {
+fn parse_ticket(s: &str) -> Result<TicketOrCode> {
     let s = s.trim();
 
     // Remove "sendme receive" prefix if present
@@ -204,26 +387,170 @@ fn parse_ticket(s: &str) -> Result<BlobTicket> {
         s
     };
 
-    s.parse::<BlobTicket>()
-        .map_err(|e| anyhow::anyhow!("Failed to parse ticket: {}", e))
+    let ticket = s
+        .parse::<TicketOrCode>()
+        .map_err(|e| anyhow::anyhow!("Failed to parse ticket: {}", e))?;
+    if let TicketOrCode::Ticket(ticket) = &ticket {
+        ensure_unlocked(ticket)?;
+    }
+    Ok(ticket)
+}
+
+/// The UI can't ask for a passphrase yet.
+fn ensure_unlocked(ticket: &ShareTicket) -> Result<()> {
+    anyhow::ensure!(
+        !ticket.encrypted,
+        "this share is protected by a passphrase, use `sendme receive` for it"
+    );
+    Ok(())
+}
+
+/// List the files of a share, exchanging a short code for its ticket first.
+async fn inspect_share(ticket: TicketOrCode) -> Result<(BlobTicket, InspectResult)> {
+    let ticket = ticket.resolve(&CommonConfig::default()).await?;
+    ensure_unlocked(&ticket)?;
+    let listing = sendme_lib::inspect(ticket.ticket.clone(), CommonConfig::default()).await?;
+    Ok((ticket.ticket, listing))
 }
 
 /// Send request.
-#[allow(dead_code)]
 struct SendRequest {
     path: String,
     transfer_id: String,
 }
 
 /// Receive request.
-#[allow(dead_code)]
 struct ReceiveRequest {
     ticket: BlobTicket,
     transfer_id: String,
+    /// Files to receive, all unless only some of an offer were accepted.
+    include: IncludeFilter,
+}
+
+/// Follow the devices on the local network, and handle offers from and to
+/// them.
+///
+/// Offers from other devices are shown until answered on `answer_rx`, by offer
+/// id. Shares are offered as they come in on `offer_rx`, by transfer id.
+fn run_nearby(
+    nearby: Nearby,
+    mut offers: mpsc::Receiver<IncomingOffer>,
+    mut answer_rx: mpsc::Receiver<(String, bool)>,
+    mut offer_rx: mpsc::Receiver<(String, EndpointId)>,
+    event_handler: EventHandler,
+    send_handles: SendHandles,
+) {
+    let nearby = Arc::new(nearby);
+    let pending = PendingOffers::default();
+
+    let mut events = nearby.events();
+    let events_handler = event_handler.clone();
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            events_handler.send_nearby(event);
+        }
+    });
+
+    let offers_handler = event_handler.clone();
+    let offers_pending = pending.clone();
+    tokio::spawn(async move {
+        while let Some(incoming) = offers.recv().await {
+            // The UI can't ask for a passphrase yet
+            if incoming.offer.encrypted {
+                offers_handler.send_nearby_message(format!(
+                    "Declined a passphrase protected share from {}, use `sendme nearby` for it",
+                    incoming.offer.from.name
+                ));
+                incoming.reject();
+                continue;
+            }
+            let offer_id = uuid::Uuid::new_v4().to_string();
+            let offer = incoming.offer.clone();
+            offers_pending
+                .lock()
+                .unwrap()
+                .insert(offer_id.clone(), incoming);
+            offers_handler.send_offer_received(offer_id, offer);
+        }
+    });
+
+    let answers_handler = event_handler.clone();
+    tokio::spawn(async move {
+        while let Some((offer_id, accept)) = answer_rx.recv().await {
+            let incoming = pending.lock().unwrap().remove(&offer_id);
+            let Some(incoming) = incoming else {
+                continue;
+            };
+            let from = incoming.offer.from.name.clone();
+            let answer = match accept {
+                true => Answer::Accept,
+                false => Answer::Reject,
+            };
+            // Waits for the sender to hand over the ticket
+            let event_handler = answers_handler.clone();
+            tokio::spawn(async move {
+                match incoming.answer(answer).await {
+                    Ok(Some(accepted)) => {
+                        event_handler.send_offer_accepted(
+                            accepted.ticket.ticket,
+                            accepted.include,
+                            from,
+                        );
+                    }
+                    Ok(None) => {}
+                    Err(e) => event_handler
+                        .send_nearby_message(format!("Failed to answer {}: {}", from, e)),
+                }
+            });
+        }
+    });
+
+    tokio::spawn(async move {
+        while let Some((transfer_id, device_id)) = offer_rx.recv().await {
+            let share = send_handles
+                .lock()
+                .unwrap()
+                .get(&transfer_id)
+                .map(|(handle, result)| (handle.endpoint().clone(), result.clone()));
+            let Some((endpoint, share)) = share else {
+                event_handler.send_nearby_message("The share is no longer served".to_string());
+                continue;
+            };
+            let name = nearby
+                .devices()
+                .into_iter()
+                .find(|device| device.id == device_id)
+                .map(|device| device.info.name)
+                .unwrap_or_else(|| device_id.fmt_short().to_string());
+            // Waits for the user of the device to answer
+            let nearby = nearby.clone();
+            let event_handler = event_handler.clone();
+            tokio::spawn(async move {
+                let offer = Offer::new(nearby.info().clone(), &share);
+                let answer = nearby
+                    .offer(&endpoint, device_id, &offer, &share.share_ticket(), None)
+                    .await;
+                let message = match answer {
+                    Ok(Answer::Accept) => format!("Accepted by {}", name),
+                    Ok(Answer::AcceptSubset(names)) => {
+                        format!("Partly accepted by {}: {}", name, names.join(", "))
+                    }
+                    Ok(Answer::Reject) => format!("Declined by {}", name),
+                    Err(e) => format!("Failed to offer to {}: {}", name, e),
+                };
+                event_handler.send_nearby_message(message);
+            });
+        }
+    });
 }
 
 /// Handle a send request.
-async fn handle_send_request(request: SendRequest, event_handler: EventHandler) -> Result<()> {
+async fn handle_send_request(
+    request: SendRequest,
+    limits: ShareLimitArgs,
+    event_handler: EventHandler,
+    send_handles: SendHandles,
+) -> Result<()> {
     let path = PathBuf::from(&request.path);
 
     if !path.exists() {
@@ -233,24 +560,64 @@ async fn handle_send_request(request: SendRequest, event_handler: EventHandler)
     let args = SendArgs {
         path,
         ticket_type: AddrInfoOptions::RelayAndAddresses,
+        // A fresh key per share, since shares run side by side
         common: CommonConfig::default(),
+        allowlist: None,
+        max_downloads: limits.max_downloads,
+        expires_after: limits.expires_after,
+        passphrase: None,
+        short_code: false,
+        symlinks: SymlinkPolicy::Skip,
+        ignore_files: false,
+        include: Vec::new(),
+        exclude: Vec::new(),
+        stdin: None,
+        compress: false,
     };
 
     let (progress_tx, mut progress_rx) = mpsc::channel(32);
     let event_handler_clone = event_handler.clone();
     let request_path_clone = request.path.clone();
+    let transfer_id = request.transfer_id.clone();
 
     // Spawn progress forwarding task (runs indefinitely for connection events)
     tokio::spawn(async move {
         while let Some(event) = progress_rx.recv().await {
-            event_handler_clone.send_transfer_update(event);
+            match event {
+                // Quota, path and speed updates belong to this share only
+                ProgressEvent::Connection(
+                    status @ (ConnectionStatus::QuotaUpdated { .. }
+                    | ConnectionStatus::ShareClosed { .. }
+                    | ConnectionStatus::PathChanged { .. }
+                    | ConnectionStatus::RequestProgress { .. }),
+                ) => event_handler_clone.send_share_update(transfer_id.clone(), status),
+                event => event_handler_clone.send_transfer_update(event),
+            }
         }
     });
 
     // Run send_with_progress and send completion event
     match sendme_lib::send_with_progress(args, progress_tx).await {
-        Ok(result) => {
-            event_handler.send_send_completed(result.ticket.to_string(), request_path_clone);
+        Ok((result, handle)) => {
+            let closed = handle.closed();
+            send_handles
+                .lock()
+                .unwrap()
+                .insert(request.transfer_id.clone(), (handle, result.clone()));
+
+            // Forget the share once its download or time limit has closed it
+            let transfer_id = request.transfer_id;
+            tokio::spawn(async move {
+                closed.await;
+                let handle = send_handles.lock().unwrap().remove(&transfer_id);
+                if let Some((handle, _)) = handle {
+                    if let Err(e) = handle.shutdown().await {
+                        tracing::warn!("failed to stop transfer {}: {}", transfer_id, e);
+                    }
+                }
+            });
+            event_handler
+                .send_send_completed(result.share_ticket().to_string(), request_path_clone);
         }
         Err(e) => {
             eprintln!("Send error: {}", e);
@@ -264,25 +631,46 @@ async fn handle_send_request(request: SendRequest, event_handler: EventHandler)
 async fn handle_receive_request(
     request: ReceiveRequest,
     event_handler: EventHandler,
+    receive_tokens: ReceiveTokens,
 ) -> Result<()> {
+    let cancel = CancellationToken::new();
+    receive_tokens
+        .lock()
+        .unwrap()
+        .insert(request.transfer_id.clone(), cancel.clone());
+
     let args = ReceiveArgs {
         ticket: request.ticket,
         common: CommonConfig::default(),
         export_dir: None,
+        include: request.include,
+        conflict_policy: ExportConflictPolicy::default(),
+        atomic_export: false,
+        cancel,
+        passphrase: None,
+        archive: None,
     };
 
     let (progress_tx, mut progress_rx) = mpsc::channel(32);
     let event_handler_clone = event_handler.clone();
+    let transfer_id = request.transfer_id.clone();
 
     // Spawn progress forwarding task
     tokio::spawn(async move {
         while let Some(event) = progress_rx.recv().await {
-            event_handler_clone.send_transfer_update(event);
+            match event {
+                ProgressEvent::Connection(status @ ConnectionStatus::PathChanged { .. }) => {
+                    event_handler_clone.send_share_update(transfer_id.clone(), status)
+                }
+                event => event_handler_clone.send_transfer_update(event),
+            }
         }
     });
 
     // Run receive operation
-    if let Err(e) = sendme_lib::receive_with_progress(args, progress_tx).await {
+    let result = sendme_lib::receive_with_progress(args, progress_tx).await;
+    receive_tokens.lock().unwrap().remove(&request.transfer_id);
+    if let Err(e) = result {
         eprintln!("Receive error: {}", e);
     }
 
@@ -1,10 +1,14 @@
 //! Application state and logic for the TUI.
 
 use crate::tui::file_search::FileSearchPopup;
-use sendme_lib::progress::{DownloadProgress, ProgressEvent};
-use sendme_lib::Hash;
+use sendme_lib::nearby::{Device, NearbyEvent};
+use sendme_lib::offer::Offer;
+use sendme_lib::progress::{ConnectionStatus, DownloadProgress, ProgressEvent, TransferRate};
+use sendme_lib::{types::InspectResult, BlobTicket, EndpointId, Hash};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Current tab in the application.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -12,13 +16,14 @@ pub enum Tab {
     Send,
     Receive,
     Transfers,
+    Nearby,
 }
 
 impl Tab {
     #[allow(dead_code)]
     /// Get all tabs in order.
     pub fn all() -> &'static [Tab] {
-        &[Tab::Send, Tab::Receive, Tab::Transfers]
+        &[Tab::Send, Tab::Receive, Tab::Transfers, Tab::Nearby]
     }
 
     /// Get tab index.
@@ -27,6 +32,7 @@ impl Tab {
             Tab::Send => 0,
             Tab::Receive => 1,
             Tab::Transfers => 2,
+            Tab::Nearby => 3,
         }
     }
 
@@ -36,6 +42,7 @@ impl Tab {
             0 => Some(Tab::Send),
             1 => Some(Tab::Receive),
             2 => Some(Tab::Transfers),
+            3 => Some(Tab::Nearby),
             _ => None,
         }
     }
@@ -46,6 +53,7 @@ impl Tab {
             Tab::Send => "Send",
             Tab::Receive => "Receive",
             Tab::Transfers => "Transfers",
+            Tab::Nearby => "Nearby",
         }
     }
 }
@@ -80,7 +88,6 @@ pub enum TransferStatus {
 }
 
 impl TransferStatus {
-    #[allow(dead_code)]
     pub fn is_active(&self) -> bool {
         matches!(
             self,
@@ -144,6 +151,45 @@ pub struct Transfer {
     pub progress: u16,
     /// File names in the collection (for receive transfers).
     pub file_names: Vec<String>,
+    /// Downloads of the share (for send transfers).
+    pub downloads: u32,
+    /// Downloads left before the share closes, if limited.
+    pub remaining_downloads: Option<u32>,
+    /// When the share expires, if limited.
+    pub expires_at: Option<Instant>,
+    /// The network path to the remote, as last reported.
+    pub network_path: Option<NetworkPath>,
+    /// Speed of the download, export or upload, as last reported.
+    pub rate: Option<TransferRate>,
+}
+
+/// The network path of a transfer.
+#[derive(Debug, Clone)]
+pub struct NetworkPath {
+    /// Whether the traffic goes straight to the remote, not via a relay.
+    pub direct: bool,
+    /// Address of the remote, if it can be reached directly.
+    pub remote_addr: Option<SocketAddr>,
+    /// The relay in use, if any.
+    pub relay_url: Option<String>,
+    /// Round trip time, once measured.
+    pub rtt: Option<Duration>,
+}
+
+impl NetworkPath {
+    /// Short description of the path, e.g. "direct to 192.168.1.20:4433, 3 ms".
+    pub fn summary(&self) -> String {
+        let path = match (self.direct, &self.remote_addr, &self.relay_url) {
+            (true, Some(addr), _) => format!("direct to {}", addr),
+            (_, Some(addr), Some(relay)) => format!("via relay {}, trying {}", relay, addr),
+            (_, _, Some(relay)) => format!("via relay {}", relay),
+            _ => "no path".to_string(),
+        };
+        match self.rtt {
+            Some(rtt) => format!("{}, {} ms", path, rtt.as_millis()),
+            None => path,
+        }
+    }
 }
 
 impl Transfer {
@@ -166,7 +212,80 @@ impl Transfer {
                 .as_secs() as i64,
             progress: 0,
             file_names: Vec::new(),
+            downloads: 0,
+            remaining_downloads: None,
+            expires_at: None,
+            network_path: None,
+            rate: None,
+        }
+    }
+
+    /// Update the quota and upload speed of a send transfer, or the network
+    /// path of any transfer.
+    pub fn update_share(&mut self, status: &ConnectionStatus) {
+        match status {
+            ConnectionStatus::QuotaUpdated {
+                downloads,
+                remaining_downloads,
+                remaining_time,
+            } => {
+                self.downloads = *downloads;
+                self.remaining_downloads = *remaining_downloads;
+                self.expires_at = remaining_time.map(|remaining| Instant::now() + remaining);
+            }
+            ConnectionStatus::ShareClosed { .. } => {
+                self.status = TransferStatus::Completed;
+                self.progress = 100;
+                self.expires_at = None;
+            }
+            ConnectionStatus::PathChanged {
+                direct,
+                remote_addr,
+                relay_url,
+                rtt,
+                ..
+            } => {
+                self.network_path = Some(NetworkPath {
+                    direct: *direct,
+                    remote_addr: *remote_addr,
+                    relay_url: relay_url.as_ref().map(|url| url.to_string()),
+                    rtt: *rtt,
+                });
+            }
+            ConnectionStatus::RequestProgress { rate, .. } => {
+                self.rate = Some(*rate);
+            }
+            _ => {}
+        }
+    }
+
+    /// Short description of the remaining quota, if the share is limited.
+    pub fn quota_summary(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(remaining) = self.remaining_downloads {
+            parts.push(format!(
+                "{} of {} left",
+                remaining,
+                self.downloads + remaining
+            ));
+        }
+        if let Some(expires_at) = self.expires_at {
+            let secs = expires_at
+                .saturating_duration_since(Instant::now())
+                .as_secs();
+            parts.push(format!("expires in {}", format_remaining(secs)));
+        }
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+
+    /// Short description of the speed, e.g. "1.5 MB/s, 20s left".
+    pub fn rate_summary(&self) -> Option<String> {
+        let rate = self.rate?;
+        let mut summary = format!("{}/s", format_size(rate.bytes_per_sec));
+        if let Some(eta) = rate.eta {
+            summary.push_str(&format!(", {} left", format_remaining(eta.as_secs())));
         }
+        Some(summary)
     }
 
     /// Update transfer progress based on progress event.
@@ -182,7 +301,12 @@ impl Transfer {
                 self.file_names = names.clone();
                 self.status = TransferStatus::Downloading;
             }
-            ProgressEvent::Download(DownloadProgress::Downloading { offset, total }) => {
+            ProgressEvent::Download(DownloadProgress::Downloading {
+                offset,
+                total,
+                rate,
+            }) => {
+                self.rate = Some(*rate);
                 self.transferred_bytes = *offset;
                 self.total_bytes = *total;
                 self.progress = if *total > 0 {
@@ -199,13 +323,13 @@ impl Transfer {
                 self.status = TransferStatus::Connecting;
             }
             ProgressEvent::Import(_, progress) => match progress {
-                sendme_lib::progress::ImportProgress::Started { total_files } => {
+                sendme_lib::progress::ImportProgress::Started { total_files, .. } => {
                     self.total_files = *total_files as u64;
                 }
                 sendme_lib::progress::ImportProgress::FileCompleted { .. } => {
                     self.transferred_files += 1;
                 }
-                sendme_lib::progress::ImportProgress::Completed { total_size } => {
+                sendme_lib::progress::ImportProgress::Completed { total_size, .. } => {
                     self.total_bytes = *total_size;
                     self.status = TransferStatus::Serving;
                 }
@@ -217,7 +341,16 @@ impl Transfer {
                     sendme_lib::progress::ExportProgress::Started { total_files } => {
                         self.total_files = *total_files as u64;
                     }
-                    sendme_lib::progress::ExportProgress::FileCompleted { .. } => {
+                    sendme_lib::progress::ExportProgress::FileProgress { rate, .. } => {
+                        self.rate = Some(*rate);
+                    }
+                    sendme_lib::progress::ExportProgress::FileCompleted { .. }
+                    | sendme_lib::progress::ExportProgress::Conflict {
+                        resolution:
+                            sendme_lib::progress::ConflictResolution::Skipped
+                            | sendme_lib::progress::ConflictResolution::SkippedIdentical,
+                        ..
+                    } => {
                         self.transferred_files += 1;
                     }
                     sendme_lib::progress::ExportProgress::Completed => {
@@ -231,6 +364,36 @@ impl Transfer {
     }
 }
 
+/// Format a number of bytes with a binary unit.
+fn format_size(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    const GB: u64 = MB * 1024;
+
+    if bytes >= GB {
+        format!("{:.1} GB", bytes as f64 / GB as f64)
+    } else if bytes >= MB {
+        format!("{:.1} MB", bytes as f64 / MB as f64)
+    } else if bytes >= KB {
+        format!("{:.1} KB", bytes as f64 / KB as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+/// Format a number of seconds as a short countdown.
+fn format_remaining(secs: u64) -> String {
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else if secs < 86400 {
+        format!("{}h {}m", secs / 3600, secs % 3600 / 60)
+    } else {
+        format!("{}d {}h", secs / 86400, secs % 86400 / 3600)
+    }
+}
+
 /// Send tab state.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SendTabState {
@@ -242,6 +405,20 @@ pub enum SendTabState {
     FileSearch,
 }
 
+/// Receive tab state.
+#[derive(Debug, Clone)]
+pub enum ReceiveTabState {
+    /// Showing ticket input field.
+    Input,
+    /// Fetching the file listing for a ticket.
+    Inspecting,
+    /// Showing the file listing, waiting for the user to confirm.
+    Confirm {
+        ticket: BlobTicket,
+        listing: Box<InspectResult>,
+    },
+}
+
 /// Transfers tab state.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TransfersTabState {
@@ -275,6 +452,8 @@ pub struct App {
     pub send_file_search: Option<FileSearchPopup>,
 
     // Receive tab state
+    /// Current state of the receive tab.
+    pub receive_tab_state: ReceiveTabState,
     /// Input ticket for receiving.
     pub receive_input_ticket: String,
     /// Message for receive tab.
@@ -285,6 +464,25 @@ pub struct App {
     pub transfers_tab_state: TransfersTabState,
     /// Index of currently selected transfer.
     pub selected_transfer_index: Option<usize>,
+    /// IDs of transfers whose sessions should be stopped.
+    pub stop_requests: Vec<String>,
+
+    // Nearby tab state
+    /// The name this device is visible as, once it is announced.
+    pub nearby_name: Option<String>,
+    /// Devices seen on the local network.
+    pub nearby_devices: Vec<Device>,
+    /// Index of the currently selected device.
+    pub selected_device_index: Option<usize>,
+    /// Message for nearby tab.
+    pub nearby_message: String,
+    /// Offers from other devices waiting for an answer, by offer id. The first
+    /// one is shown.
+    pub offers: VecDeque<(String, Offer)>,
+    /// Answers to offers, by offer id, to be sent.
+    pub offer_answers: Vec<(String, bool)>,
+    /// Shares to offer, by transfer id, and the devices to offer them to.
+    pub offer_requests: Vec<(String, EndpointId)>,
 
     /// Application running flag.
     pub running: bool,
@@ -303,10 +501,19 @@ impl App {
             send_success_path: None,
             show_qr: false,
             send_file_search: None,
+            receive_tab_state: ReceiveTabState::Input,
             receive_input_ticket: String::new(),
             receive_message: String::new(),
             transfers_tab_state: TransfersTabState::List,
             selected_transfer_index: None,
+            stop_requests: Vec::new(),
+            nearby_name: None,
+            nearby_devices: Vec::new(),
+            selected_device_index: None,
+            nearby_message: String::new(),
+            offers: VecDeque::new(),
+            offer_answers: Vec::new(),
+            offer_requests: Vec::new(),
             running: true,
         }
     }
@@ -333,6 +540,26 @@ impl App {
             return;
         }
 
+        // An offer from another device takes all input until it is answered
+        if let Some((offer_id, offer)) = self.offers.front() {
+            let accept = match key.code {
+                crossterm::event::KeyCode::Char('y' | 'Y') | crossterm::event::KeyCode::Enter => {
+                    true
+                }
+                crossterm::event::KeyCode::Char('n' | 'N') | crossterm::event::KeyCode::Esc => {
+                    false
+                }
+                _ => return,
+            };
+            self.nearby_message = match accept {
+                true => format!("Accepted the share from {}", offer.from.name),
+                false => format!("Declined the share from {}", offer.from.name),
+            };
+            self.offer_answers.push((offer_id.clone(), accept));
+            self.offers.pop_front();
+            return;
+        }
+
         // Handle ESC key - returns to input/list view from success/detail views
         if key.code == crossterm::event::KeyCode::Esc {
             match self.current_tab {
@@ -346,12 +573,18 @@ impl App {
                         self.close_file_search();
                     }
                 }
+                Tab::Receive => {
+                    if !matches!(self.receive_tab_state, ReceiveTabState::Input) {
+                        self.receive_tab_state = ReceiveTabState::Input;
+                        self.receive_message.clear();
+                    }
+                }
                 Tab::Transfers => {
                     if let TransfersTabState::Detail { .. } = &self.transfers_tab_state {
                         self.transfers_tab_state = TransfersTabState::List;
                     }
                 }
-                _ => {}
+                Tab::Nearby => {}
             }
             return;
         }
@@ -361,6 +594,7 @@ impl App {
             Tab::Send => self.handle_send_tab_key(key),
             Tab::Receive => self.handle_receive_tab_key(key),
             Tab::Transfers => self.handle_transfers_tab_key(key),
+            Tab::Nearby => self.handle_nearby_tab_key(key),
         }
     }
 
@@ -407,19 +641,49 @@ impl App {
 
     /// Handle key events in the receive tab.
     fn handle_receive_tab_key(&mut self, key: crossterm::event::KeyEvent) {
-        match key.code {
-            crossterm::event::KeyCode::Char(c) => {
-                self.receive_input_ticket.push(c);
+        match self.receive_tab_state {
+            ReceiveTabState::Input => match key.code {
+                crossterm::event::KeyCode::Char(c) => {
+                    self.receive_input_ticket.push(c);
+                }
+                crossterm::event::KeyCode::Backspace => {
+                    self.receive_input_ticket.pop();
+                }
+                crossterm::event::KeyCode::Enter => {
+                    if !self.receive_input_ticket.is_empty() {
+                        self.receive_message = "Fetching file list...".to_string();
+                    }
+                }
+                _ => {}
+            },
+            ReceiveTabState::Inspecting => {}
+            ReceiveTabState::Confirm { .. } => {
+                // Confirming with [y]/[Enter] is handled externally
+                if let crossterm::event::KeyCode::Char('n' | 'N') = key.code {
+                    self.receive_tab_state = ReceiveTabState::Input;
+                    self.receive_message.clear();
+                }
             }
-            crossterm::event::KeyCode::Backspace => {
-                self.receive_input_ticket.pop();
+        }
+    }
+
+    /// Show the listing of an inspected ticket, unless the user moved on.
+    pub fn set_receive_listing(&mut self, listing: Result<(BlobTicket, InspectResult), String>) {
+        if !matches!(self.receive_tab_state, ReceiveTabState::Inspecting) {
+            return;
+        }
+        match listing {
+            Ok((ticket, listing)) => {
+                self.receive_message.clear();
+                self.receive_tab_state = ReceiveTabState::Confirm {
+                    ticket,
+                    listing: Box::new(listing),
+                };
             }
-            crossterm::event::KeyCode::Enter => {
-                if !self.receive_input_ticket.is_empty() {
-                    self.receive_message = format!("Receiving from ticket...");
-                }
+            Err(e) => {
+                self.receive_message = format!("Failed to inspect ticket: {}", e);
+                self.receive_tab_state = ReceiveTabState::Input;
             }
-            _ => {}
         }
     }
 
@@ -465,10 +729,23 @@ impl App {
                             }
                         }
                     }
+                    crossterm::event::KeyCode::Char('s') => {
+                        if let Some(idx) = self.selected_transfer_index {
+                            if let Some(transfer) = self.transfers.get_mut(idx) {
+                                if transfer.status.is_active() {
+                                    transfer.status = TransferStatus::Cancelled;
+                                    self.stop_requests.push(transfer.id.clone());
+                                }
+                            }
+                        }
+                    }
                     crossterm::event::KeyCode::Char('d') => {
                         if let Some(idx) = self.selected_transfer_index {
                             if idx < self.transfers.len() {
-                                self.transfers.remove(idx);
+                                let transfer = self.transfers.remove(idx);
+                                if transfer.status.is_active() {
+                                    self.stop_requests.push(transfer.id);
+                                }
                                 // Reset or adjust selection
                                 if self.transfers.is_empty() {
                                     self.selected_transfer_index = None;
@@ -497,6 +774,77 @@ impl App {
         }
     }
 
+    /// Handle key events in the nearby tab.
+    fn handle_nearby_tab_key(&mut self, key: crossterm::event::KeyEvent) {
+        let count = self.nearby_devices.len();
+        match key.code {
+            crossterm::event::KeyCode::Up if count > 0 => {
+                self.selected_device_index = match self.selected_device_index {
+                    None | Some(0) => Some(count - 1),
+                    Some(idx) => Some(idx - 1),
+                };
+            }
+            crossterm::event::KeyCode::Down if count > 0 => {
+                self.selected_device_index = match self.selected_device_index {
+                    Some(idx) if idx + 1 < count => Some(idx + 1),
+                    _ => Some(0),
+                };
+            }
+            crossterm::event::KeyCode::Enter => {
+                let Some(device) = self
+                    .selected_device_index
+                    .and_then(|idx| self.nearby_devices.get(idx))
+                else {
+                    return;
+                };
+                let Some(transfer) = self.offered_transfer() else {
+                    self.nearby_message = "Send something first to offer it".to_string();
+                    return;
+                };
+                self.nearby_message = format!(
+                    "Offering {} to {}, waiting for an answer...",
+                    transfer.path, device.info.name
+                );
+                self.offer_requests.push((transfer.id.clone(), device.id));
+            }
+            _ => {}
+        }
+    }
+
+    /// Update the list of nearby devices.
+    pub fn update_nearby(&mut self, event: NearbyEvent) {
+        match event {
+            NearbyEvent::Appeared(device) => {
+                match self.nearby_devices.iter_mut().find(|d| d.id == device.id) {
+                    Some(known) => *known = device,
+                    None => self.nearby_devices.push(device),
+                }
+            }
+            NearbyEvent::Disappeared { id } => {
+                self.nearby_devices.retain(|device| device.id != id);
+            }
+        }
+        // Keep the selection in range
+        self.selected_device_index = match self.nearby_devices.len() {
+            0 => None,
+            count => self.selected_device_index.map(|idx| idx.min(count - 1)),
+        };
+    }
+
+    /// Show an offer from another device, after the ones already waiting.
+    pub fn add_offer(&mut self, offer_id: String, offer: Offer) {
+        self.offers.push_back((offer_id, offer));
+    }
+
+    /// The share offered to nearby devices: the latest send that is still
+    /// serving.
+    pub fn offered_transfer(&self) -> Option<&Transfer> {
+        self.transfers.iter().rev().find(|transfer| {
+            transfer.transfer_type == TransferType::Send
+                && transfer.status == TransferStatus::Serving
+        })
+    }
+
     /// Add a new transfer.
     pub fn add_transfer(&mut self, transfer: Transfer) {
         self.transfers.push(transfer);
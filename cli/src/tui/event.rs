@@ -1,7 +1,13 @@
 //! Event system for the TUI.
 
 use crossterm::event::{Event as CrosstermEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
-use sendme_lib::progress::ProgressEvent;
+use sendme_lib::{
+    nearby::NearbyEvent,
+    offer::Offer,
+    progress::{ConnectionStatus, ProgressEvent},
+    types::{IncludeFilter, InspectResult},
+    BlobTicket,
+};
 use std::sync::mpsc;
 use std::time::Duration;
 
@@ -14,8 +20,29 @@ pub enum AppEvent {
     Tick,
     /// Transfer progress update.
     TransferUpdate(ProgressEvent),
+    /// Quota, lifetime or network path update of a single transfer.
+    ShareUpdate {
+        transfer_id: String,
+        status: ConnectionStatus,
+    },
     /// Send completed with ticket.
     SendCompleted { ticket: String, path: String },
+    /// File listing for a ticket or short code is available.
+    InspectCompleted {
+        listing: Result<(BlobTicket, InspectResult), String>,
+    },
+    /// A device appeared or disappeared on the local network.
+    Nearby(NearbyEvent),
+    /// Another device offers a share, waiting for an answer.
+    OfferReceived { offer_id: String, offer: Offer },
+    /// An offer was accepted, and its files can be received.
+    OfferAccepted {
+        ticket: BlobTicket,
+        include: IncludeFilter,
+        from: String,
+    },
+    /// Outcome of offering a share or answering an offer.
+    NearbyMessage(String),
 }
 
 /// Event handler for the application.
@@ -66,10 +93,49 @@ impl EventHandler {
         let _ = self.sender.send(AppEvent::TransferUpdate(event));
     }
 
+    /// Send a share update event for the given transfer.
+    pub fn send_share_update(&self, transfer_id: String, status: ConnectionStatus) {
+        let _ = self.sender.send(AppEvent::ShareUpdate {
+            transfer_id,
+            status,
+        });
+    }
+
     /// Send a send completed event with ticket.
     pub fn send_send_completed(&self, ticket: String, path: String) {
         let _ = self.sender.send(AppEvent::SendCompleted { ticket, path });
     }
+
+    /// Send an inspect completed event with the file listing.
+    pub fn send_inspect_completed(&self, listing: Result<(BlobTicket, InspectResult), String>) {
+        let _ = self.sender.send(AppEvent::InspectCompleted { listing });
+    }
+
+    /// Send a change of the nearby devices.
+    pub fn send_nearby(&self, event: NearbyEvent) {
+        let _ = self.sender.send(AppEvent::Nearby(event));
+    }
+
+    /// Send an offer received from another device.
+    pub fn send_offer_received(&self, offer_id: String, offer: Offer) {
+        let _ = self
+            .sender
+            .send(AppEvent::OfferReceived { offer_id, offer });
+    }
+
+    /// Send an accepted offer, ready to be received.
+    pub fn send_offer_accepted(&self, ticket: BlobTicket, include: IncludeFilter, from: String) {
+        let _ = self.sender.send(AppEvent::OfferAccepted {
+            ticket,
+            include,
+            from,
+        });
+    }
+
+    /// Send a message for the nearby tab.
+    pub fn send_nearby_message(&self, message: String) {
+        let _ = self.sender.send(AppEvent::NearbyMessage(message));
+    }
 }
 
 /// Helper function to check if a key event is a quit command.
@@ -86,6 +152,7 @@ pub fn get_tab_switch(key: &KeyEvent) -> Option<usize> {
         KeyCode::Char('1') => Some(0),
         KeyCode::Char('2') => Some(1),
         KeyCode::Char('3') => Some(2),
+        KeyCode::Char('4') => Some(3),
         _ => None,
     }
 }
@@ -1,5 +1,6 @@
 //! Tab-specific rendering modules.
 
+pub mod nearby;
 pub mod receive;
 pub mod send;
 pub mod transfers;
@@ -0,0 +1,202 @@
+//! Nearby tab rendering.
+
+use ratatui::{
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+
+use indicatif::HumanBytes;
+use sendme_lib::offer::Offer;
+
+use crate::tui::App;
+
+/// How many files of an offer are listed.
+const OFFER_LIST_LEN: usize = 10;
+
+/// Render the nearby tab.
+pub fn render_nearby_tab(f: &mut Frame, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(5),
+            ]
+            .as_ref(),
+        )
+        .margin(1)
+        .split(area);
+
+    // Title and description
+    let visible = match &app.nearby_name {
+        Some(name) => format!("Visible to devices on the local network as {}", name),
+        None => "Not visible to other devices".to_string(),
+    };
+    let title = Paragraph::new(vec![
+        Line::from(Span::styled(
+            "Nearby Devices",
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )),
+        Line::from(vec![Span::styled(
+            visible,
+            Style::default().fg(Color::Gray),
+        )]),
+    ])
+    .alignment(Alignment::Center);
+
+    f.render_widget(title, chunks[0]);
+
+    // Device list
+    let devices: Vec<Line> = if app.nearby_devices.is_empty() {
+        vec![
+            Line::from(""),
+            Line::from(vec![Span::styled(
+                "Looking for devices...",
+                Style::default().fg(Color::DarkGray),
+            )]),
+            Line::from(""),
+            Line::from("Devices running sendme on the same network show up here."),
+        ]
+    } else {
+        app.nearby_devices
+            .iter()
+            .enumerate()
+            .map(|(idx, device)| {
+                let style = if app.selected_device_index == Some(idx) {
+                    Style::default()
+                        .fg(Color::White)
+                        .bg(Color::Blue)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default()
+                };
+                Line::from(vec![
+                    Span::styled(format!(" {} ", device.info.name), style),
+                    Span::styled(
+                        format!(
+                            "  {}  {}",
+                            device.info.model.as_deref().unwrap_or("unknown"),
+                            device.id.fmt_short()
+                        ),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                ])
+            })
+            .collect()
+    };
+
+    let list = Paragraph::new(devices)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Blue))
+                .title(" Devices "),
+        )
+        .alignment(if app.nearby_devices.is_empty() {
+            Alignment::Center
+        } else {
+            Alignment::Left
+        });
+
+    f.render_widget(list, chunks[1]);
+
+    // The share that is offered, and the outcome of the last offer
+    let share = match app.offered_transfer() {
+        Some(transfer) => Line::from(vec![
+            Span::styled("Share to offer: ", Style::default().fg(Color::Cyan)),
+            Span::raw(transfer.path.clone()),
+        ]),
+        None => Line::from(Span::styled(
+            "Send something in the Send tab to offer it to a device.",
+            Style::default().fg(Color::DarkGray),
+        )),
+    };
+    let info = Paragraph::new(vec![
+        share,
+        Line::from(Span::styled(
+            app.nearby_message.clone(),
+            Style::default().fg(Color::Yellow),
+        )),
+    ])
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::DarkGray))
+            .title(" Info "),
+    )
+    .wrap(Wrap { trim: true });
+
+    f.render_widget(info, chunks[2]);
+}
+
+/// Render an offer from another device over the current tab, asking whether
+/// to receive it.
+pub fn render_offer_prompt(f: &mut Frame, offer: &Offer, area: Rect) {
+    f.render_widget(Clear, area);
+
+    let what = match offer.encrypted {
+        true => "a passphrase protected share".to_string(),
+        false => format!("{} files", offer.files.len()),
+    };
+    let mut lines = vec![
+        Line::from(Span::styled(
+            format!(
+                "{} offers {}, {}",
+                offer.from.name,
+                what,
+                HumanBytes(offer.total_size)
+            ),
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+    ];
+    if let Some(message) = &offer.message {
+        lines.push(Line::from(Span::styled(
+            format!("\"{}\"", message),
+            Style::default().fg(Color::White),
+        )));
+        lines.push(Line::from(""));
+    }
+    for file in offer.files.iter().take(OFFER_LIST_LEN) {
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!("{:>10}  ", HumanBytes(file.size).to_string()),
+                Style::default().fg(Color::Yellow),
+            ),
+            Span::raw(file.name.clone()),
+        ]));
+    }
+    if offer.files.len() > OFFER_LIST_LEN {
+        lines.push(Line::from(Span::styled(
+            format!("and {} more", offer.files.len() - OFFER_LIST_LEN),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::styled("[y/Enter]", Style::default().fg(Color::Green)),
+        Span::raw(" Receive   "),
+        Span::styled("[n/Esc]", Style::default().fg(Color::Red)),
+        Span::raw(" Decline"),
+    ]));
+
+    let prompt = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan))
+                .title(" Incoming Share "),
+        )
+        .wrap(Wrap { trim: false })
+        .alignment(Alignment::Center);
+
+    f.render_widget(prompt, area);
+}
@@ -8,10 +8,18 @@ use ratatui::{
     Frame,
 };
 
-use crate::tui::App;
+use indicatif::HumanBytes;
+use sendme_lib::types::InspectResult;
+
+use crate::tui::{app::ReceiveTabState, App};
 
 /// Render the receive tab.
 pub fn render_receive_tab(f: &mut Frame, app: &App, area: Rect) {
+    if let ReceiveTabState::Confirm { listing, .. } = &app.receive_tab_state {
+        render_confirm(f, listing, area);
+        return;
+    }
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
@@ -34,7 +42,7 @@ pub fn render_receive_tab(f: &mut Frame, app: &App, area: Rect) {
                 .add_modifier(Modifier::BOLD),
         )),
         Line::from(vec![Span::styled(
-            "Enter a ticket to receive files from a sender.",
+            "Enter a ticket or short code to receive files from a sender.",
             Style::default().fg(Color::Gray),
         )]),
     ])
@@ -46,7 +54,7 @@ pub fn render_receive_tab(f: &mut Frame, app: &App, area: Rect) {
     let input_style = Style::default().fg(Color::White).bg(Color::DarkGray);
     let input_text = if app.receive_input_ticket.is_empty() {
         vec![Line::from(Span::styled(
-            "Paste ticket here... (e.g., sendme receive <ticket>)",
+            "Paste ticket or code here... (e.g., sendme receive <ticket>)",
             Style::default().fg(Color::DarkGray),
         ))]
     } else {
@@ -78,15 +86,16 @@ pub fn render_receive_tab(f: &mut Frame, app: &App, area: Rect) {
                     .add_modifier(Modifier::BOLD),
             )]),
             Line::from(""),
-            Line::from("  1. Paste or type the ticket from the sender"),
-            Line::from("  2. Press [Enter] to start receiving"),
-            Line::from("  3. Files will be downloaded to the current directory"),
+            Line::from("  1. Paste the ticket or type the short code from the sender"),
+            Line::from("  2. Press [Enter] to see the files in the ticket"),
+            Line::from("  3. Press [y] to download them to the current directory"),
             Line::from(""),
             Line::from(vec![Span::styled(
                 "Ticket format:",
                 Style::default().fg(Color::Yellow),
             )]),
             Line::from("  sendme receive <base32-encoded-ticket>"),
+            Line::from("  sendme receive 482913-canyon-violin"),
             Line::from(""),
             Line::from("The ticket contains connection information"),
             Line::from("and the hash of the files to receive."),
@@ -114,7 +123,7 @@ pub fn render_receive_tab(f: &mut Frame, app: &App, area: Rect) {
                     Style::default().fg(Color::Yellow),
                 )])
             } else {
-                Line::from("Press [Enter] to start receiving")
+                Line::from("Press [Enter] to see the files")
             },
         ]
     };
@@ -131,3 +140,83 @@ pub fn render_receive_tab(f: &mut Frame, app: &App, area: Rect) {
 
     f.render_widget(help, chunks[2]);
 }
+
+/// Render the file listing of a ticket and ask for confirmation.
+fn render_confirm(f: &mut Frame, listing: &InspectResult, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(3),
+            ]
+            .as_ref(),
+        )
+        .margin(1)
+        .split(area);
+
+    let title = Paragraph::new(vec![
+        Line::from(Span::styled(
+            "Receive Files?",
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )),
+        Line::from(vec![Span::styled(
+            format!(
+                "{} files, {} from {} via {}",
+                listing.files.len(),
+                HumanBytes(listing.total_size),
+                listing.endpoint_id.fmt_short(),
+                listing.connection_type
+            ),
+            Style::default().fg(Color::Gray),
+        )]),
+    ])
+    .alignment(Alignment::Center);
+
+    f.render_widget(title, chunks[0]);
+
+    let files: Vec<Line> = listing
+        .files
+        .iter()
+        .map(|file| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{:>10}  ", HumanBytes(file.size).to_string()),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled(
+                    format!("{}  ", file.hash.fmt_short()),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(file.name.clone(), Style::default().fg(Color::White)),
+            ])
+        })
+        .collect();
+
+    let list = Paragraph::new(files).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Blue))
+            .title(" Files "),
+    );
+
+    f.render_widget(list, chunks[1]);
+
+    let confirm = Paragraph::new(Line::from(vec![
+        Span::styled("[y/Enter]", Style::default().fg(Color::Green)),
+        Span::raw(" Receive   "),
+        Span::styled("[n/Esc]", Style::default().fg(Color::Red)),
+        Span::raw(" Cancel"),
+    ]))
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::DarkGray)),
+    )
+    .alignment(Alignment::Center);
+
+    f.render_widget(confirm, chunks[2]);
+}
@@ -110,6 +110,23 @@ fn render_transfers_list(f: &mut Frame, app: &App, area: Rect) {
                     transfer.path.clone()
                 };
 
+                // Limited shares show their remaining quota instead of the peer hint,
+                // downloads and exports their speed
+                let status = match (transfer.quota_summary(), transfer.rate_summary()) {
+                    (Some(quota), _) if transfer.status.is_active() => {
+                        format!("Serving: {}", quota)
+                    }
+                    (_, Some(rate))
+                        if matches!(
+                            transfer.status,
+                            TransferStatus::Downloading | TransferStatus::Exporting
+                        ) =>
+                    {
+                        format!("{} {}", transfer.status, rate)
+                    }
+                    _ => format!("{}", transfer.status),
+                };
+
                 let row_style = if is_selected {
                     Style::default()
                         .fg(Color::White)
@@ -122,7 +139,7 @@ fn render_transfers_list(f: &mut Frame, app: &App, area: Rect) {
                 Row::new(vec![
                     Cell::from(transfer.transfer_type.name()).style(type_style),
                     Cell::from(path),
-                    Cell::from(format!("{}", transfer.status)).style(status_style),
+                    Cell::from(status).style(status_style),
                     Cell::from(format!("{}%", transfer.progress))
                         .style(Style::default().fg(progress_color)),
                     Cell::from(format_bytes(transfer.total_bytes)),
@@ -246,6 +263,40 @@ fn render_transfer_detail(f: &mut Frame, app: &App, area: Rect, transfer_id: &st
         )]),
     ];
 
+    // Show the share quota (for limited send transfers)
+    if let Some(quota) = transfer.quota_summary() {
+        all_lines.push(Line::from(vec![Span::styled(
+            format!("Downloads: {} ({})", transfer.downloads, quota),
+            Style::default().fg(Color::Yellow),
+        )]));
+    }
+
+    // Show the network path, relayed paths are usually the slow ones
+    if let Some(path) = &transfer.network_path {
+        let color = if path.direct {
+            Color::Green
+        } else {
+            Color::Yellow
+        };
+        all_lines.push(Line::from(vec![Span::styled(
+            format!("Path: {}", path.summary()),
+            Style::default().fg(color),
+        )]));
+    }
+
+    // Show the speed, with the peak and the time taken so far
+    if let (Some(rate), Some(summary)) = (&transfer.rate, transfer.rate_summary()) {
+        all_lines.push(Line::from(vec![Span::styled(
+            format!(
+                "Speed: {}, peak {}/s, {}s elapsed",
+                summary,
+                format_bytes(rate.peak_bytes_per_sec),
+                rate.elapsed.as_secs()
+            ),
+            Style::default().fg(Color::Yellow),
+        )]));
+    }
+
     // Show file names if available (for receive transfers)
     if !transfer.file_names.is_empty() {
         all_lines.push(Line::from(""));
@@ -10,8 +10,12 @@ use ratatui::{
 };
 
 use crate::tui::{
-    app::Tab, tabs::receive::render_receive_tab, tabs::send::render_send_tab,
-    tabs::transfers::render_transfers_tab, App,
+    app::Tab,
+    tabs::nearby::{render_nearby_tab, render_offer_prompt},
+    tabs::receive::render_receive_tab,
+    tabs::send::render_send_tab,
+    tabs::transfers::render_transfers_tab,
+    App,
 };
 
 /// Main UI rendering function.
@@ -38,6 +42,11 @@ pub fn render_ui<B: Backend>(terminal: &mut Terminal<B>, app: &App) -> Result<()
 
         // Render footer
         render_footer(f, app.current_tab, chunks[2]);
+
+        // Offers from other devices show over any tab
+        if let Some((_, offer)) = app.offers.front() {
+            render_offer_prompt(f, offer, centered_popup_area(f.area(), 60, 60));
+        }
     })?;
     Ok(())
 }
@@ -78,6 +87,7 @@ fn render_current_tab(f: &mut Frame, app: &App, area: Rect) {
         Tab::Send => render_send_tab(f, app, area),
         Tab::Receive => render_receive_tab(f, app, area),
         Tab::Transfers => render_transfers_tab(f, app, area),
+        Tab::Nearby => render_nearby_tab(f, app, area),
     }
 }
 
@@ -85,11 +95,16 @@ fn render_current_tab(f: &mut Frame, app: &App, area: Rect) {
 fn render_footer(f: &mut Frame, current_tab: Tab, area: Rect) {
     let help_text = match current_tab {
         Tab::Send => {
-            " [1-3] Switch Tab | [q] Quit | [Enter] Send | [ESC] Return | Type to enter path "
+            " [1-4] Switch Tab | [q] Quit | [Enter] Send | [ESC] Return | Type to enter path "
+        }
+        Tab::Receive => {
+            " [1-4] Switch Tab | [q] Quit | [Enter] Preview | [ESC] Return | Type to paste ticket "
         }
-        Tab::Receive => " [1-3] Switch Tab | [q] Quit | [Enter] Receive | Type to paste ticket ",
         Tab::Transfers => {
-            " [1-3] Switch Tab | [q] Quit | [Up/Down] Navigate | [Enter] View | [d] Delete | [c] Clean up "
+            " [1-4] Switch Tab | [q] Quit | [Up/Down] Navigate | [Enter] View | [s] Stop | [d] Delete | [c] Clean up "
+        }
+        Tab::Nearby => {
+            " [1-4] Switch Tab | [q] Quit | [Up/Down] Select device | [Enter] Offer the latest share "
         }
     };
 
@@ -128,7 +143,6 @@ pub fn render_popup<B: Backend>(
 }
 
 /// Calculate a centered popup area.
-#[allow(dead_code)]
 fn centered_popup_area(parent: Rect, percent_width: u16, percent_height: u16) -> Rect {
     let width = parent.width * percent_width / 100;
     let height = parent.height * percent_height / 100;
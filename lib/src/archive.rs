@@ -0,0 +1,364 @@
+//! Export of a collection into a single tar, tar.zst or zip archive.
+//!
+//! The archive is written on a blocking thread, since the tar, zstd and zip
+//! encoders are synchronous. The blobs are read from the store, so they have
+//! been verified against their blake3 hash while they were downloaded. Entries
+//! keep the names of the collection, and the file modes, modification times,
+//! empty directories and symlinks of its [metadata](crate::metadata).
+//! [Compressed](crate::compression) files are decompressed.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use iroh_blobs::{format::collection::Collection, store::fs::FsStore, Hash};
+use tokio::io::AsyncWrite;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    crypto::{EncryptedShare, ShareKey},
+    export::{
+        blob_size, free_path, open_blocking, try_load_metadata, write_blocking, ProgressReader,
+    },
+    metadata::{self, FileMetadata, MetadataBlob},
+    progress::{ExportProgress, ProgressEvent, ProgressSenderTx},
+    validate_collection_name, ArchiveFormat, ExportConflictPolicy,
+};
+
+/// A file to add to the archive.
+struct ArchiveFile {
+    name: String,
+    hash: Hash,
+    /// Size of the file.
+    size: u64,
+    /// Size of the blob, which is larger if it is encrypted and smaller if it
+    /// is compressed.
+    blob_size: u64,
+    metadata: FileMetadata,
+}
+
+/// Everything the blocking archive writer needs.
+struct Archive {
+    files: Vec<ArchiveFile>,
+    empty_dirs: Vec<String>,
+    symlinks: Vec<(String, String)>,
+    db: FsStore,
+    key: Option<ShareKey>,
+    handle: tokio::runtime::Handle,
+    progress_tx: Option<ProgressSenderTx>,
+    cancel: CancellationToken,
+    /// Modification time of entries that don't have one.
+    now: SystemTime,
+}
+
+/// Write the files of a collection into an archive of the given format.
+///
+/// Empty directories and symlinks are only added if the collection has all of
+/// the files listed in its metadata, like for [`export`](crate::export::export).
+#[allow(clippy::too_many_arguments)]
+pub async fn export_archive(
+    db: &FsStore,
+    files: &Collection,
+    metadata: Option<MetadataBlob>,
+    format: ArchiveFormat,
+    writer: &mut (impl AsyncWrite + Unpin + ?Sized),
+    decryption: Option<&EncryptedShare>,
+    progress_tx: Option<ProgressSenderTx>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    for (name, _) in files.iter() {
+        validate_collection_name(name)?;
+    }
+    let metadata = try_load_metadata(db, metadata, decryption)
+        .await
+        .unwrap_or_default();
+    let complete = metadata.is_complete(files);
+    let symlinks = match complete {
+        true => metadata.symlinks.clone().into_iter().collect(),
+        false => Vec::new(),
+    };
+    for (name, target) in &symlinks {
+        validate_collection_name(name)?;
+        metadata::check_link_target(name, target)?;
+    }
+
+    let mut entries = Vec::new();
+    for (name, hash) in files.iter() {
+        let stored = blob_size(db, *hash)
+            .await?
+            .with_context(|| format!("{name} was not downloaded"))?;
+        let file = metadata.files.get(name).cloned().unwrap_or_default();
+        let size = match (decryption, &file.compression) {
+            (Some(share), _) => share
+                .entry(hash)
+                .map(|entry| entry.size)
+                .with_context(|| format!("{name} is not in the manifest"))?,
+            (None, Some(compression)) => compression.size,
+            (None, None) => stored,
+        };
+        entries.push(ArchiveFile {
+            name: name.clone(),
+            hash: *hash,
+            size,
+            blob_size: stored,
+            metadata: file,
+        });
+    }
+
+    if let Some(ref tx) = progress_tx {
+        let _ = tx
+            .send(ProgressEvent::Export(
+                "".to_string(),
+                ExportProgress::Started {
+                    total_files: entries.len(),
+                },
+            ))
+            .await;
+    }
+
+    let archive = Archive {
+        files: entries,
+        empty_dirs: match complete {
+            true => metadata.empty_dirs,
+            false => Vec::new(),
+        },
+        symlinks,
+        db: db.clone(),
+        key: decryption.map(|share| share.key().clone()),
+        handle: tokio::runtime::Handle::current(),
+        progress_tx: progress_tx.clone(),
+        cancel: cancel.clone(),
+        now: SystemTime::now(),
+    };
+    write_blocking(writer, move |out| archive.write(format, out)).await?;
+
+    if let Some(ref tx) = progress_tx {
+        let _ = tx
+            .send(ProgressEvent::Export(
+                "".to_string(),
+                ExportProgress::Completed,
+            ))
+            .await;
+    }
+    Ok(())
+}
+
+/// The name of the archive a collection is saved as, e.g. `photos.tar.zst`.
+///
+/// Collections with a single top level file or directory are named after it,
+/// others after their hash.
+pub fn archive_name(collection: &Collection, hash: &Hash, format: ArchiveFormat) -> String {
+    let mut roots = collection
+        .iter()
+        .filter_map(|(name, _)| name.split('/').next());
+    let stem = match roots.next() {
+        Some(first) if roots.all(|root| root == first) => first.to_string(),
+        _ => format!("sendme-{}", &hash.to_hex()[..8]),
+    };
+    format!("{stem}.{}", format.extension())
+}
+
+impl Archive {
+    fn write(self, format: ArchiveFormat, out: impl Write) -> std::io::Result<()> {
+        match format {
+            ArchiveFormat::Tar => self.write_tar(out)?.flush(),
+            ArchiveFormat::TarZst => {
+                let out = zstd::Encoder::new(out, 0)?;
+                self.write_tar(out)?.finish()?.flush()
+            }
+            ArchiveFormat::Zip => self.write_zip(out),
+        }
+    }
+
+    fn write_tar<W: Write>(&self, out: W) -> std::io::Result<W> {
+        let mut tar = tar::Builder::new(out);
+        for dir in &self.empty_dirs {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_mode(0o755);
+            header.set_mtime(unix_time(self.now));
+            header.set_size(0);
+            tar.append_data(&mut header, format!("{dir}/"), std::io::empty())?;
+        }
+        for file in &self.files {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_mode(file.metadata.mode.unwrap_or(0o644) & 0o777);
+            header.set_mtime(unix_time(file.metadata.mtime.unwrap_or(self.now)));
+            header.set_size(file.size);
+            let reader = self.open(file)?;
+            tar.append_data(&mut header, &file.name, reader)?;
+            self.completed(&file.name);
+        }
+        for (name, target) in &self.symlinks {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_mode(0o777);
+            header.set_mtime(unix_time(self.now));
+            header.set_size(0);
+            tar.append_link(&mut header, name, target)?;
+        }
+        tar.into_inner()
+    }
+
+    fn write_zip(&self, out: impl Write) -> std::io::Result<()> {
+        let mut zip = zip::ZipWriter::new_stream(out);
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .last_modified_time(zip_time(self.now));
+        for dir in &self.empty_dirs {
+            zip.add_directory(dir, options.unix_permissions(0o755))?;
+        }
+        for file in &self.files {
+            let options = options
+                .unix_permissions(file.metadata.mode.unwrap_or(0o644) & 0o777)
+                .last_modified_time(zip_time(file.metadata.mtime.unwrap_or(self.now)))
+                .large_file(file.size >= u32::MAX as u64);
+            zip.start_file(&file.name, options)?;
+            std::io::copy(&mut self.open(file)?, &mut zip)?;
+            self.completed(&file.name);
+        }
+        for (name, target) in &self.symlinks {
+            zip.add_symlink(name, target, options)?;
+        }
+        zip.finish()?.flush()
+    }
+
+    /// Open the content of a file for reading, reporting the progress.
+    fn open(&self, file: &ArchiveFile) -> std::io::Result<ProgressReader> {
+        self.send(ExportProgress::FileStarted {
+            name: file.name.clone(),
+            size: file.size,
+        });
+        let inner = open_blocking(
+            &self.db,
+            file.hash,
+            file.blob_size,
+            self.key.as_ref(),
+            file.metadata.compression.as_ref(),
+            &self.handle,
+        )?;
+        Ok(ProgressReader::new(
+            inner,
+            file.name.clone(),
+            file.size,
+            self.progress_tx.clone(),
+            self.cancel.clone(),
+        ))
+    }
+
+    fn completed(&self, name: &str) {
+        self.send(ExportProgress::FileCompleted {
+            name: name.to_string(),
+        });
+    }
+
+    fn send(&self, progress: ExportProgress) {
+        if let Some(ref tx) = self.progress_tx {
+            let name = match &progress {
+                ExportProgress::FileStarted { name, .. }
+                | ExportProgress::FileCompleted { name } => name.clone(),
+                _ => String::new(),
+            };
+            let _ = tx.blocking_send(ProgressEvent::Export(name, progress));
+        }
+    }
+}
+
+/// Where to save the archive `name` in `dir`, if at all.
+///
+/// An existing file is handled by the conflict policy. Whether it is identical
+/// can't be told without writing the archive, so `SkipIfIdentical` keeps it
+/// like `Skip`.
+pub(crate) fn archive_path(
+    dir: &Path,
+    name: &str,
+    policy: ExportConflictPolicy,
+) -> anyhow::Result<Option<PathBuf>> {
+    let path = dir.join(name);
+    if !path.exists() {
+        return Ok(Some(path));
+    }
+    match policy {
+        ExportConflictPolicy::Overwrite => Ok(Some(path)),
+        ExportConflictPolicy::Rename => Ok(Some(free_path(&path))),
+        ExportConflictPolicy::Skip | ExportConflictPolicy::SkipIfIdentical => Ok(None),
+        ExportConflictPolicy::Fail => anyhow::bail!("archive {} already exists", path.display()),
+    }
+}
+
+/// Write the archive of a collection to `path`, removing it again on failure.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn export_archive_file(
+    db: &FsStore,
+    files: &Collection,
+    metadata: Option<MetadataBlob>,
+    format: ArchiveFormat,
+    path: &Path,
+    decryption: Option<&EncryptedShare>,
+    progress_tx: Option<ProgressSenderTx>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let mut file = tokio::fs::File::create(path)
+        .await
+        .with_context(|| format!("failed to create {}", path.display()))?;
+    let res = export_archive(
+        db,
+        files,
+        metadata,
+        format,
+        &mut file,
+        decryption,
+        progress_tx,
+        cancel,
+    )
+    .await;
+    let res = match res {
+        Ok(()) => file.sync_all().await.map_err(Into::into),
+        Err(e) => Err(e),
+    };
+    if res.is_err() {
+        // Don't leave a truncated archive behind that looks complete
+        drop(file);
+        let _ = tokio::fs::remove_file(path).await;
+    }
+    res
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Convert a time to the DOS date and time of zip entries, in UTC.
+///
+/// Times outside of the range zip can represent, 1980 to 2107, are clamped.
+fn zip_time(time: SystemTime) -> zip::DateTime {
+    let secs = unix_time(time);
+    let (days, secs) = ((secs / 86400) as i64, secs % 86400);
+    // Civil date from days since 1970-01-01, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    if year < 1980 {
+        return zip::DateTime::default();
+    }
+    let (hour, minute, second) = (
+        (secs / 3600) as u8,
+        (secs / 60 % 60) as u8,
+        (secs % 60) as u8,
+    );
+    zip::DateTime::from_date_and_time(year.min(2107) as u16, month, day, hour, minute, second)
+        .unwrap_or_default()
+}
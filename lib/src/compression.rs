@@ -0,0 +1,231 @@
+//! Transparent compression of files on the wire.
+//!
+//! With [`ImportConfig::compress`], files are stored as zstd frames unless
+//! their first bytes show that they are compressed already, or compressing
+//! them doesn't make them smaller. The codec and the size and hash of the
+//! original file are recorded as the [`Compression`] of the file in the
+//! collection [metadata](crate::metadata), and receivers decompress the file
+//! when they export it. The transfer verifies the compressed blob, and the
+//! decompressed file must have exactly the recorded size.
+//!
+//! In an encrypted share, files are compressed before they are encrypted, and
+//! the manifest lists the size and hash of the original file. Receivers that
+//! don't know about compression export the compressed data as it is.
+//!
+//! [`ImportConfig::compress`]: crate::import::ImportConfig::compress
+
+use std::{io::Read, path::PathBuf};
+
+use bytes::Bytes;
+use iroh_blobs::Hash;
+use n0_future::Stream;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{self, ShareKey, CHUNK_SIZE};
+
+/// A compression format for files on the wire.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Codec {
+    /// A single zstd frame.
+    Zstd,
+}
+
+/// How a file is compressed, see [`FileMetadata::compression`].
+///
+/// [`FileMetadata::compression`]: crate::metadata::FileMetadata::compression
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Compression {
+    /// The format of the blob.
+    pub codec: Codec,
+    /// Size of the original file in bytes.
+    pub size: u64,
+    /// Hash of the original file.
+    pub hash: Hash,
+}
+
+/// Magic bytes of formats that are compressed already, by offset.
+const COMPRESSED_FORMATS: &[(usize, &[u8])] = &[
+    // gzip, zstd, xz, bzip2, lz4, 7z and rar
+    (0, b"\x1f\x8b"),
+    (0, b"\x28\xb5\x2f\xfd"),
+    (0, b"\xfd7zXZ\x00"),
+    (0, b"BZh"),
+    (0, b"\x04\x22\x4d\x18"),
+    (0, b"7z\xbc\xaf\x27\x1c"),
+    (0, b"Rar!\x1a\x07"),
+    // zip, and with it jar, apk, docx and friends
+    (0, b"PK\x03\x04"),
+    (0, b"PK\x05\x06"),
+    // png, jpeg, gif and webp
+    (0, b"\x89PNG"),
+    (0, b"\xff\xd8\xff"),
+    (0, b"GIF8"),
+    (8, b"WEBP"),
+    // mp4, mov and heic, matroska and webm, ogg, flac and mp3
+    (4, b"ftyp"),
+    (0, b"\x1a\x45\xdf\xa3"),
+    (0, b"OggS"),
+    (0, b"fLaC"),
+    (0, b"ID3"),
+];
+
+/// Number of bytes [`is_compressed`] looks at.
+pub const SNIFF_LEN: usize = 16;
+
+/// Check whether data starting with `header` is compressed already, judging
+/// by the magic bytes of common formats.
+pub fn is_compressed(header: &[u8]) -> bool {
+    COMPRESSED_FORMATS
+        .iter()
+        .any(|(offset, magic)| header.get(*offset..).is_some_and(|h| h.starts_with(magic)))
+}
+
+/// Read the first [`SNIFF_LEN`] bytes of a file, for [`is_compressed`].
+pub async fn sniff(path: &std::path::Path) -> std::io::Result<Vec<u8>> {
+    use tokio::io::AsyncReadExt;
+
+    let file = tokio::fs::File::open(path).await?;
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    file.take(SNIFF_LEN as u64).read_to_end(&mut header).await?;
+    Ok(header)
+}
+
+/// The result of compressing a file with [`compress_file`].
+#[derive(Debug, Clone, Copy)]
+pub struct Compressed {
+    /// Hash of the original file.
+    pub hash: Hash,
+    /// Size of the original file.
+    pub size: u64,
+    /// Size of the compressed data, before it is encrypted.
+    pub compressed_size: u64,
+}
+
+/// Compress a file with zstd into a stream of chunks, for adding to a store,
+/// and encrypt it with `key` if given.
+///
+/// The returned task resolves to the hash and size of the original file and
+/// the size of the compressed data once the stream has ended.
+pub fn compress_file(
+    path: PathBuf,
+    key: Option<&ShareKey>,
+) -> (
+    impl Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static,
+    tokio::task::JoinHandle<std::io::Result<Compressed>>,
+) {
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    let key = key.cloned();
+    let task = tokio::task::spawn_blocking(move || {
+        let res = compress_blocking(path, key.as_ref(), &tx);
+        if let Err(e) = &res {
+            let _ = tx.blocking_send(Err(std::io::Error::new(e.kind(), e.to_string())));
+        }
+        res
+    });
+    let stream = n0_future::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|item| (item, rx))
+    });
+    (stream, task)
+}
+
+fn compress_blocking(
+    path: PathBuf,
+    key: Option<&ShareKey>,
+    tx: &tokio::sync::mpsc::Sender<std::io::Result<Bytes>>,
+) -> std::io::Result<Compressed> {
+    let mut source = HashingReader {
+        inner: std::fs::File::open(path)?,
+        hasher: blake3::Hasher::new(),
+        size: 0,
+    };
+    let mut encoder =
+        zstd::stream::read::Encoder::new(&mut source, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+    let compressed_size = match key {
+        Some(key) => crypto::encrypt_blocking(key, &mut encoder, tx)?.1,
+        None => {
+            let mut size = 0u64;
+            loop {
+                let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+                (&mut encoder)
+                    .take(CHUNK_SIZE as u64)
+                    .read_to_end(&mut chunk)?;
+                if chunk.is_empty() {
+                    break size;
+                }
+                size += chunk.len() as u64;
+                tx.blocking_send(Ok(chunk.into()))
+                    .map_err(|_| std::io::Error::other("import was cancelled"))?;
+            }
+        }
+    };
+    drop(encoder);
+    Ok(Compressed {
+        hash: source.hasher.finalize().into(),
+        size: source.size,
+        compressed_size,
+    })
+}
+
+/// Hashes and counts everything read through it.
+struct HashingReader<R> {
+    inner: R,
+    hasher: blake3::Hasher,
+    size: u64,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+}
+
+/// Decompress what `reader` yields, which must be exactly `compression.size`
+/// bytes once decompressed.
+pub fn decompress<'a>(
+    reader: impl Read + Send + 'a,
+    compression: &Compression,
+) -> std::io::Result<Box<dyn Read + Send + 'a>> {
+    let decoder = match compression.codec {
+        Codec::Zstd => zstd::stream::read::Decoder::new(reader)?,
+    };
+    Ok(Box::new(ExactSize {
+        inner: decoder,
+        remaining: compression.size,
+    }))
+}
+
+/// Fails if the inner reader yields more or less than `remaining` bytes, so a
+/// malicious sender can't make the receiver write unbounded data.
+struct ExactSize<R> {
+    inner: R,
+    remaining: u64,
+}
+
+impl<R: Read> Read for ExactSize<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.remaining == 0 {
+            return match self.inner.read(&mut [0u8])? {
+                0 => Ok(0),
+                _ => Err(std::io::Error::other(
+                    "decompressed file is larger than expected",
+                )),
+            };
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf
+            .len()
+            .min(self.remaining.try_into().unwrap_or(usize::MAX));
+        let n = self.inner.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
@@ -0,0 +1,416 @@
+//! Passphrase encryption of shares.
+//!
+//! An encrypted share is an ordinary collection. Its first entry is the
+//! [`MANIFEST_NAME`] blob, the other entries are the encrypted files, named by
+//! their position. The manifest holds the Argon2 parameters and salt in the
+//! clear, followed by the names, sizes and hashes of the files, sealed with
+//! XChaCha20-Poly1305. A leaked ticket therefore reveals the number and rough
+//! size of the files, but neither their names nor their contents. The
+//! [metadata](crate::metadata) blob is encrypted like the files, but not listed
+//! in the manifest.
+//!
+//! Files are encrypted in chunks of [`CHUNK_SIZE`] bytes with the STREAM
+//! construction, so they never have to be held in memory as a whole.
+
+use std::{collections::BTreeMap, io::Read, path::PathBuf};
+
+use anyhow::Context;
+use argon2::{Algorithm, Argon2, Params, Version};
+use bytes::Bytes;
+use chacha20poly1305::{
+    aead::{
+        stream::{DecryptorBE32, EncryptorBE32},
+        Aead, KeyInit, Payload,
+    },
+    Key, XChaCha20Poly1305, XNonce,
+};
+use iroh_blobs::{format::collection::Collection, Hash};
+use n0_future::Stream;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Name of the manifest entry in an encrypted collection.
+pub const MANIFEST_NAME: &str = ".sendme-encrypted";
+
+/// Plaintext bytes per encrypted chunk.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Marks the manifest format.
+const MAGIC: &[u8; 8] = b"sendme\x00\x01";
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + 3 * 4 + SALT_LEN;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+/// The STREAM construction uses 5 bytes of the nonce for its counter.
+const NONCE_PREFIX_LEN: usize = NONCE_LEN - 5;
+
+/// Upper bounds for the Argon2 parameters accepted from a manifest, so a
+/// malicious sender can't make the receiver allocate unbounded memory.
+const MAX_M_COST: u32 = 1024 * 1024;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+/// A key derived from a share passphrase.
+#[derive(Clone)]
+pub struct ShareKey {
+    key: Key,
+    params: Params,
+    salt: [u8; SALT_LEN],
+}
+
+impl std::fmt::Debug for ShareKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShareKey").finish_non_exhaustive()
+    }
+}
+
+impl ShareKey {
+    /// Derive the key for a new share from a passphrase and a random salt.
+    ///
+    /// This is deliberately slow, call it from a blocking task.
+    pub fn generate(passphrase: &str) -> anyhow::Result<Self> {
+        Self::derive(passphrase, Params::default(), rand::random())
+    }
+
+    fn derive(passphrase: &str, params: Params, salt: [u8; SALT_LEN]) -> anyhow::Result<Self> {
+        anyhow::ensure!(!passphrase.is_empty(), "passphrase must not be empty");
+        let mut key = Key::default();
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| anyhow::anyhow!("failed to derive key: {e}"))?;
+        Ok(Self { key, params, salt })
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.key)
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&self.params.m_cost().to_le_bytes());
+        header.extend_from_slice(&self.params.t_cost().to_le_bytes());
+        header.extend_from_slice(&self.params.p_cost().to_le_bytes());
+        header.extend_from_slice(&self.salt);
+        header
+    }
+}
+
+/// A file of an encrypted share, as listed in its manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Name of the file within the collection.
+    pub name: String,
+    /// Hash of the decrypted content.
+    pub hash: Hash,
+    /// Size of the decrypted content in bytes.
+    pub size: u64,
+}
+
+/// Seal the list of files into a manifest blob.
+pub fn seal_manifest(key: &ShareKey, files: &[ManifestEntry]) -> anyhow::Result<Vec<u8>> {
+    let header = key.header();
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let plaintext = serde_json::to_vec(files)?;
+    let ciphertext = key
+        .cipher()
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &plaintext,
+                aad: &header,
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("failed to encrypt the manifest"))?;
+    let mut manifest = header;
+    manifest.extend_from_slice(&nonce);
+    manifest.extend_from_slice(&ciphertext);
+    Ok(manifest)
+}
+
+/// Open a manifest blob with a passphrase.
+///
+/// This is deliberately slow, call it from a blocking task.
+pub fn open_manifest(
+    passphrase: &str,
+    manifest: &[u8],
+) -> anyhow::Result<(ShareKey, Vec<ManifestEntry>)> {
+    anyhow::ensure!(
+        manifest.len() >= HEADER_LEN + NONCE_LEN + TAG_LEN && manifest.starts_with(MAGIC),
+        "unsupported encrypted share"
+    );
+    let (header, rest) = manifest.split_at(HEADER_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let read_u32 = |offset: usize| {
+        let bytes = header[offset..offset + 4].try_into().expect("4 bytes");
+        u32::from_le_bytes(bytes)
+    };
+    let (m_cost, t_cost, p_cost) = (
+        read_u32(MAGIC.len()),
+        read_u32(MAGIC.len() + 4),
+        read_u32(MAGIC.len() + 8),
+    );
+    anyhow::ensure!(
+        m_cost <= MAX_M_COST && t_cost <= MAX_T_COST && p_cost <= MAX_P_COST,
+        "the key derivation parameters of this share are too expensive"
+    );
+    let params = Params::new(m_cost, t_cost, p_cost, None)
+        .map_err(|e| anyhow::anyhow!("invalid key derivation parameters: {e}"))?;
+    let salt = header[HEADER_LEN - SALT_LEN..].try_into().expect("salt");
+    let key = ShareKey::derive(passphrase, params, salt)?;
+
+    let plaintext = key
+        .cipher()
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("wrong passphrase"))?;
+    let files = serde_json::from_slice(&plaintext).context("invalid manifest")?;
+    Ok((key, files))
+}
+
+/// Check whether a collection is an encrypted share.
+pub fn is_encrypted(collection: &Collection) -> bool {
+    collection
+        .iter()
+        .next()
+        .is_some_and(|(name, _)| name == MANIFEST_NAME)
+}
+
+/// Build the collection of an encrypted share from the manifest and the
+/// encrypted files, in manifest order.
+pub fn encrypted_collection(manifest: Hash, files: impl IntoIterator<Item = Hash>) -> Collection {
+    std::iter::once((MANIFEST_NAME.to_string(), manifest))
+        .chain(
+            files
+                .into_iter()
+                .enumerate()
+                .map(|(index, hash)| (index.to_string(), hash)),
+        )
+        .collect()
+}
+
+/// An encrypted share, opened with its passphrase.
+#[derive(Debug, Clone)]
+pub struct EncryptedShare {
+    key: ShareKey,
+    /// Manifest entries by the hash of the encrypted blob.
+    files: BTreeMap<Hash, ManifestEntry>,
+    /// The files with their real names and the hashes of the encrypted blobs.
+    view: Collection,
+}
+
+impl EncryptedShare {
+    /// Open an encrypted collection, given the content of its manifest blob.
+    pub async fn open(
+        passphrase: &str,
+        collection: &Collection,
+        manifest: Bytes,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(is_encrypted(collection), "the share is not encrypted");
+        let passphrase = passphrase.to_string();
+        let (key, entries) =
+            tokio::task::spawn_blocking(move || open_manifest(&passphrase, &manifest)).await??;
+        anyhow::ensure!(
+            entries.len() + 1 == collection.len(),
+            "the manifest does not match the collection"
+        );
+        let mut files = BTreeMap::new();
+        let mut view = Vec::with_capacity(entries.len());
+        for (index, ((name, hash), entry)) in collection.iter().skip(1).zip(entries).enumerate() {
+            anyhow::ensure!(
+                *name == index.to_string(),
+                "the manifest does not match the collection"
+            );
+            view.push((entry.name.clone(), *hash));
+            files.insert(*hash, entry);
+        }
+        Ok(Self {
+            key,
+            files,
+            view: view.into_iter().collect(),
+        })
+    }
+
+    /// The files of the share with their real names. The hashes are those of
+    /// the encrypted blobs.
+    pub fn view(&self) -> &Collection {
+        &self.view
+    }
+
+    /// The manifest entry of an encrypted blob.
+    pub fn entry(&self, blob: &Hash) -> Option<&ManifestEntry> {
+        self.files.get(blob)
+    }
+
+    /// The key of the share.
+    pub fn key(&self) -> &ShareKey {
+        &self.key
+    }
+}
+
+/// Encrypt a file into a stream of chunks, for adding to a store.
+///
+/// The returned task resolves to the hash and size of the plaintext once the
+/// stream has ended.
+pub fn encrypt_file(
+    key: &ShareKey,
+    path: PathBuf,
+) -> (
+    impl Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static,
+    tokio::task::JoinHandle<std::io::Result<(Hash, u64)>>,
+) {
+    encrypt_with(key, move || std::fs::File::open(path))
+}
+
+/// Encrypt a buffer into a stream of chunks, like [`encrypt_file`].
+pub fn encrypt_bytes(
+    key: &ShareKey,
+    data: Vec<u8>,
+) -> (
+    impl Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static,
+    tokio::task::JoinHandle<std::io::Result<(Hash, u64)>>,
+) {
+    encrypt_with(key, move || Ok(std::io::Cursor::new(data)))
+}
+
+/// Encrypt everything `reader` yields into a stream of chunks, like
+/// [`encrypt_file`].
+pub fn encrypt_reader(
+    key: &ShareKey,
+    reader: impl AsyncRead + Send + Unpin + 'static,
+) -> (
+    impl Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static,
+    tokio::task::JoinHandle<std::io::Result<(Hash, u64)>>,
+) {
+    let reader = tokio_util::io::SyncIoBridge::new(reader);
+    encrypt_with(key, move || Ok(reader))
+}
+
+fn encrypt_with<R: Read>(
+    key: &ShareKey,
+    open: impl FnOnce() -> std::io::Result<R> + Send + 'static,
+) -> (
+    impl Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static,
+    tokio::task::JoinHandle<std::io::Result<(Hash, u64)>>,
+) {
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    let key = key.clone();
+    let task = tokio::task::spawn_blocking(move || {
+        let res = open().and_then(|reader| encrypt_blocking(&key, reader, &tx));
+        if let Err(e) = &res {
+            let _ = tx.blocking_send(Err(std::io::Error::new(e.kind(), e.to_string())));
+        }
+        res
+    });
+    let stream = n0_future::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|item| (item, rx))
+    });
+    (stream, task)
+}
+
+pub(crate) fn encrypt_blocking(
+    key: &ShareKey,
+    mut reader: impl Read,
+    tx: &tokio::sync::mpsc::Sender<std::io::Result<Bytes>>,
+) -> std::io::Result<(Hash, u64)> {
+    let send = |data: Vec<u8>| {
+        tx.blocking_send(Ok(data.into()))
+            .map_err(|_| std::io::Error::other("import was cancelled"))
+    };
+    let seal_error = |_| std::io::Error::other("failed to encrypt");
+
+    let prefix: [u8; NONCE_PREFIX_LEN] = rand::random();
+    let mut encryptor = EncryptorBE32::from_aead(key.cipher(), prefix.as_ref().into());
+    send(prefix.to_vec())?;
+
+    let mut hasher = blake3::Hasher::new();
+    let mut size = 0u64;
+    let mut current = read_chunk(&mut reader)?;
+    loop {
+        hasher.update(&current);
+        size += current.len() as u64;
+        // A full chunk is only the last one if nothing follows it
+        let next = if current.len() == CHUNK_SIZE {
+            read_chunk(&mut reader)?
+        } else {
+            Vec::new()
+        };
+        if next.is_empty() {
+            send(
+                encryptor
+                    .encrypt_last(current.as_slice())
+                    .map_err(seal_error)?,
+            )?;
+            break;
+        }
+        send(
+            encryptor
+                .encrypt_next(current.as_slice())
+                .map_err(seal_error)?,
+        )?;
+        current = next;
+    }
+    Ok((hasher.finalize().into(), size))
+}
+
+fn read_chunk(reader: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    reader.take(CHUNK_SIZE as u64).read_to_end(&mut chunk)?;
+    Ok(chunk)
+}
+
+/// Reads an encrypted blob and yields the decrypted chunks.
+pub struct DecryptingReader<R> {
+    reader: R,
+    remaining: u64,
+    decryptor: Option<DecryptorBE32<XChaCha20Poly1305>>,
+}
+
+impl<R: AsyncRead + Unpin> DecryptingReader<R> {
+    /// Start reading an encrypted blob of `size` bytes.
+    pub async fn new(key: &ShareKey, mut reader: R, size: u64) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            size >= (NONCE_PREFIX_LEN + TAG_LEN) as u64,
+            "encrypted file is truncated"
+        );
+        let mut prefix = [0u8; NONCE_PREFIX_LEN];
+        reader.read_exact(&mut prefix).await?;
+        Ok(Self {
+            reader,
+            remaining: size - NONCE_PREFIX_LEN as u64,
+            decryptor: Some(DecryptorBE32::from_aead(
+                key.cipher(),
+                prefix.as_ref().into(),
+            )),
+        })
+    }
+
+    /// Read and decrypt the next chunk. Returns `None` once the blob is done.
+    pub async fn next_chunk(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        if self.decryptor.is_none() {
+            return Ok(None);
+        }
+        let len = self.remaining.min((CHUNK_SIZE + TAG_LEN) as u64) as usize;
+        let mut frame = vec![0u8; len];
+        self.reader.read_exact(&mut frame).await?;
+        self.remaining -= len as u64;
+        let open_error = |_| anyhow::anyhow!("failed to decrypt, the data is corrupt");
+        let chunk = if self.remaining == 0 {
+            let decryptor = self.decryptor.take().expect("checked above");
+            decryptor
+                .decrypt_last(frame.as_slice())
+                .map_err(open_error)?
+        } else {
+            let decryptor = self.decryptor.as_mut().expect("checked above");
+            decryptor
+                .decrypt_next(frame.as_slice())
+                .map_err(open_error)?
+        };
+        Ok(Some(chunk))
+    }
+}
@@ -1,25 +1,87 @@
 //! File export functionality.
 
-use iroh_blobs::{format::collection::Collection, store::fs::FsStore};
-use std::path::Path;
+use anyhow::Context;
+use iroh_blobs::{
+    api::blobs::BlobStatus, format::collection::Collection, store::fs::FsStore, Hash,
+};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
 
 use n0_future::StreamExt;
+use rand::Rng;
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
 
-use crate::{get_export_path, progress::ProgressSenderTx};
+use crate::{
+    compression::{self, Compression},
+    crypto::{DecryptingReader, EncryptedShare, ShareKey},
+    get_export_path,
+    metadata::{self, Metadata, MetadataBlob},
+    progress::{ConflictResolution, ExportProgress, ProgressEvent, ProgressSenderTx, RateTracker},
+    validate_collection_name, ExportConflictPolicy,
+};
+
+/// Options for writing a collection to disk.
+#[derive(Clone, Debug, Default)]
+pub struct ExportConfig {
+    /// What to do with files that already exist at the target path.
+    pub conflict_policy: ExportConflictPolicy,
+    /// Write the whole collection to a staging directory first and only move
+    /// it into place once every file has been written and synced. Files that go
+    /// into an existing directory are moved one at a time, and moved back if a
+    /// later one fails, so others can see a partly moved tree in between.
+    pub atomic: bool,
+    /// Decrypt the files of an encrypted share while writing them.
+    pub decryption: Option<EncryptedShare>,
+}
 
 /// Export a collection to a directory.
 ///
 /// If `export_dir` is None, uses the current directory.
+///
+/// Files that already exist at the target path are handled according to
+/// [`ExportConfig::conflict_policy`], and each decision is reported as
+/// [`ExportProgress::Conflict`]. Returns the paths the files were written to.
+///
+/// If `cancel` fires, the file currently being written is removed and the
+/// export stops. Files that were already exported are left in place, unless
+/// the export is [atomic](ExportConfig::atomic): then an error or cancellation
+/// leaves the export directory as it was.
+///
+/// [Compressed](crate::compression) files are decompressed while they are
+/// written, which needs the metadata blob of the collection, `metadata`.
+///
+/// [`ExportProgress::Conflict`]: crate::progress::ExportProgress::Conflict
 pub async fn export(
     db: &FsStore,
-    collection: Collection,
+    files: Collection,
+    metadata: Option<MetadataBlob>,
     progress_tx: Option<ProgressSenderTx>,
     export_dir: Option<&Path>,
-) -> anyhow::Result<()> {
+    config: &ExportConfig,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<PathBuf>> {
+    // Reject the whole collection before anything is written, so a malicious
+    // name can't leave a partial export behind
+    for (name, _) in files.iter() {
+        validate_collection_name(name)?;
+    }
+    let metadata = try_load_metadata(db, metadata, config.decryption.as_ref()).await;
+    if let Some(metadata) = &metadata {
+        for (name, target) in &metadata.symlinks {
+            validate_collection_name(name)?;
+            metadata::check_link_target(name, target)?;
+        }
+    }
+
     // Use provided export_dir or fall back to current directory
     let root = export_dir
         .map(std::path::PathBuf::from)
         .unwrap_or_else(|| std::env::current_dir().expect("Failed to get current directory"));
+    // The store only exports to absolute paths
+    let root = std::path::absolute(&root)?;
 
     tracing::info!("📤 Exporting collection to: {:?}", root);
 
@@ -44,26 +106,243 @@ pub async fn export(
             .send(crate::progress::ProgressEvent::Export(
                 "".to_string(),
                 crate::progress::ExportProgress::Started {
-                    total_files: collection.len(),
+                    total_files: files.len(),
+                },
+            ))
+            .await;
+    }
+
+    // The staging directory lives inside the export directory, so it is on the
+    // same filesystem and can be renamed into place
+    let staging = config.atomic.then(|| {
+        let suffix = rand::rng().random::<[u8; 8]>();
+        root.join(format!(
+            ".sendme-export-{}",
+            data_encoding::HEXLOWER.encode(&suffix)
+        ))
+    });
+
+    let contents = files
+        .iter()
+        .map(|(name, hash)| {
+            let compression = metadata
+                .as_ref()
+                .and_then(|metadata| metadata.files.get(name))
+                .and_then(|file| file.compression.as_ref());
+            let content = Content {
+                hash: *hash,
+                decryption: config.decryption.as_ref(),
+                compression,
+            };
+            (name.clone(), content)
+        })
+        .collect::<Vec<_>>();
+    let mut res = export_files(
+        db,
+        &contents,
+        &progress_tx,
+        &root,
+        staging.as_deref(),
+        config,
+        cancel,
+    )
+    .await
+    .and_then(|exported| {
+        if let Some(metadata) = &metadata {
+            let base = match &staging {
+                Some(staging) => staging.join("files"),
+                None => root.clone(),
+            };
+            apply_metadata(metadata, &files, &exported, &root, &base, config)?;
+        }
+        Ok(exported)
+    });
+    if let Some(staging) = &staging {
+        if res.is_ok() {
+            let (staging, root) = (staging.clone(), root.clone());
+            let committed =
+                tokio::task::spawn_blocking(move || commit_staged(&staging, &root)).await?;
+            res = committed.and(res);
+        }
+        if let Err(e) = tokio::fs::remove_dir_all(staging).await {
+            tracing::warn!("failed to remove {}: {}", staging.display(), e);
+        }
+    }
+    // Staged files were moved to the same place below the export directory
+    let exported = res?
+        .into_iter()
+        .map(|(_, target)| match &staging {
+            Some(staging) => match target.strip_prefix(staging.join("files")) {
+                Ok(path) => root.join(path),
+                Err(_) => target,
+            },
+            None => target,
+        })
+        .collect();
+
+    if let Some(ref tx) = progress_tx {
+        let _ = tx
+            .send(crate::progress::ProgressEvent::Export(
+                "".to_string(),
+                crate::progress::ExportProgress::Completed,
+            ))
+            .await;
+    }
+
+    Ok(exported)
+}
+
+/// Write the single file of a collection to `writer`, for example stdout.
+///
+/// Fails if the collection doesn't have exactly one file. The blob is read
+/// from the store, so it has already been verified against its blake3 hash
+/// while it was downloaded. File metadata is not applied, but a compressed
+/// file is decompressed.
+pub async fn export_to_writer(
+    db: &FsStore,
+    files: &Collection,
+    metadata: Option<MetadataBlob>,
+    writer: &mut (impl AsyncWrite + Unpin + ?Sized),
+    decryption: Option<&EncryptedShare>,
+    progress_tx: Option<ProgressSenderTx>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let (name, hash) = match files.iter().collect::<Vec<_>>().as_slice() {
+        [(name, hash)] => (name.clone(), *hash),
+        files => anyhow::bail!(
+            "only a single file can be written to a stream, but there are {}",
+            files.len()
+        ),
+    };
+    validate_collection_name(&name)?;
+    let compression = try_load_metadata(db, metadata, decryption)
+        .await
+        .and_then(|mut metadata| metadata.files.remove(&name))
+        .and_then(|file| file.compression);
+
+    if let Some(ref tx) = progress_tx {
+        let _ = tx
+            .send(crate::progress::ProgressEvent::Export(
+                "".to_string(),
+                crate::progress::ExportProgress::Started { total_files: 1 },
+            ))
+            .await;
+        let _ = tx
+            .send(crate::progress::ProgressEvent::Export(
+                name.clone(),
+                crate::progress::ExportProgress::FileStarted {
+                    name: name.clone(),
+                    size: 0,
                 },
             ))
             .await;
     }
 
-    for (_i, (name, hash)) in collection.iter().enumerate() {
-        let target = get_export_path(&root, name)?;
+    let content = Content {
+        hash,
+        decryption,
+        compression: compression.as_ref(),
+    };
+    write_content(db, content, &name, writer, &progress_tx, cancel).await?;
+
+    if let Some(ref tx) = progress_tx {
+        let _ = tx
+            .send(crate::progress::ProgressEvent::Export(
+                name.clone(),
+                crate::progress::ExportProgress::FileCompleted { name },
+            ))
+            .await;
+        let _ = tx
+            .send(crate::progress::ProgressEvent::Export(
+                "".to_string(),
+                crate::progress::ExportProgress::Completed,
+            ))
+            .await;
+    }
+    Ok(())
+}
+
+/// Export every file of the collection, either directly below `root` or,
+/// for an atomic export, below `staging`.
+///
+/// Returns the name and target path of every file that was written.
+async fn export_files(
+    db: &FsStore,
+    files: &[(String, Content<'_>)],
+    progress_tx: &Option<ProgressSenderTx>,
+    root: &Path,
+    staging: Option<&Path>,
+    config: &ExportConfig,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let mut exported = Vec::new();
+    for (name, content) in files {
+        if cancel.is_cancelled() {
+            anyhow::bail!("export cancelled");
+        }
+        let hash = &content.hash;
+        let target = get_export_path(root, name)?;
+        let mut export_name = name.clone();
 
-        // If file already exists, remove it to allow overwriting
         if target.exists() {
-            std::fs::remove_file(&target).map_err(|e| {
-                anyhow::anyhow!(
-                    "failed to remove existing target {}: {}",
-                    target.display(),
-                    e
-                )
-            })?;
+            // Existing files are compared with the decrypted and decompressed
+            // content
+            let expected = match (content.decryption, content.compression) {
+                (Some(share), _) => share
+                    .entry(hash)
+                    .map(|entry| (entry.hash, Some(entry.size)))
+                    .with_context(|| format!("{name} is not in the manifest"))?,
+                (None, Some(compression)) => (compression.hash, Some(compression.size)),
+                (None, None) => (*hash, blob_size(db, *hash).await?),
+            };
+            let resolution =
+                resolve_conflict(config.conflict_policy, expected, &target, name).await?;
+            if let Some(ref tx) = progress_tx {
+                let _ = tx
+                    .send(crate::progress::ProgressEvent::Export(
+                        name.clone(),
+                        crate::progress::ExportProgress::Conflict {
+                            name: name.clone(),
+                            resolution: resolution.clone(),
+                        },
+                    ))
+                    .await;
+            }
+            match resolution {
+                ConflictResolution::Overwritten
+                    if std::fs::symlink_metadata(&target).is_ok_and(|meta| meta.is_dir()) =>
+                {
+                    anyhow::bail!(
+                        "can not overwrite the directory {} with a file",
+                        target.display()
+                    );
+                }
+                // An atomic export replaces the file when it is committed
+                ConflictResolution::Overwritten if staging.is_some() => {}
+                ConflictResolution::Overwritten => {
+                    std::fs::remove_file(&target).map_err(|e| {
+                        anyhow::anyhow!(
+                            "failed to remove existing target {}: {}",
+                            target.display(),
+                            e
+                        )
+                    })?;
+                }
+                ConflictResolution::Skipped | ConflictResolution::SkippedIdentical => continue,
+                ConflictResolution::Renamed { name } => {
+                    export_name = name;
+                }
+                ConflictResolution::Failed => {
+                    anyhow::bail!("export target {} already exists", target.display());
+                }
+            }
         }
 
+        let target = match staging {
+            Some(staging) => get_export_path(&staging.join("files"), &export_name)?,
+            None => get_export_path(root, &export_name)?,
+        };
+
         if let Some(ref tx) = progress_tx {
             let _ = tx
                 .send(crate::progress::ProgressEvent::Export(
@@ -76,18 +355,47 @@ pub async fn export(
                 .await;
         }
 
+        if content.decryption.is_some() || content.compression.is_some() {
+            export_content(db, *content, name, &target, progress_tx, cancel).await?;
+            if staging.is_some() {
+                tokio::fs::File::open(&target).await?.sync_all().await?;
+            }
+            if let Some(ref tx) = progress_tx {
+                let _ = tx
+                    .send(crate::progress::ProgressEvent::Export(
+                        name.clone(),
+                        crate::progress::ExportProgress::FileCompleted { name: name.clone() },
+                    ))
+                    .await;
+            }
+            exported.push((name.clone(), target));
+            continue;
+        }
+
         let mut stream = db
             .export_with_opts(iroh_blobs::api::blobs::ExportOptions {
                 hash: *hash,
-                target,
+                target: target.clone(),
                 mode: iroh_blobs::api::blobs::ExportMode::Copy,
             })
             .stream()
             .await;
+        let mut rate = RateTracker::new();
+        let mut size = None;
 
-        while let Some(item) = stream.next().await {
+        loop {
+            let Some(item) = cancel.run_until_cancelled(stream.next()).await else {
+                drop(stream);
+                // Don't leave a truncated file behind that looks complete
+                let _ = tokio::fs::remove_file(&target).await;
+                anyhow::bail!("export cancelled");
+            };
+            let Some(item) = item else {
+                break;
+            };
             match item {
-                iroh_blobs::api::blobs::ExportProgressItem::Size(size) => {
+                iroh_blobs::api::blobs::ExportProgressItem::Size(total) => {
+                    size = Some(total);
                     if let Some(ref tx) = progress_tx {
                         let _ = tx
                             .send(crate::progress::ProgressEvent::Export(
@@ -95,11 +403,11 @@ pub async fn export(
                                 crate::progress::ExportProgress::FileProgress {
                                     name: name.clone(),
                                     offset: 0,
+                                    rate: rate.update(0, size),
                                 },
                             ))
                             .await;
                     }
-                    let _ = size;
                 }
                 iroh_blobs::api::blobs::ExportProgressItem::CopyProgress(offset) => {
                     if let Some(ref tx) = progress_tx {
@@ -109,12 +417,21 @@ pub async fn export(
                                 crate::progress::ExportProgress::FileProgress {
                                     name: name.clone(),
                                     offset,
+                                    rate: rate.update(offset, size),
                                 },
                             ))
                             .await;
                     }
                 }
                 iroh_blobs::api::blobs::ExportProgressItem::Done => {
+                    if staging.is_some() {
+                        tokio::fs::OpenOptions::new()
+                            .write(true)
+                            .open(&target)
+                            .await?
+                            .sync_all()
+                            .await?;
+                    }
                     if let Some(ref tx) = progress_tx {
                         let _ = tx
                             .send(crate::progress::ProgressEvent::Export(
@@ -131,16 +448,635 @@ pub async fn export(
                 }
             }
         }
+        exported.push((name.clone(), target));
     }
+    Ok(exported)
+}
 
-    if let Some(ref tx) = progress_tx {
-        let _ = tx
-            .send(crate::progress::ProgressEvent::Export(
-                "".to_string(),
-                crate::progress::ExportProgress::Completed,
-            ))
-            .await;
+/// Load the metadata blob of a collection, if there is one.
+///
+/// Invalid metadata is ignored with a warning.
+pub(crate) async fn try_load_metadata(
+    db: &FsStore,
+    metadata: Option<MetadataBlob>,
+    decryption: Option<&EncryptedShare>,
+) -> Option<Metadata> {
+    match load_metadata(db, metadata?, decryption).await {
+        Ok(metadata) => Some(metadata),
+        Err(e) => {
+            tracing::warn!("ignoring invalid file metadata: {e:#}");
+            None
+        }
+    }
+}
+
+/// Load the metadata blob of a collection, decrypting it if need be.
+async fn load_metadata(
+    db: &FsStore,
+    metadata: MetadataBlob,
+    decryption: Option<&EncryptedShare>,
+) -> anyhow::Result<Metadata> {
+    let hash = match metadata {
+        MetadataBlob::Child(hash) => hash,
+        // Only the metadata of unencrypted shares follows the names
+        MetadataBlob::Names(json) => return Ok(serde_json::from_slice(&json)?),
+    };
+    let data = match decryption {
+        Some(share) => {
+            let size = blob_size(db, hash)
+                .await?
+                .context("the metadata was not downloaded")?;
+            let mut reader = DecryptingReader::new(share.key(), db.reader(hash), size).await?;
+            let mut data = Vec::new();
+            while let Some(chunk) = reader.next_chunk().await? {
+                data.extend_from_slice(&chunk);
+            }
+            data
+        }
+        None => db.get_bytes(hash).await?.to_vec(),
+    };
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Apply the file metadata to the exported files below `base`.
+///
+/// Empty directories and symlinks are only created if the whole collection was
+/// exported. Existing files at the place of a symlink, below `root`, are
+/// handled according to the conflict policy.
+fn apply_metadata(
+    metadata: &Metadata,
+    files: &Collection,
+    exported: &[(String, PathBuf)],
+    root: &Path,
+    base: &Path,
+    config: &ExportConfig,
+) -> anyhow::Result<()> {
+    for (name, target) in exported {
+        if let Some(file) = metadata.files.get(name) {
+            file.apply(target)
+                .with_context(|| format!("failed to set the metadata of {}", target.display()))?;
+        }
+    }
+    if metadata.is_complete(files) {
+        for dir in &metadata.empty_dirs {
+            std::fs::create_dir_all(get_export_path(base, dir)?)?;
+        }
+        for (name, target) in &metadata.symlinks {
+            create_symlink(name, target, root, base, config.conflict_policy)?;
+        }
+    }
+    Ok(())
+}
+
+/// Create the symlink `name` below `base`, unless something that is not the
+/// same link already exists below `root` and the policy says to keep it.
+///
+/// The target must have passed [`metadata::check_link_target`].
+fn create_symlink(
+    name: &str,
+    target: &str,
+    root: &Path,
+    base: &Path,
+    policy: ExportConflictPolicy,
+) -> anyhow::Result<()> {
+    let existing = get_export_path(root, name)?;
+    if let Ok(meta) = std::fs::symlink_metadata(&existing) {
+        if meta.is_symlink() && std::fs::read_link(&existing)? == Path::new(target) {
+            return Ok(());
+        }
+        match policy {
+            ExportConflictPolicy::Fail => {
+                anyhow::bail!("export target {} already exists", existing.display())
+            }
+            ExportConflictPolicy::Overwrite | ExportConflictPolicy::SkipIfIdentical
+                if !meta.is_dir() =>
+            {
+                // An atomic export replaces it when it is committed
+                if root == base {
+                    std::fs::remove_file(&existing)?;
+                }
+            }
+            _ => {
+                tracing::warn!("not replacing {} with a symlink", existing.display());
+                return Ok(());
+            }
+        }
+    }
+
+    let path = get_export_path(base, name)?;
+    let parent = path.parent().context("symlink without a parent")?;
+    std::fs::create_dir_all(parent)?;
+    // A link placed below another link could point somewhere else than checked
+    let expected = match name.rsplit_once('/') {
+        Some((dir, _)) => get_export_path(&base.canonicalize()?, dir)?,
+        None => base.canonicalize()?,
+    };
+    anyhow::ensure!(
+        parent.canonicalize()? == expected,
+        "symlink {name} would be created below another symlink"
+    );
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, &path)
+        .with_context(|| format!("failed to create symlink {}", path.display()))?;
+    #[cfg(not(unix))]
+    tracing::warn!(
+        "not creating symlink {}, only supported on unix",
+        path.display()
+    );
+    Ok(())
+}
+
+/// A blob to export, with what it takes to turn it into the file.
+#[derive(Clone, Copy)]
+struct Content<'a> {
+    hash: Hash,
+    decryption: Option<&'a EncryptedShare>,
+    compression: Option<&'a Compression>,
+}
+
+/// Write the content of a blob into `target`, decrypting and decompressing it
+/// as need be.
+async fn export_content(
+    db: &FsStore,
+    content: Content<'_>,
+    name: &str,
+    target: &Path,
+    progress_tx: &Option<ProgressSenderTx>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    if let Some(parent) = target.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut file = tokio::fs::File::create(target).await?;
+    let res = write_content(db, content, name, &mut file, progress_tx, cancel).await;
+    if res.is_err() {
+        // Don't leave a truncated file behind that looks complete
+        drop(file);
+        let _ = tokio::fs::remove_file(target).await;
     }
+    res
+}
+
+/// Write the content of a blob into `writer`, decrypting and decompressing it
+/// as need be.
+async fn write_content(
+    db: &FsStore,
+    content: Content<'_>,
+    name: &str,
+    writer: &mut (impl AsyncWrite + Unpin + ?Sized),
+    progress_tx: &Option<ProgressSenderTx>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let Content {
+        hash,
+        decryption,
+        compression,
+    } = content;
+    match (decryption, compression) {
+        (_, Some(compression)) => {
+            let size = blob_size(db, hash)
+                .await?
+                .with_context(|| format!("{name} was not downloaded"))?;
+            let (db, key, compression) = (
+                db.clone(),
+                decryption.map(|share| share.key().clone()),
+                compression.clone(),
+            );
+            let handle = tokio::runtime::Handle::current();
+            let (name, progress_tx, cancel) =
+                (name.to_string(), progress_tx.clone(), cancel.clone());
+            write_blocking(writer, move |mut out| {
+                let reader =
+                    open_blocking(&db, hash, size, key.as_ref(), Some(&compression), &handle)?;
+                let mut reader =
+                    ProgressReader::new(reader, name, compression.size, progress_tx, cancel);
+                std::io::copy(&mut reader, &mut out)?;
+                Ok(())
+            })
+            .await
+        }
+        (Some(share), None) => {
+            write_decrypted(db, share, hash, name, writer, progress_tx, cancel).await
+        }
+        (None, None) => {
+            let size = blob_size(db, hash).await?;
+            let mut reader = db.reader(hash);
+            let mut buf = vec![0u8; 64 * 1024];
+            let mut offset = 0u64;
+            let mut rate = RateTracker::new();
+            loop {
+                let Some(n) = cancel.run_until_cancelled(reader.read(&mut buf)).await else {
+                    anyhow::bail!("export cancelled");
+                };
+                let n = n?;
+                if n == 0 {
+                    break;
+                }
+                writer.write_all(&buf[..n]).await?;
+                offset += n as u64;
+                if let Some(ref tx) = progress_tx {
+                    let _ = tx
+                        .send(crate::progress::ProgressEvent::Export(
+                            name.to_string(),
+                            crate::progress::ExportProgress::FileProgress {
+                                name: name.to_string(),
+                                offset,
+                                rate: rate.update(offset, size),
+                            },
+                        ))
+                        .await;
+                }
+            }
+            writer.flush().await?;
+            Ok(())
+        }
+    }
+}
 
+/// Decrypt the blob `hash` of an encrypted share into `writer`.
+async fn write_decrypted(
+    db: &FsStore,
+    share: &EncryptedShare,
+    hash: Hash,
+    name: &str,
+    writer: &mut (impl AsyncWrite + Unpin + ?Sized),
+    progress_tx: &Option<ProgressSenderTx>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let size = blob_size(db, hash)
+        .await?
+        .with_context(|| format!("{name} was not downloaded"))?;
+    let mut reader = DecryptingReader::new(share.key(), db.reader(hash), size).await?;
+    let mut offset = 0u64;
+    // The size of the plaintext is not known up front, so there is no ETA
+    let mut rate = RateTracker::new();
+    loop {
+        let Some(chunk) = cancel.run_until_cancelled(reader.next_chunk()).await else {
+            anyhow::bail!("export cancelled");
+        };
+        let Some(chunk) = chunk? else {
+            break;
+        };
+        writer.write_all(&chunk).await?;
+        offset += chunk.len() as u64;
+        if let Some(ref tx) = progress_tx {
+            let _ = tx
+                .send(crate::progress::ProgressEvent::Export(
+                    name.to_string(),
+                    crate::progress::ExportProgress::FileProgress {
+                        name: name.to_string(),
+                        offset,
+                        rate: rate.update(offset, None),
+                    },
+                ))
+                .await;
+        }
+    }
+    writer.flush().await?;
     Ok(())
 }
+
+/// Run `write` on a blocking thread and copy what it writes into `writer`.
+///
+/// The writer is borrowed, so the blocking thread writes into a pipe.
+pub(crate) async fn write_blocking(
+    writer: &mut (impl AsyncWrite + Unpin + ?Sized),
+    write: impl FnOnce(tokio_util::io::SyncIoBridge<tokio::io::DuplexStream>) -> std::io::Result<()>
+        + Send
+        + 'static,
+) -> anyhow::Result<()> {
+    let (pipe_reader, pipe_writer) = tokio::io::duplex(64 * 1024);
+    let task =
+        tokio::task::spawn_blocking(move || write(tokio_util::io::SyncIoBridge::new(pipe_writer)));
+    let copy = async {
+        // Dropped when the copy ends, so the task doesn't block on a full pipe
+        let mut pipe_reader = pipe_reader;
+        tokio::io::copy(&mut pipe_reader, writer).await?;
+        writer.flush().await?;
+        anyhow::Ok(())
+    };
+    // If the copy fails, the pipe is closed and the task fails as well
+    let (copied, written) = tokio::join!(copy, task);
+    written??;
+    copied
+}
+
+/// Open the content of the blob `hash` of `blob_size` bytes for reading on a
+/// blocking thread, decrypting it with `key` and decompressing it as need be.
+pub(crate) fn open_blocking(
+    db: &FsStore,
+    hash: Hash,
+    blob_size: u64,
+    key: Option<&ShareKey>,
+    compression: Option<&Compression>,
+    handle: &tokio::runtime::Handle,
+) -> std::io::Result<Box<dyn Read + Send>> {
+    let blob = db.reader(hash);
+    let reader: Box<dyn Read + Send> = match key {
+        Some(key) => {
+            let reader = handle
+                .block_on(DecryptingReader::new(key, blob, blob_size))
+                .map_err(std::io::Error::other)?;
+            Box::new(Decrypted {
+                reader,
+                handle: handle.clone(),
+                chunk: Vec::new(),
+                pos: 0,
+            })
+        }
+        None => Box::new(tokio_util::io::SyncIoBridge::new_with_handle(
+            blob,
+            handle.clone(),
+        )),
+    };
+    match compression {
+        Some(compression) => compression::decompress(reader, compression),
+        None => Ok(reader),
+    }
+}
+
+/// Reads the decrypted chunks of an encrypted blob.
+struct Decrypted<R> {
+    reader: DecryptingReader<R>,
+    handle: tokio::runtime::Handle,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: tokio::io::AsyncRead + Unpin> Read for Decrypted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            match self
+                .handle
+                .block_on(self.reader.next_chunk())
+                .map_err(std::io::Error::other)?
+            {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Reports the progress of reading a file on a blocking thread, and stops
+/// when cancelled.
+pub(crate) struct ProgressReader {
+    inner: Box<dyn Read + Send>,
+    name: String,
+    offset: u64,
+    size: u64,
+    rate: RateTracker,
+    progress_tx: Option<ProgressSenderTx>,
+    cancel: CancellationToken,
+}
+
+impl ProgressReader {
+    pub(crate) fn new(
+        inner: Box<dyn Read + Send>,
+        name: String,
+        size: u64,
+        progress_tx: Option<ProgressSenderTx>,
+        cancel: CancellationToken,
+    ) -> Self {
+        Self {
+            inner,
+            name,
+            offset: 0,
+            size,
+            rate: RateTracker::new(),
+            progress_tx,
+            cancel,
+        }
+    }
+}
+
+impl Read for ProgressReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.cancel.is_cancelled() {
+            return Err(std::io::Error::other("export cancelled"));
+        }
+        let n = self.inner.read(buf)?;
+        self.offset += n as u64;
+        if let Some(ref tx) = self.progress_tx {
+            let _ = tx.blocking_send(ProgressEvent::Export(
+                self.name.clone(),
+                ExportProgress::FileProgress {
+                    name: self.name.clone(),
+                    offset: self.offset,
+                    rate: self.rate.update(self.offset, Some(self.size)),
+                },
+            ));
+        }
+        Ok(n)
+    }
+}
+
+/// A change made to the export directory while committing a staged export.
+enum Committed {
+    /// A file or directory was moved into place where nothing existed before.
+    Created(PathBuf),
+    /// An existing file was moved to `backup` and replaced.
+    Replaced { target: PathBuf, backup: PathBuf },
+}
+
+/// Move a staged export into `root`.
+///
+/// Top level entries that don't exist yet are moved with a single rename.
+/// Existing directories are merged file by file, with replaced files moved
+/// to a backup directory first. If any step fails, all changes are undone.
+fn commit_staged(staging: &Path, root: &Path) -> anyhow::Result<()> {
+    let files = staging.join("files");
+    let backup = staging.join("backup");
+    if !files.exists() {
+        // Everything was skipped
+        return Ok(());
+    }
+    for entry in walkdir::WalkDir::new(&files) {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            sync_dir(entry.path())?;
+        }
+    }
+
+    let mut journal = Vec::new();
+    let res = std::fs::read_dir(&files)
+        .map_err(anyhow::Error::from)
+        .and_then(|entries| {
+            for entry in entries {
+                let entry = entry?;
+                commit_entry(
+                    &entry.path(),
+                    &root.join(entry.file_name()),
+                    &backup,
+                    &mut journal,
+                )?;
+            }
+            Ok(sync_dir(root)?)
+        });
+    if res.is_err() {
+        rollback(journal);
+    }
+    res
+}
+
+fn commit_entry(
+    src: &Path,
+    dst: &Path,
+    backup: &Path,
+    journal: &mut Vec<Committed>,
+) -> anyhow::Result<()> {
+    // Symlinks are moved as they are, even if they point to a directory
+    let src_is_dir = std::fs::symlink_metadata(src)?.is_dir();
+    match std::fs::symlink_metadata(dst) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            std::fs::rename(src, dst)
+                .with_context(|| format!("failed to move {} into place", dst.display()))?;
+            journal.push(Committed::Created(dst.to_path_buf()));
+        }
+        Err(e) => return Err(e.into()),
+        Ok(meta) if src_is_dir && meta.is_dir() => {
+            for entry in std::fs::read_dir(src)? {
+                let entry = entry?;
+                commit_entry(&entry.path(), &dst.join(entry.file_name()), backup, journal)?;
+            }
+            sync_dir(dst)?;
+        }
+        Ok(meta) if !src_is_dir && (meta.is_file() || meta.is_symlink()) => {
+            std::fs::create_dir_all(backup)?;
+            let saved = backup.join(journal.len().to_string());
+            std::fs::rename(dst, &saved)
+                .with_context(|| format!("failed to replace {}", dst.display()))?;
+            journal.push(Committed::Replaced {
+                target: dst.to_path_buf(),
+                backup: saved,
+            });
+            std::fs::rename(src, dst)
+                .with_context(|| format!("failed to move {} into place", dst.display()))?;
+        }
+        Ok(_) => anyhow::bail!(
+            "can not replace {} with a {}",
+            dst.display(),
+            if src_is_dir { "directory" } else { "file" }
+        ),
+    }
+    Ok(())
+}
+
+/// Undo the changes of a failed commit, most recent first.
+fn rollback(journal: Vec<Committed>) {
+    for change in journal.into_iter().rev() {
+        let res = match &change {
+            Committed::Created(path) if path.is_dir() && !path.is_symlink() => {
+                std::fs::remove_dir_all(path)
+            }
+            Committed::Created(path) => std::fs::remove_file(path),
+            Committed::Replaced { target, backup } => {
+                let _ = std::fs::remove_file(target);
+                std::fs::rename(backup, target)
+            }
+        };
+        if let Err(e) = res {
+            let path = match &change {
+                Committed::Created(path) => path,
+                Committed::Replaced { target, .. } => target,
+            };
+            tracing::warn!("failed to roll back {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Make the entries of a directory durable.
+fn sync_dir(path: &Path) -> std::io::Result<()> {
+    // Directories can't be opened for syncing on windows
+    #[cfg(unix)]
+    std::fs::File::open(path)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Decide what to do with an existing file at `target`.
+///
+/// `content` is the hash and, if known, the size of the file to export.
+async fn resolve_conflict(
+    policy: ExportConflictPolicy,
+    content: (Hash, Option<u64>),
+    target: &Path,
+    name: &str,
+) -> anyhow::Result<ConflictResolution> {
+    Ok(match policy {
+        ExportConflictPolicy::Overwrite => ConflictResolution::Overwritten,
+        ExportConflictPolicy::Skip => ConflictResolution::Skipped,
+        ExportConflictPolicy::Fail => ConflictResolution::Failed,
+        ExportConflictPolicy::Rename => {
+            let file_name = free_path(target)
+                .file_name()
+                .and_then(|n| n.to_str())
+                .map(str::to_string)
+                .ok_or_else(|| anyhow::anyhow!("invalid export target {}", target.display()))?;
+            let name = match name.rsplit_once('/') {
+                Some((dir, _)) => format!("{dir}/{file_name}"),
+                None => file_name,
+            };
+            ConflictResolution::Renamed { name }
+        }
+        ExportConflictPolicy::SkipIfIdentical => {
+            if is_identical(content, target).await? {
+                ConflictResolution::SkippedIdentical
+            } else {
+                ConflictResolution::Overwritten
+            }
+        }
+    })
+}
+
+/// The size of a blob, if it is complete.
+pub(crate) async fn blob_size(db: &FsStore, hash: Hash) -> anyhow::Result<Option<u64>> {
+    Ok(match db.status(hash).await? {
+        BlobStatus::Complete { size } => Some(size),
+        _ => None,
+    })
+}
+
+/// Check if the file at `path` has the given blake3 hash.
+async fn is_identical((hash, size): (Hash, Option<u64>), path: &Path) -> anyhow::Result<bool> {
+    let metadata = tokio::fs::metadata(path).await?;
+    if !metadata.is_file() {
+        return Ok(false);
+    }
+    // Avoid hashing the whole file if the sizes already differ
+    if size.is_some_and(|size| size != metadata.len()) {
+        return Ok(false);
+    }
+    let path = path.to_path_buf();
+    let existing = tokio::task::spawn_blocking(move || -> std::io::Result<Hash> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(std::fs::File::open(path)?)?;
+        Ok(hasher.finalize().into())
+    })
+    .await??;
+    Ok(existing == hash)
+}
+
+/// Find the first free path of the form `stem (n).ext` next to `path`.
+pub(crate) fn free_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1u64..)
+        .map(|n| path.with_file_name(format!("{stem} ({n}){ext}")))
+        .find(|p| !p.exists())
+        .expect("ran out of file names")
+}
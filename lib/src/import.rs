@@ -1,31 +1,90 @@
 //! File import functionality.
 
+use std::collections::{BTreeMap, HashMap, HashSet};
+
 use anyhow::Context;
+use bytes::{Bytes, BytesMut};
 use futures_buffered::BufferedStreamExt;
-use iroh_blobs::{format::collection::Collection, store::fs::FsStore, BlobFormat};
+use iroh_blobs::{api::TempTag, format::collection::Collection, store::fs::FsStore, BlobFormat};
+
+use n0_future::{Stream, StreamExt};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::{
+    compression::{self, Codec, Compressed, Compression},
+    crypto::{self, ManifestEntry, ShareKey},
+    metadata::{self, FileMetadata, Metadata, MetadataBlob},
+    progress::ProgressSenderTx,
+    validate_collection_name, SymlinkPolicy,
+};
 
-use n0_future::StreamExt;
+/// Name of the sendme specific ignore files, see [`ImportConfig::ignore_files`].
+pub const IGNORE_FILE_NAME: &str = ".sendmeignore";
 
-use crate::{progress::ProgressSenderTx, validate_path_component};
+/// Options for reading a file or directory from disk.
+#[derive(Clone, Debug, Default)]
+pub struct ImportConfig {
+    /// What to do with symlinks.
+    pub symlinks: SymlinkPolicy,
+    /// Leave out `.git` directories and whatever `.gitignore`, `.ignore` and
+    /// [`IGNORE_FILE_NAME`] files in the directory and its parents exclude.
+    pub ignore_files: bool,
+    /// Only import the files that match one of these globs, if any are given.
+    pub include: Vec<String>,
+    /// Leave out the files and directories that match one of these globs.
+    pub exclude: Vec<String>,
+    /// Compress the files that are not compressed already, see [`compression`].
+    pub compress: bool,
+}
 
 /// Import a file or directory into the database.
 ///
 /// The returned tag always refers to a collection. If the input is a file, this
 /// is a collection with a single blob, named like the file.
 ///
-/// If the input is a directory, the collection contains all the files in the
-/// directory.
+/// If the input is a directory, the collection contains the files in the
+/// directory that are selected by `config`. Entries with names that receivers
+/// refuse to export, see [`validate_collection_name`], are skipped with a
+/// warning.
+///
+/// The globs of [`ImportConfig::include`] and [`ImportConfig::exclude`] use the
+/// `.gitignore` syntax and are relative to the directory, so `*.log` matches at
+/// any depth and `/target` only at the top. They take precedence over ignore
+/// files.
+///
+/// The stored collection also has a metadata blob with the file modes,
+/// modification times and empty directories, see [`metadata`]. The returned
+/// collection only lists the files, and the returned size is that of the
+/// files before they are compressed.
 pub async fn import(
     path: std::path::PathBuf,
     db: &FsStore,
+    config: &ImportConfig,
     progress_tx: Option<ProgressSenderTx>,
 ) -> anyhow::Result<(iroh_blobs::Hash, u64, Collection)> {
-    import_internal(path, db, progress_tx).await
+    import_internal(path, db, config, None, progress_tx).await
+}
+
+/// Import a file or directory into the database, encrypted with `key`.
+///
+/// The stored collection is an encrypted share, see [`crypto`]. The returned
+/// collection lists the files with their real names and the hashes of the
+/// encrypted blobs, and the returned size is that of the plaintext.
+pub async fn import_encrypted(
+    path: std::path::PathBuf,
+    db: &FsStore,
+    config: &ImportConfig,
+    key: &ShareKey,
+    progress_tx: Option<ProgressSenderTx>,
+) -> anyhow::Result<(iroh_blobs::Hash, u64, Collection)> {
+    import_internal(path, db, config, Some(key), progress_tx).await
 }
 
 async fn import_internal(
     path: std::path::PathBuf,
     db: &FsStore,
+    config: &ImportConfig,
+    key: Option<&ShareKey>,
     progress_tx: Option<ProgressSenderTx>,
 ) -> anyhow::Result<(iroh_blobs::Hash, u64, Collection)> {
     let parallelism = num_cpus::get();
@@ -33,25 +92,97 @@ async fn import_internal(
     anyhow::ensure!(path.exists(), "path {} does not exist", path.display());
     let root = path.parent().context("get parent")?;
 
-    // walkdir also works for files, so we don't need to special case them
-    let files = walkdir::WalkDir::new(path.clone()).into_iter();
+    // the walker also works for files, so we don't need to special case them
+    let symlinks = config.symlinks;
+    let follow = symlinks == SymlinkPolicy::Follow;
+    let filtered = config.ignore_files || !config.include.is_empty() || !config.exclude.is_empty();
+    let files = walker(&path, config)?;
+
+    // flatten the directory structure into a list of (name, path) pairs,
+    // remembering the metadata of the files, the directories and the symlinks
+    // on the way.
+    let mut files_metadata = BTreeMap::new();
+    let mut dirs = Vec::new();
+    let mut links = BTreeMap::new();
+    let mut data_sources: Vec<(String, std::path::PathBuf)> = Vec::new();
+    // The names the walker yields in each directory it enters, to tell what
+    // it left out
+    let mut entered: HashMap<std::path::PathBuf, HashSet<std::ffi::OsString>> = HashMap::new();
+    for entry in files {
+        let entry = match entry {
+            Ok(entry) => entry,
+            // Only followed links can loop or dangle
+            Err(e) if follow && is_broken_link(&e) => {
+                tracing::warn!("skipping symlink: {e}");
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if filtered {
+            if let Some(parent) = entry.path().parent().filter(|_| entry.depth() > 0) {
+                let yielded = entered.entry(parent.to_path_buf()).or_default();
+                yielded.insert(entry.file_name().to_os_string());
+            }
+            if entry.file_type().is_some_and(|t| t.is_dir()) {
+                entered.entry(entry.path().to_path_buf()).or_default();
+            }
+        }
+        let Some(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_symlink() && symlinks == SymlinkPolicy::Skip {
+            continue;
+        }
+        if !file_type.is_file() && !file_type.is_dir() && !file_type.is_symlink() {
+            // Skip sockets, pipes and devices
+            continue;
+        }
+        let relative = entry.path().strip_prefix(root)?;
+        let name = crate::canonicalized_path_to_string(relative, true)?;
+        // Receivers refuse names that aren't portable, like `aux.c`, so leave
+        // them out instead of failing the whole share
+        if let Err(e) = validate_collection_name(&name) {
+            if entry.depth() == 0 {
+                return Err(e);
+            }
+            tracing::warn!("skipping {}: {e:#}", entry.path().display());
+            continue;
+        }
+        if file_type.is_symlink() {
+            let target = std::fs::read_link(entry.path())?;
+            match metadata::link_target(&target)
+                .filter(|target| metadata::check_link_target(&name, target).is_ok())
+            {
+                Some(target) => {
+                    links.insert(name, target);
+                }
+                None => tracing::warn!(
+                    "skipping symlink {name}, its target {} is outside of the shared directory",
+                    target.display()
+                ),
+            }
+            continue;
+        }
+        if file_type.is_dir() {
+            dirs.push(name);
+            continue;
+        }
+        files_metadata.insert(name.clone(), FileMetadata::from_fs(&entry.metadata()?));
+        data_sources.push((name, entry.into_path()));
+    }
+    let mut metadata = Metadata::new(files_metadata, dirs, links);
 
-    // flatten the directory structure into a list of (name, path) pairs.
-    // ignore symlinks.
-    let data_sources: Vec<(String, std::path::PathBuf)> = files
-        .map(|entry| {
+    // The walker never walks into what it leaves out, so count the files of
+    // left out directories separately
+    let mut skipped = 0;
+    for (dir, yielded) in entered {
+        for entry in std::fs::read_dir(&dir)? {
             let entry = entry?;
-            if !entry.file_type().is_file() {
-                // Skip symlinks. Directories are handled by WalkDir.
-                return Ok(None);
+            if !yielded.contains(&entry.file_name()) {
+                skipped += count_files(&entry.path(), follow);
             }
-            let path = entry.into_path();
-            let relative = path.strip_prefix(root)?;
-            let name = crate::canonicalized_path_to_string(relative, true)?;
-            anyhow::Ok(Some((name, path)))
-        })
-        .filter_map(Result::transpose)
-        .collect::<anyhow::Result<Vec<_>>>()?;
+        }
+    }
 
     if let Some(ref tx) = progress_tx {
         let _ = tx
@@ -59,16 +190,19 @@ async fn import_internal(
                 "".to_string(),
                 crate::progress::ImportProgress::Started {
                     total_files: data_sources.len(),
+                    skipped,
                 },
             ))
             .await;
     }
 
     // import all the files, using num_cpus workers, return names and temp tags
-    let mut names_and_tags = n0_future::stream::iter(data_sources)
+    let imported = n0_future::stream::iter(data_sources)
         .map(|(name, path)| {
             let db = db.clone();
+            let key = key.cloned();
             let progress_tx = progress_tx.clone();
+            let compress = config.compress;
             async move {
                 if let Some(ref tx) = progress_tx {
                     let _ = tx
@@ -82,91 +216,60 @@ async fn import_internal(
                         .await;
                 }
 
-                let import = db.add_path_with_opts(iroh_blobs::api::blobs::AddPathOptions {
-                    path,
-                    mode: iroh_blobs::api::blobs::ImportMode::TryReference,
-                    format: BlobFormat::Raw,
-                });
-                let mut stream = import.stream().await;
-                let mut item_size = 0u64;
-                let temp_tag = loop {
-                    let item = stream
-                        .next()
-                        .await
-                        .context("import stream ended without a tag")?;
-                    match item {
-                        iroh_blobs::api::blobs::AddProgressItem::Size(size) => {
-                            item_size = size;
-                            if let Some(ref tx) = progress_tx {
-                                let _ = tx
-                                    .send(crate::progress::ProgressEvent::Import(
-                                        name.clone(),
-                                        crate::progress::ImportProgress::FileProgress {
-                                            name: name.clone(),
-                                            offset: 0,
-                                        },
-                                    ))
-                                    .await;
-                            }
-                        }
-                        iroh_blobs::api::blobs::AddProgressItem::CopyProgress(offset) => {
-                            if let Some(ref tx) = progress_tx {
-                                let _ = tx
-                                    .send(crate::progress::ProgressEvent::Import(
-                                        name.clone(),
-                                        crate::progress::ImportProgress::FileProgress {
-                                            name: name.clone(),
-                                            offset,
-                                        },
-                                    ))
-                                    .await;
-                            }
-                        }
-                        iroh_blobs::api::blobs::AddProgressItem::CopyDone => {
-                            if let Some(ref tx) = progress_tx {
-                                let _ = tx
-                                    .send(crate::progress::ProgressEvent::Import(
-                                        name.clone(),
-                                        crate::progress::ImportProgress::FileProgress {
-                                            name: name.clone(),
-                                            offset: 0,
-                                        },
-                                    ))
-                                    .await;
-                            }
-                        }
-                        iroh_blobs::api::blobs::AddProgressItem::OutboardProgress(offset) => {
-                            if let Some(ref tx) = progress_tx {
-                                let _ = tx
-                                    .send(crate::progress::ProgressEvent::Import(
-                                        name.clone(),
-                                        crate::progress::ImportProgress::FileProgress {
-                                            name: name.clone(),
-                                            offset,
-                                        },
-                                    ))
-                                    .await;
-                            }
-                        }
-                        iroh_blobs::api::blobs::AddProgressItem::Error(cause) => {
-                            anyhow::bail!("error importing {}: {}", name, cause);
-                        }
-                        iroh_blobs::api::blobs::AddProgressItem::Done(tt) => {
-                            if let Some(ref tx) = progress_tx {
-                                let _ = tx
-                                    .send(crate::progress::ProgressEvent::Import(
-                                        name.clone(),
-                                        crate::progress::ImportProgress::FileCompleted {
-                                            name: name.clone(),
-                                        },
-                                    ))
-                                    .await;
+                let compressed = match compress {
+                    true => {
+                        import_compressed(&db, &path, key.as_ref(), &name, &progress_tx).await?
+                    }
+                    false => None,
+                };
+                let (temp_tag, item_size, content_hash, compression) = match compressed {
+                    // The manifest lists the hash and size of the original file
+                    Some((temp_tag, compressed)) => (
+                        temp_tag,
+                        compressed.size,
+                        compressed.hash,
+                        Some(Compression {
+                            codec: Codec::Zstd,
+                            size: compressed.size,
+                            hash: compressed.hash,
+                        }),
+                    ),
+                    None => {
+                        let (import, encryption) = match &key {
+                            None => (
+                                db.add_path_with_opts(iroh_blobs::api::blobs::AddPathOptions {
+                                    path,
+                                    mode: iroh_blobs::api::blobs::ImportMode::TryReference,
+                                    format: BlobFormat::Raw,
+                                }),
+                                None,
+                            ),
+                            Some(key) => {
+                                let (stream, encryption) = crypto::encrypt_file(key, path);
+                                (db.add_stream(stream).await, Some(encryption))
                             }
-                            break tt;
-                        }
+                        };
+                        let (temp_tag, item_size) =
+                            add_with_progress(import, &name, &progress_tx).await?;
+                        // The manifest lists the hash and size of the plaintext
+                        let (content_hash, item_size) = match encryption {
+                            Some(encryption) => encryption
+                                .await?
+                                .with_context(|| format!("error encrypting {name}"))?,
+                            None => (temp_tag.hash(), item_size),
+                        };
+                        (temp_tag, item_size, content_hash, None)
                     }
                 };
-                anyhow::Ok((name, temp_tag, item_size))
+                let wire_size = crate::export::blob_size(&db, temp_tag.hash())
+                    .await?
+                    .unwrap_or(item_size);
+                file_completed(&name, &progress_tx).await;
+                anyhow::Ok((
+                    (name, temp_tag, item_size, content_hash),
+                    wire_size,
+                    compression,
+                ))
             }
         })
         .buffered_unordered(parallelism)
@@ -175,29 +278,91 @@ async fn import_internal(
         .into_iter()
         .collect::<anyhow::Result<Vec<_>>>()?;
 
-    names_and_tags.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+    // The metadata records how the files are compressed
+    let mut wire_size = 0u64;
+    let mut names_and_tags = Vec::with_capacity(imported.len());
+    for (entry, size, compression) in imported {
+        wire_size += size;
+        if let Some(file) = metadata.files.get_mut(&entry.0) {
+            file.compression = compression;
+        }
+        names_and_tags.push(entry);
+    }
+    let metadata_json = serde_json::to_vec(&metadata)?;
+
+    names_and_tags.sort_by(|(a, _, _, _), (b, _, _, _)| a.cmp(b));
 
     // total size of all files
-    let size = names_and_tags.iter().map(|(_, _, size)| *size).sum::<u64>();
+    let size = names_and_tags
+        .iter()
+        .map(|(_, _, size, _)| *size)
+        .sum::<u64>();
+
+    // The metadata is encrypted like the files if need be. Older receivers
+    // must refuse a share they would export wrongly, otherwise the metadata
+    // goes along with the names, which they ignore.
+    let (metadata_tag, metadata_blob) = match key {
+        Some(key) => {
+            let (stream, encryption) = crypto::encrypt_bytes(key, metadata_json);
+            let tag = db.add_stream(stream).await.temp_tag().await?;
+            encryption.await?.context("error encrypting the metadata")?;
+            let blob = MetadataBlob::Child(tag.hash());
+            (Some(tag), blob)
+        }
+        None if metadata.is_required() => {
+            let tag = db.add_bytes(metadata_json).temp_tag().await?;
+            let blob = MetadataBlob::Child(tag.hash());
+            (Some(tag), blob)
+        }
+        None => (None, MetadataBlob::Names(metadata_json.into())),
+    };
+
+    // An encrypted share stores the real names in the sealed manifest
+    let manifest = match key {
+        Some(key) => {
+            let files = names_and_tags
+                .iter()
+                .map(|(name, _, size, hash)| ManifestEntry {
+                    name: name.clone(),
+                    hash: *hash,
+                    size: *size,
+                })
+                .collect::<Vec<_>>();
+            let manifest = crypto::seal_manifest(key, &files)?;
+            Some(db.add_bytes(manifest).temp_tag().await?)
+        }
+        None => None,
+    };
 
     // collect the (name, hash) tuples into a collection
     // we must also keep the tags around so the data does not get gced.
     let (collection, tags) = names_and_tags
         .into_iter()
-        .map(|(name, tag, _)| ((name, tag.hash()), tag))
+        .map(|(name, tag, _, _)| ((name, tag.hash()), tag))
         .unzip::<_, _, Collection, Vec<_>>();
-    let collection_tag = collection.clone().store(db).await?;
+    let stored = match &manifest {
+        Some(manifest) => {
+            crypto::encrypted_collection(manifest.hash(), collection.iter().map(|(_, hash)| *hash))
+        }
+        None => collection.clone(),
+    };
+    let collection_tag = metadata::store_collection(stored, Some(metadata_blob), db).await?;
     let hash = collection_tag.hash();
 
     // now that the collection is stored, we can drop the tags
     // data is protected by the collection
     drop(tags);
+    drop(manifest);
+    drop(metadata_tag);
 
     if let Some(ref tx) = progress_tx {
         let _ = tx
             .send(crate::progress::ProgressEvent::Import(
                 "".to_string(),
-                crate::progress::ImportProgress::Completed { total_size: size },
+                crate::progress::ImportProgress::Completed {
+                    total_size: size,
+                    wire_size,
+                },
             ))
             .await;
     }
@@ -205,14 +370,185 @@ async fn import_internal(
     Ok((hash, size, collection))
 }
 
+/// Drive an import into the store, reporting its progress as the file `name`.
+///
+/// Returns the tag of the blob and its size as reported by the store.
+async fn add_with_progress(
+    import: iroh_blobs::api::blobs::AddProgress<'_>,
+    name: &str,
+    progress_tx: &Option<ProgressSenderTx>,
+) -> anyhow::Result<(TempTag, u64)> {
+    let mut stream = import.stream().await;
+    let mut item_size = 0u64;
+    let temp_tag = loop {
+        let item = stream
+            .next()
+            .await
+            .context("import stream ended without a tag")?;
+        match item {
+            iroh_blobs::api::blobs::AddProgressItem::Size(size) => {
+                item_size = size;
+                if let Some(ref tx) = progress_tx {
+                    let _ = tx
+                        .send(crate::progress::ProgressEvent::Import(
+                            name.to_string(),
+                            crate::progress::ImportProgress::FileProgress {
+                                name: name.to_string(),
+                                offset: 0,
+                            },
+                        ))
+                        .await;
+                }
+            }
+            iroh_blobs::api::blobs::AddProgressItem::CopyProgress(offset) => {
+                if let Some(ref tx) = progress_tx {
+                    let _ = tx
+                        .send(crate::progress::ProgressEvent::Import(
+                            name.to_string(),
+                            crate::progress::ImportProgress::FileProgress {
+                                name: name.to_string(),
+                                offset,
+                            },
+                        ))
+                        .await;
+                }
+            }
+            iroh_blobs::api::blobs::AddProgressItem::CopyDone => {
+                if let Some(ref tx) = progress_tx {
+                    let _ = tx
+                        .send(crate::progress::ProgressEvent::Import(
+                            name.to_string(),
+                            crate::progress::ImportProgress::FileProgress {
+                                name: name.to_string(),
+                                offset: 0,
+                            },
+                        ))
+                        .await;
+                }
+            }
+            iroh_blobs::api::blobs::AddProgressItem::OutboardProgress(offset) => {
+                if let Some(ref tx) = progress_tx {
+                    let _ = tx
+                        .send(crate::progress::ProgressEvent::Import(
+                            name.to_string(),
+                            crate::progress::ImportProgress::FileProgress {
+                                name: name.to_string(),
+                                offset,
+                            },
+                        ))
+                        .await;
+                }
+            }
+            iroh_blobs::api::blobs::AddProgressItem::Error(cause) => {
+                anyhow::bail!("error importing {}: {}", name, cause);
+            }
+            iroh_blobs::api::blobs::AddProgressItem::Done(tt) => {
+                break tt;
+            }
+        }
+    };
+    Ok((temp_tag, item_size))
+}
+
+/// Report that the file `name` has been imported.
+async fn file_completed(name: &str, progress_tx: &Option<ProgressSenderTx>) {
+    if let Some(ref tx) = progress_tx {
+        let _ = tx
+            .send(crate::progress::ProgressEvent::Import(
+                name.to_string(),
+                crate::progress::ImportProgress::FileCompleted {
+                    name: name.to_string(),
+                },
+            ))
+            .await;
+    }
+}
+
+/// Import the file at `path` compressed, and encrypted with `key` if given.
+///
+/// Returns `None` if the file is compressed already or doesn't get smaller,
+/// and it should be imported as it is instead.
+async fn import_compressed(
+    db: &FsStore,
+    path: &std::path::Path,
+    key: Option<&ShareKey>,
+    name: &str,
+    progress_tx: &Option<ProgressSenderTx>,
+) -> anyhow::Result<Option<(TempTag, Compressed)>> {
+    if compression::is_compressed(&compression::sniff(path).await?) {
+        return Ok(None);
+    }
+    let (stream, compression) = compression::compress_file(path.to_path_buf(), key);
+    let (tag, _) = add_with_progress(db.add_stream(stream).await, name, progress_tx).await?;
+    let compressed = compression
+        .await?
+        .with_context(|| format!("error compressing {name}"))?;
+    // Dropping the tag lets the store collect the compressed blob
+    Ok((compressed.compressed_size < compressed.size).then_some((tag, compressed)))
+}
+
+/// Build the directory walker for `path`.
+fn walker(path: &std::path::Path, config: &ImportConfig) -> anyhow::Result<ignore::Walk> {
+    let mut builder = ignore::WalkBuilder::new(path);
+    builder
+        .standard_filters(config.ignore_files)
+        .follow_links(config.symlinks == SymlinkPolicy::Follow);
+    if config.ignore_files {
+        // Hidden files are sent unless they are ignored, and a .gitignore
+        // applies even if the directory is not a git checkout
+        builder
+            .hidden(false)
+            .require_git(false)
+            .add_custom_ignore_filename(IGNORE_FILE_NAME)
+            .filter_entry(|entry| entry.file_name() != ".git");
+    }
+    if !config.include.is_empty() || !config.exclude.is_empty() {
+        let mut overrides = ignore::overrides::OverrideBuilder::new(path);
+        for glob in &config.include {
+            overrides.add(glob)?;
+        }
+        for glob in &config.exclude {
+            overrides.add(&format!("!{glob}"))?;
+        }
+        builder.overrides(overrides.build()?);
+    }
+    Ok(builder.build())
+}
+
+/// Count the files below `path`, which is one if it is a file itself.
+fn count_files(path: &std::path::Path, follow: bool) -> usize {
+    walkdir::WalkDir::new(path)
+        .follow_links(follow)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .count()
+}
+
+/// Check if the walk failed on a followed symlink that loops or points to
+/// nothing.
+fn is_broken_link(error: &ignore::Error) -> bool {
+    match error {
+        ignore::Error::Loop { .. } => true,
+        ignore::Error::WithPath { path, err } => {
+            (path.is_symlink()
+                && err
+                    .io_error()
+                    .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound))
+                || is_broken_link(err)
+        }
+        ignore::Error::WithDepth { err, .. } => is_broken_link(err),
+        _ => false,
+    }
+}
+
 /// Get the export path for a given name relative to a root directory.
+///
+/// Fails if `name` is not a valid collection name, see [`validate_collection_name`].
 pub fn get_export_path(root: &std::path::Path, name: &str) -> anyhow::Result<std::path::PathBuf> {
-    let parts = name.split('/');
+    validate_collection_name(name)?;
     let mut path = root.to_path_buf();
-    for part in parts {
-        validate_path_component(part)?;
-        path.push(part);
-    }
+    path.extend(name.split('/'));
     Ok(path)
 }
 
@@ -235,6 +571,7 @@ pub async fn import_from_bytes(
     db: &FsStore,
     progress_tx: Option<ProgressSenderTx>,
 ) -> anyhow::Result<(iroh_blobs::Hash, u64, Collection)> {
+    validate_collection_name(&name)?;
     let size = data.len() as u64;
 
     if let Some(ref tx) = progress_tx {
@@ -271,10 +608,170 @@ pub async fn import_from_bytes(
         let _ = tx
             .send(crate::progress::ProgressEvent::Import(
                 "".to_string(),
-                crate::progress::ImportProgress::Completed { total_size: size },
+                crate::progress::ImportProgress::Completed {
+                    total_size: size,
+                    wire_size: size,
+                },
             ))
             .await;
     }
 
     Ok((hash, size, collection))
 }
+
+/// Import everything `reader` yields as a single file named `name`.
+///
+/// The data is streamed into the store, so it doesn't need to fit into memory
+/// and its size doesn't need to be known up front. Like [`import_from_bytes`],
+/// the collection has no metadata blob.
+pub async fn import_reader(
+    name: String,
+    reader: impl AsyncRead + Send + Unpin + 'static,
+    db: &FsStore,
+    progress_tx: Option<ProgressSenderTx>,
+) -> anyhow::Result<(iroh_blobs::Hash, u64, Collection)> {
+    import_reader_internal(name, reader, db, None, progress_tx).await
+}
+
+/// Import everything `reader` yields as a single file named `name`, encrypted
+/// with `key`, see [`import_encrypted`].
+pub async fn import_reader_encrypted(
+    name: String,
+    reader: impl AsyncRead + Send + Unpin + 'static,
+    db: &FsStore,
+    key: &ShareKey,
+    progress_tx: Option<ProgressSenderTx>,
+) -> anyhow::Result<(iroh_blobs::Hash, u64, Collection)> {
+    import_reader_internal(name, reader, db, Some(key), progress_tx).await
+}
+
+async fn import_reader_internal(
+    name: String,
+    reader: impl AsyncRead + Send + Unpin + 'static,
+    db: &FsStore,
+    key: Option<&ShareKey>,
+    progress_tx: Option<ProgressSenderTx>,
+) -> anyhow::Result<(iroh_blobs::Hash, u64, Collection)> {
+    validate_collection_name(&name)?;
+
+    if let Some(ref tx) = progress_tx {
+        let _ = tx
+            .send(crate::progress::ProgressEvent::Import(
+                "".to_string(),
+                crate::progress::ImportProgress::Started {
+                    total_files: 1,
+                    skipped: 0,
+                },
+            ))
+            .await;
+        let _ = tx
+            .send(crate::progress::ProgressEvent::Import(
+                name.clone(),
+                crate::progress::ImportProgress::FileStarted {
+                    name: name.clone(),
+                    size: 0,
+                },
+            ))
+            .await;
+    }
+
+    // The size is only known once the reader is done
+    let (tag, content_hash, size) = match key {
+        Some(key) => {
+            let (stream, encryption) = crypto::encrypt_reader(key, reader);
+            let (tag, _) =
+                add_with_progress(db.add_stream(stream).await, &name, &progress_tx).await?;
+            let (content_hash, size) = encryption.await?.context("error encrypting the input")?;
+            (tag, content_hash, size)
+        }
+        None => {
+            let (stream, read) = stream_reader(reader);
+            let (tag, _) =
+                add_with_progress(db.add_stream(stream).await, &name, &progress_tx).await?;
+            let size = read.await?.context("error reading the input")?;
+            let hash = tag.hash();
+            (tag, hash, size)
+        }
+    };
+    let wire_size = crate::export::blob_size(db, tag.hash())
+        .await?
+        .unwrap_or(size);
+    file_completed(&name, &progress_tx).await;
+
+    let collection: Collection = std::iter::once((name.clone(), tag.hash())).collect();
+    let manifest = match key {
+        Some(key) => {
+            let manifest = crypto::seal_manifest(
+                key,
+                &[ManifestEntry {
+                    name,
+                    hash: content_hash,
+                    size,
+                }],
+            )?;
+            Some(db.add_bytes(manifest).temp_tag().await?)
+        }
+        None => None,
+    };
+    let stored = match &manifest {
+        Some(manifest) => crypto::encrypted_collection(manifest.hash(), [tag.hash()]),
+        None => collection.clone(),
+    };
+    let collection_tag = stored.store(db).await?;
+    let hash = collection_tag.hash();
+    drop(tag);
+    drop(manifest);
+
+    if let Some(ref tx) = progress_tx {
+        let _ = tx
+            .send(crate::progress::ProgressEvent::Import(
+                "".to_string(),
+                crate::progress::ImportProgress::Completed {
+                    total_size: size,
+                    wire_size,
+                },
+            ))
+            .await;
+    }
+
+    Ok((hash, size, collection))
+}
+
+/// Read `reader` on a separate task into a stream of chunks, for adding to a
+/// store.
+///
+/// The returned task resolves to the number of bytes read once the stream has
+/// ended.
+fn stream_reader(
+    mut reader: impl AsyncRead + Send + Unpin + 'static,
+) -> (
+    impl Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static,
+    tokio::task::JoinHandle<std::io::Result<u64>>,
+) {
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    let task = tokio::spawn(async move {
+        let mut size = 0u64;
+        loop {
+            let mut chunk = BytesMut::with_capacity(crypto::CHUNK_SIZE);
+            match reader.read_buf(&mut chunk).await {
+                Ok(0) => break Ok(size),
+                Ok(n) => {
+                    size += n as u64;
+                    if tx.send(Ok(chunk.freeze())).await.is_err() {
+                        break Err(std::io::Error::other("import was cancelled"));
+                    }
+                }
+                Err(e) => {
+                    let _ = tx
+                        .send(Err(std::io::Error::new(e.kind(), e.to_string())))
+                        .await;
+                    break Err(e);
+                }
+            }
+        }
+    });
+    let stream = n0_future::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|item| (item, rx))
+    });
+    (stream, task)
+}
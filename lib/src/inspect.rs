@@ -0,0 +1,143 @@
+//! Inspect functionality - listing the contents of a ticket.
+
+use anyhow::Context;
+use iroh::Watcher;
+use iroh_blobs::{
+    get::request::get_hash_seq_and_sizes,
+    protocol::{ChunkRanges, GetRequest},
+    store::mem::MemStore,
+    ticket::BlobTicket,
+};
+
+use crate::{
+    crypto::{self, EncryptedShare},
+    metadata,
+    receive::{bind_endpoint, show_get_error},
+    types::*,
+};
+
+/// List the files behind a ticket without downloading them.
+///
+/// This connects to the sender and fetches the hash sequence, the size of every
+/// child and the collection metadata. Sizes are verified by fetching the last
+/// chunk of each file, which is discarded; nothing is written to disk.
+///
+/// The files of an encrypted share are not listed, use [`inspect_with_passphrase`]
+/// for those.
+pub async fn inspect(ticket: BlobTicket, common: CommonConfig) -> anyhow::Result<InspectResult> {
+    inspect_internal(ticket, common, None).await
+}
+
+/// List the files behind a ticket, decrypting the names of an encrypted share.
+///
+/// Sizes and hashes of an encrypted share are those of the decrypted files.
+pub async fn inspect_with_passphrase(
+    ticket: BlobTicket,
+    common: CommonConfig,
+    passphrase: &str,
+) -> anyhow::Result<InspectResult> {
+    inspect_internal(ticket, common, Some(passphrase)).await
+}
+
+async fn inspect_internal(
+    ticket: BlobTicket,
+    common: CommonConfig,
+    passphrase: Option<&str>,
+) -> anyhow::Result<InspectResult> {
+    let endpoint = bind_endpoint(ticket.addr(), &common).await?;
+    let hash = ticket.hash();
+    let endpoint_id = ticket.addr().id;
+
+    let connection = endpoint
+        .connect(ticket.addr().clone(), iroh_blobs::protocol::ALPN)
+        .await?;
+
+    let (_hash_seq, sizes) = get_hash_seq_and_sizes(&connection, &hash, 1024 * 1024 * 32, None)
+        .await
+        .map_err(show_get_error)?;
+
+    // The names are in the metadata blob, which is the first child
+    let store = MemStore::new();
+    let request = GetRequest::builder()
+        .root(ChunkRanges::all())
+        .child(0, ChunkRanges::all())
+        .build(hash);
+    store
+        .remote()
+        .execute_get(connection.clone(), request)
+        .complete()
+        .await
+        .map_err(show_get_error)?;
+    let (collection, _) = metadata::load_collection(hash, store.as_ref()).await?;
+
+    // The names of an encrypted share are in the manifest, the second child
+    let encrypted = crypto::is_encrypted(&collection);
+    let share = match passphrase {
+        Some(passphrase) if encrypted => {
+            let request = GetRequest::builder()
+                .child(1, ChunkRanges::all())
+                .build(hash);
+            store
+                .remote()
+                .execute_get(connection.clone(), request)
+                .complete()
+                .await
+                .map_err(show_get_error)?;
+            let (_, manifest) = collection
+                .iter()
+                .next()
+                .context("the encrypted share has no manifest")?;
+            let manifest = store.get_bytes(*manifest).await?;
+            Some(EncryptedShare::open(passphrase, &collection, manifest).await?)
+        }
+        _ => None,
+    };
+
+    let connection_type = endpoint
+        .conn_type(endpoint_id)
+        .map(|mut watcher| watcher.get())
+        .unwrap_or_default();
+
+    let files = match &share {
+        Some(share) => share
+            .view()
+            .iter()
+            .filter_map(|(_, blob)| share.entry(blob))
+            .map(|entry| InspectFile {
+                name: entry.name.clone(),
+                hash: entry.hash,
+                size: entry.size,
+            })
+            .collect(),
+        None if encrypted => Vec::new(),
+        None => collection
+            .iter()
+            .enumerate()
+            .map(|(index, (name, hash))| InspectFile {
+                name: name.clone(),
+                hash: *hash,
+                size: sizes.get(index + 1).copied().unwrap_or_default(),
+            })
+            .collect::<Vec<_>>(),
+    };
+    let total_size = if encrypted && share.is_none() {
+        // Without the manifest, only the size of the encrypted files is known.
+        // They follow the names and the manifest, the metadata follows them.
+        sizes.iter().skip(2).take(collection.len() - 1).sum()
+    } else {
+        files.iter().map(|file| file.size).sum()
+    };
+
+    connection.close(0u32.into(), b"done");
+    endpoint.close().await;
+    store.shutdown().await?;
+
+    Ok(InspectResult {
+        hash,
+        endpoint_id,
+        connection_type,
+        encrypted,
+        files,
+        total_size,
+    })
+}
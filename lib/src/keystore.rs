@@ -0,0 +1,214 @@
+//! Key store - persistent secret keys for named identities.
+//!
+//! Each identity is a file `<name>.key` holding the hex encoded secret key. The
+//! files are only readable by the current user, and the store directory defaults
+//! to `sendme/identities` below the platform config directory.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use iroh::{EndpointId, SecretKey};
+
+use crate::types::CommonConfig;
+
+/// Name of the identity used when none is given.
+pub const DEFAULT_IDENTITY: &str = "default";
+
+/// File extension of stored keys.
+const KEY_EXTENSION: &str = "key";
+
+/// A directory of named secret keys.
+#[derive(Debug, Clone)]
+pub struct KeyStore {
+    dir: PathBuf,
+}
+
+impl KeyStore {
+    /// Open a key store in the given directory.
+    ///
+    /// The directory is created on the first write.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Open the key store in the platform config directory.
+    pub fn open_default() -> anyhow::Result<Self> {
+        let config_dir = dirs::config_dir().context("could not determine the config directory")?;
+        Ok(Self::new(config_dir.join("sendme").join("identities")))
+    }
+
+    /// Open the key store selected by `common.key_dir`, or the default one.
+    pub fn from_config(common: &CommonConfig) -> anyhow::Result<Self> {
+        match &common.key_dir {
+            Some(dir) => Ok(Self::new(dir)),
+            None => Self::open_default(),
+        }
+    }
+
+    /// The directory the keys are stored in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The path of the key file for an identity.
+    pub fn path(&self, name: &str) -> anyhow::Result<PathBuf> {
+        validate_identity_name(name)?;
+        Ok(self.dir.join(format!("{name}.{KEY_EXTENSION}")))
+    }
+
+    /// Load the secret key of an identity, if it exists.
+    pub fn load(&self, name: &str) -> anyhow::Result<Option<SecretKey>> {
+        let path = self.path(name)?;
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        restrict_permissions(&path)?;
+        let key = parse_secret(text.trim())
+            .with_context(|| format!("invalid secret key in {}", path.display()))?;
+        Ok(Some(key))
+    }
+
+    /// Load the secret key of an identity, creating it if it does not exist yet.
+    pub fn load_or_create(&self, name: &str) -> anyhow::Result<SecretKey> {
+        if let Some(key) = self.load(name)? {
+            return Ok(key);
+        }
+        let path = self.path(name)?;
+        create_private_dir(&self.dir)?;
+
+        // Write to a temporary file first so a crash never leaves a truncated key
+        // behind, then link it into place. If another process created the identity
+        // in the meantime, its key wins.
+        let key = SecretKey::generate(&mut rand::rng());
+        let tmp = self.dir.join(format!(
+            ".{name}.{KEY_EXTENSION}.{}",
+            hex::encode(rand::random::<[u8; 8]>())
+        ));
+        let linked = write_private_file(&tmp, hex::encode(key.to_bytes()).as_bytes())
+            .and_then(|_| fs::hard_link(&tmp, &path));
+        fs::remove_file(&tmp).ok();
+        match linked {
+            Ok(()) => {
+                tracing::info!("created identity {name} at {}", path.display());
+                Ok(key)
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => self
+                .load(name)?
+                .context("identity was removed while it was being created"),
+            Err(e) => Err(e).with_context(|| format!("failed to write {}", path.display())),
+        }
+    }
+
+    /// List the stored identities with their endpoint ids, sorted by name.
+    pub fn list(&self) -> anyhow::Result<Vec<(String, EndpointId)>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", self.dir.display()))
+            }
+        };
+        let mut identities = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(KEY_EXTENSION) {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if validate_identity_name(name).is_err() {
+                continue;
+            }
+            if let Some(key) = self.load(name)? {
+                identities.push((name.to_string(), key.public()));
+            }
+        }
+        identities.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(identities)
+    }
+
+    /// Delete an identity. Returns false if it did not exist.
+    pub fn remove(&self, name: &str) -> anyhow::Result<bool> {
+        let path = self.path(name)?;
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e).with_context(|| format!("failed to remove {}", path.display())),
+        }
+    }
+}
+
+/// Check that an identity name is usable as a file name.
+///
+/// Names must be 1 to 64 characters long and may only contain ASCII letters,
+/// digits, `-` and `_`.
+pub fn validate_identity_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty() || name.len() > 64 {
+        anyhow::bail!("identity name must be 1 to 64 characters long");
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        anyhow::bail!("identity name {name:?} may only contain letters, digits, '-' and '_'");
+    }
+    Ok(())
+}
+
+/// Parse a hex encoded secret key.
+pub(crate) fn parse_secret(text: &str) -> anyhow::Result<SecretKey> {
+    let bytes = hex::decode(text).context("invalid hex in secret")?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("secret key must be 32 bytes"))?;
+    Ok(SecretKey::from_bytes(&bytes))
+}
+
+fn create_private_dir(dir: &Path) -> anyhow::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(dir)
+        .with_context(|| format!("failed to create {}", dir.display()))
+}
+
+fn write_private_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Make sure a key file is only accessible by its owner.
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        tracing::warn!(
+            "{} was accessible by other users, restricting it to 0600",
+            path.display()
+        );
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("failed to restrict permissions of {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> anyhow::Result<()> {
+    Ok(())
+}
@@ -5,8 +5,17 @@
 
 use anyhow::Context;
 
+pub mod archive;
+pub mod compression;
+pub mod crypto;
 pub mod export;
 pub mod import;
+pub mod inspect;
+pub mod keystore;
+pub mod metadata;
+pub mod nearby;
+pub mod offer;
+pub mod pairing;
 pub mod progress;
 pub mod receive;
 pub mod send;
@@ -16,36 +25,37 @@ pub use progress::*;
 pub use types::*;
 
 // Re-export commonly used types from dependencies
-pub use iroh::{RelayUrl, SecretKey};
-pub use iroh_blobs::{ticket::BlobTicket, BlobFormat, Hash};
+pub use iroh::{endpoint::ConnectionType, Endpoint, EndpointId, RelayUrl, SecretKey};
+pub use iroh_blobs::{format::collection::Collection, ticket::BlobTicket, BlobFormat, Hash};
+pub use tokio_util::sync::CancellationToken;
 
 // Public API
-pub use import::{get_export_path, import_from_bytes};
-pub use receive::{receive, receive_with_progress};
-pub use send::{send, send_with_progress};
+pub use import::{get_export_path, import_from_bytes, import_reader};
+pub use inspect::{inspect, inspect_with_passphrase};
+pub use keystore::{KeyStore, DEFAULT_IDENTITY};
+pub use pairing::{resolve_code, ShortCode};
+pub use receive::{receive, receive_to_writer, receive_with_progress};
+pub use send::{send, send_with_progress, SendHandle};
 
 /// Get or create a secret key for the iroh endpoint.
 ///
 /// If the `IROH_SECRET` environment variable is set, it will be parsed as a secret key.
-/// Otherwise, a new random secret key will be generated.
-pub fn get_or_create_secret(verbose: bool) -> anyhow::Result<SecretKey> {
-    match std::env::var("IROH_SECRET") {
-        Ok(secret) => {
-            let bytes = hex::decode(secret).context("invalid hex in secret")?;
-            let bytes: [u8; 32] = bytes
-                .try_into()
-                .map_err(|_| anyhow::anyhow!("secret key must be 32 bytes"))?;
-            Ok(SecretKey::from_bytes(&bytes))
-        }
-        Err(_) => {
-            let key = SecretKey::generate(&mut rand::rng());
-            if verbose {
-                let key = hex::encode(key.to_bytes());
-                eprintln!("using secret key {key}");
-            }
-            Ok(key)
-        }
+/// Otherwise, if `common.identity` is set, the key of that identity is loaded from the
+/// [`KeyStore`], and created on first use. If neither is set, a new random secret key
+/// will be generated.
+pub fn get_or_create_secret(common: &CommonConfig) -> anyhow::Result<SecretKey> {
+    if let Ok(secret) = std::env::var("IROH_SECRET") {
+        return keystore::parse_secret(&secret);
     }
+    let key = match &common.identity {
+        Some(name) => KeyStore::from_config(common)?.load_or_create(name)?,
+        None => SecretKey::generate(&mut rand::rng()),
+    };
+    if common.show_secret {
+        let key = hex::encode(key.to_bytes());
+        eprintln!("using secret key {key}");
+    }
+    Ok(key)
 }
 
 /// Convert a canonicalized path to a string.
@@ -94,13 +104,142 @@ pub fn canonicalized_path_to_string(
     Ok(path_str)
 }
 
+/// Validate a collection name received from a remote peer.
+///
+/// A valid name is a non-empty, `/` separated list of components that are all
+/// accepted by [`validate_path_component`]. Joining a valid name onto a directory
+/// can never point outside of that directory, on any platform.
+pub fn validate_collection_name(name: &str) -> anyhow::Result<()> {
+    anyhow::ensure!(!name.is_empty(), "collection name must not be empty");
+    for part in name.split('/') {
+        validate_path_component(part)
+            .with_context(|| format!("invalid collection name {name:?}"))?;
+    }
+    Ok(())
+}
+
 /// Validate a path component.
 ///
-/// Ensures the component does not contain path separators.
+/// Rejects components that are empty, `.` or `..` (also with trailing dots or
+/// spaces, which Windows strips), contain a path separator, NUL or `:`, which
+/// Windows reads as a drive letter or an alternate data stream such as
+/// `notes.txt:hidden`, or are a reserved Windows device name such as `CON` or
+/// `lpt1.txt`.
 pub fn validate_path_component(component: &str) -> anyhow::Result<()> {
+    anyhow::ensure!(!component.is_empty(), "path components must not be empty");
+    anyhow::ensure!(
+        !component.contains('/') && !component.contains('\\'),
+        "path components must not contain the path separators / or \\"
+    );
+    anyhow::ensure!(
+        !component.contains('\0'),
+        "path components must not contain NUL"
+    );
+    anyhow::ensure!(
+        !component.trim_end_matches(['.', ' ']).is_empty(),
+        "path components must not be . or .."
+    );
+    anyhow::ensure!(
+        !component.contains(':'),
+        "path components must not contain :, a drive letter or alternate data stream on Windows"
+    );
     anyhow::ensure!(
-        !component.contains('/'),
-        "path components must not contain the path separator /"
+        !is_reserved_device_name(component),
+        "path component {component:?} is a reserved device name"
     );
     Ok(())
 }
+
+/// Check if a component names a Windows device, ignoring case and extension.
+fn is_reserved_device_name(component: &str) -> bool {
+    let stem = component.split('.').next().unwrap_or_default();
+    let stem = stem.trim_end_matches(' ').to_ascii_uppercase();
+    match stem.as_str() {
+        "CON" | "PRN" | "AUX" | "NUL" | "CONIN$" | "CONOUT$" => true,
+        _ => match stem
+            .strip_prefix("COM")
+            .or_else(|| stem.strip_prefix("LPT"))
+        {
+            Some(n) => n.len() == 1 && n.as_bytes()[0].is_ascii_digit(),
+            None => false,
+        },
+    }
+}
+
+/// How often [`watch_path`] looks for a path until there is one.
+const PATH_LOOKUP_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+/// How often [`watch_path`] checks the round trip time of a path.
+const RTT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Report the path to `endpoint_id` as [`ConnectionStatus::PathChanged`] events.
+///
+/// Follows the connection type watcher of the endpoint, and also reports when
+/// the round trip time changes by more than a quarter. Nothing is reported
+/// until there is a path. Runs until the progress receiver is dropped, so the
+/// caller aborts it once the connection is done.
+pub(crate) async fn watch_path(
+    endpoint: iroh::Endpoint,
+    endpoint_id: EndpointId,
+    progress_tx: ProgressSenderTx,
+) {
+    use iroh::Watcher;
+    use n0_future::StreamExt;
+
+    // The endpoint only knows the path once it has an address of the remote
+    let watcher = loop {
+        if let Some(watcher) = endpoint.conn_type(endpoint_id) {
+            break watcher;
+        }
+        tokio::time::sleep(PATH_LOOKUP_INTERVAL).await;
+    };
+    let mut conn_types = watcher.stream();
+    let mut interval = tokio::time::interval(RTT_INTERVAL);
+    let mut conn_type = ConnectionType::None;
+    let mut reported: Option<(ConnectionType, Option<std::time::Duration>)> = None;
+    loop {
+        tokio::select! {
+            next = conn_types.next() => match next {
+                Some(next) => conn_type = next,
+                None => break,
+            },
+            _ = interval.tick() => {}
+        }
+        let rtt = endpoint.latency(endpoint_id);
+        let changed = match &reported {
+            None => conn_type != ConnectionType::None,
+            Some((last_type, last_rtt)) => *last_type != conn_type || rtt_moved(*last_rtt, rtt),
+        };
+        if !changed {
+            continue;
+        }
+        let (remote_addr, relay_url) = match &conn_type {
+            ConnectionType::Direct(addr) => (Some(*addr), None),
+            ConnectionType::Relay(url) => (None, Some(url.clone())),
+            ConnectionType::Mixed(addr, url) => (Some(*addr), Some(url.clone())),
+            ConnectionType::None => (None, None),
+        };
+        let status = ConnectionStatus::PathChanged {
+            endpoint_id: endpoint_id.fmt_short().to_string(),
+            direct: matches!(conn_type, ConnectionType::Direct(_)),
+            remote_addr,
+            relay_url,
+            rtt,
+        };
+        if progress_tx
+            .send(ProgressEvent::Connection(status))
+            .await
+            .is_err()
+        {
+            break;
+        }
+        reported = Some((conn_type.clone(), rtt));
+    }
+}
+
+/// Whether the round trip time changed enough to be reported again.
+fn rtt_moved(last: Option<std::time::Duration>, now: Option<std::time::Duration>) -> bool {
+    match (last, now) {
+        (Some(last), Some(now)) => now.abs_diff(last) > last / 4,
+        (last, now) => last.is_some() != now.is_some(),
+    }
+}
@@ -0,0 +1,272 @@
+//! File metadata that is not part of the content.
+//!
+//! A collection only maps names to hashes, so the permissions and modification
+//! times of the files, empty directories and symlinks are lost in transfer. The
+//! sender therefore adds a JSON encoded [`Metadata`] blob to the collection,
+//! which receivers that know about it apply after the export. Where it goes
+//! depends on whether older receivers can do without it, see [`MetadataBlob`].
+//!
+//! The metadata also records which files are [compressed](crate::compression),
+//! so then it must be applied for the export to be correct.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Component, Path},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use bytes::Bytes;
+use iroh_blobs::{
+    api::{blobs::AddBytesOptions, Store, TempTag},
+    format::collection::{Collection, SimpleStore},
+    hashseq::HashSeq,
+    BlobFormat, Hash,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::compression::Compression;
+
+/// Metadata of the files of a collection.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// Metadata by collection name. Every file of the collection is listed.
+    #[serde(default)]
+    pub files: BTreeMap<String, FileMetadata>,
+    /// Directories without any files, as collection names.
+    #[serde(default)]
+    pub empty_dirs: Vec<String>,
+    /// Symlink targets by collection name, see [`SymlinkPolicy::Preserve`].
+    /// Targets are relative and use `/` as the separator.
+    ///
+    /// [`SymlinkPolicy::Preserve`]: crate::SymlinkPolicy::Preserve
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub symlinks: BTreeMap<String, String>,
+}
+
+/// Metadata of a single file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMetadata {
+    /// Unix permission bits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Last modification time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<SystemTime>,
+    /// How the blob of the file is compressed, if it is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+}
+
+impl FileMetadata {
+    /// Take the metadata of a file on disk.
+    pub fn from_fs(metadata: &std::fs::Metadata) -> Self {
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(metadata.permissions().mode() & 0o7777)
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+        // Times before the epoch can't be serialized
+        let mtime = metadata
+            .modified()
+            .ok()
+            .filter(|time| time.duration_since(SystemTime::UNIX_EPOCH).is_ok());
+        Self {
+            mode,
+            mtime,
+            compression: None,
+        }
+    }
+
+    /// Apply the metadata to the file at `path`.
+    ///
+    /// Only the permission bits are applied, never setuid, setgid or sticky.
+    pub fn apply(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(mtime) = self.mtime {
+            std::fs::File::options()
+                .write(true)
+                .open(path)?
+                .set_modified(mtime)?;
+        }
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o777))?;
+        }
+        Ok(())
+    }
+}
+
+impl Metadata {
+    /// Collect the metadata of the files, directories and symlinks found while
+    /// importing.
+    ///
+    /// Only the directories that contain nothing else are kept as empty
+    /// directories.
+    pub fn new(
+        files: BTreeMap<String, FileMetadata>,
+        dirs: Vec<String>,
+        symlinks: BTreeMap<String, String>,
+    ) -> Self {
+        let parents = files
+            .keys()
+            .chain(&dirs)
+            .chain(symlinks.keys())
+            .flat_map(|name| name.match_indices('/').map(|(end, _)| &name[..end]))
+            .collect::<HashSet<_>>();
+        let empty_dirs = dirs
+            .iter()
+            .filter(|dir| !parents.contains(dir.as_str()))
+            .cloned()
+            .collect();
+        Self {
+            files,
+            empty_dirs,
+            symlinks,
+        }
+    }
+
+    /// Check whether the metadata must be applied for the export to be
+    /// correct, because files are compressed or symlinks would be missing.
+    pub fn is_required(&self) -> bool {
+        !self.symlinks.is_empty() || self.files.values().any(|file| file.compression.is_some())
+    }
+
+    /// Check whether `collection` contains every file listed in the metadata.
+    pub fn is_complete(&self, collection: &Collection) -> bool {
+        let names = collection
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<HashSet<_>>();
+        self.files.keys().all(|name| names.contains(name.as_str()))
+    }
+}
+
+/// Convert a symlink target for [`Metadata::symlinks`].
+///
+/// Returns `None` for absolute targets and targets that are not valid unicode.
+pub fn link_target(target: &Path) -> Option<String> {
+    let parts = target
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            Component::ParentDir => Some(".."),
+            Component::CurDir => Some("."),
+            Component::RootDir | Component::Prefix(_) => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
+
+/// Check that the symlink `name` with the relative `target` can't point
+/// outside of the directory the collection is exported to.
+///
+/// `..` is only accepted at the start of the target, so a link can't climb out
+/// by passing through another link.
+pub fn check_link_target(name: &str, target: &str) -> anyhow::Result<()> {
+    let escapes = || anyhow::anyhow!("symlink {name} points outside of the export directory");
+    if target.is_empty() || target.starts_with('/') || target.contains(['\\', '\0']) {
+        return Err(escapes());
+    }
+    // The number of directories above the link
+    let mut depth = name.split('/').count() - 1;
+    let mut descended = false;
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." if descended || depth == 0 => return Err(escapes()),
+            ".." => depth -= 1,
+            _ => descended = true,
+        }
+    }
+    Ok(())
+}
+
+/// Where the metadata blob of a collection is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataBlob {
+    /// The last child of the hash sequence, after the files, without a name.
+    ///
+    /// [`Collection::load`] refuses a hash sequence with more children than
+    /// names, so older receivers fail instead of exporting the files without
+    /// it. Used when the metadata [is required](Metadata::is_required) and for
+    /// encrypted shares, where the blob is encrypted like every file.
+    Child(Hash),
+    /// The JSON directly after the names, in the first child.
+    ///
+    /// [`Collection::load`] ignores anything after the names, so older
+    /// receivers export the files without applying it.
+    Names(Bytes),
+}
+
+/// Store a collection of `files` with its `metadata`.
+///
+/// Returns the tag of the hash sequence, which also protects its children.
+pub async fn store_collection(
+    files: Collection,
+    metadata: Option<MetadataBlob>,
+    db: &Store,
+) -> anyhow::Result<TempTag> {
+    let Some(metadata) = metadata else {
+        return files.store(db).await;
+    };
+    // The first blob is the one with the names
+    let names = files.to_blobs().next().expect("names blob");
+    let (names, child) = match metadata {
+        MetadataBlob::Child(hash) => (names, Some(hash)),
+        MetadataBlob::Names(json) => ([names, json].concat().into(), None),
+    };
+    let names = db.add_bytes(names).temp_tag().await?;
+    let links = std::iter::once(names.hash())
+        .chain(files.iter().map(|(_, hash)| *hash))
+        .chain(child)
+        .collect::<HashSeq>();
+    let tag = db
+        .add_bytes_with_opts(AddBytesOptions {
+            data: links.into(),
+            format: BlobFormat::HashSeq,
+        })
+        .temp_tag()
+        .await?;
+    Ok(tag)
+}
+
+/// Load the collection at `root`, returning the files and the metadata blob,
+/// if it has one.
+pub async fn load_collection(
+    root: Hash,
+    db: &Store,
+) -> anyhow::Result<(Collection, Option<MetadataBlob>)> {
+    let links = HashSeq::try_from(db.get_bytes(root).await?)?;
+    // The metadata blob is the one child without a name
+    let without_metadata = WithoutLastChild { db, root };
+    if let Ok(files) = Collection::load(root, &without_metadata).await {
+        return Ok((files, links.iter().last().map(MetadataBlob::Child)));
+    }
+    // Or it follows the names, which are encoded the same way again
+    let files = Collection::load(root, db).await?;
+    let names = links.iter().next().context("empty hash sequence")?;
+    let names = db.get_bytes(names).await?;
+    let encoded = files.to_blobs().next().map_or(0, |names| names.len());
+    let metadata = (names.len() > encoded).then(|| MetadataBlob::Names(names.slice(encoded..)));
+    Ok((files, metadata))
+}
+
+/// A store that leaves the last child out of the hash sequence at `root`.
+struct WithoutLastChild<'a> {
+    db: &'a Store,
+    root: Hash,
+}
+
+impl SimpleStore for WithoutLastChild<'_> {
+    async fn load(&self, hash: Hash) -> anyhow::Result<Bytes> {
+        let data = self.db.get_bytes(hash).await?;
+        if hash == self.root {
+            Ok(data.slice(..data.len().saturating_sub(32)))
+        } else {
+            Ok(data)
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Nearby devices on the local network.
+//!
+//! A [`Nearby`] node announces a display name and device model with mDNS, as
+//! the discovery user data of its endpoint, and keeps track of the other nodes
+//! it sees. Nodes use their own mDNS service name, so plain iroh endpoints on
+//! the same network don't show up as devices.
+//!
+//! Nearby nodes take [offers](crate::offer) of shares, so a share can be
+//! offered to one of the devices directly.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use iroh::{
+    discovery::{
+        mdns::{DiscoveryEvent, MdnsDiscovery},
+        UserData,
+    },
+    protocol::Router,
+    Endpoint, EndpointAddr, EndpointId,
+};
+use n0_future::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc};
+
+use crate::{
+    get_or_create_secret,
+    offer::{self, Answer, IncomingOffer, Offer, OfferProtocol},
+    progress::ProgressSenderTx,
+    CommonConfig, ShareTicket,
+};
+
+/// mDNS service name nearby nodes announce themselves under.
+const SERVICE_NAME: &str = "sendme";
+/// Longest device name and model that are announced, in bytes, so both fit
+/// into the discovery user data.
+const MAX_NAME_LEN: usize = 96;
+
+/// How a device presents itself to others.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    /// Display name, e.g. the host name.
+    pub name: String,
+    /// Device model, e.g. "MacBook Pro" or "Pixel 8".
+    pub model: Option<String>,
+}
+
+impl DeviceInfo {
+    fn to_user_data(&self) -> anyhow::Result<UserData> {
+        let info = Self {
+            name: truncate(&self.name),
+            model: self.model.as_deref().map(truncate),
+        };
+        Ok(serde_json::to_string(&info)?.try_into()?)
+    }
+
+    fn from_user_data(user_data: &UserData) -> Option<Self> {
+        serde_json::from_str(user_data.as_ref()).ok()
+    }
+}
+
+/// Cut a name to [`MAX_NAME_LEN`] bytes at a character boundary.
+fn truncate(name: &str) -> String {
+    let mut end = name.len().min(MAX_NAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    name[..end].to_string()
+}
+
+/// A device found on the local network.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Device {
+    /// Endpoint id of the device's nearby node.
+    pub id: EndpointId,
+    /// Name and model the device announces.
+    pub info: DeviceInfo,
+}
+
+/// A change in the list of nearby devices.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NearbyEvent {
+    /// A device was found, or changed its name or model.
+    Appeared(Device),
+    /// A device is gone.
+    Disappeared { id: EndpointId },
+}
+
+/// A node that announces this device on the local network, lists the other
+/// devices, and takes offers of shares.
+///
+/// The node runs until it is shut down or dropped.
+#[derive(Debug)]
+pub struct Nearby {
+    router: Router,
+    info: DeviceInfo,
+    devices: Arc<Mutex<BTreeMap<EndpointId, (Device, EndpointAddr)>>>,
+    events: broadcast::Sender<NearbyEvent>,
+    watcher: tokio::task::JoinHandle<()>,
+}
+
+impl Nearby {
+    /// Start announcing this device as `info`.
+    ///
+    /// The endpoint uses the identity of `common`, if any, so other devices
+    /// can recognise it across runs. Offers made to this device arrive on the
+    /// returned channel.
+    pub async fn spawn(
+        info: DeviceInfo,
+        common: &CommonConfig,
+    ) -> anyhow::Result<(Self, mpsc::Receiver<IncomingOffer>)> {
+        let secret_key = get_or_create_secret(common)?;
+        let mdns = MdnsDiscovery::builder()
+            .service_name(SERVICE_NAME)
+            .build(secret_key.public())?;
+        let mut builder = Endpoint::builder()
+            .alpns(vec![offer::ALPN.to_vec()])
+            .secret_key(secret_key)
+            .relay_mode(common.relay_mode())
+            .discovery(mdns.clone())
+            .user_data_for_discovery(info.to_user_data()?);
+        if let Some(addr) = common.magic_ipv4_addr {
+            builder = builder.bind_addr_v4(addr);
+        }
+        if let Some(addr) = common.magic_ipv6_addr {
+            builder = builder.bind_addr_v6(addr);
+        }
+        let endpoint = builder.bind().await?;
+
+        let devices = Arc::new(Mutex::new(BTreeMap::new()));
+        let (events, _) = broadcast::channel(64);
+        let watcher = tokio::spawn(watch_devices(
+            mdns,
+            endpoint.id(),
+            devices.clone(),
+            events.clone(),
+        ));
+        let (offers, offers_rx) = OfferProtocol::new();
+        let router = Router::builder(endpoint)
+            .accept(offer::ALPN, offers)
+            .spawn();
+        let nearby = Self {
+            router,
+            info,
+            devices,
+            events,
+            watcher,
+        };
+        Ok((nearby, offers_rx))
+    }
+
+    /// The endpoint id other devices see this device as.
+    pub fn id(&self) -> EndpointId {
+        self.router.endpoint().id()
+    }
+
+    /// How this device presents itself.
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    /// The devices currently seen on the local network.
+    pub fn devices(&self) -> Vec<Device> {
+        let devices = self.devices.lock().unwrap();
+        devices.values().map(|(device, _)| device.clone()).collect()
+    }
+
+    /// Follow the devices on the local network.
+    ///
+    /// The events start with [`NearbyEvent::Appeared`] for every device seen
+    /// so far, then report changes as they happen.
+    pub fn events(&self) -> NearbyEvents {
+        // Subscribe before taking the snapshot, so no change falls in between
+        let changes = self.events.subscribe();
+        let known = self
+            .devices()
+            .into_iter()
+            .map(NearbyEvent::Appeared)
+            .collect();
+        NearbyEvents { known, changes }
+    }
+
+    /// Offer a share to the device `id` from `endpoint`, the endpoint serving
+    /// the share, see [`offer::send_offer`].
+    ///
+    /// Waits for the user of the device to answer, and hands over `ticket` if
+    /// they accept all or some of the files.
+    pub async fn offer(
+        &self,
+        endpoint: &Endpoint,
+        id: EndpointId,
+        offer: &Offer,
+        ticket: &ShareTicket,
+        progress_tx: Option<ProgressSenderTx>,
+    ) -> anyhow::Result<Answer> {
+        let addr = self
+            .devices
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|(_, addr)| addr.clone())
+            .with_context(|| format!("device {} is not nearby", id.fmt_short()))?;
+        offer::send_offer(endpoint, addr, offer, ticket, progress_tx).await
+    }
+
+    /// Stop announcing this device and taking offers.
+    pub async fn shutdown(self) -> anyhow::Result<()> {
+        self.watcher.abort();
+        self.router.shutdown().await?;
+        Ok(())
+    }
+}
+
+impl Drop for Nearby {
+    fn drop(&mut self) {
+        self.watcher.abort();
+    }
+}
+
+/// Changes of the nearby devices, see [`Nearby::events`].
+#[derive(Debug)]
+pub struct NearbyEvents {
+    known: VecDeque<NearbyEvent>,
+    changes: broadcast::Receiver<NearbyEvent>,
+}
+
+impl NearbyEvents {
+    /// Wait for the next change. Returns `None` once the node is shut down.
+    pub async fn recv(&mut self) -> Option<NearbyEvent> {
+        if let Some(event) = self.known.pop_front() {
+            return Some(event);
+        }
+        loop {
+            match self.changes.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::debug!("missed {n} nearby events");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Keep the list of devices up to date with what mDNS finds.
+async fn watch_devices(
+    mdns: MdnsDiscovery,
+    own_id: EndpointId,
+    devices: Arc<Mutex<BTreeMap<EndpointId, (Device, EndpointAddr)>>>,
+    events: broadcast::Sender<NearbyEvent>,
+) {
+    let mut discovered = mdns.subscribe().await;
+    while let Some(event) = discovered.next().await {
+        let mut devices = devices.lock().unwrap();
+        match event {
+            DiscoveryEvent::Discovered { endpoint_info, .. } => {
+                let id = endpoint_info.endpoint_id;
+                if id == own_id {
+                    continue;
+                }
+                // Nodes without a name are not sendme devices
+                let Some(info) = endpoint_info
+                    .data
+                    .user_data()
+                    .and_then(DeviceInfo::from_user_data)
+                else {
+                    continue;
+                };
+                let device = Device { id, info };
+                let addr = endpoint_info.into_endpoint_addr();
+                let changed = devices.get(&id).is_none_or(|(known, _)| *known != device);
+                devices.insert(id, (device.clone(), addr));
+                if changed {
+                    events.send(NearbyEvent::Appeared(device)).ok();
+                }
+            }
+            DiscoveryEvent::Expired { endpoint_id } => {
+                if devices.remove(&endpoint_id).is_some() {
+                    events
+                        .send(NearbyEvent::Disappeared { id: endpoint_id })
+                        .ok();
+                }
+            }
+        }
+    }
+}
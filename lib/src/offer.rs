@@ -0,0 +1,333 @@
+//! Offering a share before the receiver fetches it.
+//!
+//! Receiving is pull based: whoever holds a ticket fetches the data. To push a
+//! share to someone instead, the sender first offers it over the [`ALPN`]
+//! protocol. The receiver sees a manifest of the files, who sends them and an
+//! optional message, and answers. Only if it accepts all or some of the files
+//! is it handed the ticket, which it then receives like any other, limited to
+//! the accepted files by an [`IncludeFilter`].
+//!
+//! Offers are made from the endpoint serving the share. The receiver only
+//! takes a ticket for the offered hash, served by the peer that offered it,
+//! so what it receives is what it was shown.
+//!
+//! The protocol runs on a single bidirectional stream. The sender writes the
+//! [`Offer`], the receiver writes its [`Answer`], and unless the offer was
+//! rejected the sender writes the ticket. Messages are JSON, prefixed with
+//! their length as a big endian u32.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use iroh::{
+    endpoint::{Connection, RecvStream, SendStream},
+    protocol::{AcceptError, ProtocolHandler},
+    Endpoint, EndpointAddr, EndpointId,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    nearby::DeviceInfo, progress::*, types::name_selects, validate_collection_name, Hash,
+    IncludeFilter, InspectFile, SendResult, ShareTicket,
+};
+
+/// ALPN of the offer protocol.
+pub const ALPN: &[u8] = b"/sendme/offer/0";
+
+/// Longest offer or answer accepted from a peer.
+const MAX_MESSAGE_LEN: usize = 1024 * 1024;
+/// How long an offer waits for the user to answer it.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// What a receiver is offered, before it decides to receive it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Offer {
+    /// Who makes the offer.
+    pub from: DeviceInfo,
+    /// Hash of the offered collection, which the ticket has to match.
+    pub hash: Hash,
+    /// The offered files with their sizes. Empty for passphrase protected
+    /// shares, whose names are only known with the passphrase.
+    pub files: Vec<InspectFile>,
+    /// Total size of the files in bytes.
+    pub total_size: u64,
+    /// Whether the share is protected by a passphrase.
+    pub encrypted: bool,
+    /// A note from the sender, shown with the offer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Offer {
+    /// The offer of a share, made by `from`.
+    pub fn new(from: DeviceInfo, share: &SendResult) -> Self {
+        Self {
+            from,
+            hash: share.hash,
+            files: share.files.clone(),
+            total_size: share.total_size,
+            encrypted: share.encrypted,
+            message: None,
+        }
+    }
+
+    /// Add a note to the offer.
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Check the offer of an untrusted peer.
+    fn validate(&self) -> anyhow::Result<()> {
+        for file in &self.files {
+            validate_collection_name(&file.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a receiver answers an offer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Answer {
+    /// Receive all files.
+    Accept,
+    /// Receive only the files with these names, and everything below
+    /// directories with these names, like [`IncludeFilter::Names`].
+    AcceptSubset(Vec<String>),
+    /// Receive nothing.
+    Reject,
+}
+
+impl Answer {
+    /// Whether any files are to be received.
+    pub fn is_accepted(&self) -> bool {
+        !matches!(self, Self::Reject)
+    }
+
+    /// The filter selecting the accepted files, if any are.
+    pub fn include(&self) -> Option<IncludeFilter> {
+        match self {
+            Self::Accept => Some(IncludeFilter::All),
+            Self::AcceptSubset(names) => Some(IncludeFilter::Names(names.clone())),
+            Self::Reject => None,
+        }
+    }
+}
+
+/// An accepted offer, ready to be received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Accepted {
+    /// The ticket to receive the share with.
+    pub ticket: ShareTicket,
+    /// Selects the accepted files.
+    pub include: IncludeFilter,
+}
+
+/// An offer made to this node, waiting for an answer.
+///
+/// Dropping it rejects the offer.
+#[derive(Debug)]
+pub struct IncomingOffer {
+    /// Endpoint id of the sender.
+    pub sender: EndpointId,
+    /// What is offered.
+    pub offer: Offer,
+    answer: oneshot::Sender<(Answer, oneshot::Sender<anyhow::Result<ShareTicket>>)>,
+}
+
+impl IncomingOffer {
+    /// Answer the offer. Unless it is rejected, waits for the sender to hand
+    /// over the ticket.
+    ///
+    /// Only names of offered files and their directories can be accepted, so
+    /// passphrase protected offers can only be accepted as a whole. An invalid
+    /// answer rejects the offer.
+    pub async fn answer(self, answer: Answer) -> anyhow::Result<Option<Accepted>> {
+        if let Answer::AcceptSubset(names) = &answer {
+            anyhow::ensure!(!names.is_empty(), "no files selected");
+            for name in names {
+                let offered = self
+                    .offer
+                    .files
+                    .iter()
+                    .any(|file| name_selects(name, &file.name));
+                anyhow::ensure!(offered, "{name:?} is not offered");
+            }
+        }
+        let include = answer.include();
+        let (ticket_tx, ticket_rx) = oneshot::channel();
+        self.answer
+            .send((answer, ticket_tx))
+            .map_err(|_| anyhow::anyhow!("the offer was withdrawn"))?;
+        let Some(include) = include else {
+            return Ok(None);
+        };
+        let ticket = ticket_rx
+            .await
+            .context("the sender did not send the ticket")??;
+        Ok(Some(Accepted { ticket, include }))
+    }
+
+    /// Accept all files and get the ticket to receive them with.
+    pub async fn accept(self) -> anyhow::Result<Accepted> {
+        let accepted = self.answer(Answer::Accept).await?;
+        Ok(accepted.expect("accepted offers have a ticket"))
+    }
+
+    /// Reject the offer.
+    pub fn reject(self) {
+        self.answer
+            .send((Answer::Reject, oneshot::channel().0))
+            .ok();
+    }
+}
+
+/// The receiving side of the offer protocol, handing offers to the user.
+///
+/// Register it on a router under [`ALPN`]. Offers arrive on the channel
+/// returned by [`OfferProtocol::new`], and are rejected if nobody answers
+/// them in time, or too many are waiting already.
+#[derive(Debug, Clone)]
+pub struct OfferProtocol {
+    offers_tx: mpsc::Sender<IncomingOffer>,
+}
+
+impl OfferProtocol {
+    /// Create the protocol handler and the channel its offers arrive on.
+    pub fn new() -> (Self, mpsc::Receiver<IncomingOffer>) {
+        let (offers_tx, offers_rx) = mpsc::channel(8);
+        (Self { offers_tx }, offers_rx)
+    }
+
+    async fn handle(&self, connection: &Connection) -> anyhow::Result<()> {
+        let (mut send, mut recv) = connection.accept_bi().await?;
+        let offer: Offer = read_message(&mut recv).await?;
+        offer.validate()?;
+        let (hash, encrypted) = (offer.hash, offer.encrypted);
+        let (answer_tx, answer_rx) = oneshot::channel();
+        let incoming = IncomingOffer {
+            sender: connection.remote_id(),
+            offer,
+            answer: answer_tx,
+        };
+        let reply = match self.offers_tx.try_send(incoming) {
+            Ok(()) => tokio::time::timeout(ANSWER_TIMEOUT, answer_rx)
+                .await
+                .ok()
+                .and_then(Result::ok),
+            Err(_) => None,
+        };
+        let (answer, ticket_tx) = reply.unzip();
+        let answer = answer.unwrap_or(Answer::Reject);
+        write_message(&mut send, &answer).await?;
+        if let (true, Some(ticket_tx)) = (answer.is_accepted(), ticket_tx) {
+            let ticket: String = read_message(&mut recv).await?;
+            let ticket = ticket.parse::<ShareTicket>().and_then(|ticket| {
+                anyhow::ensure!(
+                    ticket.ticket.hash() == hash
+                        && ticket.encrypted == encrypted
+                        && ticket.ticket.addr().id == connection.remote_id(),
+                    "the ticket is not for the offered share"
+                );
+                Ok(ticket)
+            });
+            ticket_tx.send(ticket).ok();
+        }
+        send.finish()?;
+        Ok(())
+    }
+}
+
+impl ProtocolHandler for OfferProtocol {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        if let Err(e) = self.handle(&connection).await {
+            let endpoint_id = connection.remote_id().fmt_short().to_string();
+            tracing::info!("offer from {endpoint_id} failed: {e:#}");
+        }
+        connection.closed().await;
+        Ok(())
+    }
+}
+
+/// Offer a share to the node at `addr`, and hand over `ticket` if it is
+/// accepted.
+///
+/// `endpoint` has to be the one serving the share, the receiver refuses
+/// tickets of other endpoints.
+///
+/// Waits for the receiver to answer, and reports the offer and the answer as
+/// [`ConnectionStatus`] events.
+pub async fn send_offer(
+    endpoint: &Endpoint,
+    addr: impl Into<EndpointAddr>,
+    offer: &Offer,
+    ticket: &ShareTicket,
+    progress_tx: Option<ProgressSenderTx>,
+) -> anyhow::Result<Answer> {
+    let addr = addr.into();
+    let endpoint_id = addr.id.to_string();
+    let connection = endpoint
+        .connect(addr, ALPN)
+        .await
+        .context("failed to reach the receiver")?;
+    if let Some(tx) = &progress_tx {
+        tx.send(ProgressEvent::Connection(ConnectionStatus::OfferSent {
+            endpoint_id: endpoint_id.clone(),
+        }))
+        .await
+        .ok();
+    }
+    let answer = make_offer(&connection, offer, ticket).await;
+    connection.close(0u32.into(), b"done");
+    let answer = answer?;
+    if let Some(tx) = &progress_tx {
+        tx.send(ProgressEvent::Connection(ConnectionStatus::OfferAnswered {
+            endpoint_id,
+            answer: answer.clone(),
+        }))
+        .await
+        .ok();
+    }
+    Ok(answer)
+}
+
+/// Send an offer and, once it is accepted, the ticket.
+async fn make_offer(
+    connection: &Connection,
+    offer: &Offer,
+    ticket: &ShareTicket,
+) -> anyhow::Result<Answer> {
+    let (mut send, mut recv) = connection.open_bi().await?;
+    write_message(&mut send, offer).await?;
+    let answer: Answer = read_message(&mut recv)
+        .await
+        .context("the receiver did not answer")?;
+    if answer.is_accepted() {
+        write_message(&mut send, &ticket.to_string()).await?;
+    }
+    send.finish()?;
+    // Wait for the receiver to read the ticket
+    recv.read_to_end(0).await.ok();
+    Ok(answer)
+}
+
+/// Write a length prefixed JSON message.
+async fn write_message(send: &mut SendStream, message: &impl Serialize) -> anyhow::Result<()> {
+    let message = serde_json::to_vec(message)?;
+    send.write_all(&(message.len() as u32).to_be_bytes())
+        .await?;
+    send.write_all(&message).await?;
+    Ok(())
+}
+
+/// Read a length prefixed JSON message.
+async fn read_message<T: serde::de::DeserializeOwned>(recv: &mut RecvStream) -> anyhow::Result<T> {
+    let mut len = [0u8; 4];
+    recv.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len) as usize;
+    anyhow::ensure!(len <= MAX_MESSAGE_LEN, "message is too long");
+    let mut message = vec![0u8; len];
+    recv.read_exact(&mut message).await?;
+    Ok(serde_json::from_slice(&message)?)
+}
@@ -0,0 +1,471 @@
+//! Short codes for pairing a receiver with a sender.
+//!
+//! Tickets are too long to read out or type on a phone. Instead, a sender can
+//! hand out a short code like `482913-canyon-violin`. The number at the start
+//! is the nameplate, a random lookup id that only tells receivers where to
+//! look: the sender announces it on the local network as discovery user data,
+//! and publishes its endpoint id under a key derived from the nameplate alone.
+//! Both sides then run a SPAKE2 exchange with the code as password over the
+//! [`ALPN`] protocol, and only a receiver that proves it knows the code is
+//! sent the ticket.
+//!
+//! The words are never published, so nothing can be used to check guesses of
+//! the code offline. The nameplate is random for every share, so concurrent
+//! shares rarely collide. The sending endpoint has a fresh key, so the record
+//! doesn't reveal the identity of the sender either.
+//!
+//! Each code can be tried once. After the first receiver that checks its guess
+//! of the code, right or wrong, the sender refuses all others, so a code can't
+//! be guessed online either. Connecting without a guess doesn't use it up.
+//!
+//! The nameplate is not secret: it is announced in the clear on the local
+//! network, and there are few enough of them to try all. So anyone can publish
+//! a record of their own under a nameplate, or use up the one guess of a code,
+//! and keep the receiver from reaching the sender. Without the words they
+//! still can't get the ticket, or make the receiver accept one of theirs:
+//! each connection is one guess of the words, and after a wrong one the SPAKE2
+//! exchange fails on both ends. Binding the record to the words would not
+//! help, as it would let anyone check guesses of them offline.
+
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    time::Duration,
+};
+
+use anyhow::Context;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use iroh::{
+    discovery::{
+        mdns::{DiscoveryEvent, MdnsDiscovery},
+        pkarr::PkarrPublisher,
+        Discovery, EndpointData, UserData,
+    },
+    endpoint::{Connection, ConnectionError, VarInt},
+    protocol::{AcceptError, ProtocolHandler},
+    Endpoint, EndpointAddr, EndpointId, SecretKey,
+};
+use n0_future::StreamExt;
+use rand::Rng;
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+
+use crate::{
+    progress::*,
+    receive::{bind_endpoint, find_endpoint, DISCOVERY_TIMEOUT},
+    CommonConfig, NetworkMode, ShareTicket,
+};
+
+/// ALPN of the pairing protocol.
+pub const ALPN: &[u8] = b"/sendme/pake/0";
+
+/// Words of a short code. There are 256, so each word adds 8 bits.
+const WORDS: [&str; 256] = [
+    "acid", "acorn", "actor", "adobe", "agent", "alarm", "album", "alpine", "amber", "anchor",
+    "angle", "ankle", "apple", "apron", "arcade", "arrow", "aspen", "atlas", "attic", "autumn",
+    "badge", "bagel", "bakery", "bamboo", "banjo", "barley", "barrel", "basil", "basket", "beacon",
+    "beaver", "bison", "blanket", "bonfire", "bottle", "breeze", "brick", "bridge", "bronze",
+    "bubble", "bucket", "bugle", "butter", "cabin", "cactus", "camel", "canal", "candle", "canoe",
+    "canyon", "carbon", "carpet", "carrot", "castle", "cedar", "cello", "cereal", "chalk",
+    "cherry", "chess", "chimney", "cider", "cinema", "circus", "citrus", "clock", "cloud",
+    "clover", "cobalt", "cocoa", "comet", "copper", "coral", "cotton", "cowboy", "coyote",
+    "crater", "crayon", "cricket", "crystal", "dagger", "daisy", "dancer", "delta", "denim",
+    "desert", "diamond", "dingo", "dolphin", "domino", "donkey", "dragon", "drum", "eagle",
+    "easel", "echo", "eclipse", "elbow", "ember", "emerald", "engine", "falcon", "fern", "ferry",
+    "fiddle", "figure", "flame", "flute", "forest", "fossil", "fox", "galaxy", "garden", "garlic",
+    "gazelle", "geyser", "ginger", "glacier", "globe", "goblet", "granite", "grape", "gravel",
+    "guitar", "hammer", "harbor", "harvest", "hazel", "helmet", "heron", "honey", "horizon",
+    "igloo", "indigo", "island", "ivory", "jacket", "jaguar", "jelly", "jigsaw", "jungle", "kayak",
+    "kernel", "kettle", "kiwi", "ladder", "lagoon", "lantern", "laser", "lemon", "lentil", "lilac",
+    "linen", "lizard", "locket", "lotus", "magnet", "mango", "maple", "marble", "meadow", "melon",
+    "meteor", "mitten", "mosaic", "muffin", "mustard", "napkin", "nectar", "nickel", "noodle",
+    "nutmeg", "oasis", "ocean", "olive", "onion", "orbit", "orchid", "otter", "oyster", "paddle",
+    "panda", "papaya", "parrot", "pasta", "peanut", "pebble", "pelican", "pepper", "piano",
+    "pickle", "pigeon", "pillow", "pirate", "planet", "plum", "pocket", "pony", "poppy", "potato",
+    "prism", "pumpkin", "puzzle", "quartz", "quill", "rabbit", "radar", "radish", "raven",
+    "ribbon", "river", "robin", "rocket", "saddle", "salmon", "sandal", "satin", "scarf", "shadow",
+    "shovel", "silver", "sketch", "sleigh", "sparrow", "spinach", "sponge", "squid", "statue",
+    "summit", "sunset", "tablet", "tango", "teapot", "thistle", "thunder", "tiger", "timber",
+    "tomato", "topaz", "tulip", "tunnel", "turtle", "valley", "velvet", "violin", "volcano",
+    "waffle", "walnut", "walrus", "willow", "window", "wizard", "yogurt", "zebra", "zephyr",
+    "zipper",
+];
+
+const MAX_NAMEPLATE: u32 = 999_999;
+/// Context for deriving the key the endpoint id of a sender is published
+/// under. Only the nameplate goes into it, never the words.
+const NAMEPLATE_KEY_CONTEXT: &str = "sendme short code nameplate v1";
+/// Discovery user data of a sender, in front of the nameplate.
+const NAMEPLATE_PREFIX: &str = "sendme-code:";
+/// A SPAKE2 message is a compressed Ed25519 point behind a one byte tag.
+const MESSAGE_LEN: usize = 33;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+/// Associated data of the confirmations, so neither can be reflected.
+const RECEIVER_CONFIRM: &[u8] = b"sendme pake receiver";
+const SENDER_CONFIRM: &[u8] = b"sendme pake sender";
+const MAX_TICKET_LEN: usize = 4096;
+/// How long the sender waits for the receiver to read the ticket.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Application error codes a pairing connection is closed with.
+const CLOSE_WRONG_CODE: u32 = 1;
+const CLOSE_CODE_USED: u32 = 2;
+
+/// A short, human friendly code that stands in for a ticket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShortCode {
+    nameplate: u32,
+    words: [&'static str; 2],
+}
+
+impl ShortCode {
+    /// Generate a random code.
+    pub fn generate() -> Self {
+        let mut rng = rand::rng();
+        Self {
+            nameplate: rng.random_range(1..=MAX_NAMEPLATE),
+            words: [
+                WORDS[rng.random_range(0..WORDS.len())],
+                WORDS[rng.random_range(0..WORDS.len())],
+            ],
+        }
+    }
+
+    /// Discovery user data the sender of the code announces.
+    pub(crate) fn user_data(&self) -> UserData {
+        format!("{NAMEPLATE_PREFIX}{}", self.nameplate)
+            .try_into()
+            .expect("short user data")
+    }
+
+    /// The key the endpoint id of the sender is published under.
+    ///
+    /// Anyone who knows the nameplate can derive it, and publish a record of
+    /// their own with it. It says nothing about the words.
+    fn nameplate_key(&self) -> SecretKey {
+        SecretKey::from_bytes(&blake3::derive_key(
+            NAMEPLATE_KEY_CONTEXT,
+            &self.nameplate.to_le_bytes(),
+        ))
+    }
+
+    fn start(&self) -> (Spake2<Ed25519Group>, Vec<u8>) {
+        Spake2::<Ed25519Group>::start_symmetric(
+            &Password::new(self.to_string()),
+            &Identity::new(ALPN),
+        )
+    }
+}
+
+impl Display for ShortCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}-{}", self.nameplate, self.words[0], self.words[1])
+    }
+}
+
+impl FromStr for ShortCode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        let mut parts = s.split('-');
+        let (Some(nameplate), Some(first), Some(second), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("a short code looks like 482913-canyon-violin");
+        };
+        let nameplate = nameplate
+            .parse::<u32>()
+            .ok()
+            .filter(|n| (1..=MAX_NAMEPLATE).contains(n))
+            .context("a short code starts with a number from 1 to 999999")?;
+        let word = |word: &str| {
+            WORDS
+                .iter()
+                .find(|w| **w == word)
+                .copied()
+                .with_context(|| format!("unknown word {word:?} in short code"))
+        };
+        Ok(Self {
+            nameplate,
+            words: [word(first)?, word(second)?],
+        })
+    }
+}
+
+/// The sending side of the pairing protocol.
+///
+/// Hands out the ticket set with [`Pairing::set_ticket`] to the first
+/// receiver that knows the code, and refuses everyone after the first guess.
+#[derive(Debug, Clone)]
+pub(crate) struct Pairing {
+    code: ShortCode,
+    ticket: Arc<OnceLock<ShareTicket>>,
+    used: Arc<AtomicBool>,
+    progress_tx: Option<ProgressSenderTx>,
+    /// Keeps the nameplate record published while the protocol runs.
+    nameplate: Option<PkarrPublisher>,
+}
+
+impl Pairing {
+    pub(crate) fn new(code: ShortCode, progress_tx: Option<ProgressSenderTx>) -> Self {
+        Self {
+            code,
+            ticket: Default::default(),
+            used: Default::default(),
+            progress_tx,
+            nameplate: None,
+        }
+    }
+
+    /// Publish `endpoint_id` under the nameplate of the code with DNS, so
+    /// receivers outside of the local network can find the sender.
+    pub(crate) fn publish_nameplate(mut self, endpoint_id: EndpointId) -> anyhow::Result<Self> {
+        let publisher = PkarrPublisher::n0_dns().build(self.code.nameplate_key());
+        let user_data = UserData::try_from(endpoint_id.to_string())?;
+        publisher.update_endpoint_data(&EndpointData::new([]).with_user_data(Some(user_data)));
+        self.nameplate = Some(publisher);
+        Ok(self)
+    }
+
+    pub(crate) fn code(&self) -> &ShortCode {
+        &self.code
+    }
+
+    /// Set the ticket handed out to the receiver. Until then, pairing fails.
+    pub(crate) fn set_ticket(&self, ticket: ShareTicket) {
+        self.ticket.set(ticket).ok();
+    }
+
+    /// Run the pairing protocol with a receiver. `guessed` is set once the
+    /// receiver's guess of the code was checked, which uses up the code.
+    async fn handshake(&self, connection: &Connection, guessed: &mut bool) -> anyhow::Result<()> {
+        let (mut send, mut recv) = connection.accept_bi().await?;
+        let (spake, outbound) = self.code.start();
+        let mut inbound = [0u8; MESSAGE_LEN];
+        recv.read_exact(&mut inbound).await?;
+        send.write_all(&outbound).await?;
+        let cipher = session_cipher(spake, &inbound)?;
+
+        let mut confirm = [0u8; NONCE_LEN + TAG_LEN];
+        recv.read_exact(&mut confirm).await?;
+        // Only checking the confirmation tells the receiver whether its guess
+        // was right, so that is what uses up the code
+        if self.used.swap(true, Ordering::SeqCst) {
+            connection.close(VarInt::from_u32(CLOSE_CODE_USED), b"code already used");
+            anyhow::bail!("the code was used");
+        }
+        *guessed = true;
+        if open(&cipher, &confirm, RECEIVER_CONFIRM).is_err() {
+            connection.close(VarInt::from_u32(CLOSE_WRONG_CODE), b"wrong code");
+            anyhow::bail!("wrong code");
+        }
+        let ticket = self.ticket.get().context("share is not ready")?;
+        send.write_all(&seal(
+            &cipher,
+            ticket.to_string().as_bytes(),
+            SENDER_CONFIRM,
+        )?)
+        .await?;
+        send.finish()?;
+        // The receiver closes the connection once it has read the ticket
+        tokio::time::timeout(CLOSE_TIMEOUT, connection.closed())
+            .await
+            .ok();
+        Ok(())
+    }
+}
+
+impl ProtocolHandler for Pairing {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let endpoint_id = connection.remote_id().fmt_short().to_string();
+        if self.used.load(Ordering::SeqCst) {
+            tracing::info!("refusing pairing with {endpoint_id}, the code was used");
+            connection.close(VarInt::from_u32(CLOSE_CODE_USED), b"code already used");
+            return Ok(());
+        }
+        let mut guessed = false;
+        let res = self.handshake(&connection, &mut guessed).await;
+        if let Err(e) = &res {
+            tracing::info!("pairing with {endpoint_id} failed: {e:#}");
+        }
+        if !guessed {
+            return Ok(());
+        }
+        if let Some(tx) = &self.progress_tx {
+            tx.send(ProgressEvent::Connection(ConnectionStatus::CodeUsed {
+                endpoint_id,
+                paired: res.is_ok(),
+            }))
+            .await
+            .ok();
+        }
+        Ok(())
+    }
+}
+
+/// Get the ticket for a short code from the sender.
+///
+/// Finds the sender by its nameplate, on the local network or with DNS. Use
+/// [`request_ticket`] to connect to a known address instead.
+pub async fn resolve_code(code: &ShortCode, common: &CommonConfig) -> anyhow::Result<ShareTicket> {
+    let endpoint = bind_endpoint(&EndpointAddr::new(code.nameplate_key().public()), common).await?;
+    let res = async {
+        let addr = find_sender(&endpoint, code, common.network)
+            .await?
+            .context("failed to find the sender of the code")?;
+        request_ticket(&endpoint, addr, code).await
+    }
+    .await;
+    endpoint.close().await;
+    res
+}
+
+/// Look for the sender of `code` on the local network and, unless `network`
+/// is [`NetworkMode::LanOnly`], with DNS, and return whichever is found first.
+async fn find_sender(
+    endpoint: &Endpoint,
+    code: &ShortCode,
+    network: NetworkMode,
+) -> anyhow::Result<Option<EndpointAddr>> {
+    let mdns = MdnsDiscovery::builder()
+        .advertise(false)
+        .build(endpoint.id())?;
+    let mut discovered = mdns.subscribe().await;
+    let user_data = code.user_data();
+    let lan = async {
+        while let Some(event) = discovered.next().await {
+            if let DiscoveryEvent::Discovered { endpoint_info, .. } = event {
+                if endpoint_info.data.user_data() == Some(&user_data) {
+                    return Some(endpoint_info.into_endpoint_addr());
+                }
+            }
+        }
+        None
+    };
+    let dns = async {
+        if network == NetworkMode::LanOnly {
+            return None;
+        }
+        let endpoint_id = lookup_nameplate(endpoint, code).await?;
+        find_endpoint(endpoint, endpoint_id).await
+    };
+    tokio::pin!(lan, dns);
+    let mut dns_done = false;
+    let find = async {
+        loop {
+            tokio::select! {
+                addr = &mut lan => return addr,
+                addr = &mut dns, if !dns_done => match addr {
+                    Some(addr) => return Some(addr),
+                    // Keep looking on the local network
+                    None => dns_done = true,
+                },
+            }
+        }
+    };
+    Ok(tokio::time::timeout(DISCOVERY_TIMEOUT, find)
+        .await
+        .ok()
+        .flatten())
+}
+
+/// Resolve the endpoint id published under the nameplate of `code`.
+async fn lookup_nameplate(endpoint: &Endpoint, code: &ShortCode) -> Option<EndpointId> {
+    let mut items = endpoint
+        .discovery()
+        .resolve(code.nameplate_key().public())?;
+    while let Some(item) = items.next().await {
+        match item {
+            Ok(item) => {
+                if let Some(id) = item.user_data().and_then(|data| data.as_ref().parse().ok()) {
+                    return Some(id);
+                }
+            }
+            Err(e) => tracing::debug!("nameplate lookup failed: {e}"),
+        }
+    }
+    None
+}
+
+/// Run the pairing protocol with the sender at `addr`.
+pub async fn request_ticket(
+    endpoint: &Endpoint,
+    addr: impl Into<EndpointAddr>,
+    code: &ShortCode,
+) -> anyhow::Result<ShareTicket> {
+    let connection = endpoint
+        .connect(addr, ALPN)
+        .await
+        .context("failed to reach the sender of the code")?;
+    let res = exchange(&connection, code)
+        .await
+        .map_err(|e| match connection.close_reason() {
+            Some(ConnectionError::ApplicationClosed(close))
+                if close.error_code == VarInt::from_u32(CLOSE_WRONG_CODE) =>
+            {
+                anyhow::anyhow!("wrong code")
+            }
+            Some(ConnectionError::ApplicationClosed(close))
+                if close.error_code == VarInt::from_u32(CLOSE_CODE_USED) =>
+            {
+                anyhow::anyhow!("the code was already used")
+            }
+            _ => e,
+        });
+    connection.close(VarInt::from_u32(0), b"done");
+    res
+}
+
+async fn exchange(connection: &Connection, code: &ShortCode) -> anyhow::Result<ShareTicket> {
+    let (mut send, mut recv) = connection.open_bi().await?;
+    let (spake, outbound) = code.start();
+    send.write_all(&outbound).await?;
+    let mut inbound = [0u8; MESSAGE_LEN];
+    recv.read_exact(&mut inbound).await?;
+    let cipher = session_cipher(spake, &inbound)?;
+
+    send.write_all(&seal(&cipher, &[], RECEIVER_CONFIRM)?)
+        .await?;
+    send.finish()?;
+    let sealed = recv.read_to_end(MAX_TICKET_LEN).await?;
+    let ticket =
+        open(&cipher, &sealed, SENDER_CONFIRM).context("the sender does not know the code")?;
+    String::from_utf8(ticket)?.parse()
+}
+
+fn session_cipher(
+    spake: Spake2<Ed25519Group>,
+    inbound: &[u8],
+) -> anyhow::Result<XChaCha20Poly1305> {
+    let key = spake
+        .finish(inbound)
+        .map_err(|e| anyhow::anyhow!("pairing failed: {e:?}"))?;
+    XChaCha20Poly1305::new_from_slice(&key).map_err(|_| anyhow::anyhow!("invalid session key"))
+}
+
+fn seal(cipher: &XChaCha20Poly1305, msg: &[u8], aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let mut out = nonce.to_vec();
+    out.extend(
+        cipher
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg, aad })
+            .map_err(|_| anyhow::anyhow!("failed to encrypt"))?,
+    );
+    Ok(out)
+}
+
+fn open(cipher: &XChaCha20Poly1305, sealed: &[u8], aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+    anyhow::ensure!(sealed.len() >= NONCE_LEN + TAG_LEN, "message too short");
+    let (nonce, msg) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(XNonce::from_slice(nonce), Payload { msg, aad })
+        .map_err(|_| anyhow::anyhow!("failed to decrypt"))
+}
@@ -1,7 +1,17 @@
 //! Progress reporting abstractions for the sendme library.
+//!
+//! Progress of downloads, exports and provider requests comes with a
+//! [`TransferRate`] computed by a [`RateTracker`], so all front-ends show the
+//! same speed and ETA.
 
-use iroh_blobs::Hash;
+use std::{net::SocketAddr, time::Duration};
+
+use iroh::RelayUrl;
+use iroh_blobs::{get::Stats, Hash};
 use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::offer::Answer;
 
 /// Unified progress event type sent through channels.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,7 +30,11 @@ pub enum ProgressEvent {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ImportProgress {
     /// Import operation started.
-    Started { total_files: usize },
+    ///
+    /// `skipped` counts the files left out by ignore files and the include and
+    /// exclude globs of the [`ImportConfig`](crate::import::ImportConfig),
+    /// including every file below a left out directory.
+    Started { total_files: usize, skipped: usize },
     /// A file import started.
     FileStarted { name: String, size: u64 },
     /// File import progress update.
@@ -28,7 +42,11 @@ pub enum ImportProgress {
     /// A file import completed.
     FileCompleted { name: String },
     /// Entire import completed.
-    Completed { total_size: u64 },
+    ///
+    /// `total_size` is the size of the files, `wire_size` that of their blobs
+    /// as they are sent, which is smaller if they are
+    /// [compressed](crate::compression) and larger if they are encrypted.
+    Completed { total_size: u64, wire_size: u64 },
 }
 
 /// Progress events for export operations.
@@ -38,14 +56,39 @@ pub enum ExportProgress {
     Started { total_files: usize },
     /// A file export started.
     FileStarted { name: String, size: u64 },
-    /// File export progress update.
-    FileProgress { name: String, offset: u64 },
+    /// File export progress update, with the rate of exporting the file.
+    FileProgress {
+        name: String,
+        offset: u64,
+        rate: TransferRate,
+    },
     /// A file export completed.
     FileCompleted { name: String },
+    /// A file already existed at the target path and was handled according
+    /// to the [`ExportConflictPolicy`](crate::ExportConflictPolicy).
+    Conflict {
+        name: String,
+        resolution: ConflictResolution,
+    },
     /// Entire export completed.
     Completed,
 }
 
+/// How an existing file at an export target was handled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictResolution {
+    /// The existing file was replaced.
+    Overwritten,
+    /// The existing file was kept and the received one was not exported.
+    Skipped,
+    /// The existing file has the same content, so it was kept.
+    SkippedIdentical,
+    /// The received file was exported under a different name.
+    Renamed { name: String },
+    /// The export was aborted.
+    Failed,
+}
+
 /// Progress events for download operations.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DownloadProgress {
@@ -55,11 +98,11 @@ pub enum DownloadProgress {
     GettingSizes,
     /// Metadata received - filenames and total size are now known.
     Metadata {
-        /// Total size in bytes of all files in the collection.
+        /// Total size in bytes of the files to receive.
         ///
-        /// When downloading from a remote sender, this is calculated by summing
-        /// the actual file sizes from the collection. When using locally cached
-        /// data, this uses the cache's total size which may include metadata overhead.
+        /// This is the sum of the sizes of their blobs, so compressed and
+        /// encrypted files count with their size on the wire. The blobs with
+        /// the names and the metadata of the collection are left out.
         total_size: u64,
         /// Number of files in the collection
         file_count: u64,
@@ -67,7 +110,11 @@ pub enum DownloadProgress {
         names: Vec<String>,
     },
     /// Downloading data.
-    Downloading { offset: u64, total: u64 },
+    Downloading {
+        offset: u64,
+        total: u64,
+        rate: TransferRate,
+    },
     /// Download completed.
     Completed,
 }
@@ -80,6 +127,11 @@ pub enum ConnectionStatus {
         endpoint_id: String,
         connection_id: u64,
     },
+    /// A client was rejected because it is not on the allowlist.
+    ClientRejected {
+        endpoint_id: String,
+        connection_id: u64,
+    },
     /// A connection was closed.
     ConnectionClosed { connection_id: u64 },
     /// A transfer request started.
@@ -89,14 +141,65 @@ pub enum ConnectionStatus {
         hash: Hash,
         size: u64,
     },
-    /// Transfer request progress update.
+    /// Transfer request progress update. The offset is within the blob being
+    /// sent, the rate is that of the whole request, with the ETA of the blob.
     RequestProgress {
         connection_id: u64,
         request_id: u64,
         offset: u64,
+        rate: TransferRate,
     },
     /// A transfer request completed.
     RequestCompleted { connection_id: u64, request_id: u64 },
+    /// The usage of a share with download or time limits changed. Sent when
+    /// the share starts and after every download.
+    QuotaUpdated {
+        /// Number of downloads so far.
+        downloads: u32,
+        /// Downloads left before the share closes, if limited.
+        remaining_downloads: Option<u32>,
+        /// Time left before the share expires, if limited.
+        remaining_time: Option<Duration>,
+    },
+    /// A share limit was reached. New requests are refused from now on.
+    ShareClosed { reason: ShareLimit },
+    /// A receiver tried the short code of the share. The code can't be used
+    /// again.
+    CodeUsed {
+        endpoint_id: String,
+        /// Whether the receiver knew the code and was sent the ticket.
+        paired: bool,
+    },
+    /// The share was [offered](crate::offer) to a receiver, which is yet to
+    /// answer.
+    OfferSent { endpoint_id: String },
+    /// A receiver answered an offer of the share.
+    OfferAnswered { endpoint_id: String, answer: Answer },
+    /// The network path to the remote changed, or its round trip time did.
+    ///
+    /// Reported by both the sender, for every receiver, and the receiver.
+    /// Paths through a relay are usually much slower than direct ones.
+    PathChanged {
+        endpoint_id: String,
+        /// Whether the traffic goes straight to the remote, not via a relay.
+        direct: bool,
+        /// The address of the remote, if it can be reached directly.
+        remote_addr: Option<SocketAddr>,
+        /// The relay in use, if any. Paths that are both direct and relayed
+        /// are not yet confirmed to work directly.
+        relay_url: Option<RelayUrl>,
+        /// Round trip time of the path, once measured.
+        rtt: Option<Duration>,
+    },
+}
+
+/// Which limit closed a share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShareLimit {
+    /// The maximum number of downloads was reached.
+    MaxDownloads,
+    /// The share expired.
+    Expired,
 }
 
 /// Channel sender type for progress events.
@@ -104,3 +207,144 @@ pub type ProgressSenderTx = tokio::sync::mpsc::Sender<ProgressEvent>;
 
 /// Channel receiver type for progress events.
 pub type ProgressReceiverRx = tokio::sync::mpsc::Receiver<ProgressEvent>;
+
+/// How fast an operation progresses, see [`RateTracker`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferRate {
+    /// Smoothed rate in bytes per second. Zero until first measured.
+    pub bytes_per_sec: u64,
+    /// Highest smoothed rate so far.
+    pub peak_bytes_per_sec: u64,
+    /// Time since the operation started.
+    pub elapsed: Duration,
+    /// Estimated time until the operation completes, if the total is known
+    /// and the rate was measured.
+    pub eta: Option<Duration>,
+}
+
+/// Shortest time between two samples of a [`RateTracker`]. Updates in between
+/// only refresh the elapsed time and the ETA.
+const RATE_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+/// Time constant of the moving average of a [`RateTracker`]. Samples older
+/// than this weigh less than a third.
+const RATE_SMOOTHING: Duration = Duration::from_secs(2);
+
+/// Computes a smoothed transfer rate, ETA and peak rate from progress offsets.
+///
+/// The rate is an exponential moving average of the rates between samples,
+/// weighted by the time between them, so it follows real changes within a
+/// few seconds without jumping on every chunk.
+#[derive(Debug, Clone)]
+pub struct RateTracker {
+    started: Instant,
+    sampled_at: Instant,
+    sampled_offset: u64,
+    rate: Option<f64>,
+    peak: f64,
+}
+
+impl Default for RateTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateTracker {
+    /// Start tracking an operation at offset zero.
+    pub fn new() -> Self {
+        Self::starting_at(0)
+    }
+
+    /// Start tracking an operation at `offset`, e.g. a download that resumes
+    /// with some data available already. Only the bytes after it count.
+    pub fn starting_at(offset: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            sampled_at: now,
+            sampled_offset: offset,
+            rate: None,
+            peak: 0.0,
+        }
+    }
+
+    /// Record that the operation reached `offset` out of `total`, and return
+    /// the current rate.
+    pub fn update(&mut self, offset: u64, total: Option<u64>) -> TransferRate {
+        let now = Instant::now();
+        let dt = now.duration_since(self.sampled_at);
+        if dt >= RATE_SAMPLE_INTERVAL {
+            let bytes = offset.saturating_sub(self.sampled_offset);
+            let sample = bytes as f64 / dt.as_secs_f64();
+            let rate = match self.rate {
+                Some(rate) => {
+                    let weight = 1.0 - (-dt.as_secs_f64() / RATE_SMOOTHING.as_secs_f64()).exp();
+                    rate + weight * (sample - rate)
+                }
+                None => sample,
+            };
+            self.rate = Some(rate);
+            self.peak = self.peak.max(rate);
+            self.sampled_at = now;
+            self.sampled_offset = offset;
+        }
+        self.rate(offset, total)
+    }
+
+    /// The rate as of the last update, with `offset` and `total` for the ETA.
+    pub fn rate(&self, offset: u64, total: Option<u64>) -> TransferRate {
+        let rate = self.rate.unwrap_or_default();
+        let eta = match total {
+            Some(total) if rate > 0.0 => Some(Duration::from_secs_f64(
+                total.saturating_sub(offset) as f64 / rate,
+            )),
+            Some(total) if offset >= total => Some(Duration::ZERO),
+            _ => None,
+        };
+        TransferRate {
+            bytes_per_sec: rate as u64,
+            peak_bytes_per_sec: self.peak as u64,
+            elapsed: self.started.elapsed(),
+            eta,
+        }
+    }
+}
+
+/// Summary of a finished download.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferSummary {
+    /// Bytes of file content received.
+    pub payload_bytes: u64,
+    /// Bytes received in total, including hashes and sizes.
+    pub total_bytes: u64,
+    /// Time the download took.
+    pub elapsed: Duration,
+    /// Average rate over the whole download, in bytes per second.
+    pub average_bytes_per_sec: u64,
+    /// Highest smoothed rate during the download, see [`TransferRate`].
+    pub peak_bytes_per_sec: u64,
+}
+
+impl TransferSummary {
+    /// Summarize a download from its stats and the last rate reported for it.
+    ///
+    /// Without a download, e.g. when everything was available locally, all
+    /// numbers are zero.
+    pub fn new(stats: &Stats, rate: TransferRate) -> Self {
+        let total_bytes = stats.total_bytes_read();
+        let secs = stats.elapsed.as_secs_f64();
+        let average_bytes_per_sec = if secs > 0.0 {
+            (total_bytes as f64 / secs) as u64
+        } else {
+            0
+        };
+        Self {
+            payload_bytes: stats.payload_bytes_read,
+            total_bytes,
+            elapsed: stats.elapsed,
+            average_bytes_per_sec,
+            // Short downloads end before the first sample
+            peak_bytes_per_sec: rate.peak_bytes_per_sec.max(average_bytes_per_sec),
+        }
+    }
+}
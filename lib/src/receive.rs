@@ -1,15 +1,33 @@
 //! Receive functionality - downloading files.
 
-use iroh::{discovery::dns::DnsDiscovery, Endpoint};
+use std::{collections::HashSet, time::Duration};
+
+use anyhow::Context;
+
+use iroh::{
+    discovery::{dns::DnsDiscovery, mdns::MdnsDiscovery, Discovery},
+    endpoint::Connection,
+    Endpoint, EndpointAddr, EndpointId,
+};
 use iroh_blobs::{
     format::collection::Collection,
     get::{request::get_hash_seq_and_sizes, GetError, Stats},
+    protocol::{ChunkRanges, GetRequest},
     store::fs::FsStore,
 };
 
-use n0_future::StreamExt;
-
-use crate::{export, get_or_create_secret, progress::*, ReceiveArgs, ReceiveResult};
+use n0_future::{task::AbortOnDropHandle, StreamExt};
+use tokio::io::AsyncWrite;
+
+use crate::{
+    archive,
+    crypto::{self, EncryptedShare},
+    export::{self, ExportConfig},
+    get_or_create_secret,
+    metadata::{self, MetadataBlob},
+    progress::*,
+    CommonConfig, IncludeFilter, NetworkMode, ReceiveArgs, ReceiveResult,
+};
 
 /// Receive a file or directory.
 ///
@@ -21,7 +39,7 @@ use crate::{export, get_or_create_secret, progress::*, ReceiveArgs, ReceiveResul
 ///
 /// On completion, it will delete the temp directory.
 pub async fn receive(args: ReceiveArgs) -> anyhow::Result<ReceiveResult> {
-    receive_internal(args, None).await
+    receive_internal(args, None, None).await
 }
 
 /// Receive a file or directory with progress reporting.
@@ -29,33 +47,57 @@ pub async fn receive_with_progress(
     args: ReceiveArgs,
     progress_tx: ProgressSenderTx,
 ) -> anyhow::Result<ReceiveResult> {
-    receive_internal(args, Some(progress_tx)).await
+    receive_internal(args, None, Some(progress_tx)).await
 }
 
-async fn receive_internal(
+/// Receive a single file and write it to `writer` instead of exporting it.
+///
+/// The download is verified like any other, and [`ReceiveArgs::export_dir`]
+/// and the export options are ignored. The collection metadata is fetched
+/// first, so a share with more than one file fails before its data is
+/// downloaded. An include filter can select a single file of such a share.
+///
+/// With [`ReceiveArgs::archive`], all received files are written to `writer`
+/// as an archive instead.
+pub async fn receive_to_writer(
     args: ReceiveArgs,
+    writer: &mut (impl AsyncWrite + Unpin + Send),
     progress_tx: Option<ProgressSenderTx>,
 ) -> anyhow::Result<ReceiveResult> {
-    let ticket = args.ticket;
-    let addr = ticket.addr().clone();
-    let secret_key = get_or_create_secret(args.common.show_secret)?;
-    let mut builder = Endpoint::builder()
-        .alpns(vec![])
-        .secret_key(secret_key)
-        .relay_mode(args.common.relay.into());
-
-    if ticket.addr().relay_urls().next().is_none() && ticket.addr().ip_addrs().next().is_none() {
-        builder = builder.discovery(DnsDiscovery::n0_dns());
-    }
+    receive_internal(args, Some(writer), progress_tx).await
+}
 
-    if let Some(addr) = args.common.magic_ipv4_addr {
-        builder = builder.bind_addr_v4(addr);
-    }
-    if let Some(addr) = args.common.magic_ipv6_addr {
-        builder = builder.bind_addr_v6(addr);
-    }
+async fn receive_internal(
+    args: ReceiveArgs,
+    writer: Option<&mut (dyn AsyncWrite + Unpin + Send)>,
+    progress_tx: Option<ProgressSenderTx>,
+) -> anyhow::Result<ReceiveResult> {
+    let addr = args.ticket.addr().clone();
+    let endpoint = bind_endpoint(&addr, &args.common).await?;
+    // The endpoint is closed however the receive ends
+    let res = receive_from(&endpoint, addr, args, writer, progress_tx).await;
+    endpoint.close().await;
+    res
+}
 
-    let endpoint = builder.bind().await?;
+/// Receive the share of `args` with `endpoint` from the sender at `addr`.
+async fn receive_from(
+    endpoint: &Endpoint,
+    addr: EndpointAddr,
+    args: ReceiveArgs,
+    writer: Option<&mut (dyn AsyncWrite + Unpin + Send)>,
+    progress_tx: Option<ProgressSenderTx>,
+) -> anyhow::Result<ReceiveResult> {
+    let ticket = args.ticket;
+    let cancel = args.cancel;
+    let addr = match args.common.network {
+        NetworkMode::LanOnly if addr.ip_addrs().next().is_none() => {
+            find_endpoint(endpoint, addr.id)
+                .await
+                .context("failed to find the sender on the local network")?
+        }
+        _ => addr,
+    };
 
     // Determine the base directory for temp files
     // Use temp_dir from args if provided (required for Android/macOS sandbox),
@@ -104,173 +146,415 @@ async fn receive_internal(
     tracing::info!("✅ FsStore loaded successfully");
 
     let hash_and_format = ticket.hash_and_format();
-    let local = db.remote().local(hash_and_format).await?;
-
-    let (stats, total_files, payload_size, metadata_collection) = if !local.is_complete() {
-        if let Some(ref tx) = progress_tx {
-            let _ = tx
-                .send(ProgressEvent::Download(DownloadProgress::Connecting))
-                .await;
+    // A writer takes a single file, unless it gets an archive
+    let single_file = writer.is_some() && args.archive.is_none();
+
+    // Reports the path to the sender for as long as data is fetched
+    let mut path = None;
+
+    // Everything that talks to the network can be cancelled. On cancellation the
+    // partial store is kept, so a later attempt with the same ticket resumes.
+    let fetch = async {
+        let mut connection = None;
+
+        // Get the collection metadata first, so an encrypted share is refused
+        // before its files are downloaded without the passphrase to decrypt them
+        let request = GetRequest::builder()
+            .root(ChunkRanges::all())
+            .child(0, ChunkRanges::all())
+            .build(hash_and_format.hash);
+        let local = db.remote().local_for_request(request).await?;
+        if !local.is_complete() {
+            let conn = connect(endpoint, addr.clone(), &progress_tx, &mut path).await?;
+            db.remote()
+                .execute_get(conn.clone(), local.missing())
+                .complete()
+                .await
+                .map_err(show_get_error)?;
+            connection = Some(conn);
         }
+        let (collection, metadata_blob) =
+            metadata::load_collection(hash_and_format.hash, db.as_ref()).await?;
+        anyhow::ensure!(
+            args.passphrase.is_some() || !crypto::is_encrypted(&collection),
+            "this share is protected by a passphrase"
+        );
 
-        let connection = endpoint.connect(addr, iroh_blobs::protocol::ALPN).await?;
-
-        if let Some(ref tx) = progress_tx {
-            let _ = tx
-                .send(ProgressEvent::Download(DownloadProgress::GettingSizes))
-                .await;
-        }
+        // To download only some files, to decrypt an encrypted share, or to
+        // check that there is a single file to write, the metadata tells which
+        // children of the hash sequence to ask for
+        let selection = match (&args.include, &args.passphrase) {
+            (IncludeFilter::All, None) if !single_file => None,
+            (include, passphrase) => {
+                // The names of an encrypted share are in the manifest, the second child
+                let share = match passphrase {
+                    Some(passphrase) if crypto::is_encrypted(&collection) => {
+                        let request = GetRequest::builder()
+                            .child(1, ChunkRanges::all())
+                            .build(hash_and_format.hash);
+                        let local = db.remote().local_for_request(request).await?;
+                        if !local.is_complete() {
+                            let conn = match connection.take() {
+                                Some(conn) => conn,
+                                None => {
+                                    connect(endpoint, addr.clone(), &progress_tx, &mut path).await?
+                                }
+                            };
+                            db.remote()
+                                .execute_get(conn.clone(), local.missing())
+                                .complete()
+                                .await
+                                .map_err(show_get_error)?;
+                            connection = Some(conn);
+                        }
+                        let (_, manifest) = collection
+                            .iter()
+                            .next()
+                            .context("the encrypted share has no manifest")?;
+                        let manifest = db.get_bytes(*manifest).await?;
+                        Some(EncryptedShare::open(passphrase, &collection, manifest).await?)
+                    }
+                    _ => None,
+                };
+                let collection = match &share {
+                    Some(share) => share.view().clone(),
+                    None => collection,
+                };
+
+                let selected = include.select(&collection)?;
+                anyhow::ensure!(
+                    !selected.is_empty(),
+                    "no files in the collection match the include filter"
+                );
+                if single_file {
+                    let files = selected.len();
+                    anyhow::ensure!(
+                        files == 1,
+                        "only a single file can be written to a stream, but there are {files}"
+                    );
+                }
+                Some((collection, selected, share, metadata_blob))
+            }
+        };
+
+        // Child 0 is the blob with the names, child 1 the manifest of an
+        // encrypted share. A metadata blob of its own follows the files.
+        let offset = match &selection {
+            Some((_, _, Some(_), _)) => 2,
+            _ => 1,
+        };
+        let request = match &selection {
+            None => GetRequest::from(hash_and_format),
+            Some((collection, selected, _, metadata_blob)) => {
+                let names = selected
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<HashSet<_>>();
+                let mut builder = GetRequest::builder().root(ChunkRanges::all());
+                for child in 0..offset {
+                    builder = builder.child(child as u64, ChunkRanges::all());
+                }
+                for (index, (name, _)) in collection.iter().enumerate() {
+                    if names.contains(name.as_str()) {
+                        builder = builder.child((index + offset) as u64, ChunkRanges::all());
+                    }
+                }
+                if matches!(metadata_blob, Some(MetadataBlob::Child(_))) {
+                    let index = offset + collection.len();
+                    builder = builder.child(index as u64, ChunkRanges::all());
+                }
+                builder.build(hash_and_format.hash)
+            }
+        };
+        let local = db.remote().local_for_request(request).await?;
+
+        let fetched = if !local.is_complete() {
+            let connection = match connection {
+                Some(connection) => connection,
+                None => connect(endpoint, addr, &progress_tx, &mut path).await?,
+            };
+
+            if let Some(ref tx) = progress_tx {
+                let _ = tx
+                    .send(ProgressEvent::Download(DownloadProgress::GettingSizes))
+                    .await;
+            }
 
-        let (hash_seq, sizes) =
-            get_hash_seq_and_sizes(&connection, &hash_and_format.hash, 1024 * 1024 * 32, None)
-                .await
-                .map_err(|e| show_get_error(e))?;
-
-        let total_size = sizes.iter().copied().sum::<u64>();
-        let payload_size = sizes.iter().skip(2).copied().sum::<u64>();
-        let total_files = (sizes.len().saturating_sub(1)) as u64;
-
-        if let Some(ref tx) = progress_tx {
-            let _ = tx
-                .send(ProgressEvent::Download(DownloadProgress::Downloading {
-                    offset: 0,
-                    total: total_size,
-                }))
-                .await;
-        }
+            let (hash_seq, sizes) =
+                get_hash_seq_and_sizes(&connection, &hash_and_format.hash, 1024 * 1024 * 32, None)
+                    .await
+                    .map_err(show_get_error)?;
+
+            // The first child is the blob with the names, followed by the files
+            // and the metadata blob
+            let total_size = match &selection {
+                None => sizes.iter().copied().sum::<u64>(),
+                Some((collection, selected, _, metadata_blob)) => {
+                    let mut size = sizes.iter().take(offset).sum::<u64>();
+                    size += selected_size(collection, selected, &sizes, offset);
+                    if matches!(metadata_blob, Some(MetadataBlob::Child(_))) {
+                        size += sizes.get(offset + collection.len()).unwrap_or(&0);
+                    }
+                    size
+                }
+            };
+
+            let local_size = local.local_bytes();
+            // Only what is downloaded counts towards the rate
+            let mut rate = RateTracker::starting_at(local_size);
+            if let Some(ref tx) = progress_tx {
+                let _ = tx
+                    .send(ProgressEvent::Download(DownloadProgress::Downloading {
+                        offset: 0,
+                        total: total_size,
+                        rate: rate.update(0, Some(total_size)),
+                    }))
+                    .await;
+            }
 
-        let local_size = local.local_bytes();
-        let get = db.remote().execute_get(connection, local.missing());
-        let mut stream = get.stream();
-        let mut stats = Stats::default();
-        let mut metadata_sent = false;
-        let mut metadata_collection: Option<Collection> = None;
-        let mut progress_count = 0u32;
+            let get = db.remote().execute_get(connection, local.missing());
+            let mut stream = get.stream();
+            let mut stats = Stats::default();
+            let mut metadata_sent = false;
+            let mut metadata_collection: Option<Collection> = None;
+            let mut progress_count = 0u32;
+
+            if let Some((collection, selected, _, _)) = &selection {
+                if let Some(ref tx) = progress_tx {
+                    let _ = tx
+                        .send(ProgressEvent::Download(DownloadProgress::Metadata {
+                            total_size: selected_size(collection, selected, &sizes, offset),
+                            file_count: selected.len() as u64,
+                            names: selected.iter().map(|(name, _)| name.clone()).collect(),
+                        }))
+                        .await;
+                }
+                metadata_sent = true;
+                metadata_collection = Some(selected.clone());
+            }
 
-        while let Some(item) = stream.next().await {
-            match item {
-                iroh_blobs::api::remote::GetProgressItem::Progress(offset) => {
-                    // Try to load collection metadata as soon as it's available
-                    // Try on first event and then every 10th event thereafter (events 1, 11, 21...) to avoid excessive load attempts
-                    if !metadata_sent {
-                        progress_count += 1;
-                        if (progress_count - 1) % 10 == 0 {
-                            if let Ok(collection) =
-                                Collection::load(hash_and_format.hash, db.as_ref()).await
-                            {
-                                // Calculate actual payload size from collection files
-                                let mut actual_payload_size = 0u64;
-                                for (name, file_hash) in collection.iter() {
-                                    // Find the size for this file hash in the hash_seq
-                                    if let Some(idx) = hash_seq.iter().position(|h| h == *file_hash)
-                                    {
-                                        if idx < sizes.len() {
-                                            actual_payload_size += sizes[idx];
-                                            tracing::debug!(
-                                                "File {}: hash at index {}, size {}",
-                                                name,
-                                                idx,
-                                                sizes[idx]
+            while let Some(item) = stream.next().await {
+                match item {
+                    iroh_blobs::api::remote::GetProgressItem::Progress(offset) => {
+                        // Try to load collection metadata as soon as it's available
+                        // Try on first event and then every 10th event thereafter (events 1, 11, 21...) to avoid excessive load attempts
+                        if !metadata_sent {
+                            progress_count += 1;
+                            if (progress_count - 1) % 10 == 0 {
+                                if let Ok((collection, _)) =
+                                    metadata::load_collection(hash_and_format.hash, db.as_ref())
+                                        .await
+                                {
+                                    // Calculate actual payload size from collection files
+                                    let mut actual_payload_size = 0u64;
+                                    for (name, file_hash) in collection.iter() {
+                                        // Find the size for this file hash in the hash_seq
+                                        if let Some(idx) =
+                                            hash_seq.iter().position(|h| h == *file_hash)
+                                        {
+                                            if idx < sizes.len() {
+                                                actual_payload_size += sizes[idx];
+                                                tracing::debug!(
+                                                    "File {}: hash at index {}, size {}",
+                                                    name,
+                                                    idx,
+                                                    sizes[idx]
+                                                );
+                                            }
+                                        } else {
+                                            tracing::warn!(
+                                                "File {} hash not found in hash_seq",
+                                                name
                                             );
                                         }
-                                    } else {
-                                        tracing::warn!("File {} hash not found in hash_seq", name);
                                     }
-                                }
 
-                                tracing::info!(
-                                    "Metadata: {} files, total size: {}",
-                                    collection.iter().count(),
-                                    actual_payload_size
-                                );
-
-                                let names: Vec<String> = collection
-                                    .iter()
-                                    .map(|(name, _hash)| name.to_string())
-                                    .collect();
-
-                                if let Some(ref tx) = progress_tx {
-                                    let _ = tx
-                                        .send(ProgressEvent::Download(DownloadProgress::Metadata {
-                                            total_size: actual_payload_size,
-                                            file_count: total_files,
-                                            names,
-                                        }))
-                                        .await;
+                                    tracing::info!(
+                                        "Metadata: {} files, total size: {}",
+                                        collection.iter().count(),
+                                        actual_payload_size
+                                    );
+
+                                    let names: Vec<String> = collection
+                                        .iter()
+                                        .map(|(name, _hash)| name.to_string())
+                                        .collect();
+
+                                    if let Some(ref tx) = progress_tx {
+                                        let _ = tx
+                                            .send(ProgressEvent::Download(
+                                                DownloadProgress::Metadata {
+                                                    total_size: actual_payload_size,
+                                                    file_count: collection.len() as u64,
+                                                    names,
+                                                },
+                                            ))
+                                            .await;
+                                    }
+                                    metadata_sent = true;
+                                    metadata_collection = Some(collection);
                                 }
-                                metadata_sent = true;
-                                metadata_collection = Some(collection);
                             }
                         }
-                    }
 
-                    if let Some(ref tx) = progress_tx {
-                        let _ = tx
-                            .send(ProgressEvent::Download(DownloadProgress::Downloading {
-                                offset: local_size + offset,
-                                total: total_size,
-                            }))
-                            .await;
+                        let offset = local_size + offset;
+                        let rate = rate.update(offset, Some(total_size));
+                        if let Some(ref tx) = progress_tx {
+                            let _ = tx
+                                .send(ProgressEvent::Download(DownloadProgress::Downloading {
+                                    offset,
+                                    total: total_size,
+                                    rate,
+                                }))
+                                .await;
+                        }
+                    }
+                    iroh_blobs::api::remote::GetProgressItem::Done(value) => {
+                        stats = value;
+                        break;
+                    }
+                    iroh_blobs::api::remote::GetProgressItem::Error(cause) => {
+                        anyhow::bail!(show_get_error(cause));
                     }
                 }
-                iroh_blobs::api::remote::GetProgressItem::Done(value) => {
-                    stats = value;
-                    break;
-                }
-                iroh_blobs::api::remote::GetProgressItem::Error(cause) => {
-                    anyhow::bail!(show_get_error(cause));
+            }
+
+            let rate = rate.rate(total_size, Some(total_size));
+            (stats, rate, metadata_collection)
+        } else {
+            // Collection already cached locally, load it and emit metadata event
+            let collection = match &selection {
+                Some((_, selected, _, _)) => selected.clone(),
+                None => {
+                    metadata::load_collection(hash_and_format.hash, db.as_ref())
+                        .await?
+                        .0
                 }
+            };
+            let total_files = collection.len() as u64;
+            let names: Vec<String> = collection
+                .iter()
+                .map(|(name, _hash)| name.to_string())
+                .collect();
+            let payload_bytes = files_size(&db, &collection).await?;
+
+            if let Some(ref tx) = progress_tx {
+                let _ = tx
+                    .send(ProgressEvent::Download(DownloadProgress::Metadata {
+                        total_size: payload_bytes,
+                        file_count: total_files,
+                        names,
+                    }))
+                    .await;
             }
-        }
 
-        (stats, total_files, payload_size, metadata_collection)
-    } else {
-        // Collection already cached locally
-        let total_files = local.children().unwrap() - 1;
-        // Use local_bytes as an approximation for total size (includes some metadata overhead)
-        let payload_bytes = local.local_bytes();
-
-        // Load collection and emit metadata event
-        let collection = Collection::load(hash_and_format.hash, db.as_ref()).await?;
-        let names: Vec<String> = collection
-            .iter()
-            .map(|(name, _hash)| name.to_string())
-            .collect();
-
-        if let Some(ref tx) = progress_tx {
-            let _ = tx
-                .send(ProgressEvent::Download(DownloadProgress::Metadata {
-                    total_size: payload_bytes,
-                    file_count: total_files,
-                    names,
-                }))
-                .await;
+            (Stats::default(), TransferRate::default(), Some(collection))
+        };
+        let share = selection.and_then(|(_, _, share, _)| share);
+        anyhow::Ok((fetched, share))
+    };
+    let fetched = cancel.run_until_cancelled(fetch).await;
+    drop(path);
+    let ((stats, rate, metadata_collection), share) = match fetched {
+        Some(Ok(res)) => res,
+        Some(Err(e)) => {
+            db.shutdown().await?;
+            return Err(e);
+        }
+        None => {
+            tracing::info!("receive cancelled, keeping {:?}", iroh_data_dir);
+            db.shutdown().await?;
+            anyhow::bail!("receive cancelled");
         }
-
-        (
-            Stats::default(),
-            total_files,
-            payload_bytes,
-            Some(collection),
-        )
     };
 
-    // Use cached collection if available, otherwise load it
-    let collection = match metadata_collection {
-        Some(col) => col,
-        None => Collection::load(hash_and_format.hash, db.as_ref()).await?,
+    // The store is shut down however the export ends
+    let export = async {
+        // Use cached collection if available, otherwise load it
+        let (loaded, metadata_blob) =
+            metadata::load_collection(hash_and_format.hash, db.as_ref()).await?;
+        let collection = metadata_collection.unwrap_or(loaded);
+        // Only the files count, not the blobs with their names and metadata
+        let payload_size = files_size(&db, &collection).await?;
+
+        let mut archive_path = None;
+        let mut exported_paths = Vec::new();
+        let exported = match (writer, args.archive) {
+            (Some(writer), Some(format)) => {
+                archive::export_archive(
+                    &db,
+                    &collection,
+                    metadata_blob,
+                    format,
+                    writer,
+                    share.as_ref(),
+                    progress_tx.clone(),
+                    &cancel,
+                )
+                .await
+            }
+            (None, Some(format)) => {
+                let export_dir = args.export_dir.as_ref().unwrap_or(&base_dir);
+                let name = archive::archive_name(&collection, &hash_and_format.hash, format);
+                match archive::archive_path(export_dir, &name, args.conflict_policy) {
+                    Ok(Some(path)) => {
+                        let res = archive::export_archive_file(
+                            &db,
+                            &collection,
+                            metadata_blob,
+                            format,
+                            &path,
+                            share.as_ref(),
+                            progress_tx.clone(),
+                            &cancel,
+                        )
+                        .await;
+                        archive_path = Some(path);
+                        res
+                    }
+                    Ok(None) => Ok(()),
+                    Err(e) => Err(e),
+                }
+            }
+            (Some(writer), None) => {
+                export::export_to_writer(
+                    &db,
+                    &collection,
+                    metadata_blob,
+                    writer,
+                    share.as_ref(),
+                    progress_tx.clone(),
+                    &cancel,
+                )
+                .await
+            }
+            (None, None) => {
+                tracing::info!("📤 Starting export to base_dir: {:?}", base_dir);
+                // Use export_dir from args if provided, otherwise export to base_dir
+                let export_dir = args.export_dir.as_ref().unwrap_or(&base_dir);
+                export::export(
+                    &db,
+                    collection.clone(),
+                    metadata_blob,
+                    progress_tx.clone(),
+                    Some(export_dir),
+                    &ExportConfig {
+                        conflict_policy: args.conflict_policy,
+                        atomic: args.atomic_export,
+                        decryption: share,
+                    },
+                    &cancel,
+                )
+                .await
+                .map(|paths| exported_paths = paths)
+            }
+        };
+        exported?;
+        anyhow::Ok((collection, payload_size, archive_path, exported_paths))
     };
-
-    tracing::info!("📤 Starting export to base_dir: {:?}", base_dir);
-    // Use export_dir from args if provided, otherwise export to base_dir
-    let export_dir = args.export_dir.as_ref().unwrap_or(&base_dir);
-    export::export(
-        &db,
-        collection.clone(),
-        progress_tx.clone(),
-        Some(export_dir),
-    )
-    .await?;
+    let exported = export.await;
+    db.shutdown().await?;
+    let (collection, payload_size, archive_path, exported_paths) = exported?;
 
     if let Some(ref tx) = progress_tx {
         let _ = tx
@@ -281,16 +565,142 @@ async fn receive_internal(
     // Clean up temp directory
     tokio::fs::remove_dir_all(iroh_data_dir).await?;
 
+    let total_files = collection.len() as u64;
+
     Ok(ReceiveResult {
         collection,
         total_files,
         payload_size,
+        summary: TransferSummary::new(&stats, rate),
         stats,
+        archive: archive_path,
+        exported: exported_paths,
     })
 }
 
+/// The size of the selected files of `collection`, from the sizes of the
+/// children of the hash sequence. The files start at child `offset`.
+fn selected_size(
+    collection: &Collection,
+    selected: &Collection,
+    sizes: &[u64],
+    offset: usize,
+) -> u64 {
+    let names = selected
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<HashSet<_>>();
+    collection
+        .iter()
+        .enumerate()
+        .filter(|(_, (name, _))| names.contains(name.as_str()))
+        .filter_map(|(index, _)| sizes.get(index + offset))
+        .sum()
+}
+
+/// The size of the blobs of the files of a collection that are in the store.
+async fn files_size(db: &FsStore, collection: &Collection) -> anyhow::Result<u64> {
+    let mut size = 0;
+    for (_, hash) in collection.iter() {
+        size += export::blob_size(db, *hash).await?.unwrap_or_default();
+    }
+    Ok(size)
+}
+
+/// Connect to the sender, reporting it on the progress channel.
+///
+/// Once connected, the path to the sender is watched with `path`, unless it
+/// already is.
+async fn connect(
+    endpoint: &Endpoint,
+    addr: EndpointAddr,
+    progress_tx: &Option<ProgressSenderTx>,
+    path: &mut Option<AbortOnDropHandle<()>>,
+) -> anyhow::Result<Connection> {
+    if let Some(ref tx) = progress_tx {
+        let _ = tx
+            .send(ProgressEvent::Download(DownloadProgress::Connecting))
+            .await;
+    }
+    let id = addr.id;
+    let connection = endpoint.connect(addr, iroh_blobs::protocol::ALPN).await?;
+    // The path is known right away now, even if the transfer is quick
+    if let (None, Some(tx)) = (&path, progress_tx) {
+        let watch = crate::watch_path(endpoint.clone(), id, tx.clone());
+        *path = Some(AbortOnDropHandle::new(tokio::spawn(watch)));
+    }
+    Ok(connection)
+}
+
+/// How long the receiver looks for a sender that isn't addressed directly.
+pub(crate) const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Look up the address of an endpoint with the discovery of `endpoint`.
+///
+/// The endpoint gives up on the first failing discovery service, and mDNS only
+/// answers once the endpoint has been seen on the local network. Here, failures
+/// and empty results are skipped until [`DISCOVERY_TIMEOUT`] instead.
+pub(crate) async fn find_endpoint(
+    endpoint: &Endpoint,
+    endpoint_id: EndpointId,
+) -> Option<EndpointAddr> {
+    let mut items = endpoint.discovery().resolve(endpoint_id)?;
+    let find = async {
+        while let Some(item) = items.next().await {
+            match item {
+                Ok(item) if !item.to_endpoint_addr().is_empty() => {
+                    return Some(item.into_endpoint_addr())
+                }
+                Ok(_) => {}
+                Err(e) => tracing::debug!("discovery failed: {e}"),
+            }
+        }
+        None
+    };
+    tokio::time::timeout(DISCOVERY_TIMEOUT, find)
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Create an endpoint for connecting to a sender at `addr`.
+///
+/// With [`NetworkMode::LanOnly`], the sender is looked up with mDNS instead of
+/// DNS.
+pub(crate) async fn bind_endpoint(
+    addr: &EndpointAddr,
+    common: &CommonConfig,
+) -> anyhow::Result<Endpoint> {
+    let secret_key = get_or_create_secret(common)?;
+    let mut builder = Endpoint::builder()
+        .alpns(vec![])
+        .secret_key(secret_key)
+        .relay_mode(common.relay_mode());
+
+    match common.network {
+        NetworkMode::LanOnly => {
+            builder = builder.discovery(MdnsDiscovery::builder());
+        }
+        NetworkMode::Internet
+            if addr.relay_urls().next().is_none() && addr.ip_addrs().next().is_none() =>
+        {
+            builder = builder.discovery(DnsDiscovery::n0_dns());
+        }
+        NetworkMode::Internet => {}
+    }
+
+    if let Some(addr) = common.magic_ipv4_addr {
+        builder = builder.bind_addr_v4(addr);
+    }
+    if let Some(addr) = common.magic_ipv6_addr {
+        builder = builder.bind_addr_v6(addr);
+    }
+
+    Ok(builder.bind().await?)
+}
+
 /// Show get error with context.
-fn show_get_error(e: GetError) -> GetError {
+pub(crate) fn show_get_error(e: GetError) -> GetError {
     match &e {
         GetError::InitialNext { .. } => {
             tracing::error!("initial connection error: {:?}", e);
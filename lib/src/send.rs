@@ -1,33 +1,59 @@
 //! Send functionality - hosting files for transfer.
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    path::PathBuf,
     sync::{Arc, Mutex},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
-use iroh::{discovery::pkarr::PkarrPublisher, Endpoint, RelayMode};
+use iroh::{
+    discovery::{mdns::MdnsDiscovery, pkarr::PkarrPublisher},
+    protocol::Router,
+    Endpoint, EndpointId, SecretKey,
+};
 use iroh_blobs::{
-    provider::events::{ConnectMode, EventMask, EventSender, ProviderMessage, RequestMode},
+    protocol::{ChunkRanges, ChunkRangesExt, ChunkRangesSeq},
+    provider::events::{
+        AbortReason, ConnectMode, EventMask, EventSender, ProviderMessage, RequestMode,
+        RequestUpdate,
+    },
     store::fs::FsStore,
-    BlobFormat, BlobsProtocol,
+    BlobFormat, BlobsProtocol, Hash,
 };
 
-use n0_future::StreamExt;
+use n0_future::{task::AbortOnDropHandle, StreamExt};
 use tokio::select;
-
-use crate::{apply_options, get_or_create_secret, progress::*, types::*, SendArgs, SendResult};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    apply_options,
+    crypto::ShareKey,
+    get_or_create_secret,
+    import::ImportConfig,
+    metadata,
+    pairing::{self, Pairing, ShortCode},
+    progress::*,
+    types::*,
+    SendArgs, SendResult,
+};
 
 use rand::Rng;
 
+/// How long receivers that are still connected may finish after the download
+/// limit of a share was reached.
+const CLOSE_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
 /// Send a file or directory.
 ///
 /// This function creates a temporary iroh node that serves the content in the
-/// given file or directory. It returns a ticket that can be used to get the data.
+/// given file or directory. It returns a ticket that can be used to get the data,
+/// together with a [`SendHandle`] that controls the lifetime of the provider.
 ///
-/// The provider will run until it is terminated. On termination, it will delete
-/// the temporary directory.
-pub async fn send(args: SendArgs) -> anyhow::Result<SendResult> {
+/// The provider will run until the handle is shut down or dropped. On termination,
+/// it will delete the temporary directory.
+pub async fn send(args: SendArgs) -> anyhow::Result<(SendResult, SendHandle)> {
     send_internal(args, None).await
 }
 
@@ -35,25 +61,161 @@ pub async fn send(args: SendArgs) -> anyhow::Result<SendResult> {
 pub async fn send_with_progress(
     args: SendArgs,
     progress_tx: ProgressSenderTx,
-) -> anyhow::Result<SendResult> {
+) -> anyhow::Result<(SendResult, SendHandle)> {
     send_internal(args, Some(progress_tx)).await
 }
 
+/// Handle to a running send session.
+///
+/// The content is served until the share is closed: when its download or time
+/// limit is reached, or the handle is shut down or dropped. Closing the share
+/// stops the provider and removes the temporary blob directory in the
+/// background; use [`SendHandle::shutdown`] to wait for that and see errors.
+#[derive(Debug)]
+pub struct SendHandle {
+    router: Router,
+    blobs_data_dir: PathBuf,
+    /// Cancelled to close the share.
+    cancel: CancellationToken,
+    /// Cancelled once the share is closed and torn down.
+    closed: CancellationToken,
+    /// Tears the share down once `cancel` is cancelled, taken by `shutdown`.
+    teardown: Option<tokio::task::JoinHandle<anyhow::Result<()>>>,
+}
+
+impl SendHandle {
+    fn new(router: Router, blobs_data_dir: PathBuf, cancel: CancellationToken) -> Self {
+        let closed = CancellationToken::new();
+        let teardown = tokio::spawn(teardown(
+            router.clone(),
+            blobs_data_dir.clone(),
+            cancel.clone(),
+            closed.clone(),
+        ));
+        Self {
+            router,
+            blobs_data_dir,
+            cancel,
+            closed,
+            teardown: Some(teardown),
+        }
+    }
+
+    /// The endpoint serving the content.
+    pub fn endpoint(&self) -> &Endpoint {
+        self.router.endpoint()
+    }
+
+    /// Returns true once the share is closed, see [`SendHandle::closed`].
+    pub fn is_closed(&self) -> bool {
+        self.closed.is_cancelled()
+    }
+
+    /// Returns a future that resolves once the share is closed: when its
+    /// download or time limit is reached, or the handle is shut down.
+    ///
+    /// By then the provider is stopped and the temporary blob directory is
+    /// deleted. The future does not borrow the handle, so it can be awaited
+    /// from a separate task.
+    pub fn closed(&self) -> impl Future<Output = ()> + Send + 'static {
+        self.closed.clone().cancelled_owned()
+    }
+
+    /// Stop serving the content and delete the temporary blob directory.
+    ///
+    /// Reports the errors of closing the share, even if a limit closed it
+    /// already.
+    pub async fn shutdown(mut self) -> anyhow::Result<()> {
+        self.cancel.cancel();
+        let teardown = self.teardown.take().expect("only taken here");
+        teardown.await?
+    }
+}
+
+impl Drop for SendHandle {
+    fn drop(&mut self) {
+        // The teardown task closes the share, unless the runtime is gone
+        self.cancel.cancel();
+        if self.teardown.is_none() || tokio::runtime::Handle::try_current().is_ok() {
+            return;
+        }
+        // Without a runtime the router and its store are stopped already
+        if self.blobs_data_dir.exists() {
+            if let Err(e) = std::fs::remove_dir_all(&self.blobs_data_dir) {
+                tracing::warn!("failed to remove {}: {}", self.blobs_data_dir.display(), e);
+            }
+        }
+    }
+}
+
+/// Close the share once `cancel` is cancelled: stop the provider, delete the
+/// temporary blob directory and then cancel `closed`.
+async fn teardown(
+    router: Router,
+    blobs_data_dir: PathBuf,
+    cancel: CancellationToken,
+    closed: CancellationToken,
+) -> anyhow::Result<()> {
+    cancel.cancelled().await;
+    let res = async {
+        // Shutting down the router also shuts down the blobs protocol and its
+        // store, which must be closed before its directory can be removed
+        router.shutdown().await?;
+        if blobs_data_dir.exists() {
+            tokio::fs::remove_dir_all(&blobs_data_dir).await?;
+        }
+        anyhow::Ok(())
+    }
+    .await;
+    if let Err(e) = &res {
+        tracing::warn!("failed to close the share: {e:#}");
+    }
+    closed.cancel();
+    res
+}
+
 async fn send_internal(
     args: SendArgs,
     progress_tx: Option<ProgressSenderTx>,
-) -> anyhow::Result<SendResult> {
-    let secret_key = get_or_create_secret(args.common.show_secret)?;
-    let relay_mode: RelayMode = args.common.relay.into();
+) -> anyhow::Result<(SendResult, SendHandle)> {
+    let relay_mode = args.common.relay_mode();
+    let lan_only = args.common.network == NetworkMode::LanOnly;
+    // The endpoint id of a short code share is published under its nameplate,
+    // so it must not be the sender's identity
+    let secret_key = match args.short_code {
+        true => SecretKey::generate(&mut rand::rng()),
+        false => get_or_create_secret(&args.common)?,
+    };
+    let pairing = if args.short_code {
+        let mut pairing = Pairing::new(ShortCode::generate(), progress_tx.clone());
+        if !lan_only {
+            pairing = pairing.publish_nameplate(secret_key.public())?;
+        }
+        Some(pairing)
+    } else {
+        None
+    };
 
+    let mut alpns = vec![iroh_blobs::protocol::ALPN.to_vec()];
+    if pairing.is_some() {
+        alpns.push(pairing::ALPN.to_vec());
+    }
     let mut builder = Endpoint::builder()
-        .alpns(vec![iroh_blobs::protocol::ALPN.to_vec()])
+        .alpns(alpns)
         .secret_key(secret_key)
         .relay_mode(relay_mode.clone());
 
-    if args.ticket_type == AddrInfoOptions::Id {
+    // Tickets without addresses and the nameplate of a code only carry the
+    // endpoint id
+    if (args.ticket_type == AddrInfoOptions::Id || pairing.is_some()) && !lan_only {
         builder = builder.discovery(PkarrPublisher::n0_dns());
     }
+    if pairing.is_some() || lan_only {
+        builder = builder.discovery(MdnsDiscovery::builder());
+    }
+    if let Some(pairing) = &pairing {
+        builder = builder.user_data_for_discovery(pairing.code().user_data());
+    }
 
     if let Some(addr) = args.common.magic_ipv4_addr {
         builder = builder.bind_addr_v4(addr);
@@ -66,17 +228,9 @@ async fn send_internal(
     let suffix = rand::rng().random::<[u8; 16]>();
 
     // Use custom temp_dir if provided (required for macOS sandbox), otherwise use cwd
-    let temp_dir_for_borrow = args
-        .common
-        .temp_dir
-        .as_ref()
-        .map(std::path::PathBuf::as_path);
-    let base_dir = match temp_dir_for_borrow {
-        Some(path) => path,
-        None => {
-            // Store cwd to avoid temporary value issue
-            &*std::path::PathBuf::from(std::env::current_dir()?)
-        }
+    let base_dir = match args.common.temp_dir.as_deref() {
+        Some(path) => path.to_path_buf(),
+        None => std::env::current_dir()?,
     };
 
     let blobs_data_dir = base_dir.join(format!(
@@ -92,7 +246,7 @@ async fn send_internal(
     }
 
     // Check if trying to share from current directory
-    if args.common.temp_dir.is_none() {
+    if args.common.temp_dir.is_none() && args.stdin.is_none() {
         let cwd = std::env::current_dir()?;
         if cwd.join(&args.path) == cwd {
             anyhow::bail!("can not share from the current directory");
@@ -100,9 +254,35 @@ async fn send_internal(
     }
 
     let path = args.path;
+    let stdin = args.stdin;
     let blobs_data_dir2 = blobs_data_dir.clone();
-    let _ticket_type = args.ticket_type;
     let progress_tx2 = progress_tx.clone();
+    let allowlist = args
+        .allowlist
+        .map(|ids| ids.into_iter().collect::<BTreeSet<_>>());
+    let limited = args.max_downloads.is_some() || args.expires_after.is_some();
+    let quota = limited.then(|| {
+        Arc::new(Mutex::new(Quota {
+            max_downloads: args.max_downloads,
+            expires_at: None,
+            files: BTreeSet::new(),
+            downloads: 0,
+            exhausted: false,
+        }))
+    });
+    let quota2 = quota.clone();
+    let passphrase = args.passphrase;
+    let encrypted = passphrase.is_some();
+    let import_config = ImportConfig {
+        symlinks: args.symlinks,
+        ignore_files: args.ignore_files,
+        include: args.include,
+        exclude: args.exclude,
+        compress: args.compress,
+    };
+    let cancel = CancellationToken::new();
+    let cancel2 = cancel.clone();
+    let pairing2 = pairing.clone();
 
     let setup = async move {
         let t0 = Instant::now();
@@ -111,41 +291,111 @@ async fn send_internal(
         let endpoint = builder.bind().await?;
         let store = FsStore::load(&blobs_data_dir2).await?;
 
-        let (event_tx, mut event_rx) = tokio::sync::mpsc::channel(32);
+        let (event_tx, event_rx) = tokio::sync::mpsc::channel(32);
         let blobs = BlobsProtocol::new(
             &store,
             Some(EventSender::new(
                 event_tx,
                 EventMask {
-                    connected: ConnectMode::Notify,
-                    get: RequestMode::NotifyLog,
+                    // With an allowlist or limits, connections and requests wait
+                    // for our verdict
+                    connected: if allowlist.is_some() || limited {
+                        ConnectMode::Intercept
+                    } else {
+                        ConnectMode::Notify
+                    },
+                    get: if limited {
+                        RequestMode::InterceptLog
+                    } else {
+                        RequestMode::NotifyLog
+                    },
+                    // Downloads are only counted for get requests
+                    get_many: if limited {
+                        RequestMode::Disabled
+                    } else {
+                        EventMask::DEFAULT.get_many
+                    },
                     ..EventMask::DEFAULT
                 },
             )),
         );
 
-        // Spawn progress handler if channel provided
-        if let Some(ref tx) = progress_tx2 {
-            tokio::task::spawn(handle_provider_progress(tx.clone(), event_rx));
-        } else {
-            // Still consume the events to prevent blocking
-            tokio::spawn(async move { while event_rx.recv().await.is_some() {} });
-        }
-
-        let import_result = crate::import::import(path, &store, progress_tx2).await?;
+        // The provider events must always be handled, since intercepted connections
+        // stall until they are answered. Without a progress channel, the progress
+        // events are dropped.
+        let tx = progress_tx2.clone().unwrap_or_else(|| {
+            let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+            tokio::spawn(async move { while rx.recv().await.is_some() {} });
+            tx
+        });
+        // Paths are only watched when somebody looks at them
+        let path_endpoint = progress_tx2.as_ref().map(|_| endpoint.clone());
+        tokio::task::spawn(handle_provider_progress(
+            tx,
+            path_endpoint,
+            allowlist,
+            quota2.clone(),
+            cancel2,
+            event_rx,
+        ));
+
+        // Deriving the key is deliberately slow
+        let key = match passphrase {
+            Some(passphrase) => {
+                Some(tokio::task::spawn_blocking(move || ShareKey::generate(&passphrase)).await??)
+            }
+            None => None,
+        };
+        let import_result = match (stdin, &key) {
+            (Some(name), Some(key)) => {
+                let stdin = tokio::io::stdin();
+                crate::import::import_reader_encrypted(name, stdin, &store, key, progress_tx2)
+                    .await?
+            }
+            (Some(name), None) => {
+                crate::import::import_reader(name, tokio::io::stdin(), &store, progress_tx2).await?
+            }
+            (None, Some(key)) => {
+                crate::import::import_encrypted(path, &store, &import_config, key, progress_tx2)
+                    .await?
+            }
+            (None, None) => {
+                crate::import::import(path, &store, &import_config, progress_tx2).await?
+            }
+        };
         let dt = t0.elapsed();
+        let mut wire_size = 0u64;
+        for (_, hash) in import_result.2.iter() {
+            wire_size += crate::export::blob_size(&store, *hash).await?.unwrap_or(0);
+        }
+        // The names of encrypted files are only known with the passphrase
+        let files = match &key {
+            Some(_) => Vec::new(),
+            None => list_files(&store, import_result.0).await?,
+        };
+
+        if let Some(quota) = &quota2 {
+            // Reading the manifest of an encrypted share only lists its files
+            let skip = crate::crypto::is_encrypted(&import_result.2) as usize;
+            let files = import_result.2.iter().skip(skip).map(|(_, hash)| *hash);
+            quota.lock().unwrap().files = files.collect();
+        }
 
-        let router = iroh::protocol::Router::builder(endpoint)
-            .accept(iroh_blobs::ALPN, blobs.clone())
-            .spawn();
+        let mut router = Router::builder(endpoint).accept(iroh_blobs::ALPN, blobs.clone());
+        if let Some(pairing) = pairing2 {
+            router = router.accept(pairing::ALPN, pairing);
+        }
+        let router = router.spawn();
 
-        anyhow::Ok((router, import_result, dt))
+        anyhow::Ok((router, import_result, wire_size, files, dt))
     };
 
-    let (router, (hash, size, collection), dt) = select! {
-        x = setup => x?,
-        _ = tokio::signal::ctrl_c() => {
-            std::process::exit(130);
+    let (router, (hash, size, collection), wire_size, files, dt) = match setup.await {
+        Ok(x) => x,
+        Err(e) => {
+            // Don't leave a half-populated store behind
+            let _ = tokio::fs::remove_dir_all(&blobs_data_dir).await;
+            return Err(e);
         }
     };
 
@@ -153,25 +403,77 @@ async fn send_internal(
     let mut addr = router.endpoint().addr();
     apply_options(&mut addr, args.ticket_type);
     let ticket = iroh_blobs::ticket::BlobTicket::new(addr, hash, BlobFormat::HashSeq);
+    if let Some(pairing) = &pairing {
+        pairing.set_ticket(ShareTicket {
+            ticket: ticket.clone(),
+            encrypted,
+        });
+    }
 
-    // Spawn a task to keep the router alive for connections
-    tokio::spawn(async move {
-        let _router = router;
-        std::future::pending::<()>().await;
-    });
+    if let Some(quota) = &quota {
+        let status = {
+            let mut quota = quota.lock().unwrap();
+            quota.expires_at = args
+                .expires_after
+                .map(|after| tokio::time::Instant::now() + after);
+            quota.status()
+        };
+        if let Some(tx) = &progress_tx {
+            tx.send(ProgressEvent::Connection(status)).await.ok();
+        }
+    }
+    if let Some(after) = args.expires_after {
+        let cancel = cancel.clone();
+        let progress_tx = progress_tx.clone();
+        tokio::spawn(async move {
+            select! {
+                _ = tokio::time::sleep(after) => {
+                    cancel.cancel();
+                    if let Some(tx) = progress_tx {
+                        tx.send(ProgressEvent::Connection(ConnectionStatus::ShareClosed {
+                            reason: ShareLimit::Expired,
+                        }))
+                        .await
+                        .ok();
+                    }
+                }
+                _ = cancel.cancelled() => {}
+            }
+        });
+    }
 
-    Ok(SendResult {
-        hash,
-        collection,
-        total_size: size,
-        import_duration: dt,
-        ticket,
-    })
+    let handle = SendHandle::new(router, blobs_data_dir, cancel);
+
+    Ok((
+        SendResult {
+            hash,
+            collection,
+            total_size: size,
+            wire_size,
+            files,
+            import_duration: dt,
+            ticket,
+            encrypted,
+            code: pairing.map(|pairing| pairing.code().clone()),
+        },
+        handle,
+    ))
 }
 
 /// Handle provider progress events and forward them to the progress channel.
+///
+/// If an allowlist is given, connections are intercepted and rejected unless the
+/// remote endpoint is on the list. If the share has a [`Quota`], requests are
+/// intercepted as well, downloads are counted, and `closed` is cancelled
+/// once the download limit is reached, which stops the provider. From then on,
+/// or once `closed` was cancelled for any other reason, new connections and
+/// requests are refused until the provider is down.
 async fn handle_provider_progress(
     progress_tx: ProgressSenderTx,
+    endpoint: Option<Endpoint>,
+    allowlist: Option<BTreeSet<EndpointId>>,
+    quota: Option<Arc<Mutex<Quota>>>,
+    closed: CancellationToken,
     mut recv: tokio::sync::mpsc::Receiver<ProviderMessage>,
 ) -> anyhow::Result<()> {
     let connections = Arc::new(Mutex::new(BTreeMap::new()));
@@ -186,19 +488,53 @@ async fn handle_provider_progress(
                 };
 
                 match item {
+                    ProviderMessage::ClientConnected(msg) => {
+                        let connection_id = msg.inner.connection_id;
+                        let allowed = match (&allowlist, msg.inner.endpoint_id) {
+                            (None, _) => true,
+                            (Some(allowlist), Some(id)) => allowlist.contains(&id),
+                            (Some(_), None) => false,
+                        };
+                        let endpoint_id = msg
+                            .inner
+                            .endpoint_id
+                            .map(|id| id.fmt_short().to_string())
+                            .unwrap_or_else(|| "?".to_string());
+                        if is_closing(&quota, &closed) {
+                            msg.tx.send(Err(AbortReason::Permission)).await.ok();
+                            continue;
+                        }
+                        if !allowed {
+                            tracing::info!("rejecting connection from {endpoint_id}");
+                            msg.tx.send(Err(AbortReason::Permission)).await.ok();
+                            let _ = progress_tx
+                                .send(ProgressEvent::Connection(ConnectionStatus::ClientRejected {
+                                    endpoint_id,
+                                    connection_id,
+                                }))
+                                .await;
+                            continue;
+                        }
+                        msg.tx.send(Ok(())).await.ok();
+                        let mut info = ConnectionInfo::new(endpoint_id.clone());
+                        info.path = watch_path(&endpoint, msg.inner.endpoint_id, &progress_tx);
+                        connections.lock().unwrap().insert(connection_id, info);
+                        let _ = progress_tx
+                            .send(ProgressEvent::Connection(ConnectionStatus::ClientConnected {
+                                endpoint_id,
+                                connection_id,
+                            }))
+                            .await;
+                    }
                     ProviderMessage::ClientConnectedNotify(msg) => {
                         let endpoint_id = msg
                             .endpoint_id
                             .map(|id| id.fmt_short().to_string())
                             .unwrap_or_else(|| "?".to_string());
                         let connection_id = msg.connection_id;
-                        connections.lock().unwrap().insert(
-                            connection_id,
-                            ConnectionInfo {
-                                requests: BTreeMap::new(),
-                                endpoint_id: endpoint_id.clone(),
-                            },
-                        );
+                        let mut info = ConnectionInfo::new(endpoint_id.clone());
+                        info.path = watch_path(&endpoint, msg.endpoint_id, &progress_tx);
+                        connections.lock().unwrap().insert(connection_id, info);
                         let _ = progress_tx
                             .send(ProgressEvent::Connection(ConnectionStatus::ClientConnected {
                                 endpoint_id,
@@ -207,63 +543,62 @@ async fn handle_provider_progress(
                             .await;
                     }
                     ProviderMessage::ConnectionClosed(msg) => {
-                        if connections.lock().unwrap().remove(&msg.connection_id).is_some() {
+                        let (removed, idle) = {
+                            let mut connections = connections.lock().unwrap();
+                            let removed = connections.remove(&msg.connection_id).is_some();
+                            (removed, connections.is_empty())
+                        };
+                        if removed {
                             let _ = progress_tx
                                 .send(ProgressEvent::Connection(ConnectionStatus::ConnectionClosed {
                                     connection_id: msg.connection_id,
                                 }))
                                 .await;
                         }
+                        // Once the download limit is reached, the share is closed
+                        // as soon as the last receiver is done
+                        if idle && is_closing(&quota, &closed) {
+                            closed.cancel();
+                        }
+                    }
+                    ProviderMessage::GetRequestReceived(msg) => {
+                        // Receivers that were counted already may finish
+                        let counted = connections
+                            .lock()
+                            .unwrap()
+                            .get(&msg.inner.connection_id)
+                            .is_some_and(|conn| conn.counted);
+                        if closed.is_cancelled() || (is_closing(&quota, &closed) && !counted) {
+                            msg.tx.send(Err(AbortReason::Permission)).await.ok();
+                            continue;
+                        }
+                        msg.tx.send(Ok(())).await.ok();
+                        tasks.push(tokio::task::spawn(track_request(
+                            RequestContext {
+                                connection_id: msg.inner.connection_id,
+                                request_id: msg.inner.request_id,
+                                ranges: msg.inner.request.ranges.clone(),
+                                progress_tx: progress_tx.clone(),
+                                connections: connections.clone(),
+                                quota: quota.clone(),
+                                closed: closed.clone(),
+                            },
+                            msg.rx,
+                        )));
                     }
                     ProviderMessage::GetRequestReceivedNotify(msg) => {
-                        let request_id = msg.request_id;
-                        let connection_id = msg.connection_id;
-                        let connections = connections.clone();
-                        let progress_tx = progress_tx.clone();
-                        tasks.push(tokio::task::spawn(async move {
-                            let mut rx = msg.rx;
-                            while let Ok(Some(msg)) = rx.recv().await {
-                                match msg {
-                                    iroh_blobs::provider::events::RequestUpdate::Started(msg) => {
-                                        let _ = progress_tx
-                                            .send(ProgressEvent::Connection(ConnectionStatus::RequestStarted {
-                                                connection_id,
-                                                request_id,
-                                                hash: msg.hash,
-                                                size: msg.size,
-                                            }))
-                                            .await;
-                                    }
-                                    iroh_blobs::provider::events::RequestUpdate::Progress(msg) => {
-                                        let _ = progress_tx
-                                            .send(ProgressEvent::Connection(ConnectionStatus::RequestProgress {
-                                                connection_id,
-                                                request_id,
-                                                offset: msg.end_offset,
-                                            }))
-                                            .await;
-                                    }
-                                    iroh_blobs::provider::events::RequestUpdate::Completed(_) => {
-                                        if let Some(conn) = connections.lock().unwrap().get_mut(&connection_id) {
-                                            let _ = conn.requests.remove(&request_id);
-                                        }
-                                        let _ = progress_tx
-                                            .send(ProgressEvent::Connection(ConnectionStatus::RequestCompleted {
-                                                connection_id,
-                                                request_id,
-                                            }))
-                                            .await;
-                                        break;
-                                    }
-                                    iroh_blobs::provider::events::RequestUpdate::Aborted(_) => {
-                                        if let Some(conn) = connections.lock().unwrap().get_mut(&connection_id) {
-                                            let _ = conn.requests.remove(&request_id);
-                                        }
-                                        break;
-                                    }
-                                }
-                            }
-                        }));
+                        tasks.push(tokio::task::spawn(track_request(
+                            RequestContext {
+                                connection_id: msg.connection_id,
+                                request_id: msg.request_id,
+                                ranges: msg.request.ranges.clone(),
+                                progress_tx: progress_tx.clone(),
+                                connections: connections.clone(),
+                                quota: quota.clone(),
+                                closed: closed.clone(),
+                            },
+                            msg.rx,
+                        )));
                     }
                     _ => {}
                 }
@@ -275,9 +610,225 @@ async fn handle_provider_progress(
     Ok(())
 }
 
+/// Whether the share stopped taking new connections and requests.
+fn is_closing(quota: &Option<Arc<Mutex<Quota>>>, closed: &CancellationToken) -> bool {
+    closed.is_cancelled()
+        || quota
+            .as_ref()
+            .is_some_and(|quota| quota.lock().unwrap().exhausted)
+}
+
+/// Everything needed to follow a single get request.
+struct RequestContext {
+    connection_id: u64,
+    request_id: u64,
+    ranges: ChunkRangesSeq,
+    progress_tx: ProgressSenderTx,
+    connections: Arc<Mutex<BTreeMap<u64, ConnectionInfo>>>,
+    quota: Option<Arc<Mutex<Quota>>>,
+    closed: CancellationToken,
+}
+
+/// Forward the updates of a get request, and count the download once the
+/// connection is sent data of any file of the share.
+///
+/// Receivers that resume, only fetch some files or split the download over
+/// several requests are counted all the same. Requests that only check the
+/// sizes of the files or read the names and metadata are not counted.
+async fn track_request(ctx: RequestContext, mut rx: irpc::channel::mpsc::Receiver<RequestUpdate>) {
+    let RequestContext {
+        connection_id,
+        request_id,
+        ranges,
+        progress_tx,
+        connections,
+        quota,
+        closed,
+    } = ctx;
+    let mut rate = RateTracker::new();
+    // Bytes sent of the blobs before the current one, and its size and offset
+    let (mut done, mut size, mut offset) = (0u64, 0u64, 0u64);
+    while let Ok(Some(msg)) = rx.recv().await {
+        match msg {
+            RequestUpdate::Started(msg) => {
+                let mut events = vec![ConnectionStatus::RequestStarted {
+                    connection_id,
+                    request_id,
+                    hash: msg.hash,
+                    size: msg.size,
+                }];
+                if let Some(quota) = &quota {
+                    // The last chunk alone is requested to check the size
+                    let requested = ranges.iter_infinite().nth(msg.index as usize);
+                    let data = ChunkRanges::chunks(..msg.size.div_ceil(1024).max(1));
+                    let sends_data = requested.is_some_and(|ranges| !ranges.is_disjoint(&data));
+                    let mut quota = quota.lock().unwrap();
+                    if sends_data && quota.files.contains(&msg.hash) {
+                        if let Some(conn) = connections.lock().unwrap().get_mut(&connection_id) {
+                            events.extend(quota.count(conn));
+                        }
+                    }
+                }
+                (done, size, offset) = (done + offset, msg.size, 0);
+                let exhausted = events
+                    .iter()
+                    .any(|event| matches!(event, ConnectionStatus::ShareClosed { .. }));
+                for event in events {
+                    let _ = progress_tx.send(ProgressEvent::Connection(event)).await;
+                }
+                if exhausted {
+                    // Don't wait forever for receivers that keep their connection open
+                    let closed = closed.clone();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(CLOSE_GRACE_PERIOD).await;
+                        closed.cancel();
+                    });
+                }
+            }
+            RequestUpdate::Progress(msg) => {
+                offset = msg.end_offset;
+                let rate = rate.update(done + offset, Some(done + size));
+                let _ = progress_tx
+                    .send(ProgressEvent::Connection(
+                        ConnectionStatus::RequestProgress {
+                            connection_id,
+                            request_id,
+                            offset,
+                            rate,
+                        },
+                    ))
+                    .await;
+            }
+            RequestUpdate::Completed(_) => {
+                if let Some(conn) = connections.lock().unwrap().get_mut(&connection_id) {
+                    let _ = conn.requests.remove(&request_id);
+                }
+                let _ = progress_tx
+                    .send(ProgressEvent::Connection(
+                        ConnectionStatus::RequestCompleted {
+                            connection_id,
+                            request_id,
+                        },
+                    ))
+                    .await;
+                break;
+            }
+            RequestUpdate::Aborted(_) => {
+                if let Some(conn) = connections.lock().unwrap().get_mut(&connection_id) {
+                    let _ = conn.requests.remove(&request_id);
+                }
+                break;
+            }
+        }
+    }
+}
+
+/// Download and time limits of a share.
+#[derive(Debug)]
+struct Quota {
+    max_downloads: Option<u32>,
+    expires_at: Option<tokio::time::Instant>,
+    /// The blobs of the files. A connection counts as a download once it is
+    /// sent data of any of them.
+    files: BTreeSet<Hash>,
+    downloads: u32,
+    /// Set once the download limit is reached.
+    exhausted: bool,
+}
+
+impl Quota {
+    /// Count the download of a connection, unless it was counted already, and
+    /// return the events to report.
+    fn count(&mut self, conn: &mut ConnectionInfo) -> Vec<ConnectionStatus> {
+        if conn.counted {
+            return Vec::new();
+        }
+        conn.counted = true;
+        self.downloads += 1;
+        let mut events = vec![self.status()];
+        if self.remaining_downloads() == Some(0) && !self.exhausted {
+            self.exhausted = true;
+            events.push(ConnectionStatus::ShareClosed {
+                reason: ShareLimit::MaxDownloads,
+            });
+        }
+        events
+    }
+
+    fn remaining_downloads(&self) -> Option<u32> {
+        self.max_downloads
+            .map(|max| max.saturating_sub(self.downloads))
+    }
+
+    fn status(&self) -> ConnectionStatus {
+        ConnectionStatus::QuotaUpdated {
+            downloads: self.downloads,
+            remaining_downloads: self.remaining_downloads(),
+            remaining_time: self
+                .expires_at
+                .map(|at| at.saturating_duration_since(tokio::time::Instant::now())),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct ConnectionInfo {
     #[allow(dead_code)]
     endpoint_id: String,
     requests: BTreeMap<u64, ()>,
+    /// Whether this connection was already counted as a download.
+    counted: bool,
+    /// Reports the path to the receiver until the connection is removed.
+    path: Option<AbortOnDropHandle<()>>,
+}
+
+impl ConnectionInfo {
+    fn new(endpoint_id: String) -> Self {
+        Self {
+            endpoint_id,
+            requests: BTreeMap::new(),
+            counted: false,
+            path: None,
+        }
+    }
+}
+
+/// Start reporting the path to a receiver, if paths are watched.
+fn watch_path(
+    endpoint: &Option<Endpoint>,
+    endpoint_id: Option<EndpointId>,
+    progress_tx: &ProgressSenderTx,
+) -> Option<AbortOnDropHandle<()>> {
+    let endpoint = endpoint.clone()?;
+    let task = crate::watch_path(endpoint, endpoint_id?, progress_tx.clone());
+    Some(AbortOnDropHandle::new(tokio::spawn(task)))
+}
+
+/// The files of the unencrypted collection at `hash`, with their original
+/// sizes.
+async fn list_files(store: &FsStore, hash: Hash) -> anyhow::Result<Vec<InspectFile>> {
+    let (files, metadata) = metadata::load_collection(hash, store).await?;
+    let metadata = crate::export::try_load_metadata(store, metadata, None)
+        .await
+        .unwrap_or_default();
+    let mut list = Vec::new();
+    for (name, hash) in files.iter() {
+        let compression = metadata
+            .files
+            .get(name)
+            .and_then(|file| file.compression.as_ref());
+        let (hash, size) = match compression {
+            Some(compression) => (compression.hash, compression.size),
+            None => (
+                *hash,
+                crate::export::blob_size(store, *hash).await?.unwrap_or(0),
+            ),
+        };
+        list.push(InspectFile {
+            name: name.clone(),
+            hash,
+            size,
+        });
+    }
+    Ok(list)
 }
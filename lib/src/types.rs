@@ -3,9 +3,13 @@
 use std::{fmt::Display, net::SocketAddrV4, net::SocketAddrV6, path::PathBuf, str::FromStr};
 
 use derive_more::{Display, FromStr};
+use globset::{Glob, GlobSetBuilder};
 use iroh::{RelayMode, RelayUrl, TransportAddr};
-use iroh_blobs::ticket::BlobTicket;
+use iroh_blobs::{format::collection::Collection, ticket::BlobTicket};
 use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::pairing::ShortCode;
 
 /// Output format for hashes.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
@@ -78,6 +82,114 @@ pub fn apply_options(addr: &mut iroh::EndpointAddr, opts: AddrInfoOptions) {
     }
 }
 
+/// What to do when an exported file already exists at the target path.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug, Display, FromStr, Serialize, Deserialize)]
+pub enum ExportConflictPolicy {
+    /// Replace the existing file. A directory is never replaced, the export
+    /// fails instead.
+    #[default]
+    Overwrite,
+    /// Keep the existing file and don't export the received one.
+    Skip,
+    /// Export to a free name next to the existing file, e.g. `name (1).txt`.
+    Rename,
+    /// Abort the export.
+    Fail,
+    /// Keep the existing file if its blake3 hash matches the received one,
+    /// otherwise replace it.
+    SkipIfIdentical,
+}
+
+/// Archive formats a received collection can be written to.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Display, FromStr, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    /// An uncompressed tar archive.
+    Tar,
+    /// A tar archive compressed with zstd.
+    TarZst,
+    /// A zip archive with deflate compressed files.
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// The file extension of the format, without the leading dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+/// What to do with symlinks found while importing a directory.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug, Display, FromStr, Serialize, Deserialize)]
+pub enum SymlinkPolicy {
+    /// Leave symlinks out.
+    #[default]
+    Skip,
+    /// Send the files and directories that symlinks point to, as if they were
+    /// in place of the links. Links that lead back into one of their parent
+    /// directories, and links to nothing, are left out.
+    Follow,
+    /// Send the links themselves, to be recreated by the receiver. Links with
+    /// an absolute target, or a target outside of the directory that contains
+    /// the sent directory, are left out.
+    Preserve,
+}
+
+/// Selects which files of a collection to receive.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum IncludeFilter {
+    /// Receive every file.
+    #[default]
+    All,
+    /// Receive the files with these names, and everything below directories
+    /// with these names.
+    Names(Vec<String>),
+    /// Receive the files whose name matches any of these glob patterns.
+    ///
+    /// `*` also matches `/`, so `*.jpg` selects jpg files in all directories.
+    Globs(Vec<String>),
+}
+
+impl IncludeFilter {
+    /// Returns the part of `collection` that this filter selects.
+    pub fn select(&self, collection: &Collection) -> anyhow::Result<Collection> {
+        let selected = match self {
+            Self::All => collection.clone(),
+            Self::Names(names) => collection
+                .iter()
+                .filter(|(name, _)| names.iter().any(|wanted| name_selects(wanted, name)))
+                .cloned()
+                .collect(),
+            Self::Globs(patterns) => {
+                let mut builder = GlobSetBuilder::new();
+                for pattern in patterns {
+                    builder.add(Glob::new(pattern)?);
+                }
+                let set = builder.build()?;
+                collection
+                    .iter()
+                    .filter(|(name, _)| set.is_match(name))
+                    .cloned()
+                    .collect()
+            }
+        };
+        Ok(selected)
+    }
+}
+
+/// Whether the name `wanted` of an [`IncludeFilter::Names`] selects the file
+/// `name`, being the file itself or a directory above it.
+pub(crate) fn name_selects(wanted: &str, name: &str) -> bool {
+    let wanted = wanted.trim_end_matches('/');
+    name == wanted
+        || name
+            .strip_prefix(wanted)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
 /// Relay mode configuration.
 #[derive(Clone, Debug)]
 pub enum RelayModeOption {
@@ -121,6 +233,19 @@ impl From<RelayModeOption> for RelayMode {
     }
 }
 
+/// Which networks to find and reach other endpoints on.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug, Display, FromStr, Serialize, Deserialize)]
+pub enum NetworkMode {
+    /// Use relays and the configured internet discovery, and mDNS where the
+    /// other end is only known by its endpoint id.
+    #[default]
+    Internet,
+    /// Stay on the local network. Relays are disabled and endpoints are only
+    /// announced and found with mDNS, so an id-only ticket works on a network
+    /// without internet access.
+    LanOnly,
+}
+
 /// Common configuration for send/receive operations.
 #[derive(Clone, Debug)]
 pub struct CommonConfig {
@@ -132,11 +257,19 @@ pub struct CommonConfig {
     pub format: Format,
     /// Relay mode configuration.
     pub relay: RelayModeOption,
+    /// Which networks to use. [`NetworkMode::LanOnly`] overrides `relay`.
+    pub network: NetworkMode,
     /// Whether to show the secret key.
     pub show_secret: bool,
     /// Optional custom temp directory for blob storage.
     /// If None, uses current working directory (not compatible with macOS sandbox).
     pub temp_dir: Option<PathBuf>,
+    /// Name of the stored identity whose secret key the endpoint uses.
+    /// If None, a new key is generated for every run.
+    pub identity: Option<String>,
+    /// Directory of the key store holding the identities.
+    /// If None, uses the platform config directory.
+    pub key_dir: Option<PathBuf>,
 }
 
 impl Default for CommonConfig {
@@ -146,14 +279,31 @@ impl Default for CommonConfig {
             magic_ipv6_addr: None,
             format: Format::default(),
             relay: RelayModeOption::Default,
+            network: NetworkMode::default(),
             show_secret: false,
             temp_dir: None,
+            identity: None,
+            key_dir: None,
+        }
+    }
+}
+
+impl CommonConfig {
+    /// The relay mode of the endpoint, which is always disabled for
+    /// [`NetworkMode::LanOnly`].
+    pub fn relay_mode(&self) -> RelayMode {
+        match self.network {
+            NetworkMode::Internet => self.relay.clone().into(),
+            NetworkMode::LanOnly => RelayMode::Disabled,
         }
     }
 }
 
 /// Arguments for sending data.
-#[derive(Clone, Debug)]
+///
+/// The default sends nothing; set at least `path`, and take the rest from
+/// [`Default`] with struct update syntax.
+#[derive(Clone, Debug, Default)]
 pub struct SendArgs {
     /// Path to the file or directory to send.
     pub path: PathBuf,
@@ -161,6 +311,33 @@ pub struct SendArgs {
     pub ticket_type: AddrInfoOptions,
     /// Common configuration.
     pub common: CommonConfig,
+    /// Endpoints that are allowed to fetch the data.
+    /// If None, anyone holding the ticket can connect.
+    pub allowlist: Option<Vec<iroh::EndpointId>>,
+    /// Stop sharing after this many downloads, complete or not.
+    pub max_downloads: Option<u32>,
+    /// Stop sharing after this much time has passed since the ticket was created.
+    pub expires_after: Option<std::time::Duration>,
+    /// Encrypt the files and their names with a key derived from this passphrase.
+    pub passphrase: Option<String>,
+    /// Also hand out the ticket for a [`ShortCode`]. The share is then served
+    /// from a fresh endpoint id, never from the identity of `common`.
+    pub short_code: bool,
+    /// What to do with symlinks in the sent directory.
+    pub symlinks: SymlinkPolicy,
+    /// Leave out what `.gitignore`, `.ignore` and `.sendmeignore` files exclude,
+    /// and `.git` directories.
+    pub ignore_files: bool,
+    /// Only send the files matching one of these globs, if any are given. See
+    /// [`ImportConfig`](crate::import::ImportConfig) for the syntax.
+    pub include: Vec<String>,
+    /// Don't send the files and directories matching one of these globs.
+    pub exclude: Vec<String>,
+    /// Send standard input as a single file with this name, instead of `path`.
+    pub stdin: Option<String>,
+    /// Compress the files that are not compressed already, see
+    /// [`compression`](crate::compression). Not applied to standard input.
+    pub compress: bool,
 }
 
 /// Arguments for receiving data.
@@ -173,10 +350,49 @@ pub struct ReceiveArgs {
     /// Optional export directory for final file location.
     /// If not set, files will be exported to temp_dir.
     pub export_dir: Option<PathBuf>,
+    /// Which files of the collection to download and export.
+    pub include: IncludeFilter,
+    /// What to do with files that already exist in the export directory.
+    pub conflict_policy: ExportConflictPolicy,
+    /// Move the received files into the export directory only once all of them
+    /// have been written, so an error never leaves a partial tree behind.
+    pub atomic_export: bool,
+    /// Cancels the receive when triggered.
+    ///
+    /// The partially downloaded store is kept, so receiving the same ticket
+    /// again resumes where it stopped.
+    pub cancel: CancellationToken,
+    /// Passphrase to decrypt an encrypted share with.
+    pub passphrase: Option<String>,
+    /// Write the received files into a single archive of this format instead
+    /// of exporting them one by one.
+    ///
+    /// [`receive`](crate::receive) saves the archive in the export directory,
+    /// named after the collection, see [`archive_name`](crate::archive::archive_name).
+    /// [`receive_to_writer`](crate::receive_to_writer) writes it to the writer.
+    pub archive: Option<ArchiveFormat>,
+}
+
+impl ReceiveArgs {
+    /// Receive everything `ticket` refers to with the default settings,
+    /// exporting into the temporary directory.
+    pub fn new(ticket: BlobTicket) -> Self {
+        Self {
+            ticket,
+            common: CommonConfig::default(),
+            export_dir: None,
+            include: IncludeFilter::All,
+            conflict_policy: ExportConflictPolicy::default(),
+            atomic_export: false,
+            cancel: CancellationToken::new(),
+            passphrase: None,
+            archive: None,
+        }
+    }
 }
 
 /// Result from a send operation.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SendResult {
     /// Hash of the collection.
     pub hash: iroh_blobs::Hash,
@@ -184,10 +400,151 @@ pub struct SendResult {
     pub collection: iroh_blobs::format::collection::Collection,
     /// Total size of all files.
     pub total_size: u64,
+    /// Total size of the blobs of all files as they are sent, which is smaller
+    /// than `total_size` if they are compressed.
+    pub wire_size: u64,
+    /// The files of the share with their sizes. Empty if the files are
+    /// encrypted, as their names are only known with the passphrase.
+    pub files: Vec<InspectFile>,
     /// Time taken for import.
     pub import_duration: std::time::Duration,
     /// Ticket for receiving the data.
     pub ticket: BlobTicket,
+    /// Whether the files are encrypted with a passphrase.
+    pub encrypted: bool,
+    /// Short code receivers can use instead of the ticket, if requested.
+    pub code: Option<ShortCode>,
+}
+
+impl SendResult {
+    /// The ticket to hand to receivers, flagged if a passphrase is needed.
+    pub fn share_ticket(&self) -> ShareTicket {
+        ShareTicket {
+            ticket: self.ticket.clone(),
+            encrypted: self.encrypted,
+        }
+    }
+}
+
+/// A ticket as shared with receivers.
+///
+/// Tickets of passphrase protected shares carry the [`ShareTicket::LOCKED_PREFIX`],
+/// so a receiver knows to ask for the passphrase before connecting. Plain blob
+/// tickets parse as unencrypted shares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareTicket {
+    /// The ticket for fetching the data.
+    pub ticket: BlobTicket,
+    /// Whether the share is protected by a passphrase.
+    pub encrypted: bool,
+}
+
+impl ShareTicket {
+    /// Prefix of the tickets of passphrase protected shares.
+    pub const LOCKED_PREFIX: &'static str = "locked";
+}
+
+impl Display for ShareTicket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.encrypted {
+            write!(f, "{}", Self::LOCKED_PREFIX)?;
+        }
+        write!(f, "{}", self.ticket)
+    }
+}
+
+impl FromStr for ShareTicket {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (s, encrypted) = match s.strip_prefix(Self::LOCKED_PREFIX) {
+            Some(rest) => (rest, true),
+            None => (s, false),
+        };
+        Ok(Self {
+            ticket: s.parse()?,
+            encrypted,
+        })
+    }
+}
+
+impl From<BlobTicket> for ShareTicket {
+    fn from(ticket: BlobTicket) -> Self {
+        Self {
+            ticket,
+            encrypted: false,
+        }
+    }
+}
+
+/// What a receiver was given to get a share: a ticket or a short code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TicketOrCode {
+    /// A ticket, used as is.
+    Ticket(ShareTicket),
+    /// A short code, exchanged for the ticket with the sender.
+    Code(ShortCode),
+}
+
+impl TicketOrCode {
+    /// Get the ticket, asking the sender for it if this is a short code.
+    pub async fn resolve(self, common: &CommonConfig) -> anyhow::Result<ShareTicket> {
+        match self {
+            Self::Ticket(ticket) => Ok(ticket),
+            Self::Code(code) => crate::resolve_code(&code, common).await,
+        }
+    }
+}
+
+impl Display for TicketOrCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ticket(ticket) => ticket.fmt(f),
+            Self::Code(code) => code.fmt(f),
+        }
+    }
+}
+
+impl FromStr for TicketOrCode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Codes start with a number, tickets never do
+        if s.trim_start().starts_with(|c: char| c.is_ascii_digit()) {
+            Ok(Self::Code(s.parse()?))
+        } else {
+            Ok(Self::Ticket(s.parse()?))
+        }
+    }
+}
+
+/// A file listed by [`inspect`](crate::inspect).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InspectFile {
+    /// Name of the file within the collection.
+    pub name: String,
+    /// Hash of the file content.
+    pub hash: iroh_blobs::Hash,
+    /// Size of the file in bytes.
+    pub size: u64,
+}
+
+/// Result from inspecting a ticket.
+#[derive(Debug, Clone)]
+pub struct InspectResult {
+    /// Hash of the collection.
+    pub hash: iroh_blobs::Hash,
+    /// Endpoint id of the sender.
+    pub endpoint_id: iroh::EndpointId,
+    /// How we were connected to the sender when the listing completed.
+    pub connection_type: iroh::endpoint::ConnectionType,
+    /// Whether the share is protected by a passphrase.
+    pub encrypted: bool,
+    /// All files in the collection. Empty for an encrypted share inspected
+    /// without its passphrase.
+    pub files: Vec<InspectFile>,
+    /// Total size of all files.
+    pub total_size: u64,
 }
 
 /// Result from a receive operation.
@@ -201,4 +558,11 @@ pub struct ReceiveResult {
     pub payload_size: u64,
     /// Statistics about the transfer.
     pub stats: iroh_blobs::get::Stats,
+    /// Summary of the download, with the numbers shown while it progressed.
+    pub summary: crate::progress::TransferSummary,
+    /// Path of the archive the files were saved in, see [`ReceiveArgs::archive`].
+    pub archive: Option<PathBuf>,
+    /// Paths the files were written to, after conflicts were resolved. Skipped
+    /// files are left out, and it is empty for archives and streams.
+    pub exported: Vec<PathBuf>,
 }
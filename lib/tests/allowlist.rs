@@ -0,0 +1,63 @@
+//! Tests for restricting a share to an allowlist of receivers.
+
+mod common;
+
+use common::*;
+use sendme_lib::{
+    progress::{ConnectionStatus, ProgressEvent},
+    types::*,
+    KeyStore,
+};
+
+#[tokio::test]
+async fn only_allowed_receivers_can_fetch() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("hello.txt"), b"hello").unwrap();
+
+    let keys = KeyStore::new(dir.path().join("keys"));
+    let allowed = keys.load_or_create("allowed").unwrap();
+
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(32);
+    let (result, handle) = sendme_lib::send_with_progress(
+        SendArgs {
+            allowlist: Some(vec![allowed.public()]),
+            ..send_args(src.join("hello.txt"), dir.path())
+        },
+        progress_tx,
+    )
+    .await
+    .unwrap();
+
+    // A receiver with a random key is turned away
+    let stranger = dir.path().join("stranger");
+    std::fs::create_dir_all(&stranger).unwrap();
+    let res = sendme_lib::receive(receive_args(result.ticket.clone(), &stranger)).await;
+    assert!(res.is_err());
+    assert!(!stranger.join("hello.txt").exists());
+
+    let rejected = loop {
+        match progress_rx.recv().await.unwrap() {
+            ProgressEvent::Connection(ConnectionStatus::ClientRejected { endpoint_id, .. }) => {
+                break endpoint_id
+            }
+            ProgressEvent::Connection(ConnectionStatus::ClientConnected { .. }) => {
+                panic!("stranger was let in")
+            }
+            _ => {}
+        }
+    };
+    assert_ne!(rejected, allowed.public().fmt_short().to_string());
+
+    // The allowed identity gets the data
+    let friend = dir.path().join("friend");
+    std::fs::create_dir_all(&friend).unwrap();
+    let mut args = receive_args(result.ticket.clone(), &friend);
+    args.common.identity = Some("allowed".to_string());
+    args.common.key_dir = Some(keys.dir().to_path_buf());
+    sendme_lib::receive(args).await.unwrap();
+    assert_eq!(std::fs::read(friend.join("hello.txt")).unwrap(), b"hello");
+
+    handle.shutdown().await.unwrap();
+}
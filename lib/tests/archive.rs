@@ -0,0 +1,173 @@
+//! Tests for receiving a collection into a tar, tar.zst or zip archive.
+
+mod common;
+
+use std::{collections::BTreeMap, io::Read};
+
+use sendme_lib::types::*;
+
+fn send_args(path: std::path::PathBuf, dir: &std::path::Path) -> SendArgs {
+    SendArgs {
+        symlinks: SymlinkPolicy::Preserve,
+        ..common::send_args(path, dir)
+    }
+}
+
+fn receive_args(
+    ticket: sendme_lib::BlobTicket,
+    dir: &std::path::Path,
+    archive: ArchiveFormat,
+) -> ReceiveArgs {
+    ReceiveArgs {
+        archive: Some(archive),
+        ..common::receive_args(ticket, dir)
+    }
+}
+
+/// A directory with nested files, an executable, an empty directory and, on
+/// unix, a symlink.
+fn create_source(dir: &std::path::Path) -> std::path::PathBuf {
+    let src = dir.join("src");
+    std::fs::create_dir_all(src.join("sub")).unwrap();
+    std::fs::create_dir_all(src.join("empty")).unwrap();
+    std::fs::write(src.join("a.txt"), b"a").unwrap();
+    std::fs::write(src.join("sub").join("b.txt"), vec![b'b'; 100_000]).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::write(src.join("run.sh"), b"#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(src.join("run.sh"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+        std::os::unix::fs::symlink("a.txt", src.join("link.txt")).unwrap();
+    }
+    src
+}
+
+/// The entries of a tar archive by name, with their mode and content or link
+/// target.
+fn tar_entries(data: &[u8]) -> BTreeMap<String, (u32, Vec<u8>)> {
+    let mut archive = tar::Archive::new(data);
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().into_owned();
+            let mode = entry.header().mode().unwrap();
+            let mut content = Vec::new();
+            match entry.link_name().unwrap() {
+                Some(target) => content.extend(target.to_string_lossy().as_bytes()),
+                None => {
+                    entry.read_to_end(&mut content).unwrap();
+                }
+            }
+            (name, (mode, content))
+        })
+        .collect()
+}
+
+fn check_entries(entries: &BTreeMap<String, (u32, Vec<u8>)>) {
+    assert_eq!(entries["src/a.txt"].1, b"a");
+    assert_eq!(entries["src/sub/b.txt"].1, vec![b'b'; 100_000]);
+    assert!(entries.contains_key("src/empty/"));
+    #[cfg(unix)]
+    {
+        assert_eq!(entries["src/run.sh"].0 & 0o777, 0o755);
+        assert_eq!(entries["src/link.txt"].1, b"a.txt");
+    }
+}
+
+#[tokio::test]
+async fn receive_into_tar_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = create_source(dir.path());
+    let (result, handle) = sendme_lib::send(send_args(src, dir.path())).await.unwrap();
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    let received = sendme_lib::receive(receive_args(
+        result.ticket.clone(),
+        &out,
+        ArchiveFormat::Tar,
+    ))
+    .await
+    .unwrap();
+    let path = out.join("src.tar");
+    assert_eq!(received.archive.as_deref(), Some(path.as_path()));
+    // Only the archive is written
+    assert_eq!(std::fs::read_dir(&out).unwrap().count(), 1);
+    check_entries(&tar_entries(&std::fs::read(&path).unwrap()));
+
+    // An existing archive is handled by the conflict policy
+    let received = sendme_lib::receive(ReceiveArgs {
+        conflict_policy: ExportConflictPolicy::Rename,
+        ..receive_args(result.ticket.clone(), &out, ArchiveFormat::Tar)
+    })
+    .await
+    .unwrap();
+    assert_eq!(received.archive, Some(out.join("src (1).tar")));
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn receive_tar_zst_to_writer() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = create_source(dir.path());
+    let (result, handle) = sendme_lib::send(send_args(src, dir.path())).await.unwrap();
+
+    let mut out = Vec::new();
+    sendme_lib::receive_to_writer(
+        receive_args(result.ticket.clone(), dir.path(), ArchiveFormat::TarZst),
+        &mut out,
+        None,
+    )
+    .await
+    .unwrap();
+    check_entries(&tar_entries(&zstd::decode_all(out.as_slice()).unwrap()));
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn receive_encrypted_share_into_zip() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = create_source(dir.path());
+    let (result, handle) = sendme_lib::send(SendArgs {
+        passphrase: Some("correct horse".to_string()),
+        ..send_args(src, dir.path())
+    })
+    .await
+    .unwrap();
+
+    let mut out = Vec::new();
+    sendme_lib::receive_to_writer(
+        ReceiveArgs {
+            passphrase: Some("correct horse".to_string()),
+            ..receive_args(result.ticket.clone(), dir.path(), ArchiveFormat::Zip)
+        },
+        &mut out,
+        None,
+    )
+    .await
+    .unwrap();
+
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(out)).unwrap();
+    let mut content = Vec::new();
+    zip.by_name("src/sub/b.txt")
+        .unwrap()
+        .read_to_end(&mut content)
+        .unwrap();
+    assert_eq!(content, vec![b'b'; 100_000]);
+    assert!(zip.by_name("src/empty/").unwrap().is_dir());
+    #[cfg(unix)]
+    {
+        assert_eq!(
+            zip.by_name("src/run.sh").unwrap().unix_mode().unwrap() & 0o777,
+            0o755
+        );
+        assert!(zip.by_name("src/link.txt").unwrap().is_symlink());
+    }
+
+    handle.shutdown().await.unwrap();
+}
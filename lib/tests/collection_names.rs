@@ -0,0 +1,143 @@
+//! Property tests for collection name validation.
+//!
+//! Collection names come from the sender and are joined onto the receiver's
+//! export directory, so no accepted name may ever escape that directory.
+
+use std::path::{Component, Path};
+
+use proptest::prelude::*;
+use sendme_lib::{canonicalized_path_to_string, get_export_path, validate_collection_name};
+
+/// A component that is valid on every platform.
+fn valid_component() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9_-][a-zA-Z0-9 ._-]{0,15}"
+        .prop_filter("not dots or spaces only", |s| {
+            !s.trim_end_matches(['.', ' ']).is_empty()
+        })
+        .prop_filter("not a device name", |s| {
+            let stem = s.split('.').next().unwrap().trim_end().to_ascii_uppercase();
+            !matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+                && !((stem.starts_with("COM") || stem.starts_with("LPT"))
+                    && stem.len() == 4
+                    && stem.as_bytes()[3].is_ascii_digit())
+        })
+}
+
+fn valid_name() -> impl Strategy<Value = String> {
+    prop::collection::vec(valid_component(), 1..6).prop_map(|parts| parts.join("/"))
+}
+
+/// Segments that must never be accepted anywhere in a name.
+fn bad_component() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just("".to_string()),
+        Just(".".to_string()),
+        Just("..".to_string()),
+        Just("... ".to_string()),
+        "[a-z]{0,4}\\\\[a-z]{0,4}",
+        "[a-z]{0,4}\0[a-z]{0,4}",
+        "[a-zA-Z]:[a-z]{0,4}",
+        "[a-z0-9 ]{1,4}:[a-z]{0,4}",
+        "(?i)(con|prn|aux|nul|com[0-9]|lpt[0-9])(\\.[a-z]{1,3})?",
+    ]
+}
+
+proptest! {
+    #[test]
+    fn valid_names_are_accepted(name in valid_name()) {
+        prop_assert!(validate_collection_name(&name).is_ok(), "{:?}", name);
+    }
+
+    #[test]
+    fn names_with_a_bad_component_are_rejected(
+        before in prop::collection::vec(valid_component(), 0..3),
+        bad in bad_component(),
+        after in prop::collection::vec(valid_component(), 0..3),
+    ) {
+        let name = before
+            .into_iter()
+            .chain(std::iter::once(bad))
+            .chain(after)
+            .collect::<Vec<_>>()
+            .join("/");
+        prop_assert!(validate_collection_name(&name).is_err(), "{:?}", name);
+    }
+
+    #[test]
+    fn accepted_names_stay_inside_the_root(name in any::<String>()) {
+        let root = Path::new("/export/root");
+        if let Ok(path) = get_export_path(root, &name) {
+            let rest = path.strip_prefix(root).unwrap();
+            prop_assert!(rest
+                .components()
+                .all(|c| matches!(c, Component::Normal(_))));
+            prop_assert_eq!(rest.components().count(), name.split('/').count());
+        }
+    }
+
+    #[test]
+    fn export_path_agrees_with_validation(name in "[a-zA-Z./\\\\:\0 ]{0,12}") {
+        prop_assert_eq!(
+            get_export_path(Path::new("root"), &name).is_ok(),
+            validate_collection_name(&name).is_ok()
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn canonicalized_names_roundtrip(name in valid_name()) {
+        prop_assert_eq!(canonicalized_path_to_string(Path::new(&name), true).unwrap(), name);
+    }
+}
+
+#[test]
+fn known_traversal_names_are_rejected() {
+    for name in [
+        "",
+        "/",
+        "/etc/passwd",
+        "../../.bashrc",
+        "a/../../b",
+        "a/./b",
+        "a//b",
+        "a/",
+        "..\\..\\windows\\system32",
+        "C:\\Windows",
+        "c:/Windows",
+        "C:",
+        "12:30 meeting.txt",
+        "notes.txt:hidden",
+        "dir/file:stream:$DATA",
+        "dir/aux",
+        "dir/Con.txt",
+        "LPT1",
+        "com9.log",
+        "nul\0",
+        "a\0b",
+        ". . ",
+    ] {
+        assert!(
+            validate_collection_name(name).is_err(),
+            "{name:?} should be rejected"
+        );
+    }
+}
+
+#[test]
+fn ordinary_names_are_accepted() {
+    for name in [
+        "file.txt",
+        ".bashrc",
+        "dir/sub dir/file name.tar.gz",
+        "...hidden",
+        "console.log",
+        "com10",
+        "auxiliary/notes.md",
+        "12.30 meeting.txt",
+    ] {
+        assert!(
+            validate_collection_name(name).is_ok(),
+            "{name:?} should be accepted"
+        );
+    }
+}
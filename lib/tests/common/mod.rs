@@ -0,0 +1,70 @@
+//! Fixtures shared by the integration tests.
+//!
+//! Every test file is its own crate and uses only some of these.
+#![allow(dead_code)]
+
+use std::{
+    net::{Ipv4Addr, SocketAddrV4},
+    path::{Path, PathBuf},
+};
+
+use sendme_lib::{types::*, BlobTicket};
+
+/// Config of a node without relays that keeps its stores below `temp_dir`.
+pub fn common(temp_dir: &Path) -> CommonConfig {
+    CommonConfig {
+        relay: RelayModeOption::Disabled,
+        temp_dir: Some(temp_dir.to_path_buf()),
+        ..Default::default()
+    }
+}
+
+/// Like [`common`], but only reachable on the loopback interface, so the
+/// path between two nodes is known.
+pub fn loopback(temp_dir: &Path) -> CommonConfig {
+    CommonConfig {
+        magic_ipv4_addr: Some(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)),
+        ..common(temp_dir)
+    }
+}
+
+/// Like [`loopback`], on the local network only, so the sender can't be
+/// reached any other way than through the address announced with mDNS.
+pub fn lan_only(temp_dir: &Path) -> CommonConfig {
+    CommonConfig {
+        network: NetworkMode::LanOnly,
+        ..loopback(temp_dir)
+    }
+}
+
+/// Share `path` with a ticket that carries the direct addresses.
+pub fn send_args(path: impl Into<PathBuf>, temp_dir: &Path) -> SendArgs {
+    SendArgs {
+        path: path.into(),
+        ticket_type: AddrInfoOptions::Addresses,
+        common: common(temp_dir),
+        ..Default::default()
+    }
+}
+
+/// Receive everything `ticket` refers to into `dir`.
+pub fn receive_args(ticket: BlobTicket, dir: &Path) -> ReceiveArgs {
+    ReceiveArgs {
+        common: common(dir),
+        export_dir: Some(dir.to_path_buf()),
+        ..ReceiveArgs::new(ticket)
+    }
+}
+
+/// Collect the events sent on a progress channel until it closes.
+pub fn collect_events(
+    mut rx: tokio::sync::mpsc::Receiver<sendme_lib::ProgressEvent>,
+) -> tokio::task::JoinHandle<Vec<sendme_lib::ProgressEvent>> {
+    tokio::spawn(async move {
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        events
+    })
+}
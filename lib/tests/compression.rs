@@ -0,0 +1,195 @@
+//! Tests for compressing files on the wire.
+
+mod common;
+
+use common::receive_args;
+use sendme_lib::{
+    compression::{is_compressed, Codec},
+    import::{import, ImportConfig},
+    metadata::{self, Metadata, MetadataBlob},
+    progress::{ConflictResolution, ExportProgress, ProgressEvent},
+    types::*,
+};
+
+fn send_args(path: std::path::PathBuf, dir: &std::path::Path) -> SendArgs {
+    SendArgs {
+        compress: true,
+        ..common::send_args(path, dir)
+    }
+}
+
+/// A log that compresses well.
+fn log() -> Vec<u8> {
+    (0..5_000)
+        .flat_map(|i| format!("2024-01-01 12:00:00 INFO request {i} served\n").into_bytes())
+        .collect()
+}
+
+/// A directory with a log, a png and data that doesn't compress.
+fn create_source(dir: &std::path::Path) -> std::path::PathBuf {
+    let src = dir.join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("app.log"), log()).unwrap();
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend(std::iter::repeat_n(0u8, 10_000));
+    std::fs::write(src.join("image.png"), png).unwrap();
+    let random = (0..10_000)
+        .map(|_| rand::random::<u8>())
+        .collect::<Vec<_>>();
+    std::fs::write(src.join("random.bin"), random).unwrap();
+    src
+}
+
+#[test]
+fn compressed_formats_are_recognised() {
+    assert!(is_compressed(b"\x1f\x8b\x08\x00"));
+    assert!(is_compressed(b"PK\x03\x04rest"));
+    assert!(is_compressed(b"RIFF\x00\x00\x00\x00WEBPVP8 "));
+    assert!(is_compressed(b"\x00\x00\x00\x20ftypisom"));
+    assert!(!is_compressed(b"fn main() {}\n"));
+    assert!(!is_compressed(b""));
+}
+
+#[tokio::test]
+async fn only_compressible_files_are_compressed() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = create_source(dir.path());
+    let db = iroh_blobs::store::fs::FsStore::load(dir.path().join("db"))
+        .await
+        .unwrap();
+
+    let config = ImportConfig {
+        compress: true,
+        ..Default::default()
+    };
+    let (hash, size, _) = import(src, &db, &config, None).await.unwrap();
+    // Receivers that don't know about the metadata refuse the collection
+    assert!(sendme_lib::Collection::load(hash, db.as_ref())
+        .await
+        .is_err());
+    let (files, metadata) = metadata::load_collection(hash, db.as_ref()).await.unwrap();
+    let Some(MetadataBlob::Child(metadata)) = metadata else {
+        panic!("the metadata of a compressed share must be a child");
+    };
+    let metadata: Metadata =
+        serde_json::from_slice(&db.get_bytes(metadata).await.unwrap()).unwrap();
+
+    let log = log();
+    let compression = metadata.files["src/app.log"].compression.clone().unwrap();
+    assert_eq!(compression.codec, Codec::Zstd);
+    assert_eq!(compression.size, log.len() as u64);
+    assert_eq!(compression.hash, sendme_lib::Hash::new(&log));
+    let (_, blob) = files
+        .iter()
+        .find(|(name, _)| name == "src/app.log")
+        .unwrap();
+    let blob = db.get_bytes(*blob).await.unwrap();
+    assert!(blob.len() < log.len() / 10);
+    assert_eq!(zstd::decode_all(&blob[..]).unwrap(), log);
+
+    // Sniffed as compressed, and not worth compressing
+    assert_eq!(metadata.files["src/image.png"].compression, None);
+    assert_eq!(metadata.files["src/random.bin"].compression, None);
+    assert_eq!(size, log.len() as u64 + 10_008 + 10_000);
+
+    db.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn compressed_files_are_decompressed_on_export() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = create_source(dir.path());
+
+    for passphrase in [None, Some("correct horse".to_string())] {
+        let (result, handle) = sendme_lib::send(SendArgs {
+            passphrase: passphrase.clone(),
+            ..send_args(src.clone(), dir.path())
+        })
+        .await
+        .unwrap();
+        assert!(result.wire_size < result.total_size / 2);
+
+        let out = tempfile::tempdir().unwrap();
+        let received = sendme_lib::receive(ReceiveArgs {
+            passphrase: passphrase.clone(),
+            ..receive_args(result.ticket.clone(), out.path())
+        })
+        .await
+        .unwrap();
+        assert_eq!(received.total_files, 3);
+        for name in ["app.log", "image.png", "random.bin"] {
+            assert_eq!(
+                std::fs::read(out.path().join("src").join(name)).unwrap(),
+                std::fs::read(src.join(name)).unwrap(),
+                "{name}"
+            );
+        }
+
+        // The existing file is compared with the decompressed content
+        let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(1024);
+        sendme_lib::receive_with_progress(
+            ReceiveArgs {
+                passphrase: passphrase.clone(),
+                conflict_policy: ExportConflictPolicy::SkipIfIdentical,
+                ..receive_args(result.ticket.clone(), out.path())
+            },
+            progress_tx,
+        )
+        .await
+        .unwrap();
+        let mut identical = Vec::new();
+        while let Ok(event) = progress_rx.try_recv() {
+            if let ProgressEvent::Export(
+                _,
+                ExportProgress::Conflict {
+                    name,
+                    resolution: ConflictResolution::SkippedIdentical,
+                },
+            ) = event
+            {
+                identical.push(name);
+            }
+        }
+        assert_eq!(identical.len(), 3);
+
+        handle.shutdown().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn compressed_file_to_writer_and_archive() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("app.log"), log()).unwrap();
+    let (result, handle) = sendme_lib::send(send_args(dir.path().join("app.log"), dir.path()))
+        .await
+        .unwrap();
+
+    let mut out = Vec::new();
+    sendme_lib::receive_to_writer(
+        receive_args(result.ticket.clone(), dir.path()),
+        &mut out,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(out, log());
+
+    let mut out = Vec::new();
+    sendme_lib::receive_to_writer(
+        ReceiveArgs {
+            archive: Some(ArchiveFormat::Tar),
+            ..receive_args(result.ticket.clone(), dir.path())
+        },
+        &mut out,
+        None,
+    )
+    .await
+    .unwrap();
+    let mut archive = tar::Archive::new(out.as_slice());
+    let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+    let mut content = Vec::new();
+    std::io::Read::read_to_end(&mut entry, &mut content).unwrap();
+    assert_eq!(content, log());
+
+    handle.shutdown().await.unwrap();
+}
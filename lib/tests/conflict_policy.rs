@@ -0,0 +1,191 @@
+//! Tests for the policies for files that already exist where a received file
+//! is exported.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use common::*;
+use sendme_lib::types::*;
+
+/// A directory with a file at the top and one in a subdirectory.
+fn create_source(dir: &Path) -> PathBuf {
+    let src = dir.join("src");
+    std::fs::create_dir_all(src.join("sub")).unwrap();
+    std::fs::write(src.join("a.txt"), b"new").unwrap();
+    std::fs::write(src.join("sub").join("b.txt"), b"b").unwrap();
+    src
+}
+
+/// An export directory that already has a different `src/a.txt`.
+fn create_out(dir: &Path, name: &str) -> PathBuf {
+    let out = dir.join(name);
+    std::fs::create_dir_all(out.join("src")).unwrap();
+    std::fs::write(out.join("src").join("a.txt"), b"old").unwrap();
+    out
+}
+
+fn receive_args(
+    ticket: sendme_lib::BlobTicket,
+    dir: &Path,
+    conflict_policy: ExportConflictPolicy,
+) -> ReceiveArgs {
+    ReceiveArgs {
+        conflict_policy,
+        ..common::receive_args(ticket, dir)
+    }
+}
+
+#[tokio::test]
+async fn existing_files_are_handled_by_the_policy() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = create_source(dir.path());
+    let (result, handle) = sendme_lib::send(send_args(src, dir.path())).await.unwrap();
+
+    let out = create_out(dir.path(), "skip");
+    let received = sendme_lib::receive(receive_args(
+        result.ticket.clone(),
+        &out,
+        ExportConflictPolicy::Skip,
+    ))
+    .await
+    .unwrap();
+    assert_eq!(std::fs::read(out.join("src/a.txt")).unwrap(), b"old");
+    assert_eq!(std::fs::read(out.join("src/sub/b.txt")).unwrap(), b"b");
+    assert_eq!(received.exported, vec![out.join("src/sub/b.txt")]);
+
+    let out = create_out(dir.path(), "overwrite");
+    let received = sendme_lib::receive(receive_args(
+        result.ticket.clone(),
+        &out,
+        ExportConflictPolicy::Overwrite,
+    ))
+    .await
+    .unwrap();
+    assert_eq!(std::fs::read(out.join("src/a.txt")).unwrap(), b"new");
+    assert_eq!(received.exported.len(), 2);
+
+    let out = create_out(dir.path(), "rename");
+    let received = sendme_lib::receive(receive_args(
+        result.ticket.clone(),
+        &out,
+        ExportConflictPolicy::Rename,
+    ))
+    .await
+    .unwrap();
+    assert_eq!(std::fs::read(out.join("src/a.txt")).unwrap(), b"old");
+    assert_eq!(std::fs::read(out.join("src/a (1).txt")).unwrap(), b"new");
+    assert!(received.exported.contains(&out.join("src/a (1).txt")));
+    assert!(!received.exported.contains(&out.join("src/a.txt")));
+
+    let out = create_out(dir.path(), "fail");
+    let res = sendme_lib::receive(receive_args(
+        result.ticket.clone(),
+        &out,
+        ExportConflictPolicy::Fail,
+    ))
+    .await;
+    assert!(res.is_err());
+    assert_eq!(std::fs::read(out.join("src/a.txt")).unwrap(), b"old");
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn renamed_file_is_reported_at_its_new_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("a.txt");
+    std::fs::write(&file, b"new").unwrap();
+    let (result, handle) = sendme_lib::send(send_args(file, dir.path())).await.unwrap();
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    std::fs::write(out.join("a.txt"), b"old").unwrap();
+    for atomic_export in [false, true] {
+        let received = sendme_lib::receive(ReceiveArgs {
+            atomic_export,
+            ..receive_args(result.ticket.clone(), &out, ExportConflictPolicy::Rename)
+        })
+        .await
+        .unwrap();
+        let expected = match atomic_export {
+            false => out.join("a (1).txt"),
+            true => out.join("a (2).txt"),
+        };
+        assert_eq!(received.exported, vec![expected.clone()]);
+        assert_eq!(std::fs::read(expected).unwrap(), b"new");
+    }
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn directory_is_not_overwritten_with_a_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = create_source(dir.path());
+    let (result, handle) = sendme_lib::send(send_args(src, dir.path())).await.unwrap();
+
+    for atomic_export in [false, true] {
+        let out = dir.path().join(format!("out-{atomic_export}"));
+        std::fs::create_dir_all(out.join("src").join("a.txt")).unwrap();
+        std::fs::write(out.join("src").join("a.txt").join("keep"), b"keep").unwrap();
+
+        let err = sendme_lib::receive(ReceiveArgs {
+            atomic_export,
+            ..receive_args(result.ticket.clone(), &out, ExportConflictPolicy::Overwrite)
+        })
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("directory"), "{err}");
+        assert_eq!(
+            std::fs::read(out.join("src").join("a.txt").join("keep")).unwrap(),
+            b"keep"
+        );
+    }
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn failed_atomic_export_is_rolled_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = create_source(dir.path());
+    for i in 0..10 {
+        std::fs::write(src.join(format!("{i}.txt")), b"new").unwrap();
+    }
+    std::fs::write(src.join("created.txt"), b"new").unwrap();
+    let (result, handle) = sendme_lib::send(send_args(src, dir.path())).await.unwrap();
+
+    // The files are replaced one by one when the export is committed, until
+    // the file in the place of the received `sub` directory stops it
+    let out = create_out(dir.path(), "out");
+    for i in 0..10 {
+        std::fs::write(out.join("src").join(format!("{i}.txt")), b"old").unwrap();
+    }
+    std::fs::write(out.join("src").join("sub"), b"old").unwrap();
+
+    let err = sendme_lib::receive(ReceiveArgs {
+        atomic_export: true,
+        ..receive_args(result.ticket.clone(), &out, ExportConflictPolicy::Overwrite)
+    })
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("can not replace"), "{err}");
+
+    for i in 0..10 {
+        let path = out.join("src").join(format!("{i}.txt"));
+        assert_eq!(std::fs::read(path).unwrap(), b"old");
+    }
+    assert_eq!(std::fs::read(out.join("src/a.txt")).unwrap(), b"old");
+    assert_eq!(std::fs::read(out.join("src/sub")).unwrap(), b"old");
+    assert!(!out.join("src/created.txt").exists());
+    // The staging directory is removed
+    assert!(!std::fs::read_dir(&out).unwrap().any(|entry| entry
+        .unwrap()
+        .file_name()
+        .to_string_lossy()
+        .starts_with(".sendme-export-")));
+    assert_eq!(std::fs::read_dir(out.join("src")).unwrap().count(), 12);
+
+    handle.shutdown().await.unwrap();
+}
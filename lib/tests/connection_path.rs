@@ -0,0 +1,63 @@
+//! Tests for reporting the network path between sender and receiver.
+
+mod common;
+
+use common::*;
+use sendme_lib::{types::*, ConnectionStatus, ProgressEvent};
+
+/// The first path reported among `events`.
+fn first_path(events: &[ProgressEvent]) -> Option<&ConnectionStatus> {
+    events.iter().find_map(|event| match event {
+        ProgressEvent::Connection(status @ ConnectionStatus::PathChanged { .. }) => Some(status),
+        _ => None,
+    })
+}
+
+#[tokio::test]
+async fn both_ends_report_a_direct_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("hello.txt");
+    std::fs::write(&file, b"hello").unwrap();
+
+    let (send_tx, send_rx) = tokio::sync::mpsc::channel(64);
+    let (share, handle) = sendme_lib::send_with_progress(
+        SendArgs {
+            common: loopback(dir.path()),
+            ..send_args(file, dir.path())
+        },
+        send_tx,
+    )
+    .await
+    .unwrap();
+    let sent = collect_events(send_rx);
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    let (recv_tx, recv_rx) = tokio::sync::mpsc::channel(64);
+    let received = collect_events(recv_rx);
+    sendme_lib::receive_with_progress(
+        ReceiveArgs {
+            common: loopback(dir.path()),
+            ..receive_args(share.ticket.clone(), &out)
+        },
+        recv_tx,
+    )
+    .await
+    .unwrap();
+    handle.shutdown().await.unwrap();
+
+    for events in [received.await.unwrap(), sent.await.unwrap()] {
+        let Some(ConnectionStatus::PathChanged {
+            direct,
+            remote_addr,
+            relay_url,
+            ..
+        }) = first_path(&events)
+        else {
+            panic!("no path reported in {events:?}");
+        };
+        assert!(direct);
+        assert!(remote_addr.unwrap().ip().is_loopback());
+        assert!(relay_url.is_none());
+    }
+}
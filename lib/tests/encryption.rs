@@ -0,0 +1,147 @@
+//! Tests for passphrase-protected shares.
+
+mod common;
+
+use common::*;
+use sendme_lib::types::*;
+
+fn receive_args(
+    ticket: sendme_lib::BlobTicket,
+    dir: &std::path::Path,
+    passphrase: Option<&str>,
+) -> ReceiveArgs {
+    ReceiveArgs {
+        passphrase: passphrase.map(str::to_string),
+        ..common::receive_args(ticket, dir)
+    }
+}
+
+#[tokio::test]
+async fn encrypted_share_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("src");
+    std::fs::create_dir_all(src.join("sub")).unwrap();
+    std::fs::write(src.join("hello.txt"), b"hello").unwrap();
+    // Spans several encryption chunks
+    let big: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(src.join("sub").join("big.bin"), &big).unwrap();
+
+    let (result, handle) = sendme_lib::send(SendArgs {
+        passphrase: Some("correct horse".to_string()),
+        ..send_args(src.clone(), dir.path())
+    })
+    .await
+    .unwrap();
+    assert!(result.encrypted);
+
+    let shared = result.share_ticket();
+    let parsed: ShareTicket = shared.to_string().parse().unwrap();
+    assert!(shared.to_string().starts_with(ShareTicket::LOCKED_PREFIX));
+    assert_eq!(parsed, shared);
+
+    // Without the passphrase nothing but the size is visible
+    let listing = sendme_lib::inspect(result.ticket.clone(), common(dir.path()))
+        .await
+        .unwrap();
+    assert!(listing.encrypted);
+    assert!(listing.files.is_empty());
+
+    let listing = sendme_lib::inspect_with_passphrase(
+        result.ticket.clone(),
+        common(dir.path()),
+        "correct horse",
+    )
+    .await
+    .unwrap();
+    let mut names: Vec<_> = listing.files.iter().map(|f| f.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, vec!["src/hello.txt", "src/sub/big.bin"]);
+    assert_eq!(listing.total_size, 5 + big.len() as u64);
+
+    let out = dir.path().join("none");
+    std::fs::create_dir_all(&out).unwrap();
+    assert!(
+        sendme_lib::receive(receive_args(result.ticket.clone(), &out, None))
+            .await
+            .is_err()
+    );
+
+    let out = dir.path().join("wrong");
+    std::fs::create_dir_all(&out).unwrap();
+    let err = sendme_lib::receive(receive_args(result.ticket.clone(), &out, Some("wrong")))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("wrong passphrase"), "{err:#}");
+    assert!(!out.join("src").exists());
+
+    let out = dir.path().join("right");
+    std::fs::create_dir_all(&out).unwrap();
+    sendme_lib::receive(receive_args(
+        result.ticket.clone(),
+        &out,
+        Some("correct horse"),
+    ))
+    .await
+    .unwrap();
+    assert_eq!(
+        std::fs::read(out.join("src").join("hello.txt")).unwrap(),
+        b"hello"
+    );
+    assert_eq!(
+        std::fs::read(out.join("src").join("sub").join("big.bin")).unwrap(),
+        big
+    );
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn encrypted_share_is_refused_before_the_download() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("big.bin");
+    std::fs::write(&file, vec![7u8; 200_000]).unwrap();
+    let (result, handle) = sendme_lib::send(SendArgs {
+        passphrase: Some("correct horse".to_string()),
+        max_downloads: Some(1),
+        ..send_args(file, dir.path())
+    })
+    .await
+    .unwrap();
+
+    let out = dir.path().join("none");
+    std::fs::create_dir_all(&out).unwrap();
+    let err = sendme_lib::receive(receive_args(result.ticket.clone(), &out, None))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("passphrase"), "{err}");
+
+    // The refused receive didn't use up the only download
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    sendme_lib::receive(receive_args(
+        result.ticket.clone(),
+        &out,
+        Some("correct horse"),
+    ))
+    .await
+    .unwrap();
+    assert_eq!(
+        std::fs::read(out.join("big.bin")).unwrap(),
+        vec![7u8; 200_000]
+    );
+
+    handle.shutdown().await.unwrap();
+}
+
+#[test]
+fn plain_tickets_are_not_locked() {
+    let addr = iroh::SecretKey::generate(&mut rand::rng()).public();
+    let ticket = sendme_lib::BlobTicket::new(
+        addr.into(),
+        sendme_lib::Hash::new(b"hello"),
+        sendme_lib::BlobFormat::HashSeq,
+    );
+    let parsed: ShareTicket = ticket.to_string().parse().unwrap();
+    assert!(!parsed.encrypted);
+    assert_eq!(parsed.ticket, ticket);
+}
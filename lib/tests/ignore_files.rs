@@ -0,0 +1,160 @@
+//! Tests for ignore files, include/exclude globs and the other files that are
+//! left out when sending a directory.
+
+mod common;
+
+use common::*;
+use sendme_lib::{
+    progress::{ImportProgress, ProgressEvent},
+    types::*,
+};
+
+/// A project with build output, logs, a git directory and ignore files.
+fn create_project(dir: &std::path::Path) -> std::path::PathBuf {
+    let project = dir.join("project");
+    for (name, content) in [
+        (".gitignore", "target/\n*.log\n"),
+        (".sendmeignore", "secret.txt\n"),
+        (".git/HEAD", "ref: refs/heads/main\n"),
+        (".env", "KEY=value\n"),
+        ("src/main.rs", "fn main() {}\n"),
+        ("docs/readme.md", "# readme\n"),
+        ("target/debug/app", "binary"),
+        ("debug.log", "log"),
+        ("secret.txt", "secret"),
+    ] {
+        let path = project.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    project
+}
+
+/// Send with `args`, returning the sorted names and the skipped count.
+async fn sent_files(args: SendArgs) -> (Vec<String>, usize) {
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(64);
+    let (result, handle) = sendme_lib::send_with_progress(args, progress_tx)
+        .await
+        .unwrap();
+    let skipped = loop {
+        if let ProgressEvent::Import(_, ImportProgress::Started { skipped, .. }) =
+            progress_rx.recv().await.unwrap()
+        {
+            break skipped;
+        }
+    };
+    let mut names: Vec<_> = result
+        .collection
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect();
+    names.sort();
+    handle.shutdown().await.unwrap();
+    (names, skipped)
+}
+
+#[tokio::test]
+async fn everything_is_sent_by_default() {
+    let dir = tempfile::tempdir().unwrap();
+    let project = create_project(dir.path());
+
+    let (names, skipped) = sent_files(send_args(project, dir.path())).await;
+    assert_eq!(names.len(), 9);
+    assert_eq!(skipped, 0);
+}
+
+#[tokio::test]
+async fn ignore_files_are_honoured() {
+    let dir = tempfile::tempdir().unwrap();
+    let project = create_project(dir.path());
+
+    let (names, skipped) = sent_files(SendArgs {
+        ignore_files: true,
+        ..send_args(project, dir.path())
+    })
+    .await;
+    assert_eq!(
+        names,
+        vec![
+            "project/.env",
+            "project/.gitignore",
+            "project/.sendmeignore",
+            "project/docs/readme.md",
+            "project/src/main.rs",
+        ]
+    );
+    assert_eq!(skipped, 4);
+}
+
+#[tokio::test]
+async fn include_and_exclude_globs() {
+    let dir = tempfile::tempdir().unwrap();
+    let project = create_project(dir.path());
+
+    let (names, skipped) = sent_files(SendArgs {
+        include: vec!["*.rs".to_string(), "*.md".to_string()],
+        exclude: vec!["/docs".to_string()],
+        ..send_args(project.clone(), dir.path())
+    })
+    .await;
+    assert_eq!(names, vec!["project/src/main.rs"]);
+    assert_eq!(skipped, 8);
+
+    // Explicit globs win over ignore files
+    let (names, _) = sent_files(SendArgs {
+        ignore_files: true,
+        include: vec!["*.log".to_string()],
+        ..send_args(project, dir.path())
+    })
+    .await;
+    assert_eq!(names, vec!["project/debug.log"]);
+}
+
+#[tokio::test]
+async fn files_in_left_out_directories_count() {
+    let dir = tempfile::tempdir().unwrap();
+    let project = create_project(dir.path());
+    for i in 0..100 {
+        let path = project.join(format!("target/debug/deps/{i}.o"));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "object").unwrap();
+    }
+
+    // .git/HEAD, target/debug/app, the objects, debug.log and secret.txt
+    let (_, skipped) = sent_files(SendArgs {
+        ignore_files: true,
+        ..send_args(project, dir.path())
+    })
+    .await;
+    assert_eq!(skipped, 104);
+}
+
+/// Names that Windows can't create, so receivers refuse them everywhere.
+#[tokio::test]
+#[cfg(unix)]
+async fn unportable_names_are_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let project = dir.path().join("project");
+    for name in ["ok.txt", "aux.c", "con/x.h", "debug.log"] {
+        let path = project.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, name).unwrap();
+    }
+
+    let (names, skipped) = sent_files(send_args(project.clone(), dir.path())).await;
+    assert_eq!(names, vec!["project/debug.log", "project/ok.txt"]);
+    assert_eq!(skipped, 0);
+
+    // They don't count as ignored files
+    let (names, skipped) = sent_files(SendArgs {
+        exclude: vec!["*.log".to_string()],
+        ..send_args(project.clone(), dir.path())
+    })
+    .await;
+    assert_eq!(names, vec!["project/ok.txt"]);
+    assert_eq!(skipped, 1);
+
+    // A single file with such a name can't be sent at all
+    let res = sendme_lib::send(send_args(project.join("aux.c"), dir.path())).await;
+    assert!(res.is_err());
+}
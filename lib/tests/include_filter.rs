@@ -0,0 +1,144 @@
+//! Tests for receiving only some of the files of a collection.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use common::*;
+use sendme_lib::{
+    progress::{DownloadProgress, ProgressEvent},
+    types::*,
+};
+
+/// Files of different sizes, two at the top and two in a subdirectory.
+fn create_source(dir: &Path) -> PathBuf {
+    let src = dir.join("src");
+    std::fs::create_dir_all(src.join("sub")).unwrap();
+    std::fs::write(src.join("a.txt"), vec![b'a'; 1_000]).unwrap();
+    std::fs::write(src.join("b.txt"), vec![b'b'; 2_000]).unwrap();
+    std::fs::write(src.join("sub").join("c.bin"), vec![b'c'; 5_000]).unwrap();
+    std::fs::write(src.join("sub").join("d.bin"), vec![b'd'; 7_000]).unwrap();
+    src
+}
+
+/// The names of the files below `dir`, relative to it.
+fn files_below(dir: &Path) -> Vec<String> {
+    let mut files = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| {
+            let path = entry.path().strip_prefix(dir).unwrap();
+            path.to_string_lossy().replace('\\', "/")
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+#[tokio::test]
+async fn only_selected_files_are_received() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = create_source(dir.path());
+    let (result, handle) = sendme_lib::send(send_args(src, dir.path())).await.unwrap();
+
+    let out = dir.path().join("sub");
+    std::fs::create_dir_all(&out).unwrap();
+    let (tx, rx) = tokio::sync::mpsc::channel(64);
+    let events = collect_events(rx);
+    let received = sendme_lib::receive_with_progress(
+        ReceiveArgs {
+            include: IncludeFilter::Names(vec!["src/sub".to_string()]),
+            ..receive_args(result.ticket.clone(), &out)
+        },
+        tx,
+    )
+    .await
+    .unwrap();
+    assert_eq!(received.total_files, 2);
+    assert_eq!(received.payload_size, 12_000);
+    assert_eq!(files_below(&out), vec!["src/sub/c.bin", "src/sub/d.bin"]);
+
+    let events = events.await.unwrap();
+    let metadata = events.iter().find_map(|event| match event {
+        ProgressEvent::Download(DownloadProgress::Metadata {
+            total_size,
+            file_count,
+            ..
+        }) => Some((*total_size, *file_count)),
+        _ => None,
+    });
+    assert_eq!(metadata, Some((12_000, 2)));
+    // Only the selected files and the small blobs of the collection are fetched
+    let total = events.iter().find_map(|event| match event {
+        ProgressEvent::Download(DownloadProgress::Downloading { total, .. }) => Some(*total),
+        _ => None,
+    });
+    let total = total.unwrap();
+    assert!((12_000..13_000).contains(&total), "{total}");
+
+    let out = dir.path().join("txt");
+    std::fs::create_dir_all(&out).unwrap();
+    let received = sendme_lib::receive(ReceiveArgs {
+        include: IncludeFilter::Globs(vec!["*.txt".to_string()]),
+        ..receive_args(result.ticket.clone(), &out)
+    })
+    .await
+    .unwrap();
+    assert_eq!(received.total_files, 2);
+    assert_eq!(received.payload_size, 3_000);
+    assert_eq!(files_below(&out), vec!["src/a.txt", "src/b.txt"]);
+
+    // Without a filter, the metadata blob doesn't count either
+    let out = dir.path().join("all");
+    std::fs::create_dir_all(&out).unwrap();
+    let received = sendme_lib::receive(receive_args(result.ticket.clone(), &out))
+        .await
+        .unwrap();
+    assert_eq!(received.total_files, 4);
+    assert_eq!(received.payload_size, 15_000);
+
+    let out = dir.path().join("none");
+    std::fs::create_dir_all(&out).unwrap();
+    let res = sendme_lib::receive(ReceiveArgs {
+        include: IncludeFilter::Names(vec!["src/missing.txt".to_string()]),
+        ..receive_args(result.ticket.clone(), &out)
+    })
+    .await;
+    assert!(res.is_err());
+    assert!(!out.join("src").exists());
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn only_selected_files_of_an_encrypted_share_are_received() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = create_source(dir.path());
+    let (result, handle) = sendme_lib::send(SendArgs {
+        passphrase: Some("correct horse".to_string()),
+        ..send_args(src, dir.path())
+    })
+    .await
+    .unwrap();
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    let received = sendme_lib::receive(ReceiveArgs {
+        include: IncludeFilter::Names(vec!["src/b.txt".to_string()]),
+        passphrase: Some("correct horse".to_string()),
+        ..receive_args(result.ticket.clone(), &out)
+    })
+    .await
+    .unwrap();
+    assert_eq!(received.total_files, 1);
+    // The encrypted blob is a little larger than the file
+    assert!((2_000..3_000).contains(&received.payload_size));
+    assert_eq!(files_below(&out), vec!["src/b.txt"]);
+    assert_eq!(
+        std::fs::read(out.join("src").join("b.txt")).unwrap(),
+        vec![b'b'; 2_000]
+    );
+
+    handle.shutdown().await.unwrap();
+}
@@ -0,0 +1,148 @@
+//! Tests for listing the files of a share without downloading them.
+
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+use sendme_lib::{
+    progress::{ConnectionStatus, ProgressEvent},
+    types::*,
+};
+
+/// A directory with files that are larger than the last chunk group, which
+/// is all that is fetched to check the size of a file.
+fn create_source(dir: &std::path::Path) -> std::path::PathBuf {
+    let src = dir.join("src");
+    std::fs::create_dir_all(src.join("sub")).unwrap();
+    std::fs::write(src.join("a.txt"), vec![b'a'; 100_000]).unwrap();
+    std::fs::write(src.join("sub").join("b.bin"), vec![b'b'; 300_000]).unwrap();
+    src
+}
+
+/// Names and sizes of a listing, sorted by name.
+fn listed(listing: &InspectResult) -> Vec<(String, u64)> {
+    let mut files = listing
+        .files
+        .iter()
+        .map(|file| (file.name.clone(), file.size))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+/// A share with the progress events of the sender.
+type Share = (
+    SendResult,
+    sendme_lib::SendHandle,
+    tokio::sync::mpsc::Receiver<ProgressEvent>,
+);
+
+/// Share the files of [`create_source`] with a single download.
+async fn limited_share(dir: &std::path::Path, passphrase: Option<&str>) -> Share {
+    let src = create_source(dir);
+    let (progress_tx, progress_rx) = tokio::sync::mpsc::channel(256);
+    let (result, handle) = sendme_lib::send_with_progress(
+        SendArgs {
+            max_downloads: Some(1),
+            passphrase: passphrase.map(str::to_string),
+            ..send_args(src, dir)
+        },
+        progress_tx,
+    )
+    .await
+    .unwrap();
+    (result, handle, progress_rx)
+}
+
+/// Check that the single download of a [`limited_share`] is still available,
+/// so no file was sent in full before.
+async fn check_download_is_left(
+    dir: &std::path::Path,
+    passphrase: Option<&str>,
+    (result, handle, mut progress_rx): Share,
+) {
+    let out = dir.join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    sendme_lib::receive(ReceiveArgs {
+        passphrase: passphrase.map(str::to_string),
+        ..receive_args(result.ticket.clone(), &out)
+    })
+    .await
+    .unwrap();
+    assert_eq!(
+        std::fs::read(out.join("src").join("a.txt")).unwrap(),
+        vec![b'a'; 100_000]
+    );
+
+    tokio::time::timeout(Duration::from_secs(30), handle.closed())
+        .await
+        .expect("share was not closed");
+    let mut downloads = Vec::new();
+    while let Ok(event) = progress_rx.try_recv() {
+        if let ProgressEvent::Connection(ConnectionStatus::QuotaUpdated { downloads: n, .. }) =
+            event
+        {
+            downloads.push(n);
+        }
+    }
+    assert_eq!(downloads, vec![0, 1]);
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn inspect_lists_files_without_downloading_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let share = limited_share(dir.path(), None).await;
+
+    for _ in 0..2 {
+        let listing = sendme_lib::inspect(share.0.ticket.clone(), common(dir.path()))
+            .await
+            .unwrap();
+        assert!(!listing.encrypted);
+        assert_eq!(
+            listed(&listing),
+            vec![
+                ("src/a.txt".to_string(), 100_000),
+                ("src/sub/b.bin".to_string(), 300_000),
+            ]
+        );
+        assert_eq!(listing.total_size, 400_000);
+    }
+
+    check_download_is_left(dir.path(), None, share).await;
+}
+
+#[tokio::test]
+async fn inspect_lists_encrypted_files_without_downloading_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let passphrase = Some("correct horse");
+    let share = limited_share(dir.path(), passphrase).await;
+    let ticket = share.0.ticket.clone();
+
+    let listing =
+        sendme_lib::inspect_with_passphrase(ticket.clone(), common(dir.path()), "correct horse")
+            .await
+            .unwrap();
+    assert!(listing.encrypted);
+    // The names and sizes are those of the decrypted files
+    assert_eq!(
+        listed(&listing),
+        vec![
+            ("src/a.txt".to_string(), 100_000),
+            ("src/sub/b.bin".to_string(), 300_000),
+        ]
+    );
+    assert_eq!(listing.total_size, 400_000);
+
+    // Without the passphrase only the size of the encrypted files is known
+    let listing = sendme_lib::inspect(ticket, common(dir.path()))
+        .await
+        .unwrap();
+    assert!(listing.encrypted);
+    assert!(listing.files.is_empty());
+    assert!((400_000..401_000).contains(&listing.total_size));
+
+    check_download_is_left(dir.path(), passphrase, share).await;
+}
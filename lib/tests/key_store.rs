@@ -0,0 +1,94 @@
+//! Tests for the persistent identity key store.
+
+use sendme_lib::{get_or_create_secret, keystore::validate_identity_name, CommonConfig, KeyStore};
+
+#[test]
+fn identity_is_stable() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = KeyStore::new(dir.path().join("identities"));
+    assert!(store.load("default").unwrap().is_none());
+
+    let first = store.load_or_create("default").unwrap();
+    let second = KeyStore::new(store.dir())
+        .load_or_create("default")
+        .unwrap();
+    assert_eq!(first.public(), second.public());
+
+    let other = store.load_or_create("work").unwrap();
+    assert_ne!(first.public(), other.public());
+
+    let names = store
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|(name, id)| (name, id.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            ("default".to_string(), first.public().to_string()),
+            ("work".to_string(), other.public().to_string()),
+        ]
+    );
+
+    assert!(store.remove("work").unwrap());
+    assert!(!store.remove("work").unwrap());
+    assert_eq!(store.list().unwrap().len(), 1);
+}
+
+#[test]
+fn common_config_selects_identity() {
+    if std::env::var_os("IROH_SECRET").is_some() {
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let common = CommonConfig {
+        identity: Some("laptop".to_string()),
+        key_dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let first = get_or_create_secret(&common).unwrap();
+    let second = get_or_create_secret(&common).unwrap();
+    assert_eq!(first.public(), second.public());
+
+    let ephemeral = CommonConfig {
+        key_dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let random = get_or_create_secret(&ephemeral).unwrap();
+    assert_ne!(first.public(), random.public());
+}
+
+#[cfg(unix)]
+#[test]
+fn key_files_are_private() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let store = KeyStore::new(dir.path().join("identities"));
+    store.load_or_create("default").unwrap();
+
+    let path = store.path("default").unwrap();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    let mode = std::fs::metadata(store.dir()).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o700);
+
+    // Loosened permissions are restricted again on load
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    store.load("default").unwrap().unwrap();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[test]
+fn identity_names_are_validated() {
+    for name in ["default", "work-laptop", "phone_2"] {
+        assert!(validate_identity_name(name).is_ok(), "{name}");
+    }
+    for name in ["", "../x", "a/b", ".hidden", "a b", &"x".repeat(65)] {
+        assert!(validate_identity_name(name).is_err(), "{name:?}");
+    }
+    let store = KeyStore::new(tempfile::tempdir().unwrap().path());
+    assert!(store.load_or_create("../escape").is_err());
+}
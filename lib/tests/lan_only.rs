@@ -0,0 +1,38 @@
+//! Tests for staying on the local network.
+
+mod common;
+
+use common::*;
+use sendme_lib::types::*;
+
+#[tokio::test]
+async fn id_only_ticket_resolves_on_the_local_network() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("hello.txt");
+    std::fs::write(&file, b"hello").unwrap();
+
+    let (result, handle) = sendme_lib::send(SendArgs {
+        ticket_type: AddrInfoOptions::Id,
+        common: lan_only(dir.path()),
+        ..send_args(file, dir.path())
+    })
+    .await
+    .unwrap();
+    let addr = result.ticket.addr();
+    assert!(addr.ip_addrs().next().is_none());
+    assert!(addr.relay_urls().next().is_none());
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    let receive = sendme_lib::receive(ReceiveArgs {
+        common: lan_only(dir.path()),
+        ..receive_args(result.ticket.clone(), &out)
+    });
+    tokio::time::timeout(std::time::Duration::from_secs(30), receive)
+        .await
+        .expect("the sender was not found")
+        .unwrap();
+    assert_eq!(std::fs::read(out.join("hello.txt")).unwrap(), b"hello");
+
+    handle.shutdown().await.unwrap();
+}
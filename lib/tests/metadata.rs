@@ -0,0 +1,202 @@
+//! Tests for preserving file modes, modification times and empty directories.
+
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use common::*;
+use sendme_lib::{
+    import::{import, ImportConfig},
+    metadata::{self, Metadata, MetadataBlob},
+    types::*,
+};
+
+/// A directory with an executable, a file with an old mtime and empty directories.
+fn create_source(dir: &std::path::Path) -> (std::path::PathBuf, SystemTime) {
+    let src = dir.join("src");
+    std::fs::create_dir_all(src.join("empty")).unwrap();
+    std::fs::create_dir_all(src.join("nested").join("deeper")).unwrap();
+    std::fs::write(src.join("run.sh"), b"#!/bin/sh\necho hi\n").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(src.join("run.sh"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+    }
+    let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    std::fs::write(src.join("old.txt"), b"old").unwrap();
+    std::fs::File::options()
+        .write(true)
+        .open(src.join("old.txt"))
+        .unwrap()
+        .set_modified(mtime)
+        .unwrap();
+    (src, mtime)
+}
+
+#[cfg(unix)]
+fn mode(path: &std::path::Path) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+}
+
+#[tokio::test]
+async fn metadata_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let (src, mtime) = create_source(dir.path());
+
+    let (result, handle) = sendme_lib::send(send_args(src, dir.path())).await.unwrap();
+    let mut names: Vec<_> = result.collection.iter().map(|(name, _)| name).collect();
+    names.sort();
+    assert_eq!(names, vec!["src/old.txt", "src/run.sh"]);
+
+    let listing = sendme_lib::inspect(result.ticket.clone(), common(dir.path()))
+        .await
+        .unwrap();
+    assert_eq!(listing.files.len(), 2);
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    let received = sendme_lib::receive(receive_args(result.ticket.clone(), &out))
+        .await
+        .unwrap();
+    assert_eq!(received.total_files, 2);
+
+    let dst = out.join("src");
+    assert_eq!(std::fs::read_dir(&out).unwrap().count(), 1);
+    assert!(dst.join("empty").is_dir());
+    assert!(dst.join("nested").join("deeper").is_dir());
+    assert_eq!(
+        std::fs::metadata(dst.join("old.txt"))
+            .unwrap()
+            .modified()
+            .unwrap(),
+        mtime
+    );
+    #[cfg(unix)]
+    {
+        assert_eq!(mode(&dst.join("run.sh")), 0o755);
+        assert_eq!(mode(&dst.join("old.txt")) & 0o111, 0);
+    }
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn plain_share_loads_as_a_stock_collection() {
+    let dir = tempfile::tempdir().unwrap();
+    let (src, mtime) = create_source(dir.path());
+    let db = iroh_blobs::store::fs::FsStore::load(dir.path().join("db"))
+        .await
+        .unwrap();
+
+    let (hash, _, collection) = import(src, &db, &ImportConfig::default(), None)
+        .await
+        .unwrap();
+    // Older receivers load the files and ignore the metadata
+    let stored = sendme_lib::Collection::load(hash, db.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(stored, collection);
+
+    let (files, metadata) = metadata::load_collection(hash, db.as_ref()).await.unwrap();
+    assert_eq!(files, collection);
+    let Some(MetadataBlob::Names(metadata)) = metadata else {
+        panic!("the metadata of a plain share must follow the names");
+    };
+    let metadata: Metadata = serde_json::from_slice(&metadata).unwrap();
+    assert_eq!(metadata.files["src/old.txt"].mtime, Some(mtime));
+    assert!(metadata.empty_dirs.contains(&"src/empty".to_string()));
+
+    db.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn metadata_of_encrypted_share() {
+    let dir = tempfile::tempdir().unwrap();
+    let (src, mtime) = create_source(dir.path());
+
+    let (result, handle) = sendme_lib::send(SendArgs {
+        passphrase: Some("correct horse".to_string()),
+        ..send_args(src, dir.path())
+    })
+    .await
+    .unwrap();
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    sendme_lib::receive(ReceiveArgs {
+        passphrase: Some("correct horse".to_string()),
+        atomic_export: true,
+        ..receive_args(result.ticket.clone(), &out)
+    })
+    .await
+    .unwrap();
+
+    let dst = out.join("src");
+    assert_eq!(std::fs::read_dir(&out).unwrap().count(), 1);
+    assert!(dst.join("empty").is_dir());
+    assert_eq!(
+        std::fs::metadata(dst.join("old.txt"))
+            .unwrap()
+            .modified()
+            .unwrap(),
+        mtime
+    );
+    #[cfg(unix)]
+    assert_eq!(mode(&dst.join("run.sh")), 0o755);
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn partial_receive_applies_metadata_of_selected_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let (src, _) = create_source(dir.path());
+
+    let (result, handle) = sendme_lib::send(send_args(src, dir.path())).await.unwrap();
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    let received = sendme_lib::receive(ReceiveArgs {
+        include: IncludeFilter::Globs(vec!["**/*.sh".to_string()]),
+        ..receive_args(result.ticket.clone(), &out)
+    })
+    .await
+    .unwrap();
+    assert_eq!(received.total_files, 1);
+
+    let dst = out.join("src");
+    assert!(!dst.join("old.txt").exists());
+    // Empty directories only come with the whole collection
+    assert!(!dst.join("empty").exists());
+    #[cfg(unix)]
+    assert_eq!(mode(&dst.join("run.sh")), 0o755);
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn file_named_like_the_metadata_is_sent() {
+    let dir = tempfile::tempdir().unwrap();
+    let (src, _) = create_source(dir.path());
+    std::fs::write(src.join(".sendme-metadata"), b"{}").unwrap();
+
+    let (result, handle) = sendme_lib::send(send_args(src, dir.path())).await.unwrap();
+    assert_eq!(result.collection.len(), 3);
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    let received = sendme_lib::receive(receive_args(result.ticket.clone(), &out))
+        .await
+        .unwrap();
+    assert_eq!(received.total_files, 3);
+
+    let dst = out.join("src");
+    assert_eq!(std::fs::read(dst.join(".sendme-metadata")).unwrap(), b"{}");
+    assert!(dst.join("empty").is_dir());
+    #[cfg(unix)]
+    assert_eq!(mode(&dst.join("run.sh")), 0o755);
+
+    handle.shutdown().await.unwrap();
+}
@@ -0,0 +1,96 @@
+//! Tests for finding nearby devices and offering shares to them.
+
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+use sendme_lib::{
+    nearby::{DeviceInfo, Nearby, NearbyEvent},
+    offer::{Answer, Offer},
+    types::*,
+};
+
+fn device(name: &str) -> DeviceInfo {
+    DeviceInfo {
+        name: name.to_string(),
+        model: Some("Test Rig".to_string()),
+    }
+}
+
+#[tokio::test]
+async fn offer_a_share_to_a_nearby_device() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("hello.txt");
+    std::fs::write(&file, b"hello").unwrap();
+
+    let (laptop, _) = Nearby::spawn(device("laptop"), &lan_only(dir.path()))
+        .await
+        .unwrap();
+    let (phone, mut offers) = Nearby::spawn(device("phone"), &lan_only(dir.path()))
+        .await
+        .unwrap();
+
+    // The laptop finds the phone, with its name and model
+    let mut events = laptop.events();
+    let found = tokio::time::timeout(Duration::from_secs(30), async {
+        loop {
+            match events.recv().await.unwrap() {
+                NearbyEvent::Appeared(device) if device.id == phone.id() => break device,
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("the phone was not found");
+    assert_eq!(found.info, device("phone"));
+    assert!(laptop.devices().contains(&found));
+
+    let (share, handle) = sendme_lib::send(SendArgs {
+        common: lan_only(dir.path()),
+        ..send_args(file, dir.path())
+    })
+    .await
+    .unwrap();
+
+    // The phone declines the first offer and accepts the second
+    let offered = tokio::spawn(async move {
+        let first = offers.recv().await.unwrap();
+        first.reject();
+        let second = offers.recv().await.unwrap();
+        assert_eq!(second.offer.from, device("laptop"));
+        assert_eq!(second.offer.files.len(), 1);
+        assert_eq!(second.offer.files[0].name, "hello.txt");
+        assert_eq!(second.offer.files[0].size, 5);
+        assert_eq!(second.offer.total_size, 5);
+        second.accept().await.unwrap()
+    });
+    let offer = Offer::new(laptop.info().clone(), &share);
+    let ticket = share.share_ticket();
+    let answer = laptop
+        .offer(handle.endpoint(), phone.id(), &offer, &ticket, None)
+        .await;
+    assert_eq!(answer.unwrap(), Answer::Reject);
+    let answer = laptop
+        .offer(handle.endpoint(), phone.id(), &offer, &ticket, None)
+        .await;
+    assert_eq!(answer.unwrap(), Answer::Accept);
+    let accepted = offered.await.unwrap();
+    assert_eq!(accepted.ticket, share.share_ticket());
+    assert_eq!(accepted.include, IncludeFilter::All);
+    let ticket = accepted.ticket;
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    sendme_lib::receive(ReceiveArgs {
+        common: lan_only(dir.path()),
+        ..receive_args(ticket.ticket, &out)
+    })
+    .await
+    .unwrap();
+    assert_eq!(std::fs::read(out.join("hello.txt")).unwrap(), b"hello");
+
+    handle.shutdown().await.unwrap();
+    laptop.shutdown().await.unwrap();
+    phone.shutdown().await.unwrap();
+}
@@ -0,0 +1,235 @@
+//! Tests for offering shares before receivers fetch them.
+
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+use iroh::{protocol::Router, Endpoint, RelayMode};
+use sendme_lib::{
+    nearby::DeviceInfo,
+    offer::{self, Answer, Offer, OfferProtocol},
+    types::*,
+    ConnectionStatus, ProgressEvent,
+};
+
+mod common;
+use common::{loopback, receive_args};
+
+/// Share `path` from loopback, so offers and tickets agree on addresses.
+fn send_args(path: std::path::PathBuf, temp_dir: &std::path::Path) -> SendArgs {
+    SendArgs {
+        common: loopback(temp_dir),
+        ..common::send_args(path, temp_dir)
+    }
+}
+
+/// An endpoint on loopback, without relays or discovery.
+async fn endpoint() -> Endpoint {
+    Endpoint::empty_builder(RelayMode::Disabled)
+        .bind_addr_v4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+        .bind()
+        .await
+        .unwrap()
+}
+
+/// A receiver taking offers, and its address.
+async fn receiver() -> (
+    Router,
+    iroh::EndpointAddr,
+    tokio::sync::mpsc::Receiver<offer::IncomingOffer>,
+) {
+    let (offers, offers_rx) = OfferProtocol::new();
+    let router = Router::builder(endpoint().await)
+        .accept(offer::ALPN, offers)
+        .spawn();
+    let addr = router.endpoint().addr();
+    (router, addr, offers_rx)
+}
+
+fn laptop() -> DeviceInfo {
+    DeviceInfo {
+        name: "laptop".to_string(),
+        model: None,
+    }
+}
+
+#[tokio::test]
+async fn accept_a_subset_of_an_offer() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("photos");
+    std::fs::create_dir_all(src.join("raw")).unwrap();
+    std::fs::write(src.join("a.jpg"), b"aaaa").unwrap();
+    std::fs::write(src.join("b.jpg"), b"bbbbbb").unwrap();
+    std::fs::write(src.join("raw/c.raw"), b"cc").unwrap();
+    let (share, handle) = sendme_lib::send(send_args(src, dir.path())).await.unwrap();
+
+    let (router, addr, mut offers) = receiver().await;
+    let answered = tokio::spawn(async move {
+        let incoming = offers.recv().await.unwrap();
+        let offer = incoming.offer.clone();
+        let answer =
+            Answer::AcceptSubset(vec!["photos/a.jpg".to_string(), "photos/raw".to_string()]);
+        (offer, incoming.answer(answer).await.unwrap().unwrap())
+    });
+
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(8);
+    let offer = Offer::new(laptop(), &share).with_message("the holiday pictures");
+    let answer = offer::send_offer(
+        handle.endpoint(),
+        addr,
+        &offer,
+        &share.share_ticket(),
+        Some(progress_tx),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(answer, Answer::AcceptSubset(ref names) if names.len() == 2));
+    assert!(matches!(
+        progress_rx.recv().await,
+        Some(ProgressEvent::Connection(
+            ConnectionStatus::OfferSent { .. }
+        ))
+    ));
+    assert!(matches!(
+        progress_rx.recv().await,
+        Some(ProgressEvent::Connection(ConnectionStatus::OfferAnswered { answer: a, .. })) if a == answer
+    ));
+
+    // The receiver saw the manifest
+    let (seen, accepted) = answered.await.unwrap();
+    assert_eq!(seen, offer);
+    assert_eq!(seen.message.as_deref(), Some("the holiday pictures"));
+    let mut files: Vec<_> = seen
+        .files
+        .iter()
+        .map(|f| (f.name.as_str(), f.size))
+        .collect();
+    files.sort();
+    assert_eq!(
+        files,
+        [
+            ("photos/a.jpg", 4),
+            ("photos/b.jpg", 6),
+            ("photos/raw/c.raw", 2)
+        ]
+    );
+    assert_eq!(seen.total_size, 12);
+    assert_eq!(accepted.ticket, share.share_ticket());
+
+    // Only the accepted files are received
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    sendme_lib::receive(ReceiveArgs {
+        common: loopback(dir.path()),
+        include: accepted.include,
+        ..receive_args(accepted.ticket.ticket, &out)
+    })
+    .await
+    .unwrap();
+    assert_eq!(std::fs::read(out.join("photos/a.jpg")).unwrap(), b"aaaa");
+    assert_eq!(std::fs::read(out.join("photos/raw/c.raw")).unwrap(), b"cc");
+    assert!(!out.join("photos/b.jpg").exists());
+
+    handle.shutdown().await.unwrap();
+    router.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn rejected_offers_get_no_ticket() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("hello.txt");
+    std::fs::write(&file, b"hello").unwrap();
+    let (share, handle) = sendme_lib::send(send_args(file, dir.path())).await.unwrap();
+
+    let (router, addr, mut offers) = receiver().await;
+    let answered = tokio::spawn(async move {
+        // Only offered files can be accepted, an invalid answer rejects
+        let incoming = offers.recv().await.unwrap();
+        let answer = Answer::AcceptSubset(vec!["other.txt".to_string()]);
+        assert!(incoming.answer(answer).await.is_err());
+        let incoming = offers.recv().await.unwrap();
+        incoming.answer(Answer::Reject).await.unwrap()
+    });
+
+    let offer = Offer::new(laptop(), &share);
+    for _ in 0..2 {
+        let answer = offer::send_offer(
+            handle.endpoint(),
+            addr.clone(),
+            &offer,
+            &share.share_ticket(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(answer, Answer::Reject);
+    }
+    assert!(answered.await.unwrap().is_none());
+
+    handle.shutdown().await.unwrap();
+    router.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn tickets_must_match_the_offer() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("offered.txt"), b"offered").unwrap();
+    std::fs::write(dir.path().join("other.txt"), b"other").unwrap();
+    let (share, handle) = sendme_lib::send(send_args(dir.path().join("offered.txt"), dir.path()))
+        .await
+        .unwrap();
+    let (other, other_handle) =
+        sendme_lib::send(send_args(dir.path().join("other.txt"), dir.path()))
+            .await
+            .unwrap();
+
+    let (router, addr, mut offers) = receiver().await;
+    let answered = tokio::spawn(async move {
+        let mut errors = Vec::new();
+        for _ in 0..2 {
+            let incoming = offers.recv().await.unwrap();
+            errors.push(incoming.accept().await.unwrap_err().to_string());
+        }
+        errors
+    });
+
+    // The ticket of another share, from the endpoint serving the offered one
+    let offer = Offer::new(laptop(), &share);
+    offer::send_offer(
+        handle.endpoint(),
+        addr.clone(),
+        &offer,
+        &other.share_ticket(),
+        None,
+    )
+    .await
+    .unwrap();
+    // The right ticket, from an endpoint that doesn't serve it
+    let sender = endpoint().await;
+    offer::send_offer(&sender, addr, &offer, &share.share_ticket(), None)
+        .await
+        .unwrap();
+    for error in answered.await.unwrap() {
+        assert!(error.contains("not for the offered share"), "{error}");
+    }
+
+    sender.close().await;
+    handle.shutdown().await.unwrap();
+    other_handle.shutdown().await.unwrap();
+    router.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn encrypted_offers_list_no_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("secret.txt");
+    std::fs::write(&file, b"secret").unwrap();
+    let mut args = send_args(file, dir.path());
+    args.passphrase = Some("correct horse battery staple".to_string());
+    let (share, handle) = sendme_lib::send(args).await.unwrap();
+
+    let offer = Offer::new(laptop(), &share);
+    assert!(offer.encrypted);
+    assert!(offer.files.is_empty());
+    assert_eq!(offer.total_size, 6);
+
+    handle.shutdown().await.unwrap();
+}
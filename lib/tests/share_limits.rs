@@ -0,0 +1,150 @@
+//! Tests for download-count and expiry limits on a share.
+
+use std::time::Duration;
+
+use sendme_lib::{
+    progress::{ConnectionStatus, ProgressEvent, ShareLimit},
+    types::*,
+};
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn share_closes_after_max_downloads() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("hello.txt"), b"hello").unwrap();
+
+    let mut args = send_args(src.clone(), dir.path());
+    args.max_downloads = Some(1);
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(64);
+    let (result, handle) = sendme_lib::send_with_progress(args, progress_tx)
+        .await
+        .unwrap();
+
+    // Listing the files does not use up the quota
+    let listing = sendme_lib::inspect(result.ticket.clone(), common(dir.path()))
+        .await
+        .unwrap();
+    assert_eq!(listing.files.len(), 1);
+
+    let first = dir.path().join("first");
+    std::fs::create_dir_all(&first).unwrap();
+    sendme_lib::receive(receive_args(result.ticket.clone(), &first))
+        .await
+        .unwrap();
+    assert_eq!(
+        std::fs::read(first.join("src").join("hello.txt")).unwrap(),
+        b"hello"
+    );
+
+    tokio::time::timeout(Duration::from_secs(30), handle.closed())
+        .await
+        .expect("share was not closed");
+
+    let mut quota = Vec::new();
+    let mut closed = None;
+    while let Ok(event) = progress_rx.try_recv() {
+        match event {
+            ProgressEvent::Connection(ConnectionStatus::QuotaUpdated {
+                downloads,
+                remaining_downloads,
+                ..
+            }) => quota.push((downloads, remaining_downloads)),
+            ProgressEvent::Connection(ConnectionStatus::ShareClosed { reason }) => {
+                closed = Some(reason)
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(quota, vec![(0, Some(1)), (1, Some(0))]);
+    assert_eq!(closed, Some(ShareLimit::MaxDownloads));
+    assert!(handle.is_closed());
+
+    // Nobody is serving the ticket anymore, without shutting the handle down
+    let second = dir.path().join("second");
+    std::fs::create_dir_all(&second).unwrap();
+    let res = tokio::time::timeout(
+        Duration::from_secs(5),
+        sendme_lib::receive(receive_args(result.ticket, &second)),
+    )
+    .await;
+    assert!(!matches!(res, Ok(Ok(_))));
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn partial_download_uses_up_the_quota() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("a.txt"), vec![b'a'; 10_000]).unwrap();
+    std::fs::write(src.join("b.txt"), vec![b'b'; 10_000]).unwrap();
+
+    let mut args = send_args(src, dir.path());
+    args.max_downloads = Some(1);
+    let (result, handle) = sendme_lib::send(args).await.unwrap();
+
+    // Only one of the two files, so not every blob of the share is sent
+    let first = dir.path().join("first");
+    std::fs::create_dir_all(&first).unwrap();
+    sendme_lib::receive(ReceiveArgs {
+        include: IncludeFilter::Names(vec!["src/a.txt".to_string()]),
+        ..receive_args(result.ticket.clone(), &first)
+    })
+    .await
+    .unwrap();
+    assert!(first.join("src").join("a.txt").exists());
+    assert!(!first.join("src").join("b.txt").exists());
+
+    tokio::time::timeout(Duration::from_secs(30), handle.closed())
+        .await
+        .expect("share was not closed");
+
+    // The rest can't be fetched with another receive
+    let second = dir.path().join("second");
+    std::fs::create_dir_all(&second).unwrap();
+    let res = tokio::time::timeout(
+        Duration::from_secs(5),
+        sendme_lib::receive(ReceiveArgs {
+            include: IncludeFilter::Names(vec!["src/b.txt".to_string()]),
+            ..receive_args(result.ticket, &second)
+        }),
+    )
+    .await;
+    assert!(!matches!(res, Ok(Ok(_))));
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn share_closes_when_expired() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("hello.txt");
+    std::fs::write(&file, b"hello").unwrap();
+
+    let mut args = send_args(file, dir.path());
+    args.expires_after = Some(Duration::from_millis(500));
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(64);
+    let (_result, handle) = sendme_lib::send_with_progress(args, progress_tx)
+        .await
+        .unwrap();
+
+    tokio::time::timeout(Duration::from_secs(10), handle.closed())
+        .await
+        .expect("share did not expire");
+
+    let closed = loop {
+        if let ProgressEvent::Connection(ConnectionStatus::ShareClosed { reason }) =
+            progress_rx.recv().await.unwrap()
+        {
+            break reason;
+        }
+    };
+    assert_eq!(closed, ShareLimit::Expired);
+
+    handle.shutdown().await.unwrap();
+}
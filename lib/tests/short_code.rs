@@ -0,0 +1,205 @@
+//! Tests for pairing with short codes.
+
+use sendme_lib::{
+    pairing::{request_ticket, ALPN},
+    progress::{ConnectionStatus, ProgressEvent},
+    types::*,
+    ShortCode,
+};
+
+mod common;
+use common::*;
+
+async fn bind() -> iroh::Endpoint {
+    iroh::Endpoint::builder()
+        .relay_mode(iroh::RelayMode::Disabled)
+        .bind()
+        .await
+        .unwrap()
+}
+
+#[test]
+fn codes_roundtrip() {
+    let code = ShortCode::generate();
+    assert_eq!(code.to_string().parse::<ShortCode>().unwrap(), code);
+    assert_eq!(
+        " 7-Canyon-Violin "
+            .parse::<ShortCode>()
+            .unwrap()
+            .to_string(),
+        "7-canyon-violin"
+    );
+    assert!("0-canyon-violin".parse::<ShortCode>().is_err());
+    assert!("1000000-canyon-violin".parse::<ShortCode>().is_err());
+    assert!("7-canyon".parse::<ShortCode>().is_err());
+    assert!("7-canyon-notaword".parse::<ShortCode>().is_err());
+
+    assert!(matches!(
+        "7-canyon-violin".parse::<TicketOrCode>().unwrap(),
+        TicketOrCode::Code(_)
+    ));
+}
+
+#[tokio::test]
+async fn code_pairs_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("hello.txt");
+    std::fs::write(&file, b"hello").unwrap();
+
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(64);
+    let (result, handle) = sendme_lib::send_with_progress(
+        SendArgs {
+            short_code: true,
+            ..send_args(file, dir.path())
+        },
+        progress_tx,
+    )
+    .await
+    .unwrap();
+    let code = result.code.clone().unwrap();
+
+    let addr = handle.endpoint().addr();
+    let endpoint = bind().await;
+    let ticket = request_ticket(&endpoint, addr.clone(), &code)
+        .await
+        .unwrap();
+    assert_eq!(ticket, result.share_ticket());
+
+    let paired = loop {
+        if let ProgressEvent::Connection(ConnectionStatus::CodeUsed { paired, .. }) =
+            progress_rx.recv().await.unwrap()
+        {
+            break paired;
+        }
+    };
+    assert!(paired);
+
+    // The code can't be used again
+    let err = request_ticket(&endpoint, addr, &code).await.unwrap_err();
+    assert!(err.to_string().contains("already used"), "{err:#}");
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    sendme_lib::receive(receive_args(ticket.ticket, &out))
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(out.join("hello.txt")).unwrap(), b"hello");
+
+    endpoint.close().await;
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn wrong_code_burns_the_code() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("hello.txt");
+    std::fs::write(&file, b"hello").unwrap();
+
+    let (result, handle) = sendme_lib::send(SendArgs {
+        short_code: true,
+        ..send_args(file, dir.path())
+    })
+    .await
+    .unwrap();
+    let code = result.code.unwrap();
+    let wrong = loop {
+        let wrong = ShortCode::generate();
+        if wrong != code {
+            break wrong;
+        }
+    };
+
+    let addr = handle.endpoint().addr();
+    let endpoint = bind().await;
+    let err = request_ticket(&endpoint, addr.clone(), &wrong)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("wrong code"), "{err:#}");
+
+    let err = request_ticket(&endpoint, addr, &code).await.unwrap_err();
+    assert!(err.to_string().contains("already used"), "{err:#}");
+
+    endpoint.close().await;
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn connecting_does_not_use_the_code() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("hello.txt");
+    std::fs::write(&file, b"hello").unwrap();
+
+    let (result, handle) = sendme_lib::send(SendArgs {
+        short_code: true,
+        ..send_args(file, dir.path())
+    })
+    .await
+    .unwrap();
+    let code = result.code.clone().unwrap();
+
+    let addr = handle.endpoint().addr();
+    let endpoint = bind().await;
+    // Someone who found the nameplate connects, but doesn't guess the code
+    let connection = endpoint.connect(addr.clone(), ALPN).await.unwrap();
+    let (mut send, _recv) = connection.open_bi().await.unwrap();
+    send.write_all(b"x").await.unwrap();
+    connection.close(0u32.into(), b"bye");
+
+    let ticket = request_ticket(&endpoint, addr, &code).await.unwrap();
+    assert_eq!(ticket, result.share_ticket());
+
+    endpoint.close().await;
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn code_share_does_not_use_the_identity() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("hello.txt");
+    std::fs::write(&file, b"hello").unwrap();
+    let keys = dir.path().join("keys");
+    let identity = sendme_lib::KeyStore::new(&keys)
+        .load_or_create("default")
+        .unwrap();
+
+    let (result, handle) = sendme_lib::send(SendArgs {
+        short_code: true,
+        common: CommonConfig {
+            identity: Some("default".to_string()),
+            key_dir: Some(keys),
+            ..common(dir.path())
+        },
+        ..send_args(file, dir.path())
+    })
+    .await
+    .unwrap();
+    assert_ne!(result.ticket.addr().id, identity.public());
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn code_resolves_by_nameplate_on_the_local_network() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("hello.txt");
+    std::fs::write(&file, b"hello").unwrap();
+
+    let (result, handle) = sendme_lib::send(SendArgs {
+        short_code: true,
+        common: lan_only(dir.path()),
+        ..send_args(file, dir.path())
+    })
+    .await
+    .unwrap();
+    let code = result.code.clone().unwrap();
+
+    let common = lan_only(dir.path());
+    let resolve = sendme_lib::resolve_code(&code, &common);
+    let ticket = tokio::time::timeout(std::time::Duration::from_secs(30), resolve)
+        .await
+        .expect("the sender was not found")
+        .unwrap();
+    assert_eq!(ticket, result.share_ticket());
+
+    handle.shutdown().await.unwrap();
+}
@@ -0,0 +1,116 @@
+//! Tests for importing from a reader and receiving into a writer.
+
+use sendme_lib::{import::import_reader, types::*};
+
+mod common;
+use common::send_args;
+
+fn receive_args(ticket: sendme_lib::BlobTicket, dir: &std::path::Path) -> ReceiveArgs {
+    ReceiveArgs {
+        export_dir: None,
+        ..common::receive_args(ticket, dir)
+    }
+}
+
+/// Data that spans several chunks and doesn't end on a chunk boundary.
+fn data() -> Vec<u8> {
+    (0..200_000u32).map(|i| (i % 251) as u8).collect()
+}
+
+#[tokio::test]
+async fn import_reader_streams_into_the_store() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = iroh_blobs::store::fs::FsStore::load(dir.path().join("db"))
+        .await
+        .unwrap();
+    let data = data();
+
+    let (hash, size, collection) = import_reader(
+        "data.bin".to_string(),
+        std::io::Cursor::new(data.clone()),
+        &db,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(size, data.len() as u64);
+    let (name, blob) = collection.iter().next().unwrap();
+    assert_eq!(name, "data.bin");
+    assert_eq!(*blob, sendme_lib::Hash::new(&data));
+    assert_eq!(db.get_bytes(*blob).await.unwrap(), data);
+    let stored = sendme_lib::Collection::load(hash, db.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(stored, collection);
+
+    db.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn receive_to_writer_writes_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = data();
+    std::fs::write(dir.path().join("data.bin"), &data).unwrap();
+
+    for passphrase in [None, Some("correct horse".to_string())] {
+        let (result, handle) = sendme_lib::send(SendArgs {
+            passphrase: passphrase.clone(),
+            ..send_args(dir.path().join("data.bin"), dir.path())
+        })
+        .await
+        .unwrap();
+
+        let mut out = Vec::new();
+        let received = sendme_lib::receive_to_writer(
+            ReceiveArgs {
+                passphrase,
+                ..receive_args(result.ticket.clone(), dir.path())
+            },
+            &mut out,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(received.total_files, 1);
+        assert_eq!(out, data);
+
+        handle.shutdown().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn receive_to_writer_needs_a_single_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("a.txt"), b"a").unwrap();
+    std::fs::write(src.join("b.txt"), b"b").unwrap();
+
+    let (result, handle) = sendme_lib::send(send_args(src, dir.path())).await.unwrap();
+
+    let mut out = Vec::new();
+    let err = sendme_lib::receive_to_writer(
+        receive_args(result.ticket.clone(), dir.path()),
+        &mut out,
+        None,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("single file"), "{err:#}");
+    assert!(out.is_empty());
+
+    // An include filter can pick one of them
+    sendme_lib::receive_to_writer(
+        ReceiveArgs {
+            include: IncludeFilter::Names(vec!["src/b.txt".to_string()]),
+            ..receive_args(result.ticket.clone(), dir.path())
+        },
+        &mut out,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(out, b"b");
+
+    handle.shutdown().await.unwrap();
+}
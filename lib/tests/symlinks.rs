@@ -0,0 +1,163 @@
+//! Tests for the symlink policies of directory imports.
+#![cfg(unix)]
+
+use std::os::unix::fs::symlink;
+
+use sendme_lib::{metadata::check_link_target, types::*};
+
+mod common;
+use common::receive_args;
+
+fn send_args(path: std::path::PathBuf, dir: &std::path::Path, symlinks: SymlinkPolicy) -> SendArgs {
+    SendArgs {
+        symlinks,
+        ..common::send_args(path, dir)
+    }
+}
+
+/// A directory with links to a file, a directory, its parent, an absolute path
+/// and back to itself.
+fn create_source(dir: &std::path::Path) -> std::path::PathBuf {
+    let src = dir.join("src");
+    std::fs::create_dir_all(src.join("sub")).unwrap();
+    std::fs::write(src.join("real.txt"), b"real").unwrap();
+    std::fs::write(src.join("sub").join("a.txt"), b"a").unwrap();
+    std::fs::write(dir.join("outside.txt"), b"outside").unwrap();
+    symlink("real.txt", src.join("link.txt")).unwrap();
+    symlink("sub", src.join("dirlink")).unwrap();
+    symlink("../outside.txt", src.join("up.txt")).unwrap();
+    symlink(dir.join("outside.txt"), src.join("absolute.txt")).unwrap();
+    symlink("..", src.join("sub").join("loop")).unwrap();
+    src
+}
+
+fn names(collection: &sendme_lib::Collection) -> Vec<&str> {
+    let mut names: Vec<_> = collection.iter().map(|(name, _)| name.as_str()).collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn symlinks_are_skipped_by_default() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = create_source(dir.path());
+
+    let (result, handle) = sendme_lib::send(send_args(src, dir.path(), SymlinkPolicy::Skip))
+        .await
+        .unwrap();
+    assert_eq!(
+        names(&result.collection),
+        vec!["src/real.txt", "src/sub/a.txt"]
+    );
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn followed_symlinks_are_sent_as_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = create_source(dir.path());
+    symlink("missing.txt", src.join("dangling.txt")).unwrap();
+
+    let (result, handle) = sendme_lib::send(send_args(src, dir.path(), SymlinkPolicy::Follow))
+        .await
+        .unwrap();
+    // The loop and the dangling link are left out
+    assert_eq!(
+        names(&result.collection),
+        vec![
+            "src/absolute.txt",
+            "src/dirlink/a.txt",
+            "src/link.txt",
+            "src/real.txt",
+            "src/sub/a.txt",
+            "src/up.txt",
+        ]
+    );
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    sendme_lib::receive(receive_args(result.ticket.clone(), &out))
+        .await
+        .unwrap();
+    let absolute = out.join("src").join("absolute.txt");
+    assert!(!absolute.is_symlink());
+    assert_eq!(std::fs::read(absolute).unwrap(), b"outside");
+
+    handle.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn preserved_symlinks_are_recreated() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = create_source(dir.path());
+
+    let (result, handle) = sendme_lib::send(send_args(src, dir.path(), SymlinkPolicy::Preserve))
+        .await
+        .unwrap();
+    assert_eq!(
+        names(&result.collection),
+        vec!["src/real.txt", "src/sub/a.txt"]
+    );
+
+    for atomic in [false, true] {
+        let out = dir.path().join(format!("out-{atomic}"));
+        std::fs::create_dir_all(&out).unwrap();
+        sendme_lib::receive(ReceiveArgs {
+            atomic_export: atomic,
+            ..receive_args(result.ticket.clone(), &out)
+        })
+        .await
+        .unwrap();
+
+        let dst = out.join("src");
+        assert_eq!(
+            std::fs::read_link(dst.join("link.txt")).unwrap(),
+            std::path::Path::new("real.txt")
+        );
+        assert_eq!(std::fs::read(dst.join("link.txt")).unwrap(), b"real");
+        assert_eq!(
+            std::fs::read(dst.join("dirlink").join("a.txt")).unwrap(),
+            b"a"
+        );
+        assert!(dst.join("sub").join("loop").is_symlink());
+        // Still inside of the export directory, but nothing is there
+        assert_eq!(
+            std::fs::read_link(dst.join("up.txt")).unwrap(),
+            std::path::Path::new("../outside.txt")
+        );
+        assert!(!dst.join("absolute.txt").is_symlink());
+    }
+
+    handle.shutdown().await.unwrap();
+}
+
+#[test]
+fn link_targets_must_stay_inside_the_export_directory() {
+    for (name, target) in [
+        ("src/link", "real.txt"),
+        ("src/link", "../other/file"),
+        ("src/sub/link", "../../src"),
+        ("src/link", "./sub/./file"),
+        ("link", "."),
+    ] {
+        assert!(
+            check_link_target(name, target).is_ok(),
+            "{name} -> {target}"
+        );
+    }
+    for (name, target) in [
+        ("src/link", "/etc/passwd"),
+        ("src/link", "../../outside"),
+        ("link", ".."),
+        ("src/link", "sub/../../.."),
+        ("src/link", "sub/.."),
+        ("src/link", ""),
+        ("src/link", "..\\..\\outside"),
+    ] {
+        assert!(
+            check_link_target(name, target).is_err(),
+            "{name} -> {target}"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Tests for transfer rates, ETAs and download summaries.
+
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+use sendme_lib::{types::*, ConnectionStatus, DownloadProgress, ProgressEvent, RateTracker};
+
+#[tokio::test]
+async fn rate_follows_progress() {
+    const CHUNK: u64 = 10 * 1024;
+    const TOTAL: u64 = 100 * CHUNK;
+    // Bytes available before the transfer started don't count
+    let start = 50 * CHUNK;
+    let mut tracker = RateTracker::starting_at(start);
+
+    let rate = tracker.update(start, Some(TOTAL));
+    assert_eq!(rate.bytes_per_sec, 0);
+    assert_eq!(rate.eta, None);
+
+    let mut offset = start;
+    let mut rate = rate;
+    for _ in 0..5 {
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        offset += CHUNK;
+        rate = tracker.update(offset, Some(TOTAL));
+    }
+    // At most one chunk per 150 ms, allowing for slow timers
+    assert!(rate.bytes_per_sec > 1024, "{rate:?}");
+    assert!(rate.bytes_per_sec <= CHUNK * 1000 / 150, "{rate:?}");
+    assert!(rate.peak_bytes_per_sec >= rate.bytes_per_sec);
+    assert!(rate.elapsed >= Duration::from_millis(750));
+    let eta = rate.eta.expect("the total is known");
+    let expected = (TOTAL - offset) as f64 / rate.bytes_per_sec as f64;
+    assert!((eta.as_secs_f64() - expected).abs() < 1.0, "{rate:?}");
+
+    // Without a total there is no ETA, but still a rate
+    let rate = tracker.update(offset, None);
+    assert_eq!(rate.eta, None);
+    assert!(rate.bytes_per_sec > 0);
+}
+
+#[tokio::test]
+async fn receive_reports_rates_and_a_summary() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("data.bin");
+    let data = (0..4 * 1024 * 1024u32)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    std::fs::write(&file, &data).unwrap();
+
+    let (send_tx, send_rx) = tokio::sync::mpsc::channel(64);
+    let (share, handle) = sendme_lib::send_with_progress(
+        SendArgs {
+            common: loopback(dir.path()),
+            ..send_args(file, dir.path())
+        },
+        send_tx,
+    )
+    .await
+    .unwrap();
+    let sent = collect_events(send_rx);
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).unwrap();
+    let (recv_tx, recv_rx) = tokio::sync::mpsc::channel(64);
+    let received = collect_events(recv_rx);
+    let result = sendme_lib::receive_with_progress(
+        ReceiveArgs {
+            common: loopback(dir.path()),
+            ..receive_args(share.ticket.clone(), &out)
+        },
+        recv_tx,
+    )
+    .await
+    .unwrap();
+    handle.shutdown().await.unwrap();
+
+    let summary = result.summary;
+    assert_eq!(summary.payload_bytes, result.stats.payload_bytes_read);
+    assert!(summary.payload_bytes >= data.len() as u64);
+    assert!(summary.total_bytes >= summary.payload_bytes);
+    assert_eq!(summary.elapsed, result.stats.elapsed);
+    assert!(summary.average_bytes_per_sec > 0);
+    assert!(summary.peak_bytes_per_sec >= summary.average_bytes_per_sec);
+
+    let received = received.await.unwrap();
+    let rates = received
+        .iter()
+        .filter_map(|event| match event {
+            ProgressEvent::Download(DownloadProgress::Downloading { rate, .. }) => Some(rate),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert!(!rates.is_empty());
+    assert!(rates
+        .windows(2)
+        .all(|pair| pair[0].elapsed <= pair[1].elapsed));
+
+    let sent = sent.await.unwrap();
+    assert!(sent.iter().any(|event| matches!(
+        event,
+        ProgressEvent::Connection(ConnectionStatus::RequestProgress { .. })
+    )));
+}